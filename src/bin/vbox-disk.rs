@@ -0,0 +1,62 @@
+//! Offline tool for VBOX disk images: `vbox-disk <info|check|repair> <image>`.
+//! Run it only while no VBOX instance has the image open.
+
+use std::env;
+use std::process::ExitCode;
+
+use vbox::vfs::fsck::{self, Report};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: vbox-disk <info|check|repair> <image>");
+        return ExitCode::from(2);
+    }
+    let path = &args[2];
+
+    let result = match args[1].as_str() {
+        "info" => fsck::inspect(path).map(|info| {
+            println!("Image:        {}", path);
+            println!("Format:       v{}", info.version);
+            println!("Block size:   {} bytes", info.block_size);
+            println!("Capacity:     {} blocks", info.capacity);
+            println!("Stored:       {} blocks, {} bytes of data", info.blocks, info.data_bytes);
+            println!("Journal:      {} pending records", info.journal_records);
+            println!("Snapshots:    {}", info.snapshots.len());
+            for snapshot in info.snapshots {
+                println!("  {}", snapshot);
+            }
+            true
+        }),
+        "check" => fsck::check(path).map(|report| print_report(path, &report)),
+        "repair" => fsck::repair(path).map(|report| {
+            for fixed in &report.fixed {
+                println!("{}: fixed: {}", path, fixed);
+            }
+            print_report(path, &report)
+        }),
+        other => {
+            eprintln!("vbox-disk: unknown command '{}'", other);
+            return ExitCode::from(2);
+        }
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("vbox-disk: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn print_report(path: &str, report: &Report) -> bool {
+    for problem in &report.problems {
+        println!("{}: {}", path, problem);
+    }
+    if report.is_clean() {
+        println!("{}: clean", path);
+    }
+    report.is_clean()
+}
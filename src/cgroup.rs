@@ -0,0 +1,406 @@
+//! Hierarchical resource groups, after Linux cgroup v2.
+//!
+//! Groups form a tree under `/`. Every process belongs to one group (its
+//! parent's, or the root) and counts against the limits of that group and
+//! of every ancestor:
+//!
+//! - `cpu.weight` scales the time slices of the group's processes;
+//! - `pids.max` caps how many processes the subtree may have;
+//! - `memory.max` caps the subtree's resident memory: the memory manager
+//!   swaps the subtree's own pages out, or OOM-kills inside it;
+//! - `io.max` is a disk budget in bytes per second; `ThrottledDisk` makes
+//!   processes over it wait.
+//!
+//! Each `Scheduler` owns a tree, exposed under `/sys/fs/cgroup`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_CPU_WEIGHT: u32 = 100;
+pub const MAX_CPU_WEIGHT: u32 = 10_000;
+
+const EAGAIN: &str = "Resource temporarily unavailable";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Limits {
+    /// Relative share of CPU time, 1 to 10000.
+    pub cpu_weight: u32,
+    pub pids_max: Option<usize>,
+    /// Bytes of resident memory.
+    pub memory_max: Option<usize>,
+    /// Bytes per second read from or written to disk.
+    pub io_max: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            cpu_weight: DEFAULT_CPU_WEIGHT,
+            pids_max: None,
+            memory_max: None,
+            io_max: None,
+        }
+    }
+}
+
+/// What a group and its descendants used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Usage {
+    pub cpu_time: Duration,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub reads: u64,
+    pub writes: u64,
+    /// Time processes waited for the I/O budget.
+    pub io_throttled: Duration,
+    pub oom_kills: u64,
+}
+
+#[derive(Default)]
+struct Group {
+    limits: Limits,
+    procs: BTreeSet<u32>,
+    usage: Usage,
+    // I/O token bucket, refilled at `io_max` bytes per second up to one
+    // second's worth; it goes negative while processes wait off a debt
+    io_tokens: f64,
+    io_refilled: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Tree {
+    groups: BTreeMap<String, Group>,
+    // Processes outside the root group
+    membership: BTreeMap<u32, String>,
+}
+
+#[derive(Clone)]
+pub struct Cgroups {
+    inner: Arc<Mutex<Tree>>,
+}
+
+impl Default for Cgroups {
+    fn default() -> Self {
+        let mut tree = Tree::default();
+        tree.groups.insert("/".into(), Group::default());
+        Cgroups {
+            inner: Arc::new(Mutex::new(tree)),
+        }
+    }
+}
+
+/// `/a/b` for any spelling of it; the root is `/`.
+pub fn normalize(path: &str) -> String {
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    format!("/{}", parts.join("/"))
+}
+
+fn parent_of(path: &str) -> Option<&str> {
+    match path.rfind('/') {
+        _ if path == "/" => None,
+        Some(0) => Some("/"),
+        Some(i) => Some(&path[..i]),
+        None => None,
+    }
+}
+
+// `path` and its ancestors, innermost first
+fn ancestors(path: &str) -> Vec<&str> {
+    let mut out = vec![path];
+    let mut current = path;
+    while let Some(parent) = parent_of(current) {
+        out.push(parent);
+        current = parent;
+    }
+    out
+}
+
+fn is_within(path: &str, group: &str) -> bool {
+    group == "/" || path == group || path.starts_with(&format!("{}/", group))
+}
+
+impl Tree {
+    fn group_of(&self, pid: u32) -> &str {
+        self.membership.get(&pid).map(String::as_str).unwrap_or("/")
+    }
+
+    fn group(&self, path: &str) -> Result<&Group, String> {
+        self.groups.get(path).ok_or_else(|| "No such file or directory".into())
+    }
+
+    fn subtree_procs(&self, path: &str) -> BTreeSet<u32> {
+        self.groups
+            .iter()
+            .filter(|(group, _)| is_within(group, path))
+            .flat_map(|(_, group)| group.procs.iter().copied())
+            .collect()
+    }
+
+    fn charge(&mut self, pid: u32, f: impl Fn(&mut Usage)) {
+        let path = self.group_of(pid).to_string();
+        for ancestor in ancestors(&path) {
+            if let Some(group) = self.groups.get_mut(ancestor) {
+                f(&mut group.usage);
+            }
+        }
+    }
+}
+
+impl Cgroups {
+    fn lock(&self) -> MutexGuard<'_, Tree> {
+        self.inner.lock().unwrap()
+    }
+
+    /// Creates an empty group; its parent must exist.
+    pub fn create(&self, path: &str) -> Result<(), String> {
+        let path = normalize(path);
+        let mut tree = self.lock();
+        if tree.groups.contains_key(&path) {
+            return Err("File exists".into());
+        }
+        let parent = parent_of(&path).ok_or("File exists")?;
+        tree.group(parent)?;
+        tree.groups.insert(path, Group::default());
+        Ok(())
+    }
+
+    /// Removes a group with no processes and no child groups.
+    pub fn remove(&self, path: &str) -> Result<(), String> {
+        let path = normalize(path);
+        let mut tree = self.lock();
+        let group = tree.group(&path)?;
+        let has_children = tree.groups.keys().any(|other| parent_of(other) == Some(path.as_str()));
+        if path == "/" || !group.procs.is_empty() || has_children {
+            return Err("Device or resource busy".into());
+        }
+        tree.groups.remove(&path);
+        Ok(())
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.lock().groups.contains_key(&normalize(path))
+    }
+
+    /// Names of the child groups of `path`.
+    pub fn children(&self, path: &str) -> Vec<String> {
+        let path = normalize(path);
+        let tree = self.lock();
+        tree.groups
+            .keys()
+            .filter(|other| parent_of(other) == Some(path.as_str()))
+            .map(|other| other.rsplit('/').next().unwrap().to_string())
+            .collect()
+    }
+
+    pub fn limits(&self, path: &str) -> Result<Limits, String> {
+        Ok(self.lock().group(&normalize(path))?.limits)
+    }
+
+    pub fn set_limits(&self, path: &str, limits: Limits) -> Result<(), String> {
+        if !(1..=MAX_CPU_WEIGHT).contains(&limits.cpu_weight) {
+            return Err("Invalid argument".into());
+        }
+        let path = normalize(path);
+        let mut tree = self.lock();
+        let group = tree.groups.get_mut(&path).ok_or("No such file or directory")?;
+        if group.limits.io_max != limits.io_max {
+            group.io_refilled = None;
+        }
+        group.limits = limits;
+        Ok(())
+    }
+
+    /// Usage of `path` and everything below it.
+    pub fn usage(&self, path: &str) -> Result<Usage, String> {
+        Ok(self.lock().group(&normalize(path))?.usage)
+    }
+
+    /// Processes directly in `path`.
+    pub fn procs(&self, path: &str) -> Result<Vec<u32>, String> {
+        Ok(self.lock().group(&normalize(path))?.procs.iter().copied().collect())
+    }
+
+    /// Processes in `path` and its descendants.
+    pub fn subtree_procs(&self, path: &str) -> BTreeSet<u32> {
+        self.lock().subtree_procs(&normalize(path))
+    }
+
+    pub fn group_of(&self, pid: u32) -> String {
+        self.lock().group_of(pid).to_string()
+    }
+
+    /// Places a new process in `parent`'s group, unless that would take
+    /// the group or an ancestor past `pids.max`.
+    pub fn admit(&self, pid: u32, parent: Option<u32>) -> Result<(), String> {
+        let mut tree = self.lock();
+        let path = parent.map_or("/", |parent| tree.group_of(parent)).to_string();
+        for ancestor in ancestors(&path) {
+            let max = tree.groups[ancestor].limits.pids_max;
+            if max.is_some_and(|max| tree.subtree_procs(ancestor).len() >= max) {
+                return Err(EAGAIN.into());
+            }
+        }
+        tree.groups.get_mut(&path).unwrap().procs.insert(pid);
+        if path != "/" {
+            tree.membership.insert(pid, path);
+        }
+        Ok(())
+    }
+
+    /// Moves a process to `path`. As on Linux, moving may exceed `pids.max`;
+    /// only new processes are refused.
+    pub fn attach(&self, pid: u32, path: &str) -> Result<(), String> {
+        let path = normalize(path);
+        let mut tree = self.lock();
+        tree.group(&path)?;
+        let old = tree.group_of(pid).to_string();
+        if !tree.groups[&old].procs.contains(&pid) {
+            return Err("No such process".into());
+        }
+        tree.groups.get_mut(&old).unwrap().procs.remove(&pid);
+        tree.groups.get_mut(&path).unwrap().procs.insert(pid);
+        tree.membership.remove(&pid);
+        if path != "/" {
+            tree.membership.insert(pid, path);
+        }
+        Ok(())
+    }
+
+    /// Forgets an exited process.
+    pub fn exit(&self, pid: u32) {
+        let mut tree = self.lock();
+        let path = tree.membership.remove(&pid).unwrap_or_else(|| "/".into());
+        if let Some(group) = tree.groups.get_mut(&path) {
+            group.procs.remove(&pid);
+        }
+    }
+
+    /// How much longer than the default `pid`'s time slices are: the
+    /// product of the weights on its path, relative to 100. The root's
+    /// weight has no siblings to be relative to and is left out.
+    pub fn cpu_factor(&self, pid: u32) -> f64 {
+        let tree = self.lock();
+        ancestors(tree.group_of(pid))
+            .into_iter()
+            .filter(|path| *path != "/")
+            .map(|path| tree.groups[path].limits.cpu_weight as f64 / DEFAULT_CPU_WEIGHT as f64)
+            .product()
+    }
+
+    pub fn charge_cpu(&self, pid: u32, ran: Duration) {
+        self.lock().charge(pid, |usage| usage.cpu_time += ran);
+    }
+
+    /// Charges a disk transfer and returns how long `pid` must wait before
+    /// it is within the budgets of its groups.
+    pub fn charge_io(&self, pid: u32, bytes: usize, write: bool) -> Duration {
+        let mut tree = self.lock();
+        let now = Instant::now();
+        let path = tree.group_of(pid).to_string();
+        let mut wait = Duration::ZERO;
+        for ancestor in ancestors(&path) {
+            let group = tree.groups.get_mut(ancestor).unwrap();
+            let rate = match group.limits.io_max {
+                Some(rate) => rate.max(1) as f64,
+                None => continue,
+            };
+            let elapsed = group.io_refilled.map_or(rate, |at| now.duration_since(at).as_secs_f64() * rate);
+            group.io_tokens = (group.io_tokens + elapsed).min(rate) - bytes as f64;
+            group.io_refilled = Some(now);
+            if group.io_tokens < 0.0 {
+                wait = wait.max(Duration::from_secs_f64(-group.io_tokens / rate));
+            }
+        }
+        tree.charge(pid, |usage| {
+            if write {
+                usage.write_bytes += bytes as u64;
+                usage.writes += 1;
+            } else {
+                usage.read_bytes += bytes as u64;
+                usage.reads += 1;
+            }
+            usage.io_throttled += wait;
+        });
+        wait
+    }
+
+    /// The `memory.max` ceilings over `pid`, each with the processes it
+    /// covers, innermost first.
+    pub fn memory_scopes(&self, pid: u32) -> Vec<(usize, BTreeSet<u32>)> {
+        let tree = self.lock();
+        ancestors(tree.group_of(pid))
+            .into_iter()
+            .filter_map(|path| {
+                let max = tree.groups[path].limits.memory_max?;
+                Some((max, tree.subtree_procs(path)))
+            })
+            .collect()
+    }
+
+    pub fn record_oom_kill(&self, pid: u32) {
+        self.lock().charge(pid, |usage| usage.oom_kills += 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hierarchy_and_pids_max() {
+        let cgroups = Cgroups::default();
+        cgroups.create("/web").unwrap();
+        cgroups.create("web/db/").unwrap();
+        assert_eq!(cgroups.create("/web"), Err("File exists".into()));
+        assert_eq!(cgroups.create("/nope/x"), Err("No such file or directory".into()));
+        assert_eq!(cgroups.children("/"), vec!["web"]);
+
+        let mut limits = cgroups.limits("/web").unwrap();
+        limits.pids_max = Some(2);
+        cgroups.set_limits("/web", limits).unwrap();
+        limits.cpu_weight = 0;
+        assert_eq!(cgroups.set_limits("/web", limits), Err("Invalid argument".into()));
+
+        cgroups.admit(1, None).unwrap();
+        cgroups.attach(1, "/web/db").unwrap();
+        // Children inherit their parent's group and count against /web
+        cgroups.admit(2, Some(1)).unwrap();
+        assert_eq!(cgroups.group_of(2), "/web/db");
+        assert_eq!(cgroups.admit(3, Some(2)), Err(EAGAIN.into()));
+        cgroups.admit(3, None).unwrap();
+        assert_eq!(cgroups.subtree_procs("/web").len(), 2);
+
+        assert_eq!(cgroups.remove("/web"), Err("Device or resource busy".into()));
+        cgroups.exit(1);
+        cgroups.exit(2);
+        cgroups.remove("/web/db").unwrap();
+        cgroups.remove("/web").unwrap();
+        assert!(!cgroups.exists("/web"));
+    }
+
+    #[test]
+    fn test_weights_and_io_budget() {
+        let cgroups = Cgroups::default();
+        cgroups.create("/batch").unwrap();
+        cgroups.create("/batch/low").unwrap();
+        cgroups
+            .set_limits("/batch", Limits { cpu_weight: 50, io_max: Some(1000), ..Limits::default() })
+            .unwrap();
+        cgroups
+            .set_limits("/batch/low", Limits { cpu_weight: 50, ..Limits::default() })
+            .unwrap();
+        cgroups.admit(1, None).unwrap();
+        cgroups.attach(1, "/batch/low").unwrap();
+        assert_eq!(cgroups.cpu_factor(1), 0.25);
+
+        // A second's worth of budget, then a debt to wait off
+        assert_eq!(cgroups.charge_io(1, 1000, true), Duration::ZERO);
+        let wait = cgroups.charge_io(1, 500, false);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        let usage = cgroups.usage("/").unwrap();
+        assert_eq!((usage.write_bytes, usage.read_bytes, usage.reads), (1000, 500, 1));
+        cgroups.charge_cpu(1, Duration::from_millis(3));
+        assert_eq!(cgroups.usage("/batch").unwrap().cpu_time, Duration::from_millis(3));
+    }
+}
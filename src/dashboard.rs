@@ -7,7 +7,10 @@ use crate::vps::manager::VpsManager;
 
 pub struct HttpDashboard {
     scheduler: Arc<Scheduler>,
+    // For the disk and VFS figures, which are placeholders for now
+    #[allow(dead_code)]
     fs: FileSystem,
+    #[allow(dead_code)]
     disk: Arc<dyn Disk + Send + Sync>,
     vps_manager: Arc<Mutex<VpsManager>>,
 }
@@ -161,7 +164,8 @@ impl HttpDashboard {
             "total_data_size": 171, // placeholder
             "vfs_directories": 1,
             "vfs_files": 0,
-            "total_file_data": 0
+            "total_file_data": 0,
+            "virtual_memory": self.scheduler.memory().stats()
         })
     }
 }
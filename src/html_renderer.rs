@@ -4,6 +4,12 @@ pub struct HtmlRenderer {
     max_width: usize,
 }
 
+impl Default for HtmlRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HtmlRenderer {
     pub fn new() -> Self {
         HtmlRenderer { max_width: 80 }
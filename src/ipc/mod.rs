@@ -0,0 +1,226 @@
+//! Kernel-style IPC objects: anonymous and named pipes, message queues and
+//! shared memory segments.
+//!
+//! Each `Scheduler` owns an `Ipc` namespace, so processes of one VPS never
+//! see another's objects. Waiting operations release the caller's CPU, and
+//! objects record the PIDs that created and used them for `/proc/ipc`.
+
+mod pipe;
+mod queue;
+mod shm;
+
+pub use pipe::{Fifo, Pipe, PipeReader, PipeWriter, PIPE_CAPACITY};
+pub use queue::{Message, MessageQueue, DEFAULT_MAX_MESSAGES, DEFAULT_MAX_MESSAGE_SIZE};
+pub use shm::{SharedMemory, ShmMapping};
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use crate::scheduler;
+
+// How long a waiting operation sleeps before checking whether its process
+// was killed meanwhile
+const WAIT_SLICE: Duration = Duration::from_millis(50);
+
+const EAGAIN: &str = "Resource temporarily unavailable";
+
+/// PID the objects are charged to: the calling process, or 0 outside the
+/// scheduler.
+fn caller_pid() -> u32 {
+    scheduler::current_pid().unwrap_or(0)
+}
+
+#[derive(Clone, Default)]
+pub struct Ipc {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    // Pipes go away with their last end or FIFO node
+    pipes: BTreeMap<u32, Weak<Pipe>>,
+    queues: BTreeMap<String, Arc<MessageQueue>>,
+    segments: BTreeMap<String, Arc<SharedMemory>>,
+    next_pipe: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct PipeInfo {
+    pub id: u32,
+    /// Set for FIFOs.
+    pub path: Option<String>,
+    pub buffered: usize,
+    pub readers: usize,
+    pub writers: usize,
+    pub creator: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct QueueInfo {
+    pub name: String,
+    pub owner: u32,
+    pub messages: usize,
+    pub bytes: usize,
+    pub last_sender: Option<u32>,
+    pub last_receiver: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct ShmInfo {
+    pub name: String,
+    pub size: usize,
+    pub creator: u32,
+    pub attached: Vec<u32>,
+    pub last_pid: Option<u32>,
+}
+
+impl Ipc {
+    fn new_pipe(&self, path: Option<String>) -> Arc<Pipe> {
+        let mut registry = self.inner.lock().unwrap();
+        registry.pipes.retain(|_, pipe| pipe.strong_count() > 0);
+        registry.next_pipe += 1;
+        let pipe = Arc::new(Pipe::new(registry.next_pipe, path));
+        registry.pipes.insert(pipe.id, Arc::downgrade(&pipe));
+        pipe
+    }
+
+    /// An anonymous pipe.
+    pub fn pipe(&self) -> (PipeReader, PipeWriter) {
+        pipe::pipe(self.new_pipe(None))
+    }
+
+    /// A FIFO node for `path`, to be added to the VFS (see
+    /// `FileSystem::mknod`).
+    pub fn fifo(&self, path: &str) -> Fifo {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        Fifo::new(name, self.new_pipe(Some(path.to_string())))
+    }
+
+    pub fn create_queue(&self, name: &str) -> Result<Arc<MessageQueue>, String> {
+        self.create_queue_with(name, DEFAULT_MAX_MESSAGES, DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    pub fn create_queue_with(
+        &self,
+        name: &str,
+        max_messages: usize,
+        max_message_size: usize,
+    ) -> Result<Arc<MessageQueue>, String> {
+        let mut registry = self.inner.lock().unwrap();
+        if registry.queues.contains_key(name) {
+            return Err("File exists".into());
+        }
+        let queue = Arc::new(MessageQueue::new(name, max_messages, max_message_size));
+        registry.queues.insert(name.to_string(), queue.clone());
+        Ok(queue)
+    }
+
+    pub fn queue(&self, name: &str) -> Result<Arc<MessageQueue>, String> {
+        let registry = self.inner.lock().unwrap();
+        registry.queues.get(name).cloned().ok_or_else(|| "No such file or directory".into())
+    }
+
+    /// Removes a queue; processes still using it get `Identifier removed`.
+    pub fn unlink_queue(&self, name: &str) -> Result<(), String> {
+        let queue = self.inner.lock().unwrap().queues.remove(name);
+        let queue = queue.ok_or("No such file or directory")?;
+        queue.remove();
+        Ok(())
+    }
+
+    pub fn create_shm(&self, name: &str, size: usize) -> Result<Arc<SharedMemory>, String> {
+        let mut registry = self.inner.lock().unwrap();
+        if registry.segments.contains_key(name) {
+            return Err("File exists".into());
+        }
+        let segment = Arc::new(SharedMemory::new(name, size));
+        registry.segments.insert(name.to_string(), segment.clone());
+        Ok(segment)
+    }
+
+    pub fn shm(&self, name: &str) -> Result<Arc<SharedMemory>, String> {
+        let registry = self.inner.lock().unwrap();
+        registry.segments.get(name).cloned().ok_or_else(|| "No such file or directory".into())
+    }
+
+    /// Removes a segment's name; it lives on until the last mapping goes.
+    pub fn unlink_shm(&self, name: &str) -> Result<(), String> {
+        let segment = self.inner.lock().unwrap().segments.remove(name);
+        segment.map(|_| ()).ok_or_else(|| "No such file or directory".into())
+    }
+
+    pub fn pipes(&self) -> Vec<PipeInfo> {
+        let registry = self.inner.lock().unwrap();
+        registry
+            .pipes
+            .values()
+            .filter_map(Weak::upgrade)
+            .map(|pipe| {
+                let state = pipe.lock();
+                PipeInfo {
+                    id: pipe.id,
+                    path: pipe.path.clone(),
+                    buffered: state.data.len(),
+                    readers: state.readers,
+                    writers: state.writers,
+                    creator: pipe.creator,
+                }
+            })
+            .collect()
+    }
+
+    /// The pipe ends `pid` holds open, as `/proc/<pid>/fd` shows them:
+    /// `pipe:[<id>]` for anonymous pipes, the path for FIFOs.
+    pub fn descriptors(&self, pid: u32) -> Vec<String> {
+        let registry = self.inner.lock().unwrap();
+        let mut fds = Vec::new();
+        for pipe in registry.pipes.values().filter_map(Weak::upgrade) {
+            let target = match &pipe.path {
+                Some(path) => path.clone(),
+                None => format!("pipe:[{}]", pipe.id),
+            };
+            let state = pipe.lock();
+            let held = state.holders.iter().filter(|(holder, _)| *holder == pid).count();
+            fds.extend(std::iter::repeat_n(target, held));
+        }
+        fds
+    }
+
+    pub fn queues(&self) -> Vec<QueueInfo> {
+        let registry = self.inner.lock().unwrap();
+        registry
+            .queues
+            .values()
+            .map(|queue| {
+                let state = queue.lock();
+                QueueInfo {
+                    name: queue.name.clone(),
+                    owner: queue.owner,
+                    messages: state.messages.len(),
+                    bytes: state.bytes,
+                    last_sender: state.last_sender,
+                    last_receiver: state.last_receiver,
+                }
+            })
+            .collect()
+    }
+
+    pub fn segments(&self) -> Vec<ShmInfo> {
+        let registry = self.inner.lock().unwrap();
+        registry
+            .segments
+            .values()
+            .map(|segment| {
+                let state = segment.lock();
+                ShmInfo {
+                    name: segment.name.clone(),
+                    size: segment.size,
+                    creator: segment.creator,
+                    attached: state.attached.keys().copied().collect(),
+                    last_pid: state.last_pid,
+                }
+            })
+            .collect()
+    }
+}
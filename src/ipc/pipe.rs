@@ -0,0 +1,359 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use super::{caller_pid, EAGAIN, WAIT_SLICE};
+use crate::scheduler;
+use crate::vfs::{Metadata, NodeType, VfsNode};
+
+/// Bytes a pipe buffers before writers block, as on Linux.
+pub const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// A byte channel with reference-counted ends. Reading an empty pipe blocks
+/// while a writer is left, then returns 0 (end of file); writing to a pipe
+/// nobody can read fails with `Broken pipe`.
+pub struct Pipe {
+    pub(crate) id: u32,
+    /// Where the FIFO was created; `None` for anonymous pipes.
+    pub(crate) path: Option<String>,
+    pub(crate) creator: u32,
+    state: Mutex<PipeState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+pub(crate) struct PipeState {
+    pub(crate) data: VecDeque<u8>,
+    pub(crate) readers: usize,
+    pub(crate) writers: usize,
+    /// PIDs holding an end open and whether it is the write end, in the
+    /// order they opened them.
+    pub(crate) holders: Vec<(u32, bool)>,
+    // Ends opened so far, so a blocking FIFO open notices a peer that
+    // came and went while it waited
+    reader_opens: u64,
+    writer_opens: u64,
+}
+
+impl Pipe {
+    pub(crate) fn new(id: u32, path: Option<String>) -> Self {
+        Pipe {
+            id,
+            path,
+            creator: caller_pid(),
+            state: Mutex::new(PipeState::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.state.lock().unwrap()
+    }
+
+    // Waits for `ready` without holding a CPU. The lock is released before
+    // returning, so a process killed meanwhile unwinds without poisoning it.
+    fn wait_until(&self, ready: impl Fn(&PipeState) -> bool) {
+        scheduler::blocking(|| {
+            let state = self.lock();
+            drop(self.changed.wait_timeout_while(state, WAIT_SLICE, |s| !ready(s)).unwrap());
+        });
+    }
+
+    fn wait_until_opened(&self, opened: impl Fn(&PipeState) -> bool) {
+        while !opened(&self.lock()) {
+            self.wait_until(&opened);
+        }
+    }
+
+    fn open_reader(self: &Arc<Self>) -> PipeReader {
+        let pid = caller_pid();
+        let mut state = self.lock();
+        state.readers += 1;
+        state.reader_opens += 1;
+        state.holders.push((pid, false));
+        self.changed.notify_all();
+        PipeReader { pipe: self.clone(), pid }
+    }
+
+    fn open_writer(self: &Arc<Self>) -> PipeWriter {
+        let pid = caller_pid();
+        let mut state = self.lock();
+        state.writers += 1;
+        state.writer_opens += 1;
+        state.holders.push((pid, true));
+        self.changed.notify_all();
+        PipeWriter { pipe: self.clone(), pid }
+    }
+
+    fn close(&self, pid: u32, write: bool) {
+        let mut state = self.lock();
+        if write {
+            state.writers -= 1;
+        } else {
+            state.readers -= 1;
+        }
+        if let Some(i) = state.holders.iter().position(|holder| *holder == (pid, write)) {
+            state.holders.remove(i);
+        }
+        self.changed.notify_all();
+    }
+}
+
+/// Read end of a pipe. Clones are further read ends.
+pub struct PipeReader {
+    pipe: Arc<Pipe>,
+    // Process that opened this end
+    pid: u32,
+}
+
+impl PipeReader {
+    /// Reads what is buffered, up to `buf.len()` bytes, waiting for data
+    /// while a writer is left. Returns 0 at end of file.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, String> {
+        loop {
+            match self.try_read(buf) {
+                Err(e) if e == EAGAIN => self.pipe.wait_until(|s| !s.data.is_empty() || s.writers == 0),
+                result => return result,
+            }
+        }
+    }
+
+    /// Like `read`, but fails with `Resource temporarily unavailable`
+    /// instead of waiting.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, String> {
+        let mut state = self.pipe.lock();
+        if state.data.is_empty() {
+            return if state.writers == 0 || buf.is_empty() {
+                Ok(0)
+            } else {
+                Err(EAGAIN.into())
+            };
+        }
+        let n = buf.len().min(state.data.len());
+        for (slot, byte) in buf.iter_mut().zip(state.data.drain(..n)) {
+            *slot = byte;
+        }
+        self.pipe.changed.notify_all();
+        Ok(n)
+    }
+
+    /// Reads until every writer is gone.
+    pub fn read_to_end(&self) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.read(&mut buf)? {
+                0 => return Ok(out),
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+}
+
+impl Clone for PipeReader {
+    fn clone(&self) -> Self {
+        self.pipe.open_reader()
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.pipe.close(self.pid, false);
+    }
+}
+
+/// Write end of a pipe. Clones are further write ends.
+pub struct PipeWriter {
+    pipe: Arc<Pipe>,
+    pid: u32,
+}
+
+impl PipeWriter {
+    /// Writes all of `data`, waiting for room when the pipe is full.
+    pub fn write(&self, mut data: &[u8]) -> Result<usize, String> {
+        let total = data.len();
+        while !data.is_empty() {
+            match self.try_write(data) {
+                Ok(n) => data = &data[n..],
+                Err(e) if e == EAGAIN => self.pipe.wait_until(|s| s.data.len() < PIPE_CAPACITY || s.readers == 0),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(total)
+    }
+
+    /// Writes as much of `data` as fits without waiting.
+    pub fn try_write(&self, data: &[u8]) -> Result<usize, String> {
+        let mut state = self.pipe.lock();
+        if state.readers == 0 {
+            return Err("Broken pipe".into());
+        }
+        let n = data.len().min(PIPE_CAPACITY - state.data.len());
+        if n == 0 && !data.is_empty() {
+            return Err(EAGAIN.into());
+        }
+        state.data.extend(&data[..n]);
+        self.pipe.changed.notify_all();
+        Ok(n)
+    }
+}
+
+impl Clone for PipeWriter {
+    fn clone(&self) -> Self {
+        self.pipe.open_writer()
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.pipe.close(self.pid, true);
+    }
+}
+
+pub(crate) fn pipe(pipe: Arc<Pipe>) -> (PipeReader, PipeWriter) {
+    (pipe.open_reader(), pipe.open_writer())
+}
+
+/// A named pipe: a VFS node whose opens share one pipe. FIFOs live only in
+/// memory and are not stored on disk.
+#[derive(Clone)]
+pub struct Fifo {
+    name: String,
+    pipe: Arc<Pipe>,
+    meta: Arc<Mutex<Metadata>>,
+}
+
+impl Fifo {
+    pub(crate) fn new(name: &str, pipe: Arc<Pipe>) -> Self {
+        Fifo {
+            name: name.to_string(),
+            pipe,
+            meta: Arc::new(Mutex::new(Metadata::new(NodeType::Fifo, 0o644))),
+        }
+    }
+
+    /// Opens the read end, waiting until someone opens the write end.
+    pub fn open_read(&self) -> PipeReader {
+        let reader = self.pipe.open_reader();
+        let opens = self.pipe.lock().writer_opens;
+        self.pipe.wait_until_opened(|s| s.writers > 0 || s.writer_opens != opens);
+        reader
+    }
+
+    /// Opens the write end, waiting until someone opens the read end.
+    pub fn open_write(&self) -> PipeWriter {
+        let writer = self.pipe.open_writer();
+        let opens = self.pipe.lock().reader_opens;
+        self.pipe.wait_until_opened(|s| s.readers > 0 || s.reader_opens != opens);
+        writer
+    }
+
+    /// Opens the read end without waiting for a writer.
+    pub fn open_read_nonblock(&self) -> PipeReader {
+        self.pipe.open_reader()
+    }
+
+    /// Opens the write end; fails with `No such device or address` if
+    /// nobody has the FIFO open for reading.
+    pub fn open_write_nonblock(&self) -> Result<PipeWriter, String> {
+        if self.pipe.lock().readers == 0 {
+            return Err("No such device or address".into());
+        }
+        Ok(self.pipe.open_writer())
+    }
+
+    /// Bytes written and not read yet.
+    pub fn buffered(&self) -> usize {
+        self.pipe.lock().data.len()
+    }
+}
+
+impl VfsNode for Fifo {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Fifo
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn with_name(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        let mut fifo = self.clone();
+        fifo.name = name.to_string();
+        fifo.meta.lock().unwrap().touch_change();
+        Some(Arc::new(fifo))
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut meta = *self.meta.lock().unwrap();
+        meta.size = 0;
+        meta
+    }
+
+    fn set_permissions(&self, mode: u32, uid: u32, gid: u32) -> Result<(), String> {
+        let mut meta = self.meta.lock().unwrap();
+        meta.mode = mode & 0o7777;
+        meta.uid = uid;
+        meta.gid = gid;
+        meta.touch_change();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::Ipc;
+    use crate::scheduler::{Process, Scheduler};
+    use std::time::Duration;
+
+    #[test]
+    fn test_pipe_eof_and_broken_pipe() {
+        let ipc = Ipc::default();
+        let (reader, writer) = ipc.pipe();
+        let mut buf = [0u8; 4];
+        assert_eq!(reader.try_read(&mut buf), Err(EAGAIN.into()));
+        writer.write(b"hello").unwrap();
+        assert_eq!(reader.read(&mut buf), Ok(4));
+        assert_eq!(&buf, b"hell");
+        drop(writer);
+        assert_eq!(reader.read_to_end(), Ok(b"o".to_vec()));
+
+        let (reader, writer) = ipc.pipe();
+        drop(reader);
+        assert_eq!(writer.write(b"x"), Err("Broken pipe".into()));
+    }
+
+    #[test]
+    fn test_producer_consumer_over_a_pipe() {
+        let scheduler = Scheduler::with_cpus(1);
+        let (reader, writer) = scheduler.ipc().pipe();
+        // More than the pipe holds, so the producer has to wait for room
+        let payload: Vec<u8> = (0..PIPE_CAPACITY * 2).map(|i| i as u8).collect();
+        let expected = payload.clone();
+        scheduler.add_process(Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                writer.write(&payload).unwrap();
+            }),
+            None,
+        ));
+        let consumer = scheduler
+            .spawn(Process::with_exit_code(
+                0,
+                1,
+                Duration::ZERO,
+                Box::new(move || (reader.read_to_end().unwrap() != expected) as i32),
+                None,
+            ))
+            .unwrap();
+        scheduler.run();
+        assert_eq!(consumer.info().exit_code, Some(0));
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use super::{caller_pid, EAGAIN, WAIT_SLICE};
+use crate::scheduler;
+
+/// Messages a queue holds before senders block.
+pub const DEFAULT_MAX_MESSAGES: usize = 10;
+/// Largest message a queue accepts, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 8192;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// Positive message type receivers can select on.
+    pub kind: i64,
+    pub data: Vec<u8>,
+    pub sender: u32,
+}
+
+/// A message queue with System V style typed messages.
+pub struct MessageQueue {
+    pub(crate) name: String,
+    pub(crate) owner: u32,
+    max_messages: usize,
+    max_message_size: usize,
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+pub(crate) struct QueueState {
+    pub(crate) messages: VecDeque<Message>,
+    pub(crate) bytes: usize,
+    pub(crate) last_sender: Option<u32>,
+    pub(crate) last_receiver: Option<u32>,
+    removed: bool,
+}
+
+impl MessageQueue {
+    pub(crate) fn new(name: &str, max_messages: usize, max_message_size: usize) -> Self {
+        MessageQueue {
+            name: name.to_string(),
+            owner: caller_pid(),
+            max_messages: max_messages.max(1),
+            max_message_size,
+            state: Mutex::new(QueueState::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // See `Pipe::wait_until`
+    fn wait_until(&self, ready: impl Fn(&QueueState) -> bool) {
+        scheduler::blocking(|| {
+            let state = self.lock();
+            drop(self.changed.wait_timeout_while(state, WAIT_SLICE, |s| !ready(s)).unwrap());
+        });
+    }
+
+    /// Queues a message, waiting while the queue is full.
+    pub fn send(&self, kind: i64, data: &[u8]) -> Result<(), String> {
+        loop {
+            match self.try_send(kind, data) {
+                Err(e) if e == EAGAIN => {
+                    self.wait_until(|s| s.removed || s.messages.len() < self.max_messages)
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like `send`, but fails with `Resource temporarily unavailable`
+    /// instead of waiting.
+    pub fn try_send(&self, kind: i64, data: &[u8]) -> Result<(), String> {
+        if kind <= 0 {
+            return Err("Invalid argument".into());
+        }
+        if data.len() > self.max_message_size {
+            return Err("Message too long".into());
+        }
+        let mut state = self.lock();
+        if state.removed {
+            return Err("Identifier removed".into());
+        }
+        if state.messages.len() >= self.max_messages {
+            return Err(EAGAIN.into());
+        }
+        let sender = caller_pid();
+        state.bytes += data.len();
+        state.last_sender = Some(sender);
+        state.messages.push_back(Message {
+            kind,
+            data: data.to_vec(),
+            sender,
+        });
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Takes the first message `kind` selects, waiting until there is one:
+    /// 0 takes any message, a positive `kind` only messages of that type,
+    /// and a negative one the lowest type up to `-kind`.
+    pub fn receive(&self, kind: i64) -> Result<Message, String> {
+        loop {
+            match self.try_receive(kind) {
+                Err(e) if e == NO_MESSAGE => self.wait_until(|s| s.removed || select(&s.messages, kind).is_some()),
+                result => return result,
+            }
+        }
+    }
+
+    /// Like `receive`, but fails with `No message of desired type` instead
+    /// of waiting.
+    pub fn try_receive(&self, kind: i64) -> Result<Message, String> {
+        let mut state = self.lock();
+        if state.removed {
+            return Err("Identifier removed".into());
+        }
+        let index = select(&state.messages, kind).ok_or(NO_MESSAGE)?;
+        let message = state.messages.remove(index).unwrap();
+        state.bytes -= message.data.len();
+        state.last_receiver = Some(caller_pid());
+        self.changed.notify_all();
+        Ok(message)
+    }
+
+    /// Wakes everyone waiting on the queue with `Identifier removed`.
+    pub(crate) fn remove(&self) {
+        self.lock().removed = true;
+        self.changed.notify_all();
+    }
+}
+
+const NO_MESSAGE: &str = "No message of desired type";
+
+fn select(messages: &VecDeque<Message>, kind: i64) -> Option<usize> {
+    match kind {
+        0 => (!messages.is_empty()).then_some(0),
+        k if k > 0 => messages.iter().position(|m| m.kind == k),
+        k => messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.kind <= -k)
+            .min_by_key(|(i, m)| (m.kind, *i))
+            .map(|(i, _)| i),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::Ipc;
+
+    #[test]
+    fn test_message_types() {
+        let ipc = Ipc::default();
+        let queue = ipc.create_queue("/jobs").unwrap();
+        assert_eq!(ipc.create_queue("/jobs").err(), Some("File exists".into()));
+        for (kind, text) in [(3, "c"), (1, "a"), (2, "b"), (1, "a2")] {
+            queue.send(kind, text.as_bytes()).unwrap();
+        }
+        assert_eq!(queue.try_receive(2).unwrap().data, b"b");
+        assert_eq!(queue.try_receive(-3).unwrap().data, b"a");
+        assert_eq!(queue.try_receive(0).unwrap().data, b"c");
+        assert_eq!(queue.try_receive(3), Err(NO_MESSAGE.into()));
+        assert_eq!(queue.send(0, b"x"), Err("Invalid argument".into()));
+
+        ipc.unlink_queue("/jobs").unwrap();
+        assert_eq!(queue.try_receive(0), Err("Identifier removed".into()));
+        assert!(ipc.queue("/jobs").is_err());
+    }
+
+    #[test]
+    fn test_full_queue_blocks_the_sender() {
+        let ipc = Ipc::default();
+        let queue = ipc.create_queue("/q").unwrap();
+        for i in 0..DEFAULT_MAX_MESSAGES {
+            queue.try_send(1, &[i as u8]).unwrap();
+        }
+        assert_eq!(queue.try_send(1, b"x"), Err(EAGAIN.into()));
+
+        let consumer = {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                queue.receive(0).unwrap()
+            })
+        };
+        queue.send(1, b"last").unwrap();
+        assert_eq!(consumer.join().unwrap().data, vec![0]);
+        assert_eq!(queue.len(), DEFAULT_MAX_MESSAGES);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::caller_pid;
+
+/// A shared memory segment. Processes map it with `attach`; the segment
+/// tracks which PIDs have it mapped and which created it.
+pub struct SharedMemory {
+    pub(crate) name: String,
+    pub(crate) size: usize,
+    pub(crate) creator: u32,
+    state: Mutex<SegmentState>,
+}
+
+pub(crate) struct SegmentState {
+    data: Vec<u8>,
+    // Mappings per PID
+    pub(crate) attached: BTreeMap<u32, usize>,
+    pub(crate) last_pid: Option<u32>,
+}
+
+impl SharedMemory {
+    pub(crate) fn new(name: &str, size: usize) -> Self {
+        SharedMemory {
+            name: name.to_string(),
+            size,
+            creator: caller_pid(),
+            state: Mutex::new(SegmentState {
+                data: vec![0; size],
+                attached: BTreeMap::new(),
+                last_pid: None,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, SegmentState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Maps the segment into the calling process until the mapping is
+    /// dropped.
+    pub fn attach(self: &Arc<Self>) -> ShmMapping {
+        let pid = caller_pid();
+        let mut state = self.lock();
+        *state.attached.entry(pid).or_insert(0) += 1;
+        state.last_pid = Some(pid);
+        ShmMapping {
+            segment: self.clone(),
+            pid,
+        }
+    }
+
+    /// PIDs that have the segment mapped.
+    pub fn attached_pids(&self) -> Vec<u32> {
+        self.lock().attached.keys().copied().collect()
+    }
+}
+
+/// A process's view of a shared memory segment.
+pub struct ShmMapping {
+    segment: Arc<SharedMemory>,
+    pid: u32,
+}
+
+impl ShmMapping {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn len(&self) -> usize {
+        self.segment.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segment.size == 0
+    }
+
+    fn check(&self, offset: usize, len: usize) -> Result<(), String> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.segment.size => Ok(()),
+            _ => Err("Bad address".into()),
+        }
+    }
+
+    pub fn read(&self, offset: usize, len: usize) -> Result<Vec<u8>, String> {
+        self.check(offset, len)?;
+        Ok(self.segment.lock().data[offset..offset + len].to_vec())
+    }
+
+    pub fn write(&self, offset: usize, data: &[u8]) -> Result<(), String> {
+        self.check(offset, data.len())?;
+        self.segment.lock().data[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Runs `f` on the whole segment with no other process touching it.
+    pub fn update<T>(&self, f: impl FnOnce(&mut [u8]) -> T) -> T {
+        f(&mut self.segment.lock().data)
+    }
+}
+
+impl Drop for ShmMapping {
+    fn drop(&mut self) {
+        let mut state = self.segment.lock();
+        if let Some(count) = state.attached.get_mut(&self.pid) {
+            *count -= 1;
+            if *count == 0 {
+                state.attached.remove(&self.pid);
+            }
+        }
+        state.last_pid = Some(self.pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scheduler::{context, Process, Scheduler};
+    use std::time::Duration;
+
+    #[test]
+    fn test_mappings_are_tracked_per_pid() {
+        let scheduler = Scheduler::with_cpus(2);
+        // A counter, then how many processes saw it complete
+        let segment = scheduler.ipc().create_shm("/counter", 16).unwrap();
+        assert_eq!(scheduler.ipc().create_shm("/counter", 16).err(), Some("File exists".into()));
+        let mut processes = Vec::new();
+        for _ in 0..2 {
+            let ipc = scheduler.ipc().clone();
+            let process = scheduler.spawn(Process::new(
+                0,
+                1,
+                Duration::ZERO,
+                Box::new(move || {
+                    let segment = ipc.shm("/counter").unwrap();
+                    let mapping = segment.attach();
+                    let count = || u64::from_le_bytes(mapping.read(0, 8).unwrap().try_into().unwrap());
+                    for _ in 0..100 {
+                        mapping.update(|bytes| {
+                            let n = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+                            bytes[..8].copy_from_slice(&(n + 1).to_le_bytes());
+                        });
+                        context::yield_now();
+                    }
+                    // Neither process detaches before both saw the other mapped
+                    while count() < 200 {
+                        context::sleep(Duration::from_millis(1));
+                    }
+                    assert_eq!(segment.attached_pids(), vec![1, 2]);
+                    mapping.update(|bytes| bytes[8] += 1);
+                    while mapping.read(8, 1).unwrap()[0] < 2 {
+                        context::sleep(Duration::from_millis(1));
+                    }
+                    assert_eq!(mapping.read(12, 8), Err("Bad address".into()));
+                }),
+                None,
+            ));
+            processes.push(process.unwrap());
+        }
+        scheduler.run();
+        assert!(processes.iter().all(|p| p.info().exit_code == Some(0)));
+        assert!(segment.attached_pids().is_empty());
+
+        let mapping = segment.attach();
+        assert_eq!(mapping.read(0, 8).unwrap(), 200u64.to_le_bytes());
+        scheduler.ipc().unlink_shm("/counter").unwrap();
+        // Existing mappings outlive the name
+        assert!(scheduler.ipc().shm("/counter").is_err());
+        mapping.write(0, &[1]).unwrap();
+    }
+}
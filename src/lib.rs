@@ -1,7 +1,12 @@
+pub mod cgroup;
 pub mod dashboard;
 pub mod html_renderer;
+pub mod ipc;
+pub mod mm;
+pub mod net;
 pub mod scheduler;
 pub mod shell;
+pub mod tty;
 pub mod utils;
 pub mod vfs;
 pub mod vps;
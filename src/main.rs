@@ -1,50 +1,68 @@
-mod dashboard;
-mod html_renderer;
-mod scheduler;
-mod shell;
-mod utils;
-mod vfs;
-mod vps;
-
-use scheduler::{Process, Scheduler};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use vfs::{Disk, FileDisk, FileSystem};
-use vps::manager::VpsManager;
+use vbox::scheduler::{Process, Scheduler};
+use vbox::vfs::{Disk, FileDisk, FileSystem, ThrottledDisk, UserDb};
+use vbox::net::Network;
+use vbox::vps::manager::{VpsManager, REGISTRY_FILE};
+use vbox::{dashboard, shell};
 
 fn main() {
-    // Initialize VFS
-    let mut fs = FileSystem::new();
-
     // Create Scheduler
     let scheduler = Arc::new(Scheduler::new());
 
-    // Create VPS Manager
-    let vps_manager = Arc::new(Mutex::new(VpsManager::new()));
+    // Initialize VFS on top of the virtual disk; its I/O is charged to the
+    // resource groups of the processes doing it
+    let disk: Arc<dyn Disk + Send + Sync> = Arc::new(ThrottledDisk::new(
+        Arc::new(FileDisk::new("vbox_disk.bin")),
+        scheduler.cgroups().clone(),
+    ));
+    let mut fs = FileSystem::new();
+    if let Err(e) = fs.attach_disk(disk.clone()) {
+        eprintln!("Could not load filesystem from disk: {}", e);
+    }
+    println!("Disk initialized: {} blocks in use", disk.get_allocated_blocks());
+
+    // Create VPS Manager, its VPSes on the subnet in VBOX_SUBNET if set
+    let network = match std::env::var("VBOX_SUBNET") {
+        Ok(subnet) => match subnet.parse().and_then(Network::new) {
+            Ok(network) => network,
+            Err(e) => {
+                eprintln!("Invalid VBOX_SUBNET '{}': {}", subnet, e);
+                Network::default()
+            }
+        },
+        Err(_) => Network::default(),
+    };
+    let bridge = network.bridge();
+    let vps_manager = Arc::new(Mutex::new(VpsManager::open_on(Path::new(REGISTRY_FILE), network)));
 
-    // Set /proc
-    let proc_dir = Arc::new(crate::vfs::ProcDirectory::new(scheduler.clone()));
-    fs.set_proc(proc_dir);
+    // Mount /proc
+    let proc_dir = Arc::new(
+        vbox::vfs::ProcDirectory::new(scheduler.clone())
+            .with_mounts(&fs.mounts)
+            .with_disk(disk.clone())
+            .with_vps(vps_manager.clone()),
+    );
+    fs.mount("/proc", "proc", proc_dir).unwrap();
 
-    // Set /dev
-    let dev_dir = Arc::new(crate::vfs::DevDirectory::new());
-    fs.set_dev(dev_dir);
+    // Mount /dev
+    let dev_dir = Arc::new(vbox::vfs::DevDirectory::new());
+    fs.mount("/dev", "devfs", dev_dir).unwrap();
 
-    // Set /network
-    let network_dir = Arc::new(crate::vfs::NetworkDirectory::new());
-    fs.set_network(network_dir);
+    // Mount /sys (resource groups under /sys/fs/cgroup)
+    let sys_dir = Arc::new(vbox::vfs::SysDirectory::new(scheduler.clone()));
+    fs.mount("/sys", "sysfs", sys_dir).unwrap();
 
-    // Create /network directory
-    fs.create_dir("/network").unwrap();
+    // Mount /network, the host's routing table
+    let network_dir = Arc::new(vbox::vfs::NetworkDirectory::new(bridge.routes().clone()));
+    fs.mount("/network", "netfs", network_dir).unwrap();
 
-    // Test Virtual Disk
-    let disk = Arc::new(FileDisk::new("vbox_disk.bin"));
-    let block_id = disk.allocate_block();
-    disk.write_block(block_id, b"Hello Virtual Disk!");
-    if let Some(data) = disk.read_block(block_id) {
-        println!("Disk initialized: {:?}", String::from_utf8_lossy(&data));
-    }
+    // Create /etc/passwd, /etc/group and /etc/shadow
+    UserDb::bootstrap(&fs).unwrap();
+
+    fs.sync().unwrap();
 
     // Create Shell Process
     let fs_clone = fs.clone();
@@ -56,11 +74,13 @@ fn main() {
         1,
         Duration::from_secs(0),
         Box::new(move || {
-            let mut shell = shell::Shell::new(fs_clone, disk_clone, scheduler_clone, vps_manager_clone);
+            let mut shell =
+                shell::Shell::new(fs_clone, disk_clone, scheduler_clone, vps_manager_clone).with_interface(bridge);
             shell.run();
         }),
         None,
-    );
+    )
+    .with_command(&["sh"]);
     scheduler.add_process(shell_process);
 
     // Start HTTP Dashboard
@@ -74,6 +94,13 @@ fn main() {
         dashboard.start(8080);
     });
 
+    // Ctrl-C interrupts the shell's foreground job instead of vbox itself
+    if let Err(e) = ctrlc::set_handler(|| {
+        shell::interrupt_foreground();
+    }) {
+        eprintln!("Could not install the Ctrl-C handler: {}", e);
+    }
+
     // Run Scheduler
     scheduler.run();
 }
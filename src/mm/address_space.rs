@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use super::PAGE_SIZE;
+
+/// Where a process's heap starts; address 0 stays unmapped so null
+/// pointers fault.
+pub const HEAP_START: u64 = 0x1000_0000;
+
+/// Where a virtual page currently lives.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PageTableEntry {
+    /// Physical frame, while the page is resident.
+    pub frame: Option<usize>,
+    /// Swap blocks holding the page while it is swapped out.
+    pub swap: Option<Vec<u64>>,
+    /// Written since it was last loaded, so eviction has to save it.
+    pub dirty: bool,
+}
+
+impl PageTableEntry {
+    pub fn is_present(&self) -> bool {
+        self.frame.is_some()
+    }
+}
+
+/// Virtual page number to entry. Pages that were never touched have no
+/// entry: they are zero-filled on their first fault.
+#[derive(Clone, Debug, Default)]
+pub struct PageTable {
+    entries: BTreeMap<u64, PageTableEntry>,
+}
+
+impl PageTable {
+    pub fn get(&self, vpn: u64) -> Option<&PageTableEntry> {
+        self.entries.get(&vpn)
+    }
+
+    pub fn entry(&mut self, vpn: u64) -> &mut PageTableEntry {
+        self.entries.entry(vpn).or_default()
+    }
+
+    pub fn remove(&mut self, vpn: u64) -> Option<PageTableEntry> {
+        self.entries.remove(&vpn)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &PageTableEntry)> {
+        self.entries.iter().map(|(vpn, pte)| (*vpn, pte))
+    }
+
+    pub fn resident(&self) -> usize {
+        self.entries.values().filter(|pte| pte.is_present()).count()
+    }
+
+    pub fn swapped(&self) -> usize {
+        self.entries.values().filter(|pte| pte.swap.is_some()).count()
+    }
+}
+
+/// A process's virtual memory: the regions it allocated and the page table
+/// mapping them.
+#[derive(Clone, Debug)]
+pub struct AddressSpace {
+    // Region start -> length in bytes, page aligned
+    regions: BTreeMap<u64, usize>,
+    pub(crate) pages: PageTable,
+    next: u64,
+}
+
+impl Default for AddressSpace {
+    fn default() -> Self {
+        AddressSpace {
+            regions: BTreeMap::new(),
+            pages: PageTable::default(),
+            next: HEAP_START,
+        }
+    }
+}
+
+impl AddressSpace {
+    /// Reserves `len` bytes, rounded up to whole pages. Nothing is backed
+    /// until it is touched.
+    pub(crate) fn reserve(&mut self, len: usize) -> u64 {
+        let len = pages_for(len) * PAGE_SIZE;
+        let start = self.next;
+        self.regions.insert(start, len);
+        // Leave an unmapped guard page between regions
+        self.next += (len + PAGE_SIZE) as u64;
+        start
+    }
+
+    /// Drops the region starting at `addr` and returns its pages.
+    pub(crate) fn unreserve(&mut self, addr: u64) -> Result<Vec<(u64, PageTableEntry)>, String> {
+        let len = self.regions.remove(&addr).ok_or("Invalid argument")?;
+        let first = addr / PAGE_SIZE as u64;
+        let pages = (first..first + (len / PAGE_SIZE) as u64)
+            .filter_map(|vpn| self.pages.remove(vpn).map(|pte| (vpn, pte)))
+            .collect();
+        Ok(pages)
+    }
+
+    /// Whether `[addr, addr + len)` lies inside one allocated region.
+    pub(crate) fn contains(&self, addr: u64, len: usize) -> bool {
+        match self.regions.range(..=addr).next_back() {
+            Some((start, size)) => addr
+                .checked_add(len as u64)
+                .is_some_and(|end| end <= start + *size as u64),
+            None => false,
+        }
+    }
+
+    /// Bytes allocated, whether backed or not.
+    pub fn virtual_size(&self) -> usize {
+        self.regions.values().sum()
+    }
+
+    pub fn regions(&self) -> Vec<(u64, usize)> {
+        self.regions.iter().map(|(start, len)| (*start, *len)).collect()
+    }
+
+    pub fn page_table(&self) -> &PageTable {
+        &self.pages
+    }
+}
+
+pub(crate) fn pages_for(len: usize) -> usize {
+    len.div_ceil(PAGE_SIZE).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions_are_page_aligned_and_bounded() {
+        let mut space = AddressSpace::default();
+        let a = space.reserve(10);
+        let b = space.reserve(PAGE_SIZE + 1);
+        assert_eq!(a, HEAP_START);
+        assert_eq!(b % PAGE_SIZE as u64, 0);
+        assert_eq!(space.virtual_size(), 3 * PAGE_SIZE);
+
+        assert!(space.contains(a, PAGE_SIZE));
+        assert!(!space.contains(a, PAGE_SIZE + 1));
+        assert!(!space.contains(0, 1));
+        assert!(space.contains(b + PAGE_SIZE as u64, PAGE_SIZE));
+
+        space.pages.entry(a / PAGE_SIZE as u64).dirty = true;
+        assert_eq!(space.unreserve(a).unwrap().len(), 1);
+        assert_eq!(space.unreserve(a).err(), Some("Invalid argument".into()));
+        assert!(!space.contains(a, 1));
+    }
+}
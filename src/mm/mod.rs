@@ -0,0 +1,505 @@
+//! Simulated virtual memory.
+//!
+//! Every process gets an `AddressSpace`: jobs reserve memory with
+//! `MemoryManager::alloc` and touch it through `read` and `write`, which
+//! fault pages into a fixed pool of physical frames on demand. When the
+//! pool is full the replacement policy (LRU or clock) picks a page to swap
+//! out to a `Disk`; when swap is full too, or there is none, the OOM killer
+//! ends the process using the most memory.
+//!
+//! Like `Ipc`, a `MemoryManager` belongs to a `Scheduler` and charges
+//! memory to the calling process. A resource group at its `memory.max`
+//! reclaims from its own processes before anyone else's.
+
+mod address_space;
+mod physical;
+mod swap;
+
+pub use address_space::{AddressSpace, PageTable, PageTableEntry, HEAP_START};
+pub use physical::Replacement;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use address_space::pages_for;
+use physical::PhysicalMemory;
+use swap::SwapSpace;
+
+use crate::cgroup::Cgroups;
+use crate::scheduler;
+use crate::vfs::Disk;
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// Frames of a scheduler nobody configured: 16 MiB.
+pub const DEFAULT_FRAMES: usize = 4096;
+
+const ENOMEM: &str = "Cannot allocate memory";
+
+/// PID the memory is charged to: the calling process, or 0 outside the
+/// scheduler.
+fn caller_pid() -> u32 {
+    scheduler::current_pid().unwrap_or(0)
+}
+
+pub(crate) type OomKiller = Arc<dyn Fn(u32) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct MemoryManager {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    physical: PhysicalMemory,
+    swap: Option<SwapSpace>,
+    spaces: BTreeMap<u32, AddressSpace>,
+    oom_killer: Option<OomKiller>,
+    cgroups: Option<Cgroups>,
+    page_faults: u64,
+    swap_ins: u64,
+    swap_outs: u64,
+    oom_kills: u64,
+}
+
+/// Memory of one process, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct MemoryUsage {
+    /// Allocated, whether backed or not.
+    pub virtual_bytes: usize,
+    /// Held in physical frames.
+    pub resident_bytes: usize,
+    pub swapped_bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct MemoryStats {
+    pub replacement: String,
+    pub frames: usize,
+    pub free_frames: usize,
+    /// Pages the swap holds at most; 0 without swap.
+    pub swap_pages: usize,
+    pub swap_used: usize,
+    pub page_faults: u64,
+    pub swap_ins: u64,
+    pub swap_outs: u64,
+    pub oom_kills: u64,
+}
+
+impl Default for MemoryManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAMES, Replacement::default())
+    }
+}
+
+impl MemoryManager {
+    /// A pool of `frames` physical pages and no swap.
+    pub fn new(frames: usize, replacement: Replacement) -> Self {
+        MemoryManager {
+            inner: Arc::new(Mutex::new(Inner {
+                physical: PhysicalMemory::new(frames.max(1), replacement),
+                swap: None,
+                spaces: BTreeMap::new(),
+                oom_killer: None,
+                cgroups: None,
+                page_faults: 0,
+                swap_ins: 0,
+                swap_outs: 0,
+                oom_kills: 0,
+            })),
+        }
+    }
+
+    /// Swaps evicted pages to `disk`, up to `max_pages` of them.
+    pub fn with_swap(self, disk: Arc<dyn Disk + Send + Sync>, max_pages: usize) -> Self {
+        self.lock().swap = Some(SwapSpace::new(disk, max_pages));
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+
+    /// Called with the PID of every process the OOM killer picks.
+    pub(crate) fn set_oom_killer(&self, killer: OomKiller) {
+        self.lock().oom_killer = Some(killer);
+    }
+
+    /// Resource groups whose `memory.max` applies.
+    pub(crate) fn set_cgroups(&self, cgroups: Cgroups) {
+        self.lock().cgroups = Some(cgroups);
+    }
+
+    /// Reserves `len` bytes for the calling process and returns their
+    /// address. Pages are only backed once touched.
+    pub fn alloc(&self, len: usize) -> Result<u64, String> {
+        if len == 0 {
+            return Err("Invalid argument".into());
+        }
+        let mut inner = self.lock();
+        // More than could ever be backed at once
+        let swap_pages = inner.swap.as_ref().map_or(0, |swap| swap.max_pages);
+        if pages_for(len) > inner.physical.frames.len() + swap_pages {
+            return Err(ENOMEM.into());
+        }
+        Ok(inner.spaces.entry(caller_pid()).or_default().reserve(len))
+    }
+
+    /// Frees the allocation starting at `addr`.
+    pub fn free(&self, addr: u64) -> Result<(), String> {
+        let mut inner = self.lock();
+        let space = inner.spaces.get_mut(&caller_pid()).ok_or("Invalid argument")?;
+        let pages = space.unreserve(addr)?;
+        for (_, pte) in pages {
+            inner.drop_page(pte);
+        }
+        Ok(())
+    }
+
+    pub fn read(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        let mut out = Vec::with_capacity(len);
+        self.access(addr, len, |page, offset, range| {
+            out.extend_from_slice(&page[offset..offset + range.len()]);
+            false
+        })?;
+        Ok(out)
+    }
+
+    pub fn write(&self, addr: u64, data: &[u8]) -> Result<(), String> {
+        self.access(addr, data.len(), |page, offset, range| {
+            page[offset..offset + range.len()].copy_from_slice(&data[range]);
+            true
+        })
+    }
+
+    // Runs `f` on every page of `[addr, addr + len)` with the page's bytes,
+    // the offset into it and the matching range of the buffer; `f` returns
+    // whether it wrote. Faulting may OOM-kill processes, which happens
+    // once the lock is released.
+    fn access(
+        &self,
+        addr: u64,
+        len: usize,
+        mut f: impl FnMut(&mut [u8], usize, std::ops::Range<usize>) -> bool,
+    ) -> Result<(), String> {
+        let pid = caller_pid();
+        let mut killed = Vec::new();
+        let result = {
+            let mut inner = self.lock();
+            let result = inner.access(pid, addr, len, &mut f, &mut killed);
+            (inner.oom_killer.clone(), result)
+        };
+        let (killer, result) = result;
+        if let Some(killer) = killer {
+            for pid in killed {
+                killer(pid);
+            }
+        }
+        result
+    }
+
+    pub fn usage(&self, pid: u32) -> MemoryUsage {
+        let inner = self.lock();
+        inner.spaces.get(&pid).map(usage).unwrap_or_default()
+    }
+
+    /// A copy of the address space of `pid`.
+    pub fn address_space(&self, pid: u32) -> Option<AddressSpace> {
+        self.lock().spaces.get(&pid).cloned()
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        let inner = self.lock();
+        MemoryStats {
+            replacement: inner.physical.replacement.to_string(),
+            frames: inner.physical.frames.len(),
+            free_frames: inner.physical.free_frames(),
+            swap_pages: inner.swap.as_ref().map_or(0, |swap| swap.max_pages),
+            swap_used: inner.swap.as_ref().map_or(0, |swap| swap.used_pages),
+            page_faults: inner.page_faults,
+            swap_ins: inner.swap_ins,
+            swap_outs: inner.swap_outs,
+            oom_kills: inner.oom_kills,
+        }
+    }
+
+    /// Frees everything `pid` holds; called when it exits.
+    pub(crate) fn release(&self, pid: u32) {
+        self.lock().release(pid);
+    }
+}
+
+fn usage(space: &AddressSpace) -> MemoryUsage {
+    MemoryUsage {
+        virtual_bytes: space.virtual_size(),
+        resident_bytes: space.pages.resident() * PAGE_SIZE,
+        swapped_bytes: space.pages.swapped() * PAGE_SIZE,
+    }
+}
+
+impl Inner {
+    fn access(
+        &mut self,
+        pid: u32,
+        addr: u64,
+        len: usize,
+        f: &mut impl FnMut(&mut [u8], usize, std::ops::Range<usize>) -> bool,
+        killed: &mut Vec<u32>,
+    ) -> Result<(), String> {
+        if !self.spaces.get(&pid).is_some_and(|space| space.contains(addr, len)) {
+            return Err("Bad address".into());
+        }
+        let mut done = 0;
+        while done < len {
+            let at = addr + done as u64;
+            let vpn = at / PAGE_SIZE as u64;
+            let offset = (at % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - offset).min(len - done);
+            let frame = self.fault_in(pid, vpn, killed)?;
+            let wrote = f(&mut self.physical.frames[frame].data, offset, done..done + n);
+            if wrote {
+                self.spaces.get_mut(&pid).unwrap().pages.entry(vpn).dirty = true;
+            }
+            done += n;
+        }
+        Ok(())
+    }
+
+    // The frame holding page `vpn` of `pid`, loading it if needed
+    fn fault_in(&mut self, pid: u32, vpn: u64, killed: &mut Vec<u32>) -> Result<usize, String> {
+        if let Some(frame) = self.spaces[&pid].pages.get(vpn).and_then(|pte| pte.frame) {
+            self.physical.touch(frame);
+            return Ok(frame);
+        }
+        self.page_faults += 1;
+        let scopes = self.cgroups.as_ref().map(|c| c.memory_scopes(pid)).unwrap_or_default();
+        let frame = loop {
+            // A group at its memory.max has to make room among its own
+            // processes, even with frames free
+            let full = scopes
+                .iter()
+                .find(|(max, pids)| (self.resident_pages(pids) + 1) * PAGE_SIZE > *max)
+                .map(|(_, pids)| pids);
+            if full.is_none() {
+                if let Some(frame) = self.physical.allocate((pid, vpn)) {
+                    break frame;
+                }
+            }
+            let in_scope = |pid: u32| full.is_none_or(|pids| pids.contains(&pid));
+            if self.evict(&in_scope) {
+                continue;
+            }
+            let victim = self.oom_victim(&in_scope).ok_or(ENOMEM)?;
+            self.release(victim);
+            self.oom_kills += 1;
+            if let Some(cgroups) = &self.cgroups {
+                cgroups.record_oom_kill(victim);
+            }
+            killed.push(victim);
+            if victim == pid {
+                return Err("Out of memory".into());
+            }
+        };
+
+        let pte = self.spaces.get_mut(&pid).unwrap().pages.entry(vpn);
+        pte.frame = Some(frame);
+        if let Some(blocks) = pte.swap.take() {
+            // The swap copy is gone, so the page must be saved again if
+            // it is evicted
+            pte.dirty = true;
+            let swap = self.swap.as_mut().expect("swapped page without swap");
+            match swap.load(&blocks) {
+                Ok(data) => self.physical.frames[frame].data = data,
+                Err(e) => {
+                    self.physical.release(frame);
+                    self.spaces.get_mut(&pid).unwrap().pages.entry(vpn).frame = None;
+                    return Err(e);
+                }
+            }
+            self.swap_ins += 1;
+        }
+        Ok(frame)
+    }
+
+    fn resident_pages(&self, pids: &BTreeSet<u32>) -> usize {
+        pids.iter()
+            .filter_map(|pid| self.spaces.get(pid))
+            .map(|space| space.pages.resident())
+            .sum()
+    }
+
+    // Frees one frame of a process `in_scope` accepts. Pages never written
+    // are all zeros and are simply dropped; the rest go to swap.
+    fn evict(&mut self, in_scope: &dyn Fn(u32) -> bool) -> bool {
+        let can_swap = self.swap.as_ref().is_some_and(|swap| !swap.is_full());
+        let spaces = &self.spaces;
+        let victim = self.physical.victim(|(pid, vpn)| {
+            let dirty = spaces[&pid].pages.get(vpn).is_some_and(|pte| pte.dirty);
+            !in_scope(pid) || (dirty && !can_swap)
+        });
+        let frame = match victim {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let (pid, vpn) = self.physical.frames[frame].owner.unwrap();
+        let dirty = self.spaces[&pid].pages.get(vpn).is_some_and(|pte| pte.dirty);
+        let mut blocks = None;
+        if dirty {
+            let swap = self.swap.as_mut().unwrap();
+            match swap.store(&self.physical.frames[frame].data) {
+                Ok(stored) => blocks = Some(stored),
+                Err(_) => return false,
+            }
+            self.swap_outs += 1;
+        }
+        let pte = self.spaces.get_mut(&pid).unwrap().pages.entry(vpn);
+        pte.frame = None;
+        pte.dirty = false;
+        pte.swap = blocks;
+        if pte.swap.is_none() {
+            self.spaces.get_mut(&pid).unwrap().pages.remove(vpn);
+        }
+        self.physical.release(frame);
+        true
+    }
+
+    // The process `in_scope` accepts that uses the most memory
+    fn oom_victim(&self, in_scope: &dyn Fn(u32) -> bool) -> Option<u32> {
+        self.spaces
+            .iter()
+            .filter(|(pid, _)| in_scope(**pid))
+            .map(|(pid, space)| (space.pages.resident() + space.pages.swapped(), *pid))
+            .filter(|(pages, _)| *pages > 0)
+            .max()
+            .map(|(_, pid)| pid)
+    }
+
+    fn drop_page(&mut self, pte: PageTableEntry) {
+        if let Some(frame) = pte.frame {
+            self.physical.release(frame);
+        }
+        if let (Some(blocks), Some(swap)) = (pte.swap, self.swap.as_mut()) {
+            swap.discard(&blocks);
+        }
+    }
+
+    fn release(&mut self, pid: u32) {
+        if let Some(space) = self.spaces.remove(&pid) {
+            for (_, pte) in space.pages.iter() {
+                self.drop_page(pte.clone());
+            }
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Give the swap blocks back to the disk
+        let pids: Vec<u32> = self.spaces.keys().copied().collect();
+        for pid in pids {
+            self.release(pid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::RamDisk;
+
+    #[test]
+    fn test_pages_are_backed_on_first_touch() {
+        let memory = MemoryManager::new(4, Replacement::Lru);
+        let addr = memory.alloc(3 * PAGE_SIZE).unwrap();
+        assert_eq!(memory.usage(0).resident_bytes, 0);
+
+        // Straddles the first two pages
+        memory.write(addr + PAGE_SIZE as u64 - 2, b"abcd").unwrap();
+        assert_eq!(memory.read(addr + PAGE_SIZE as u64 - 2, 4).unwrap(), b"abcd");
+        assert_eq!(memory.read(addr, 2).unwrap(), vec![0, 0]);
+        let usage = memory.usage(0);
+        assert_eq!(usage.virtual_bytes, 3 * PAGE_SIZE);
+        assert_eq!(usage.resident_bytes, 2 * PAGE_SIZE);
+        assert_eq!(memory.stats().page_faults, 2);
+
+        assert_eq!(memory.read(addr, 3 * PAGE_SIZE + 1).err(), Some("Bad address".into()));
+        assert_eq!(memory.write(0, b"x").err(), Some("Bad address".into()));
+        assert_eq!(memory.alloc(5 * PAGE_SIZE).err(), Some(ENOMEM.into()));
+
+        memory.free(addr).unwrap();
+        assert_eq!(memory.free(addr).err(), Some("Invalid argument".into()));
+        assert_eq!(memory.stats().free_frames, 4);
+    }
+
+    #[test]
+    fn test_swapping_keeps_the_data() {
+        let disk = Arc::new(RamDisk::with_geometry(512, 256));
+        let memory = MemoryManager::new(2, Replacement::Clock).with_swap(disk.clone(), 8);
+        let addr = memory.alloc(6 * PAGE_SIZE).unwrap();
+        for page in 0..6u8 {
+            memory.write(addr + page as u64 * PAGE_SIZE as u64, &[page + 1; 16]).unwrap();
+        }
+        let stats = memory.stats();
+        assert_eq!(stats.swap_used, 4);
+        assert_eq!(stats.swap_outs, 4);
+        // A 4 KiB page takes eight 512 byte blocks
+        assert_eq!(disk.get_allocated_blocks(), 32);
+
+        for page in 0..6u8 {
+            let data = memory.read(addr + page as u64 * PAGE_SIZE as u64, 16).unwrap();
+            assert_eq!(data, vec![page + 1; 16]);
+        }
+        assert!(memory.stats().swap_ins >= 4);
+
+        drop(memory);
+        assert_eq!(disk.get_allocated_blocks(), 0);
+    }
+
+    #[test]
+    fn test_oom_killer_picks_the_biggest_process() {
+        use crate::scheduler::{Process, ProcessState, Scheduler};
+        use std::time::Duration;
+
+        let scheduler = Scheduler::with_cpus(1).with_memory(MemoryManager::new(8, Replacement::Lru));
+        let hog = {
+            let memory = scheduler.memory().clone();
+            scheduler.spawn(Process::new(
+                0,
+                2,
+                Duration::ZERO,
+                Box::new(move || {
+                    let addr = memory.alloc(6 * PAGE_SIZE).unwrap();
+                    for page in 0..6 {
+                        memory.write(addr + page * PAGE_SIZE as u64, b"hog").unwrap();
+                    }
+                    loop {
+                        scheduler::sleep(Duration::from_millis(5));
+                    }
+                }),
+                None,
+            ))
+            .unwrap()
+        };
+        let memory = scheduler.memory().clone();
+        let small = scheduler.spawn(Process::with_exit_code(
+            0,
+            1,
+            Duration::from_millis(20),
+            Box::new(move || {
+                let addr = memory.alloc(4 * PAGE_SIZE).unwrap();
+                for page in 0..4 {
+                    memory.write(addr + page * PAGE_SIZE as u64, b"mine").unwrap();
+                }
+                (memory.read(addr, 4).unwrap() != b"mine") as i32
+            }),
+            None,
+        ))
+        .unwrap();
+        scheduler.run();
+
+        assert_eq!(hog.status(), ProcessState::Terminated);
+        assert_eq!(hog.info().exit_code, Some(137));
+        assert_eq!(small.info().exit_code, Some(0));
+        assert_eq!(scheduler.memory().stats().oom_kills, 1);
+        assert_eq!(scheduler.memory().stats().free_frames, 8);
+    }
+}
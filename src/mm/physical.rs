@@ -0,0 +1,170 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::PAGE_SIZE;
+
+/// How a full pool picks the page to evict.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Replacement {
+    /// The least recently used page.
+    Lru,
+    /// Second chance: a hand sweeps the frames, sparing (and clearing) the
+    /// ones referenced since it last passed.
+    #[default]
+    Clock,
+}
+
+impl Replacement {
+    pub fn name(self) -> &'static str {
+        match self {
+            Replacement::Lru => "lru",
+            Replacement::Clock => "clock",
+        }
+    }
+}
+
+impl fmt::Display for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Replacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru" => Ok(Replacement::Lru),
+            "clock" => Ok(Replacement::Clock),
+            _ => Err(format!("unknown replacement policy: {}", s)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Frame {
+    // Allocated on first use and dropped when the frame is freed, so a
+    // large pool costs nothing until it fills up
+    pub(crate) data: Vec<u8>,
+    /// (pid, virtual page number) of the page in the frame.
+    pub(crate) owner: Option<(u32, u64)>,
+    referenced: bool,
+    last_used: u64,
+}
+
+/// The simulated RAM: a fixed number of page frames.
+pub(crate) struct PhysicalMemory {
+    pub(crate) frames: Vec<Frame>,
+    free: Vec<usize>,
+    pub(crate) replacement: Replacement,
+    hand: usize,
+    clock: u64,
+}
+
+impl PhysicalMemory {
+    pub(crate) fn new(frames: usize, replacement: Replacement) -> Self {
+        PhysicalMemory {
+            frames: (0..frames).map(|_| Frame::default()).collect(),
+            // Hand out low frames first
+            free: (0..frames).rev().collect(),
+            replacement,
+            hand: 0,
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn free_frames(&self) -> usize {
+        self.free.len()
+    }
+
+    /// A zeroed frame for `owner`, if any is free.
+    pub(crate) fn allocate(&mut self, owner: (u32, u64)) -> Option<usize> {
+        let frame = self.free.pop()?;
+        let slot = &mut self.frames[frame];
+        slot.data = vec![0; PAGE_SIZE];
+        slot.owner = Some(owner);
+        self.touch(frame);
+        Some(frame)
+    }
+
+    pub(crate) fn release(&mut self, frame: usize) {
+        let slot = &mut self.frames[frame];
+        if slot.owner.take().is_some() {
+            slot.data = Vec::new();
+            slot.referenced = false;
+            self.free.push(frame);
+        }
+    }
+
+    /// Records an access for the replacement policy.
+    pub(crate) fn touch(&mut self, frame: usize) {
+        self.clock += 1;
+        let slot = &mut self.frames[frame];
+        slot.referenced = true;
+        slot.last_used = self.clock;
+    }
+
+    /// The frame the policy would evict next, skipping those `pinned`
+    /// rejects. Clock clears reference bits as it goes.
+    pub(crate) fn victim(&mut self, pinned: impl Fn((u32, u64)) -> bool) -> Option<usize> {
+        match self.replacement {
+            Replacement::Lru => self
+                .frames
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| f.owner.is_some_and(|owner| !pinned(owner)))
+                .min_by_key(|(_, f)| f.last_used)
+                .map(|(i, _)| i),
+            Replacement::Clock => {
+                // Two sweeps: the first may only clear reference bits
+                for _ in 0..2 * self.frames.len() {
+                    let i = self.hand;
+                    self.hand = (self.hand + 1) % self.frames.len();
+                    let frame = &mut self.frames[i];
+                    match frame.owner {
+                        Some(owner) if !pinned(owner) => {
+                            if !frame.referenced {
+                                return Some(i);
+                            }
+                            frame.referenced = false;
+                        }
+                        _ => {}
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_and_clock_victims() {
+        let mut lru = PhysicalMemory::new(3, Replacement::Lru);
+        for vpn in 0..3 {
+            lru.allocate((1, vpn)).unwrap();
+        }
+        assert_eq!(lru.allocate((1, 3)), None);
+        lru.touch(0);
+        assert_eq!(lru.victim(|_| false), Some(1));
+        assert_eq!(lru.victim(|(_, vpn)| vpn == 1), Some(2));
+
+        let mut clock = PhysicalMemory::new(3, Replacement::Clock);
+        for vpn in 0..3 {
+            clock.allocate((1, vpn)).unwrap();
+        }
+        // Everything was referenced: the hand clears all bits, then comes
+        // back to frame 0
+        assert_eq!(clock.victim(|_| false), Some(0));
+        clock.touch(1);
+        // Frame 1 gets a second chance
+        assert_eq!(clock.victim(|_| false), Some(2));
+
+        clock.release(2);
+        assert_eq!(clock.free_frames(), 1);
+        assert!(clock.frames[2].data.is_empty());
+    }
+}
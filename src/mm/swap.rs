@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use super::PAGE_SIZE;
+use crate::vfs::Disk;
+
+/// Swap space on a `Disk`. A page takes as many blocks as it needs; the
+/// blocks are freed when the page is loaded back or its owner goes away.
+pub(crate) struct SwapSpace {
+    disk: Arc<dyn Disk + Send + Sync>,
+    /// Pages the swap may hold, whatever room the disk has left.
+    pub(crate) max_pages: usize,
+    pub(crate) used_pages: usize,
+}
+
+impl SwapSpace {
+    pub(crate) fn new(disk: Arc<dyn Disk + Send + Sync>, max_pages: usize) -> Self {
+        SwapSpace {
+            disk,
+            max_pages,
+            used_pages: 0,
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.used_pages >= self.max_pages
+    }
+
+    /// Writes a page out and returns the blocks holding it.
+    pub(crate) fn store(&mut self, page: &[u8]) -> Result<Vec<u64>, String> {
+        if self.is_full() {
+            return Err("No space left on device".into());
+        }
+        let mut blocks = Vec::new();
+        for chunk in page.chunks(self.disk.block_size()) {
+            let written = self
+                .disk
+                .allocate_block()
+                .and_then(|block| self.disk.write_block(block, chunk).map(|_| block));
+            match written {
+                Ok(block) => blocks.push(block),
+                Err(e) => {
+                    self.release(&blocks);
+                    return Err(e.into());
+                }
+            }
+        }
+        self.used_pages += 1;
+        Ok(blocks)
+    }
+
+    /// Reads a page back and frees its blocks.
+    pub(crate) fn load(&mut self, blocks: &[u64]) -> Result<Vec<u8>, String> {
+        let mut page = Vec::with_capacity(PAGE_SIZE);
+        for block in blocks {
+            page.extend(self.disk.read_block(*block)?);
+        }
+        page.resize(PAGE_SIZE, 0);
+        self.discard(blocks);
+        Ok(page)
+    }
+
+    /// Frees a swapped page nobody will load.
+    pub(crate) fn discard(&mut self, blocks: &[u64]) {
+        self.release(blocks);
+        self.used_pages -= 1;
+    }
+
+    fn release(&self, blocks: &[u64]) {
+        for block in blocks {
+            let _ = self.disk.free_block(*block);
+        }
+    }
+}
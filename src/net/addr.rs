@@ -0,0 +1,90 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+/// An IPv4 prefix such as `192.168.1.0/24`. The address keeps whatever host
+/// bits it was written with; `network()` clears them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cidr {
+    pub addr: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn new(addr: Ipv4Addr, prefix: u8) -> Result<Self, String> {
+        if prefix > 32 {
+            return Err(format!("invalid prefix length: {}", prefix));
+        }
+        Ok(Cidr { addr, prefix })
+    }
+
+    pub fn mask(&self) -> u32 {
+        match self.prefix {
+            0 => 0,
+            n => u32::MAX << (32 - n),
+        }
+    }
+
+    /// The prefix with the host bits cleared.
+    pub fn network(&self) -> Cidr {
+        Cidr {
+            addr: Ipv4Addr::from(u32::from(self.addr) & self.mask()),
+            prefix: self.prefix,
+        }
+    }
+
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.addr) | !self.mask())
+    }
+
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(addr) & self.mask() == u32::from(self.addr) & self.mask()
+    }
+
+    /// Number of addresses in the prefix.
+    pub fn size(&self) -> u64 {
+        1u64 << (32 - self.prefix)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// `a.b.c.d/n`; a bare address is a /32.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, prefix.parse::<u8>().map_err(|_| format!("invalid prefix: {}", s))?),
+            None => (s, 32),
+        };
+        let addr = addr.parse::<Ipv4Addr>().map_err(|_| format!("invalid address: {}", s))?;
+        Cidr::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&format!("{}/{}", self.addr, self.prefix))
+    }
+}
+
+/// An Ethernet address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: MacAddr = MacAddr([0xff; 6]);
+
+    /// A locally administered address derived from `addr`, so every
+    /// interface of a subnet gets a distinct one.
+    pub fn for_ip(addr: Ipv4Addr) -> MacAddr {
+        let [a, b, c, d] = addr.octets();
+        MacAddr([0x02, 0x00, a, b, c, d])
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        f.pad(&format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g))
+    }
+}
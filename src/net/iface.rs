@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::socket::{self, SocketInfo, SocketTable};
+use super::{
+    Arp, ArpOp, Cidr, Frame, Icmp, IpPayload, Ipv4Packet, MacAddr, Payload, RoutingTable, Switch, DEFAULT_TTL,
+};
+use crate::scheduler;
+
+/// How long an address is looked up with ARP before giving up.
+pub const ARP_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Echo replies kept for pings that have not collected them yet
+const MAX_REPLIES: usize = 64;
+
+// How long a waiting operation sleeps before checking whether its process
+// was killed meanwhile
+const WAIT_SLICE: Duration = Duration::from_millis(50);
+
+const ENETDOWN: &str = "Network is down";
+const ENETUNREACH: &str = "Network is unreachable";
+const EHOSTUNREACH: &str = "No route to host";
+
+/// Packet counters of an interface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Stats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+}
+
+/// An answer to `Interface::ping`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EchoReply {
+    pub from: Ipv4Addr,
+    pub seq: u16,
+    pub ttl: u8,
+    pub bytes: usize,
+    pub time: Duration,
+}
+
+pub(super) struct State {
+    // ARP cache
+    neighbours: BTreeMap<Ipv4Addr, MacAddr>,
+    // Echo replies received: sender, id, seq, ttl, bytes
+    replies: VecDeque<(Ipv4Addr, u16, u16, u8, usize)>,
+    stats: Stats,
+    pub(super) sockets: SocketTable,
+}
+
+/// A network card plugged into a `Switch`, with the little of an IP stack
+/// the simulation needs: ARP to find neighbours, a routing table to pick
+/// the next hop, answering pings, and the host's sockets. Frames are handled
+/// as they arrive, on the sender's thread.
+pub struct Interface {
+    name: String,
+    mac: MacAddr,
+    cidr: Cidr,
+    switch: Arc<Switch>,
+    routes: Arc<RoutingTable>,
+    port: AtomicUsize,
+    up: AtomicBool,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Interface {
+    /// A new interface with address `cidr`, plugged into `switch`. It starts
+    /// up, with a routing table holding the route to its subnet.
+    pub fn connect(name: &str, cidr: Cidr, switch: &Arc<Switch>) -> Arc<Interface> {
+        let routes = Arc::new(RoutingTable::new());
+        routes.add_link(name, cidr);
+        let iface = Arc::new(Interface {
+            name: name.to_string(),
+            mac: MacAddr::for_ip(cidr.addr),
+            cidr,
+            switch: switch.clone(),
+            routes,
+            port: AtomicUsize::new(0),
+            up: AtomicBool::new(true),
+            state: Mutex::new(State {
+                neighbours: BTreeMap::new(),
+                replies: VecDeque::new(),
+                stats: Stats::default(),
+                sockets: SocketTable::default(),
+            }),
+            changed: Condvar::new(),
+        });
+        iface.port.store(switch.connect(&iface), Ordering::SeqCst);
+        iface
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    pub fn addr(&self) -> Ipv4Addr {
+        self.cidr.addr
+    }
+
+    /// The address with the prefix length of its subnet.
+    pub fn cidr(&self) -> Cidr {
+        self.cidr
+    }
+
+    /// The routing table of the host this interface belongs to.
+    pub fn routes(&self) -> &Arc<RoutingTable> {
+        &self.routes
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.up.load(Ordering::SeqCst)
+    }
+
+    /// Brings the link up or down. A down interface neither sends nor
+    /// receives, and forgets its neighbours.
+    pub fn set_up(&self, up: bool) {
+        self.up.store(up, Ordering::SeqCst);
+        if !up {
+            let mut state = self.lock();
+            state.neighbours.clear();
+            state.replies.clear();
+        }
+    }
+
+    /// The ARP cache.
+    pub fn neighbours(&self) -> Vec<(Ipv4Addr, MacAddr)> {
+        self.lock().neighbours.iter().map(|(ip, mac)| (*ip, *mac)).collect()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.lock().stats
+    }
+
+    /// The sockets open on this interface's host.
+    pub fn sockets(&self) -> Vec<SocketInfo> {
+        self.lock().sockets.list(self.addr())
+    }
+
+    /// Sends `data` to `dst` through the route the routing table picks,
+    /// looking up the hardware address of the next hop first.
+    pub fn send(&self, dst: Ipv4Addr, data: IpPayload) -> Result<(), String> {
+        if !self.is_up() {
+            return Err(ENETDOWN.into());
+        }
+        let packet = Ipv4Packet {
+            src: self.addr(),
+            dst,
+            ttl: DEFAULT_TTL,
+            data,
+        };
+        if dst == self.addr() || dst.is_loopback() {
+            self.deliver(packet);
+            return Ok(());
+        }
+        let route = self.routes.lookup(dst).ok_or(ENETUNREACH)?;
+        let mac = self.resolve(route.gateway.unwrap_or(dst))?;
+        self.transmit(Frame {
+            src: self.mac,
+            dst: mac,
+            payload: Payload::Ipv4(packet),
+        })
+    }
+
+    /// Sends an echo request with `seq` to `dst` and waits up to `timeout`
+    /// for the reply.
+    pub fn ping(&self, dst: Ipv4Addr, seq: u16, timeout: Duration) -> Result<EchoReply, String> {
+        // Like ping(8), the request is tagged with the caller's PID
+        let id = scheduler::current_pid().unwrap_or(0) as u16;
+        let start = Instant::now();
+        self.send(dst, IpPayload::Icmp(Icmp::EchoRequest { id, seq, data: vec![0; 56] }))?;
+        let mut reply = None;
+        self.wait_until(Some(start + timeout), |state| {
+            let found = state
+                .replies
+                .iter()
+                .position(|(from, i, s, _, _)| (*from, *i, *s) == (dst, id, seq));
+            reply = found.and_then(|index| state.replies.remove(index));
+            reply.is_some()
+        });
+        match reply {
+            Some((from, _, seq, ttl, bytes)) => Ok(EchoReply {
+                from,
+                seq,
+                ttl,
+                bytes,
+                time: start.elapsed(),
+            }),
+            None => Err("Request timed out".into()),
+        }
+    }
+
+    // Hardware address of the neighbour `ip`, asking with ARP if unknown
+    fn resolve(&self, ip: Ipv4Addr) -> Result<MacAddr, String> {
+        if let Some(mac) = self.lock().neighbours.get(&ip) {
+            return Ok(*mac);
+        }
+        self.transmit(Frame {
+            src: self.mac,
+            dst: MacAddr::BROADCAST,
+            payload: Payload::Arp(Arp {
+                op: ArpOp::Request,
+                sender_mac: self.mac,
+                sender_ip: self.addr(),
+                target_mac: MacAddr([0; 6]),
+                target_ip: ip,
+            }),
+        })?;
+        let mut mac = None;
+        self.wait_until(Some(Instant::now() + ARP_TIMEOUT), |state| {
+            mac = state.neighbours.get(&ip).copied();
+            mac.is_some()
+        });
+        mac.ok_or_else(|| EHOSTUNREACH.into())
+    }
+
+    fn transmit(&self, frame: Frame) -> Result<(), String> {
+        if !self.is_up() {
+            return Err(ENETDOWN.into());
+        }
+        self.lock().stats.tx_packets += 1;
+        self.switch.forward(self.port.load(Ordering::SeqCst), frame);
+        Ok(())
+    }
+
+    /// Handles a frame from the switch.
+    pub(crate) fn receive(&self, frame: &Frame) {
+        if !self.is_up() || (frame.dst != self.mac && frame.dst != MacAddr::BROADCAST) {
+            return;
+        }
+        self.lock().stats.rx_packets += 1;
+        match &frame.payload {
+            Payload::Arp(arp) => self.receive_arp(arp),
+            Payload::Ipv4(packet) if packet.dst == self.addr() => self.deliver(packet.clone()),
+            Payload::Ipv4(_) => {}
+        }
+    }
+
+    fn receive_arp(&self, arp: &Arp) {
+        // Only conversations this interface is part of are cached
+        if arp.target_ip != self.addr() {
+            return;
+        }
+        self.lock().neighbours.insert(arp.sender_ip, arp.sender_mac);
+        self.changed.notify_all();
+        if arp.op == ArpOp::Request {
+            let _ = self.transmit(Frame {
+                src: self.mac,
+                dst: arp.sender_mac,
+                payload: Payload::Arp(Arp {
+                    op: ArpOp::Reply,
+                    sender_mac: self.mac,
+                    sender_ip: self.addr(),
+                    target_mac: arp.sender_mac,
+                    target_ip: arp.sender_ip,
+                }),
+            });
+        }
+    }
+
+    // A packet for this interface's address
+    fn deliver(&self, packet: Ipv4Packet) {
+        match packet.data {
+            IpPayload::Icmp(Icmp::EchoRequest { id, seq, data }) => {
+                let _ = self.send(packet.src, IpPayload::Icmp(Icmp::EchoReply { id, seq, data }));
+            }
+            IpPayload::Icmp(Icmp::EchoReply { id, seq, data }) => {
+                let mut state = self.lock();
+                if state.replies.len() == MAX_REPLIES {
+                    state.replies.pop_front();
+                }
+                state.replies.push_back((packet.src, id, seq, packet.ttl, data.len()));
+                self.changed.notify_all();
+            }
+            data => socket::receive(self, packet.src, data),
+        }
+    }
+
+    pub(super) fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    // Wakes whoever waits on the state
+    pub(super) fn notify(&self) {
+        self.changed.notify_all();
+    }
+
+    // Waits until `ready` holds or `deadline`, if any, passes, without
+    // holding a CPU. Returns whether `ready` held.
+    pub(super) fn wait_until(&self, deadline: Option<Instant>, mut ready: impl FnMut(&mut State) -> bool) -> bool {
+        loop {
+            let mut state = self.lock();
+            if ready(&mut state) {
+                return true;
+            }
+            let left = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => WAIT_SLICE,
+            };
+            if left.is_zero() {
+                return false;
+            }
+            drop(state);
+            scheduler::blocking(|| {
+                let state = self.lock();
+                drop(self.changed.wait_timeout(state, left.min(WAIT_SLICE)).unwrap());
+            });
+        }
+    }
+}
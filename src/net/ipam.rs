@@ -0,0 +1,100 @@
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+
+use super::Cidr;
+
+/// Hands out the host addresses of a subnet. The network and broadcast
+/// addresses and the gateway are never handed out, and an address is only
+/// handed out again once it has been released.
+pub struct Ipam {
+    subnet: Cidr,
+    gateway: Ipv4Addr,
+    in_use: BTreeSet<Ipv4Addr>,
+}
+
+impl Ipam {
+    /// Manages `subnet`, whose first host address is the gateway.
+    pub fn new(subnet: Cidr) -> Result<Self, String> {
+        let subnet = subnet.network();
+        if subnet.size() < 4 {
+            return Err(format!("{}: subnet too small", subnet));
+        }
+        Ok(Ipam {
+            subnet,
+            gateway: Ipv4Addr::from(u32::from(subnet.addr) + 1),
+            in_use: BTreeSet::new(),
+        })
+    }
+
+    pub fn subnet(&self) -> Cidr {
+        self.subnet
+    }
+
+    pub fn gateway(&self) -> Ipv4Addr {
+        self.gateway
+    }
+
+    /// The lowest free host address.
+    pub fn allocate(&mut self) -> Result<Ipv4Addr, String> {
+        let first = u32::from(self.gateway) + 1;
+        let last = u32::from(self.subnet.broadcast()) - 1;
+        let addr = (first..=last)
+            .map(Ipv4Addr::from)
+            .find(|addr| !self.in_use.contains(addr))
+            .ok_or_else(|| format!("{}: no free addresses", self.subnet))?;
+        self.in_use.insert(addr);
+        Ok(addr)
+    }
+
+    /// Claims `addr`, e.g. one a VPS had before a restart.
+    pub fn reserve(&mut self, addr: Ipv4Addr) -> Result<(), String> {
+        if !self.subnet.contains(addr) {
+            return Err(format!("{} is outside {}", addr, self.subnet));
+        }
+        if addr == self.subnet.addr || addr == self.subnet.broadcast() || addr == self.gateway {
+            return Err(format!("{} is reserved", addr));
+        }
+        if !self.in_use.insert(addr) {
+            return Err(format!("{} is already in use", addr));
+        }
+        Ok(())
+    }
+
+    pub fn release(&mut self, addr: Ipv4Addr) {
+        self.in_use.remove(&addr);
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.in_use.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addresses_are_unique_until_released() {
+        let mut ipam = Ipam::new("10.0.0.0/29".parse().unwrap()).unwrap();
+        assert_eq!(ipam.gateway(), Ipv4Addr::new(10, 0, 0, 1));
+        let a = ipam.allocate().unwrap();
+        let b = ipam.allocate().unwrap();
+        assert_eq!((a, b), (Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 3)));
+
+        // A reloaded address is taken, and so skipped
+        ipam.reserve(Ipv4Addr::new(10, 0, 0, 4)).unwrap();
+        assert!(ipam.reserve(b).is_err());
+        assert!(ipam.reserve(Ipv4Addr::new(10, 0, 0, 1)).is_err());
+        assert!(ipam.reserve(Ipv4Addr::new(10, 0, 0, 7)).is_err());
+        assert!(ipam.reserve(Ipv4Addr::new(10, 0, 1, 2)).is_err());
+        assert_eq!(ipam.allocate().unwrap(), Ipv4Addr::new(10, 0, 0, 5));
+        assert_eq!(ipam.allocate().unwrap(), Ipv4Addr::new(10, 0, 0, 6));
+        assert!(ipam.allocate().is_err());
+
+        // Deleting the second of three does not hand out the third again
+        ipam.release(b);
+        assert_eq!(ipam.allocate().unwrap(), b);
+        assert!(ipam.allocate().is_err());
+        assert_eq!(ipam.in_use(), 5);
+    }
+}
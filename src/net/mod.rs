@@ -0,0 +1,148 @@
+//! A simulated network between the host and its VPSes.
+//!
+//! Every VPS has an interface plugged into one `Switch`, a learning bridge,
+//! and so does the host, whose interface holds the gateway address. Frames
+//! are structures, not bytes: ARP finds a neighbour's hardware address and
+//! ICMP echo answers `ping`. `Ipam` hands out the addresses of the subnet,
+//! and each host has a `RoutingTable` deciding where its packets go.
+//!
+//! On top, processes get sockets: TCP-like streams and UDP datagrams, with
+//! ports kept per host by its interface.
+
+mod addr;
+mod iface;
+mod ipam;
+mod packet;
+mod route;
+mod socket;
+mod switch;
+
+pub use addr::{Cidr, MacAddr};
+pub use iface::{EchoReply, Interface, Stats, ARP_TIMEOUT};
+pub use ipam::Ipam;
+pub use packet::{
+    Arp, ArpOp, Datagram, Frame, Icmp, IpPayload, Ipv4Packet, Payload, Segment, SegmentKind, DEFAULT_TTL,
+};
+pub use route::{Route, RoutingTable};
+pub use socket::{
+    Protocol, SocketInfo, TcpListener, TcpSocket, TcpState, TcpStream, UdpSocket, DEFAULT_BACKLOG, EPHEMERAL_PORTS,
+};
+pub use switch::Switch;
+
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+
+/// Subnet of the VPSes unless configured otherwise.
+pub const DEFAULT_SUBNET: &str = "192.168.1.0/24";
+
+/// Name of the host's interface on the switch.
+pub const BRIDGE: &str = "vbr0";
+
+/// A subnet: its switch, its addresses, and the host's interface on it.
+pub struct Network {
+    switch: Arc<Switch>,
+    ipam: Mutex<Ipam>,
+    bridge: Arc<Interface>,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUBNET.parse().unwrap()).unwrap()
+    }
+}
+
+impl Network {
+    pub fn new(subnet: Cidr) -> Result<Self, String> {
+        let ipam = Ipam::new(subnet)?;
+        let switch = Arc::new(Switch::new());
+        let gateway = Cidr::new(ipam.gateway(), subnet.prefix)?;
+        let bridge = Interface::connect(BRIDGE, gateway, &switch);
+        Ok(Network {
+            switch,
+            ipam: Mutex::new(ipam),
+            bridge,
+        })
+    }
+
+    pub fn subnet(&self) -> Cidr {
+        self.ipam.lock().unwrap().subnet()
+    }
+
+    /// The host's interface, at the gateway address.
+    pub fn bridge(&self) -> Arc<Interface> {
+        self.bridge.clone()
+    }
+
+    pub fn switch(&self) -> &Arc<Switch> {
+        &self.switch
+    }
+
+    /// A free address of the subnet.
+    pub fn allocate(&self) -> Result<Ipv4Addr, String> {
+        self.ipam.lock().unwrap().allocate()
+    }
+
+    /// Claims `addr`, which must be free.
+    pub fn reserve(&self, addr: Ipv4Addr) -> Result<(), String> {
+        self.ipam.lock().unwrap().reserve(addr)
+    }
+
+    pub fn release(&self, addr: Ipv4Addr) {
+        self.ipam.lock().unwrap().release(addr)
+    }
+
+    /// A new interface called `name` with address `addr`, plugged into the
+    /// switch, whose default route goes through the host.
+    pub fn connect(&self, name: &str, addr: Ipv4Addr) -> Arc<Interface> {
+        let cidr = Cidr {
+            addr,
+            prefix: self.subnet().prefix,
+        };
+        let iface = Interface::connect(name, cidr, &self.switch);
+        // Refused only for an address outside the subnet, which has no way out
+        let _ = iface.routes().add(Route::default_via(self.bridge.addr(), name));
+        iface
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ping_resolves_neighbours_and_needs_the_link_up() {
+        let network = Network::new("10.1.0.0/24".parse().unwrap()).unwrap();
+        let a = network.connect("eth0", network.allocate().unwrap());
+        let b = network.connect("eth0", network.allocate().unwrap());
+        assert_ne!(a.mac(), b.mac());
+
+        let reply = a.ping(b.addr(), 1, Duration::from_secs(1)).unwrap();
+        assert_eq!((reply.from, reply.seq, reply.ttl, reply.bytes), (b.addr(), 1, DEFAULT_TTL, 56));
+        // Both ends learnt each other; nobody else was bothered
+        assert_eq!(a.neighbours(), [(b.addr(), b.mac())]);
+        assert_eq!(b.neighbours(), [(a.addr(), a.mac())]);
+        assert!(network.bridge().neighbours().is_empty());
+        assert!(b.ping(network.bridge().addr(), 1, Duration::from_secs(1)).is_ok());
+        assert!(a.ping(a.addr(), 2, Duration::from_secs(1)).is_ok());
+
+        // Nobody answers for a free address or a down interface
+        let free = Ipv4Addr::new(10, 1, 0, 200);
+        assert_eq!(a.ping(free, 3, Duration::from_secs(1)).unwrap_err(), "No route to host");
+        b.set_up(false);
+        assert!(a.ping(b.addr(), 4, Duration::from_millis(50)).is_err());
+        assert_eq!(b.ping(a.addr(), 1, Duration::from_secs(1)).unwrap_err(), "Network is down");
+        assert!(a.stats().tx_packets >= 4 && b.stats().rx_packets >= 2);
+
+        // Other networks are behind the host, which does not forward, and
+        // unreachable without a default route
+        let far = Ipv4Addr::new(10, 2, 0, 1);
+        assert_eq!(a.routes().lookup(far).unwrap().gateway, Some(network.bridge().addr()));
+        let before = network.bridge().stats().rx_packets;
+        assert_eq!(a.ping(far, 5, Duration::from_millis(50)).unwrap_err(), "Request timed out");
+        assert!(network.bridge().stats().rx_packets > before);
+        a.routes().remove("0.0.0.0/0".parse().unwrap(), None).unwrap();
+        assert_eq!(a.ping(far, 6, Duration::from_secs(1)).unwrap_err(), "Network is unreachable");
+        assert_eq!(network.bridge().ping(far, 1, Duration::from_secs(1)).unwrap_err(), "Network is unreachable");
+    }
+}
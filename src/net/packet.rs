@@ -0,0 +1,88 @@
+use std::net::Ipv4Addr;
+
+use super::MacAddr;
+
+/// Time to live of the packets an interface sends.
+pub const DEFAULT_TTL: u8 = 64;
+
+/// What travels over the switch: an Ethernet frame. Packets are kept as
+/// structures rather than bytes; nothing here needs a wire format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub src: MacAddr,
+    pub dst: MacAddr,
+    pub payload: Payload,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Payload {
+    Arp(Arp),
+    Ipv4(Ipv4Packet),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArpOp {
+    Request,
+    Reply,
+}
+
+/// "Who has `target_ip`? Tell `sender_ip`", or the answer to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arp {
+    pub op: ArpOp,
+    pub sender_mac: MacAddr,
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: MacAddr,
+    pub target_ip: Ipv4Addr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ipv4Packet {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub ttl: u8,
+    pub data: IpPayload,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IpPayload {
+    Icmp(Icmp),
+    Tcp(Segment),
+    Udp(Datagram),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Icmp {
+    EchoRequest { id: u16, seq: u16, data: Vec<u8> },
+    EchoReply { id: u16, seq: u16, data: Vec<u8> },
+}
+
+/// A TCP segment. The switch neither loses nor reorders frames, so there
+/// are no sequence numbers or windows: a segment is one step of the
+/// connection's life.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub kind: SegmentKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Opens a connection.
+    Syn,
+    /// Accepts it.
+    SynAck,
+    Data(Vec<u8>),
+    /// The sender will send no more.
+    Fin,
+    /// Refuses or aborts a connection.
+    Rst,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Datagram {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub data: Vec<u8>,
+}
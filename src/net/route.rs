@@ -0,0 +1,252 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::sync::{Mutex, MutexGuard};
+
+use super::Cidr;
+
+const EEXIST: &str = "File exists";
+const ESRCH: &str = "No such process";
+
+/// An entry of a routing table: packets for `destination` leave through
+/// `iface`, to `gateway` if there is one or straight to their destination
+/// if it is on the link.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub destination: Cidr,
+    pub gateway: Option<Ipv4Addr>,
+    pub iface: String,
+    pub metric: u32,
+    // Address of the interface, for the routes of its own subnet
+    pub src: Option<Ipv4Addr>,
+}
+
+impl Route {
+    /// The route to the subnet `cidr` of interface `iface`.
+    pub fn link(iface: &str, cidr: Cidr) -> Route {
+        Route {
+            destination: cidr.network(),
+            gateway: None,
+            iface: iface.to_string(),
+            metric: 0,
+            src: Some(cidr.addr),
+        }
+    }
+
+    /// A route for everything else, through `gateway`.
+    pub fn default_via(gateway: Ipv4Addr, iface: &str) -> Route {
+        Route {
+            destination: Cidr {
+                addr: Ipv4Addr::UNSPECIFIED,
+                prefix: 0,
+            },
+            gateway: Some(gateway),
+            iface: iface.to_string(),
+            metric: 0,
+            src: None,
+        }
+    }
+}
+
+impl fmt::Display for Route {
+    /// One line of `ip route`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.destination.prefix == 0 {
+            write!(f, "default")?;
+        } else {
+            write!(f, "{}", self.destination)?;
+        }
+        if let Some(gateway) = self.gateway {
+            write!(f, " via {}", gateway)?;
+        }
+        write!(f, " dev {}", self.iface)?;
+        if self.src.is_some() {
+            write!(f, " proto kernel")?;
+        }
+        if self.gateway.is_none() {
+            write!(f, " scope link")?;
+        }
+        if let Some(src) = self.src {
+            write!(f, " src {}", src)?;
+        }
+        if self.metric != 0 {
+            write!(f, " metric {}", self.metric)?;
+        }
+        Ok(())
+    }
+}
+
+struct Table {
+    // Interfaces the table can send through, with their addresses
+    links: Vec<(String, Cidr)>,
+    routes: Vec<Route>,
+}
+
+/// The routes of one host. Lookups pick the most specific route, and among
+/// equally specific ones the lowest metric.
+pub struct RoutingTable {
+    inner: Mutex<Table>,
+}
+
+impl Default for RoutingTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        RoutingTable {
+            inner: Mutex::new(Table {
+                links: Vec::new(),
+                routes: Vec::new(),
+            }),
+        }
+    }
+
+    /// Makes interface `iface`, at `cidr`, usable by routes, and adds the
+    /// route to its subnet.
+    pub fn add_link(&self, iface: &str, cidr: Cidr) {
+        let mut table = self.lock();
+        table.links.retain(|(name, _)| name != iface);
+        table.links.push((iface.to_string(), cidr));
+        let route = Route::link(iface, cidr);
+        if !table.routes.iter().any(|r| same_key(r, &route)) {
+            table.routes.push(route);
+        }
+    }
+
+    /// The address of interface `iface`.
+    pub fn link_addr(&self, iface: &str) -> Option<Ipv4Addr> {
+        self.lock().links.iter().find(|(name, _)| name == iface).map(|(_, cidr)| cidr.addr)
+    }
+
+    /// Adds `route`. Without an interface, the one whose subnet holds the
+    /// gateway is used. The destination must have no host bits set, the
+    /// gateway must be on the interface's subnet, and no route with the
+    /// same destination and metric may exist. Returns the route added.
+    pub fn add(&self, mut route: Route) -> Result<Route, String> {
+        if route.destination.network() != route.destination {
+            return Err("Invalid prefix for given prefix length".into());
+        }
+        let mut table = self.lock();
+        if route.iface.is_empty() {
+            let gateway = route.gateway.ok_or("Device is required when there is no gateway")?;
+            let link = table.links.iter().find(|(_, cidr)| cidr.contains(gateway));
+            route.iface = link.ok_or("Nexthop has invalid gateway")?.0.clone();
+        }
+        let cidr = match table.links.iter().find(|(name, _)| *name == route.iface) {
+            Some((_, cidr)) => *cidr,
+            None => return Err(format!("Cannot find device \"{}\"", route.iface)),
+        };
+        if let Some(gateway) = route.gateway {
+            let network = cidr.network();
+            if !cidr.contains(gateway) || gateway == network.addr || gateway == network.broadcast() {
+                return Err("Nexthop has invalid gateway".into());
+            }
+        }
+        if table.routes.iter().any(|r| same_key(r, &route)) {
+            return Err(EEXIST.into());
+        }
+        table.routes.push(route.clone());
+        Ok(route)
+    }
+
+    /// Removes the route to `destination` with `metric`, or with any metric
+    /// if none is given.
+    pub fn remove(&self, destination: Cidr, metric: Option<u32>) -> Result<Route, String> {
+        let mut table = self.lock();
+        let index = table
+            .routes
+            .iter()
+            .position(|r| r.destination == destination && metric.is_none_or(|m| m == r.metric))
+            .ok_or(ESRCH)?;
+        Ok(table.routes.remove(index))
+    }
+
+    /// The route `addr` takes: longest prefix first, then lowest metric.
+    pub fn lookup(&self, addr: Ipv4Addr) -> Option<Route> {
+        self.lock()
+            .routes
+            .iter()
+            .filter(|r| r.destination.contains(addr))
+            .min_by_key(|r| (std::cmp::Reverse(r.destination.prefix), r.metric))
+            .cloned()
+    }
+
+    /// All routes, the default one first and the rest by destination, as
+    /// `ip route` lists them.
+    pub fn routes(&self) -> Vec<Route> {
+        let mut routes = self.lock().routes.clone();
+        routes.sort_by_key(|r| (r.destination.prefix != 0, r.destination, r.metric));
+        routes
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Table> {
+        self.inner.lock().unwrap()
+    }
+}
+
+// Two routes with these equal cannot both be in a table
+fn same_key(a: &Route, b: &Route) -> bool {
+    a.destination == b.destination && a.metric == b.metric
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(destination: &str, gateway: Option<&str>, iface: &str, metric: u32) -> Route {
+        Route {
+            destination: destination.parse().unwrap(),
+            gateway: gateway.map(|gw| gw.parse().unwrap()),
+            iface: iface.to_string(),
+            metric,
+            src: None,
+        }
+    }
+
+    #[test]
+    fn test_longest_prefix_wins_then_lowest_metric() {
+        let table = RoutingTable::new();
+        table.add_link("eth0", "10.0.0.5/24".parse().unwrap());
+        table.add(Route::default_via("10.0.0.1".parse().unwrap(), "eth0")).unwrap();
+        table.add(route("172.16.0.0/12", Some("10.0.0.2"), "", 0)).unwrap();
+        table.add(route("172.16.5.0/24", Some("10.0.0.3"), "eth0", 20)).unwrap();
+        table.add(route("172.16.5.0/24", Some("10.0.0.4"), "eth0", 10)).unwrap();
+
+        let via = |addr: &str| table.lookup(addr.parse().unwrap()).map(|r| r.gateway);
+        assert_eq!(via("10.0.0.9"), Some(None));
+        assert_eq!(via("8.8.8.8"), Some(Some("10.0.0.1".parse().unwrap())));
+        assert_eq!(via("172.20.1.1"), Some(Some("10.0.0.2".parse().unwrap())));
+        assert_eq!(via("172.16.5.7"), Some(Some("10.0.0.4".parse().unwrap())));
+        table.remove("172.16.5.0/24".parse().unwrap(), Some(10)).unwrap();
+        assert_eq!(via("172.16.5.7"), Some(Some("10.0.0.3".parse().unwrap())));
+        table.remove("0.0.0.0/0".parse().unwrap(), None).unwrap();
+        assert_eq!(via("8.8.8.8"), None);
+
+        let lines: Vec<String> = table.routes().iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "10.0.0.0/24 dev eth0 proto kernel scope link src 10.0.0.5",
+                "172.16.0.0/12 via 10.0.0.2 dev eth0",
+                "172.16.5.0/24 via 10.0.0.3 dev eth0 metric 20",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bad_routes_are_refused() {
+        let table = RoutingTable::new();
+        table.add_link("eth0", "10.0.0.5/24".parse().unwrap());
+        let err = |r: Route| table.add(r).unwrap_err();
+        assert_eq!(err(route("10.1.0.1/16", Some("10.0.0.1"), "eth0", 0)), "Invalid prefix for given prefix length");
+        assert_eq!(err(route("10.1.0.0/16", Some("10.0.0.1"), "eth1", 0)), "Cannot find device \"eth1\"");
+        assert_eq!(err(route("10.1.0.0/16", Some("10.9.0.1"), "eth0", 0)), "Nexthop has invalid gateway");
+        assert_eq!(err(route("10.1.0.0/16", Some("10.9.0.1"), "", 0)), "Nexthop has invalid gateway");
+        assert_eq!(err(route("10.1.0.0/16", Some("10.0.0.255"), "eth0", 0)), "Nexthop has invalid gateway");
+        assert_eq!(err(route("10.0.0.0/24", None, "eth0", 0)), "File exists");
+        assert_eq!(table.remove("10.1.0.0/16".parse().unwrap(), None).unwrap_err(), "No such process");
+        assert_eq!(table.routes().len(), 1);
+    }
+}
@@ -0,0 +1,702 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Datagram, Interface, IpPayload, Segment, SegmentKind};
+use crate::scheduler;
+
+/// Ports handed out to sockets that do not pick one, as on Linux.
+pub const EPHEMERAL_PORTS: RangeInclusive<u16> = 32768..=60999;
+
+/// Connections a listener keeps waiting for `accept` unless told otherwise.
+pub const DEFAULT_BACKLOG: usize = 128;
+
+// Datagrams a UDP socket holds before dropping new ones
+const MAX_DATAGRAMS: usize = 64;
+
+const EADDRINUSE: &str = "Address already in use";
+const EADDRNOTAVAIL: &str = "Cannot assign requested address";
+const EAGAIN: &str = "Resource temporarily unavailable";
+const ECONNREFUSED: &str = "Connection refused";
+const ECONNRESET: &str = "Connection reset by peer";
+const EINVAL: &str = "Invalid argument";
+const ENOTCONN: &str = "Transport endpoint is not connected";
+const EPIPE: &str = "Broken pipe";
+const ETIMEDOUT: &str = "Connection timed out";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        })
+    }
+}
+
+/// Where a TCP socket is in the life of a connection. With no lost frames
+/// there is no TIME_WAIT, and closing takes one FIN each way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpState {
+    /// Bound, or done with its connection.
+    Closed,
+    Listen,
+    SynSent,
+    Established,
+    /// This end sent FIN; the peer may still send.
+    FinWait,
+    /// The peer sent FIN; this end may still send.
+    CloseWait,
+}
+
+impl fmt::Display for TcpState {
+    /// The name `netstat` uses.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            TcpState::Closed => "CLOSE",
+            TcpState::Listen => "LISTEN",
+            TcpState::SynSent => "SYN_SENT",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::FinWait => "FIN_WAIT2",
+            TcpState::CloseWait => "CLOSE_WAIT",
+        })
+    }
+}
+
+/// A socket as `netstat` shows it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SocketInfo {
+    pub protocol: Protocol,
+    pub local: SocketAddrV4,
+    pub peer: Option<SocketAddrV4>,
+    /// `None` for UDP.
+    pub state: Option<TcpState>,
+    /// Bytes received and not read yet; for listeners, connections waiting
+    /// for `accept`.
+    pub recv_queue: usize,
+    pub pid: u32,
+}
+
+/// The sockets of one host, kept by its interface.
+#[derive(Default)]
+pub(super) struct SocketTable {
+    entries: BTreeMap<u64, Entry>,
+    next_id: u64,
+    // Where the search for a free ephemeral port starts
+    next_port: u16,
+}
+
+struct Entry {
+    protocol: Protocol,
+    // 0 until bound
+    port: u16,
+    peer: Option<SocketAddrV4>,
+    state: TcpState,
+    pid: u32,
+    // Connections a listener accepted on its own, which share its port
+    accepted: bool,
+    received: VecDeque<u8>,
+    datagrams: VecDeque<(SocketAddrV4, Vec<u8>)>,
+    // Listener: connections waiting for accept(), and how many may wait
+    pending: VecDeque<u64>,
+    backlog: usize,
+    peer_closed: bool,
+    reset: bool,
+}
+
+impl SocketTable {
+    fn insert(&mut self, protocol: Protocol, pid: u32) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.insert(
+            id,
+            Entry {
+                protocol,
+                port: 0,
+                peer: None,
+                state: TcpState::Closed,
+                pid,
+                accepted: false,
+                received: VecDeque::new(),
+                datagrams: VecDeque::new(),
+                pending: VecDeque::new(),
+                backlog: 0,
+                peer_closed: false,
+                reset: false,
+            },
+        );
+        id
+    }
+
+    // Only a handle removes its entry, so a handle's entry is always there
+    fn entry(&mut self, id: u64) -> &mut Entry {
+        self.entries.get_mut(&id).unwrap()
+    }
+
+    fn port_in_use(&self, protocol: Protocol, port: u16) -> bool {
+        self.entries.values().any(|e| e.protocol == protocol && e.port == port && !e.accepted)
+    }
+
+    fn bind(&mut self, id: u64, port: u16) -> Result<u16, String> {
+        let protocol = self.entry(id).protocol;
+        if self.entry(id).port != 0 {
+            return Err(EINVAL.into());
+        }
+        let port = match port {
+            0 => self.ephemeral_port(protocol)?,
+            port if self.port_in_use(protocol, port) => return Err(EADDRINUSE.into()),
+            port => port,
+        };
+        self.entry(id).port = port;
+        Ok(port)
+    }
+
+    fn ephemeral_port(&mut self, protocol: Protocol) -> Result<u16, String> {
+        let (first, last) = (*EPHEMERAL_PORTS.start(), *EPHEMERAL_PORTS.end());
+        let start = self.next_port.clamp(first, last);
+        let ports = (start..=last).chain(first..start);
+        let port = ports.into_iter().find(|port| !self.port_in_use(protocol, *port)).ok_or(EADDRNOTAVAIL)?;
+        self.next_port = port.checked_add(1).unwrap_or(first);
+        Ok(port)
+    }
+
+    fn connection(&mut self, port: u16, peer: SocketAddrV4) -> Option<&mut Entry> {
+        self.entries.values_mut().find(|e| {
+            e.protocol == Protocol::Tcp && e.port == port && e.peer == Some(peer) && e.state != TcpState::Listen
+        })
+    }
+
+    // Handles a TCP segment from `from` for `port`; returns what to answer
+    fn tcp_input(&mut self, from: SocketAddrV4, port: u16, kind: SegmentKind) -> Option<SegmentKind> {
+        if let Some(conn) = self.connection(port, from) {
+            // The peer's first data or FIN can overtake its SYN-ACK, which
+            // leaves on another thread; either means it accepted
+            if conn.state == TcpState::SynSent && matches!(kind, SegmentKind::Data(_) | SegmentKind::Fin) {
+                conn.state = TcpState::Established;
+            }
+            match kind {
+                SegmentKind::SynAck if conn.state == TcpState::SynSent => conn.state = TcpState::Established,
+                SegmentKind::Syn | SegmentKind::SynAck => {}
+                SegmentKind::Data(data) => {
+                    if conn.peer_closed || !matches!(conn.state, TcpState::Established | TcpState::FinWait) {
+                        return Some(SegmentKind::Rst);
+                    }
+                    conn.received.extend(data);
+                }
+                SegmentKind::Fin => {
+                    conn.peer_closed = true;
+                    conn.state = match conn.state {
+                        TcpState::Established => TcpState::CloseWait,
+                        TcpState::FinWait => TcpState::Closed,
+                        state => state,
+                    };
+                }
+                SegmentKind::Rst => {
+                    conn.reset = true;
+                    conn.state = TcpState::Closed;
+                }
+            }
+            return None;
+        }
+        match kind {
+            SegmentKind::Syn => {
+                let listener = self
+                    .entries
+                    .iter()
+                    .find(|(_, e)| e.protocol == Protocol::Tcp && e.port == port && e.state == TcpState::Listen)
+                    .map(|(id, e)| (*id, e.pid, e.pending.len() < e.backlog));
+                let (listener, pid) = match listener {
+                    Some((id, pid, true)) => (id, pid),
+                    _ => return Some(SegmentKind::Rst),
+                };
+                let child = self.insert(Protocol::Tcp, pid);
+                let entry = self.entry(child);
+                entry.port = port;
+                entry.peer = Some(from);
+                entry.state = TcpState::Established;
+                entry.accepted = true;
+                self.entry(listener).pending.push_back(child);
+                Some(SegmentKind::SynAck)
+            }
+            // Never answer a reset, or two hosts could trade them forever
+            SegmentKind::Rst => None,
+            _ => Some(SegmentKind::Rst),
+        }
+    }
+
+    fn udp_input(&mut self, from: SocketAddrV4, port: u16, data: Vec<u8>) {
+        let socket = self.entries.values_mut().find(|e| e.protocol == Protocol::Udp && e.port == port);
+        if let Some(socket) = socket.filter(|s| s.datagrams.len() < MAX_DATAGRAMS) {
+            socket.datagrams.push_back((from, data));
+        }
+    }
+
+    pub(super) fn list(&self, addr: Ipv4Addr) -> Vec<SocketInfo> {
+        let mut sockets: Vec<SocketInfo> = self
+            .entries
+            .values()
+            .filter(|e| e.port != 0)
+            .map(|e| SocketInfo {
+                protocol: e.protocol,
+                local: SocketAddrV4::new(addr, e.port),
+                peer: e.peer,
+                state: (e.protocol == Protocol::Tcp).then_some(e.state),
+                recv_queue: match (e.protocol, e.state) {
+                    (Protocol::Tcp, TcpState::Listen) => e.pending.len(),
+                    (Protocol::Tcp, _) => e.received.len(),
+                    (Protocol::Udp, _) => e.datagrams.iter().map(|(_, data)| data.len()).sum(),
+                },
+                pid: e.pid,
+            })
+            .collect();
+        sockets.sort_by_key(|s| (s.protocol, s.local.port(), s.peer));
+        sockets
+    }
+}
+
+/// Handles a TCP segment or UDP datagram that arrived for the interface.
+pub(super) fn receive(iface: &Interface, src: Ipv4Addr, payload: IpPayload) {
+    match payload {
+        IpPayload::Tcp(segment) => {
+            let from = SocketAddrV4::new(src, segment.src_port);
+            let reply = iface.lock().sockets.tcp_input(from, segment.dst_port, segment.kind);
+            iface.notify();
+            if let Some(kind) = reply {
+                let _ = iface.send(
+                    src,
+                    IpPayload::Tcp(Segment {
+                        src_port: segment.dst_port,
+                        dst_port: segment.src_port,
+                        kind,
+                    }),
+                );
+            }
+        }
+        IpPayload::Udp(datagram) => {
+            let from = SocketAddrV4::new(src, datagram.src_port);
+            iface.lock().sockets.udp_input(from, datagram.dst_port, datagram.data);
+            iface.notify();
+        }
+        IpPayload::Icmp(_) => {}
+    }
+}
+
+// An entry of the socket table, removed when the handle goes. Dropping a
+// connection closes it; dropping a listener refuses what it had not
+// accepted yet.
+struct Handle {
+    iface: Arc<Interface>,
+    id: u64,
+}
+
+impl Handle {
+    fn new(iface: &Arc<Interface>, protocol: Protocol) -> Handle {
+        let pid = scheduler::current_pid().unwrap_or(0);
+        let id = iface.lock().sockets.insert(protocol, pid);
+        Handle {
+            iface: iface.clone(),
+            id,
+        }
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Entry) -> T) -> T {
+        f(self.iface.lock().sockets.entry(self.id))
+    }
+
+    fn bind(&self, port: u16) -> Result<u16, String> {
+        self.iface.lock().sockets.bind(self.id, port)
+    }
+
+    // Binds to an ephemeral port unless bound already
+    fn ensure_bound(&self) -> Result<u16, String> {
+        match self.with(|e| e.port) {
+            0 => self.bind(0),
+            port => Ok(port),
+        }
+    }
+
+    fn local_addr(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.iface.addr(), self.with(|e| e.port))
+    }
+
+    fn send_segment(&self, peer: SocketAddrV4, kind: SegmentKind) -> Result<(), String> {
+        let segment = Segment {
+            src_port: self.with(|e| e.port),
+            dst_port: peer.port(),
+            kind,
+        };
+        self.iface.send(*peer.ip(), IpPayload::Tcp(segment))
+    }
+
+    // Waits until `ready` gives an answer, for at most `timeout`
+    fn wait<T>(&self, timeout: Option<Duration>, mut ready: impl FnMut(&mut Entry) -> Option<T>) -> Option<T> {
+        let mut result = None;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.iface.wait_until(deadline, |state| {
+            result = ready(state.sockets.entry(self.id));
+            result.is_some()
+        });
+        result
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let (entry, refused) = {
+            let mut state = self.iface.lock();
+            let entry = state.sockets.entries.remove(&self.id).unwrap();
+            let refused: Vec<Entry> =
+                entry.pending.iter().filter_map(|id| state.sockets.entries.remove(id)).collect();
+            (entry, refused)
+        };
+        let close = |conn: &Entry, kind: SegmentKind| {
+            if let Some(peer) = conn.peer {
+                let segment = Segment {
+                    src_port: conn.port,
+                    dst_port: peer.port(),
+                    kind,
+                };
+                let _ = self.iface.send(*peer.ip(), IpPayload::Tcp(segment));
+            }
+        };
+        if matches!(entry.state, TcpState::Established | TcpState::CloseWait) && !entry.reset {
+            close(&entry, SegmentKind::Fin);
+        }
+        for conn in refused {
+            close(&conn, SegmentKind::Rst);
+        }
+    }
+}
+
+/// A TCP socket that neither listens nor is connected yet.
+pub struct TcpSocket {
+    handle: Handle,
+}
+
+impl TcpSocket {
+    pub fn new(iface: &Arc<Interface>) -> Self {
+        TcpSocket {
+            handle: Handle::new(iface, Protocol::Tcp),
+        }
+    }
+
+    /// Takes `port` on the interface's host, or an ephemeral one for 0.
+    /// Returns the port.
+    pub fn bind(&self, port: u16) -> Result<u16, String> {
+        self.handle.bind(port)
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.handle.local_addr()
+    }
+
+    /// Accepts connections, up to `backlog` of them waiting for `accept`.
+    pub fn listen(self, backlog: usize) -> Result<TcpListener, String> {
+        self.handle.ensure_bound()?;
+        self.handle.with(|e| {
+            e.state = TcpState::Listen;
+            e.backlog = backlog.max(1);
+        });
+        Ok(TcpListener { handle: self.handle })
+    }
+
+    /// Connects to `addr`, waiting up to `timeout` for it to answer.
+    pub fn connect(self, addr: SocketAddrV4, timeout: Duration) -> Result<TcpStream, String> {
+        self.handle.ensure_bound()?;
+        // Answers to a loopback address come from the interface's own
+        let addr = match addr.ip().is_loopback() {
+            true => SocketAddrV4::new(self.handle.iface.addr(), addr.port()),
+            false => addr,
+        };
+        self.handle.with(|e| {
+            e.peer = Some(addr);
+            e.state = TcpState::SynSent;
+        });
+        self.handle.send_segment(addr, SegmentKind::Syn)?;
+        let outcome = self.handle.wait(Some(timeout), |e| match e.state {
+            TcpState::SynSent => None,
+            TcpState::Closed if e.reset => Some(Err(ECONNREFUSED)),
+            _ => Some(Ok(())),
+        });
+        match outcome {
+            Some(Ok(())) => Ok(TcpStream {
+                handle: self.handle,
+                timeout: None,
+            }),
+            Some(Err(e)) => Err(e.into()),
+            None => Err(ETIMEDOUT.into()),
+        }
+    }
+}
+
+/// A listening TCP socket.
+pub struct TcpListener {
+    handle: Handle,
+}
+
+impl TcpListener {
+    /// Listens on `port`, or on an ephemeral one for 0.
+    pub fn bind(iface: &Arc<Interface>, port: u16) -> Result<Self, String> {
+        let socket = TcpSocket::new(iface);
+        socket.bind(port)?;
+        socket.listen(DEFAULT_BACKLOG)
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.handle.local_addr()
+    }
+
+    /// Waits for a connection and returns it with the address it came from.
+    pub fn accept(&self) -> Result<(TcpStream, SocketAddrV4), String> {
+        let id = self.handle.wait(None, |e| e.pending.pop_front()).ok_or(EAGAIN)?;
+        let handle = Handle {
+            iface: self.handle.iface.clone(),
+            id,
+        };
+        let peer = handle.with(|e| {
+            e.pid = scheduler::current_pid().unwrap_or(e.pid);
+            e.peer.unwrap()
+        });
+        Ok((TcpStream { handle, timeout: None }, peer))
+    }
+}
+
+/// A TCP connection.
+pub struct TcpStream {
+    handle: Handle,
+    timeout: Option<Duration>,
+}
+
+impl TcpStream {
+    /// Connects to `addr` from an ephemeral port.
+    pub fn connect(iface: &Arc<Interface>, addr: SocketAddrV4, timeout: Duration) -> Result<Self, String> {
+        TcpSocket::new(iface).connect(addr, timeout)
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.handle.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> SocketAddrV4 {
+        self.handle.with(|e| e.peer.unwrap())
+    }
+
+    /// How long `recv` waits for data; `None`, the default, waits for good.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn send(&self, data: &[u8]) -> Result<usize, String> {
+        let peer = self.handle.with(|e| match e.state {
+            _ if e.reset => Err(ECONNRESET),
+            TcpState::Established | TcpState::CloseWait => Ok(e.peer.unwrap()),
+            _ => Err(EPIPE),
+        })?;
+        self.handle.send_segment(peer, SegmentKind::Data(data.to_vec()))?;
+        Ok(data.len())
+    }
+
+    /// Reads what has arrived, waiting for something if nothing has. Returns
+    /// 0 once the peer has closed its end and everything was read.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize, String> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let read = self.handle.wait(self.timeout, |e| {
+            if !e.received.is_empty() {
+                let n = buf.len().min(e.received.len());
+                for (slot, byte) in buf.iter_mut().zip(e.received.drain(..n)) {
+                    *slot = byte;
+                }
+                Some(Ok(n))
+            } else if e.reset {
+                Some(Err(ECONNRESET))
+            } else if e.peer_closed {
+                Some(Ok(0))
+            } else {
+                None
+            }
+        });
+        match read {
+            Some(read) => read.map_err(String::from),
+            None => Err(EAGAIN.into()),
+        }
+    }
+
+    /// Reads until the peer closes its end.
+    pub fn recv_to_end(&self) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            match self.recv(&mut buf)? {
+                0 => return Ok(data),
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    /// Tells the peer nothing more will be sent; receiving goes on.
+    pub fn shutdown(&self) -> Result<(), String> {
+        let peer = self.handle.with(|e| {
+            let next = match e.state {
+                _ if e.reset => return Err(ENOTCONN),
+                TcpState::Established => TcpState::FinWait,
+                TcpState::CloseWait => TcpState::Closed,
+                TcpState::FinWait | TcpState::Closed => return Ok(None),
+                _ => return Err(ENOTCONN),
+            };
+            e.state = next;
+            Ok(e.peer)
+        })?;
+        match peer {
+            Some(peer) => self.handle.send_segment(peer, SegmentKind::Fin),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A UDP socket. Datagrams for a port nobody has bound are dropped.
+pub struct UdpSocket {
+    handle: Handle,
+    timeout: Option<Duration>,
+}
+
+impl UdpSocket {
+    /// Binds to `port`, or to an ephemeral one for 0.
+    pub fn bind(iface: &Arc<Interface>, port: u16) -> Result<Self, String> {
+        let handle = Handle::new(iface, Protocol::Udp);
+        handle.bind(port)?;
+        Ok(UdpSocket { handle, timeout: None })
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.handle.local_addr()
+    }
+
+    /// How long `recv_from` waits; `None`, the default, waits for good.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn send_to(&self, data: &[u8], addr: SocketAddrV4) -> Result<usize, String> {
+        let datagram = Datagram {
+            src_port: self.handle.with(|e| e.port),
+            dst_port: addr.port(),
+            data: data.to_vec(),
+        };
+        self.handle.iface.send(*addr.ip(), IpPayload::Udp(datagram))?;
+        Ok(data.len())
+    }
+
+    /// Waits for a datagram and copies as much of it as fits into `buf`;
+    /// the rest is lost.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddrV4), String> {
+        let (from, data) = self.handle.wait(self.timeout, |e| e.datagrams.pop_front()).ok_or(EAGAIN)?;
+        let n = buf.len().min(data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok((n, from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Network;
+    use super::*;
+
+    #[test]
+    fn test_streams_connect_transfer_and_close() {
+        let network = Network::new("10.3.0.0/24".parse().unwrap()).unwrap();
+        let server = network.connect("eth0", network.allocate().unwrap());
+        let client = network.connect("eth0", network.allocate().unwrap());
+        let addr = SocketAddrV4::new(server.addr(), 80);
+        let timeout = Duration::from_secs(1);
+
+        assert_eq!(TcpStream::connect(&client, addr, timeout).err().unwrap(), ECONNREFUSED);
+        let listener = TcpListener::bind(&server, 80).unwrap();
+        assert_eq!(TcpListener::bind(&server, 80).err().unwrap(), EADDRINUSE);
+        // Ports belong to a host: the client's port 80 is still free
+        drop(TcpListener::bind(&client, 80).unwrap());
+
+        let conn = TcpStream::connect(&client, addr, timeout).unwrap();
+        assert!(EPHEMERAL_PORTS.contains(&conn.local_addr().port()));
+        let (accepted, from) = listener.accept().unwrap();
+        assert_eq!(from, conn.local_addr());
+        assert_eq!(accepted.peer_addr(), conn.local_addr());
+
+        conn.send(b"GET /").unwrap();
+        conn.shutdown().unwrap();
+        assert_eq!(conn.send(b"more").unwrap_err(), EPIPE);
+        assert_eq!(accepted.recv_to_end().unwrap(), b"GET /");
+        accepted.send(b"200 OK").unwrap();
+        let states = |iface: &Interface| iface.sockets().iter().map(|s| s.state.unwrap()).collect::<Vec<_>>();
+        assert_eq!(states(&server), [TcpState::Listen, TcpState::CloseWait]);
+        drop(accepted);
+        assert_eq!(conn.recv_to_end().unwrap(), b"200 OK");
+        assert_eq!(states(&client), [TcpState::Closed]);
+        drop(conn);
+        assert!(client.sockets().is_empty());
+
+        // Nothing is listening once the listener is gone
+        drop(listener);
+        assert!(server.sockets().is_empty());
+        assert_eq!(TcpStream::connect(&client, addr, timeout).err().unwrap(), ECONNREFUSED);
+    }
+
+    #[test]
+    fn test_datagrams_and_resets() {
+        let network = Network::new("10.4.0.0/24".parse().unwrap()).unwrap();
+        let a = network.connect("eth0", network.allocate().unwrap());
+        let b = network.connect("eth0", network.allocate().unwrap());
+
+        let mut server = UdpSocket::bind(&b, 53).unwrap();
+        let client = UdpSocket::bind(&a, 0).unwrap();
+        client.send_to(b"query", SocketAddrV4::new(b.addr(), 53)).unwrap();
+        client.send_to(b"lost", SocketAddrV4::new(b.addr(), 54)).unwrap();
+        let mut buf = [0; 16];
+        let (n, from) = server.recv_from(&mut buf).unwrap();
+        assert_eq!((&buf[..n], from), (&b"query"[..], client.local_addr()));
+        server.set_read_timeout(Some(Duration::from_millis(20)));
+        assert_eq!(server.recv_from(&mut buf).unwrap_err(), EAGAIN);
+
+        // A listener that goes away refuses the connections it never accepted
+        let listener = TcpListener::bind(&b, 22).unwrap();
+        let conn = TcpStream::connect(&a, SocketAddrV4::new(b.addr(), 22), Duration::from_secs(1)).unwrap();
+        drop(listener);
+        assert_eq!(conn.recv(&mut buf).unwrap_err(), ECONNRESET);
+        assert_eq!(conn.send(b"x").unwrap_err(), ECONNRESET);
+
+        // Loopback reaches the host's own sockets; a host behind the
+        // gateway never answers
+        let listener = TcpListener::bind(&a, 7).unwrap();
+        let conn = TcpStream::connect(&a, "127.0.0.1:7".parse().unwrap(), Duration::from_secs(1)).unwrap();
+        assert_eq!(listener.accept().unwrap().1, conn.local_addr());
+        let far = "10.5.0.1:7".parse().unwrap();
+        assert_eq!(TcpStream::connect(&a, far, Duration::from_millis(50)).err().unwrap(), ETIMEDOUT);
+    }
+
+    #[test]
+    fn test_data_overtaking_the_syn_ack_completes_the_handshake() {
+        let mut table = SocketTable::default();
+        let id = table.insert(Protocol::Tcp, 0);
+        let port = table.bind(id, 0).unwrap();
+        let peer: SocketAddrV4 = "10.6.0.2:80".parse().unwrap();
+        table.entry(id).peer = Some(peer);
+        table.entry(id).state = TcpState::SynSent;
+
+        assert_eq!(table.tcp_input(peer, port, SegmentKind::Data(b"hi".to_vec())), None);
+        assert_eq!(table.entry(id).state, TcpState::Established);
+        assert_eq!(table.tcp_input(peer, port, SegmentKind::SynAck), None);
+        assert_eq!(table.entry(id).received, b"hi");
+        // Anyone else is turned away
+        let stranger = "10.6.0.3:80".parse().unwrap();
+        assert_eq!(table.tcp_input(stranger, port, SegmentKind::Data(b"x".to_vec())), Some(SegmentKind::Rst));
+    }
+}
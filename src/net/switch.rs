@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use super::{Frame, Interface, MacAddr};
+
+/// A learning bridge. It remembers which port each source address was seen
+/// on and sends frames for a known address there only; broadcasts and
+/// frames for unknown addresses go to every other port.
+#[derive(Default)]
+pub struct Switch {
+    inner: Mutex<Ports>,
+}
+
+#[derive(Default)]
+struct Ports {
+    // Interfaces plugged in, by port number; gone ones are skipped
+    ports: Vec<Weak<Interface>>,
+    // Forwarding database: the port each address was last seen on
+    fdb: HashMap<MacAddr, usize>,
+}
+
+impl Switch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plugs `iface` into a free port and returns the port number.
+    pub(crate) fn connect(&self, iface: &Arc<Interface>) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let free = inner.ports.iter().position(|port| port.strong_count() == 0);
+        match free {
+            Some(port) => {
+                inner.ports[port] = Arc::downgrade(iface);
+                inner.fdb.retain(|_, p| *p != port);
+                port
+            }
+            None => {
+                inner.ports.push(Arc::downgrade(iface));
+                inner.ports.len() - 1
+            }
+        }
+    }
+
+    /// Forwards `frame`, which arrived on port `from`. Delivery happens on
+    /// the caller's thread, with no lock of the switch held, so receivers
+    /// may answer right away.
+    pub(crate) fn forward(&self, from: usize, frame: Frame) {
+        let targets: Vec<Arc<Interface>> = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.fdb.insert(frame.src, from);
+            let known = inner.fdb.get(&frame.dst).copied().filter(|_| frame.dst != MacAddr::BROADCAST);
+            inner
+                .ports
+                .iter()
+                .enumerate()
+                .filter(|(port, _)| *port != from && known.is_none_or(|known| known == *port))
+                .filter_map(|(_, iface)| iface.upgrade())
+                .collect()
+        };
+        for target in targets {
+            target.receive(&frame);
+        }
+    }
+
+    /// Interfaces plugged in.
+    pub fn interfaces(&self) -> Vec<Arc<Interface>> {
+        self.inner.lock().unwrap().ports.iter().filter_map(Weak::upgrade).collect()
+    }
+}
@@ -0,0 +1,81 @@
+//! Yield points for code running inside a scheduled process.
+//!
+//! The scheduler records which process a thread belongs to; these functions
+//! look it up, so jobs and anything they call (the shell, for instance) can
+//! cooperate with the scheduler without being handed a context. Outside a
+//! scheduled process they fall back to plain thread behaviour.
+//!
+//! Yield points are also where signal handlers run.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::process::ProcessState;
+use super::scheduler::Shared;
+use super::signal::SignalHandle;
+
+thread_local! {
+    static CURRENT: RefCell<Option<(Arc<Shared>, u32)>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn enter(shared: Arc<Shared>, pid: u32) {
+    CURRENT.with(|current| *current.borrow_mut() = Some((shared, pid)));
+}
+
+fn current() -> Option<(Arc<Shared>, u32)> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// PID of the calling process, if it runs under a scheduler.
+pub fn current_pid() -> Option<u32> {
+    current().map(|(_, pid)| pid)
+}
+
+/// Signal handle of the calling process.
+pub fn signals() -> Option<SignalHandle> {
+    current().and_then(|(shared, pid)| shared.signals(pid))
+}
+
+/// The calling process's PID if it belongs to `shared`.
+pub(crate) fn current_pid_in(shared: &Arc<Shared>) -> Option<u32> {
+    current().filter(|(s, _)| Arc::ptr_eq(s, shared)).map(|(_, pid)| pid)
+}
+
+/// Lets another ready process run once the time slice is used up.
+pub fn yield_now() {
+    match current() {
+        Some((shared, pid)) => {
+            shared.yield_now(pid);
+            shared.run_handlers(pid);
+        }
+        None => thread::yield_now(),
+    }
+}
+
+/// Sleeps without holding a CPU. A caught signal ends the sleep early.
+pub fn sleep(duration: Duration) {
+    match current() {
+        Some((shared, pid)) => {
+            shared.sleep(pid, duration);
+            shared.run_handlers(pid);
+        }
+        None => thread::sleep(duration),
+    }
+}
+
+/// Runs `f`, which may block (reading input, waiting on a channel), with the
+/// process `Blocked` so its CPU goes to someone else.
+pub fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match current() {
+        Some((shared, pid)) => {
+            shared.suspend(pid, ProcessState::Blocked);
+            let result = f();
+            shared.resume(pid);
+            shared.run_handlers(pid);
+            result
+        }
+        None => f(),
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+// Windows of the three averages, as in /proc/loadavg
+const WINDOWS: [Duration; 3] = [
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(15 * 60),
+];
+
+/// System load as `/proc/loadavg` shows it: how many processes wanted a
+/// CPU on average over the last 1, 5 and 15 minutes, plus a snapshot of
+/// the run queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+    /// Processes running or ready to run right now.
+    pub runnable: usize,
+    /// Processes that have not finished.
+    pub total: usize,
+    pub last_pid: u32,
+}
+
+/// Exponentially decaying averages of the number of runnable processes.
+/// The scheduler samples the count every time it takes its lock, so each
+/// interval is weighted with the count it actually had.
+pub(crate) struct LoadTracker {
+    averages: [f64; 3],
+    sampled: Instant,
+}
+
+impl LoadTracker {
+    pub(crate) fn new() -> Self {
+        LoadTracker {
+            averages: [0.0; 3],
+            sampled: Instant::now(),
+        }
+    }
+
+    /// Accounts the time since the last sample, during which `runnable`
+    /// processes wanted a CPU.
+    pub(crate) fn sample(&mut self, runnable: usize) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.sampled).as_secs_f64();
+        self.sampled = now;
+        for (average, window) in self.averages.iter_mut().zip(WINDOWS) {
+            let decay = (-elapsed / window.as_secs_f64()).exp();
+            *average = *average * decay + runnable as f64 * (1.0 - decay);
+        }
+    }
+
+    pub(crate) fn averages(&self) -> [f64; 3] {
+        self.averages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_averages_follow_the_run_queue() {
+        let mut tracker = LoadTracker::new();
+        tracker.sampled -= Duration::from_secs(60);
+        tracker.sample(2);
+        let [one, five, fifteen] = tracker.averages();
+        // A minute at 2 moves the 1-minute average most of the way there
+        assert!((one - 2.0 * (1.0 - (-1.0f64).exp())).abs() < 1e-3);
+        assert!(one > five && five > fifteen && fifteen > 0.0);
+
+        tracker.sampled -= Duration::from_secs(3600);
+        tracker.sample(0);
+        assert!(tracker.averages().iter().all(|average| *average < 0.05));
+    }
+}
@@ -1,5 +1,16 @@
+pub mod context;
+pub mod load;
+pub mod policy;
 pub mod process;
+#[allow(clippy::module_inception)]
 pub mod scheduler;
+pub mod signal;
+pub mod timer;
 
-pub use process::Process;
-pub use scheduler::Scheduler;
+pub use context::{blocking, current_pid, signals, sleep, yield_now};
+pub use load::LoadAverage;
+pub use policy::{policy_by_name, Descheduled, Runnable, SchedulingPolicy};
+pub use process::{Job, Process, ProcessInfo, ProcessState};
+pub use scheduler::{ProcessHandle, Scheduler, DEFAULT_QUANTUM, PANIC_EXIT_CODE};
+pub use signal::{Disposition, Signal, SignalHandle};
+pub use timer::TimerWheel;
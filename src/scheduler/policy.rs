@@ -0,0 +1,390 @@
+//! Scheduling policies: which ready process gets the next free CPU, and for
+//! how long.
+//!
+//! The scheduler owns the process table and hands a policy only what it
+//! needs: processes becoming ready (`enqueue`), CPU time they used when they
+//! leave a CPU (`account`), and requests for the next one to run
+//! (`pick_next`).
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::scheduler::DEFAULT_QUANTUM;
+
+/// What a policy knows about a ready process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Runnable {
+    pub pid: u32,
+    pub priority: u32,
+    pub deadline: Option<Instant>,
+}
+
+/// Why a process left its CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Descheduled {
+    /// Its time slice ran out at a yield point.
+    Preempted,
+    /// It blocked or went to sleep.
+    Blocked,
+    Exited,
+}
+
+pub trait SchedulingPolicy: Send {
+    fn name(&self) -> &'static str;
+    /// `process` is ready to run.
+    fn enqueue(&mut self, process: Runnable);
+    /// Removes and returns the process that should run next.
+    fn pick_next(&mut self) -> Option<u32>;
+    /// Drops a queued process (e.g. one that was killed while ready).
+    fn remove(&mut self, pid: u32);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// How long `pid` may run before a yield point preempts it.
+    fn time_slice(&self, _pid: u32) -> Duration {
+        DEFAULT_QUANTUM
+    }
+    /// `pid` used `ran` of CPU time before leaving the CPU.
+    fn account(&mut self, _pid: u32, _ran: Duration, _why: Descheduled) {}
+}
+
+/// Builds a policy from its name: `priority`, `rr`, `mlfq`, `fair` or `edf`.
+pub fn policy_by_name(name: &str) -> Option<Box<dyn SchedulingPolicy>> {
+    match name {
+        "priority" => Some(Box::new(PriorityPolicy::default())),
+        "rr" | "round-robin" => Some(Box::new(RoundRobin::default())),
+        "mlfq" => Some(Box::new(Mlfq::default())),
+        "fair" | "cfs" => Some(Box::new(FairShare::default())),
+        "edf" => Some(Box::new(EarliestDeadlineFirst::default())),
+        _ => None,
+    }
+}
+
+/// Highest `priority` first, first come first served among equals.
+#[derive(Default)]
+pub struct PriorityPolicy {
+    queue: BinaryHeap<(u32, Reverse<u64>, u32)>,
+    order: u64,
+}
+
+impl SchedulingPolicy for PriorityPolicy {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn enqueue(&mut self, process: Runnable) {
+        self.queue.push((process.priority, Reverse(self.order), process.pid));
+        self.order += 1;
+    }
+
+    fn pick_next(&mut self) -> Option<u32> {
+        self.queue.pop().map(|(_, _, pid)| pid)
+    }
+
+    fn remove(&mut self, pid: u32) {
+        self.queue.retain(|(_, _, p)| *p != pid);
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// Plain FIFO; priorities are ignored.
+#[derive(Default)]
+pub struct RoundRobin {
+    queue: VecDeque<u32>,
+}
+
+impl SchedulingPolicy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "rr"
+    }
+
+    fn enqueue(&mut self, process: Runnable) {
+        self.queue.push_back(process.pid);
+    }
+
+    fn pick_next(&mut self) -> Option<u32> {
+        self.queue.pop_front()
+    }
+
+    fn remove(&mut self, pid: u32) {
+        self.queue.retain(|p| *p != pid);
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+pub const MLFQ_LEVELS: usize = 3;
+/// Every process goes back to the top level this often, so CPU-bound
+/// processes at the bottom are not starved forever.
+pub const MLFQ_BOOST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Multilevel feedback queue. New processes start at the top level; using a
+/// whole slice moves a process one level down, where slices are twice as
+/// long. Higher levels always run first.
+pub struct Mlfq {
+    queues: [VecDeque<u32>; MLFQ_LEVELS],
+    levels: HashMap<u32, usize>,
+    last_boost: Instant,
+}
+
+impl Default for Mlfq {
+    fn default() -> Self {
+        Mlfq {
+            queues: Default::default(),
+            levels: HashMap::new(),
+            last_boost: Instant::now(),
+        }
+    }
+}
+
+impl Mlfq {
+    pub fn level(&self, pid: u32) -> usize {
+        self.levels.get(&pid).copied().unwrap_or(0)
+    }
+
+    fn boost(&mut self) {
+        for level in 1..MLFQ_LEVELS {
+            let moved: Vec<u32> = self.queues[level].drain(..).collect();
+            self.queues[0].extend(moved);
+        }
+        self.levels.values_mut().for_each(|level| *level = 0);
+        self.last_boost = Instant::now();
+    }
+}
+
+impl SchedulingPolicy for Mlfq {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+
+    fn enqueue(&mut self, process: Runnable) {
+        let level = *self.levels.entry(process.pid).or_insert(0);
+        self.queues[level].push_back(process.pid);
+    }
+
+    fn pick_next(&mut self) -> Option<u32> {
+        if self.last_boost.elapsed() >= MLFQ_BOOST_INTERVAL {
+            self.boost();
+        }
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+
+    fn remove(&mut self, pid: u32) {
+        for queue in &mut self.queues {
+            queue.retain(|p| *p != pid);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    fn time_slice(&self, pid: u32) -> Duration {
+        DEFAULT_QUANTUM * (1 << self.level(pid))
+    }
+
+    fn account(&mut self, pid: u32, _ran: Duration, why: Descheduled) {
+        match why {
+            Descheduled::Preempted => {
+                let level = self.levels.entry(pid).or_insert(0);
+                *level = (*level + 1).min(MLFQ_LEVELS - 1);
+            }
+            Descheduled::Blocked => {}
+            Descheduled::Exited => {
+                self.levels.remove(&pid);
+            }
+        }
+    }
+}
+
+/// CFS-like fair share: runs the process with the least weighted CPU time
+/// (`vruntime`). A process's weight is its priority (at least 1), so a
+/// priority 2 process gets twice the CPU of a priority 1 one.
+#[derive(Default)]
+pub struct FairShare {
+    queue: BTreeSet<(u128, u64, u32)>,
+    vruntime: HashMap<u32, u128>,
+    weights: HashMap<u32, u32>,
+    min_vruntime: u128,
+    order: u64,
+}
+
+impl FairShare {
+    pub fn vruntime(&self, pid: u32) -> u128 {
+        self.vruntime.get(&pid).copied().unwrap_or(0)
+    }
+}
+
+impl SchedulingPolicy for FairShare {
+    fn name(&self) -> &'static str {
+        "fair"
+    }
+
+    fn enqueue(&mut self, process: Runnable) {
+        // Newcomers and sleepers start level with the others instead of
+        // catching up on time they did not want
+        let vruntime = self.vruntime(process.pid).max(self.min_vruntime);
+        self.vruntime.insert(process.pid, vruntime);
+        self.weights.insert(process.pid, process.priority.max(1));
+        self.queue.insert((vruntime, self.order, process.pid));
+        self.order += 1;
+    }
+
+    fn pick_next(&mut self) -> Option<u32> {
+        let entry = self.queue.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(entry.0);
+        Some(entry.2)
+    }
+
+    fn remove(&mut self, pid: u32) {
+        self.queue.retain(|(_, _, p)| *p != pid);
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn account(&mut self, pid: u32, ran: Duration, why: Descheduled) {
+        if why == Descheduled::Exited {
+            self.vruntime.remove(&pid);
+            self.weights.remove(&pid);
+            return;
+        }
+        let weight = self.weights.get(&pid).copied().unwrap_or(1) as u128;
+        let vruntime = self.vruntime.entry(pid).or_insert(0);
+        *vruntime += ran.as_nanos() / weight;
+        // A process running alone must not leave newcomers far behind
+        let least_queued = self.queue.first().map_or(*vruntime, |entry| entry.0);
+        self.min_vruntime = self.min_vruntime.max((*vruntime).min(least_queued));
+    }
+}
+
+/// Earliest deadline first. Processes without a deadline run only when no
+/// process with one is ready, by priority.
+#[derive(Default)]
+pub struct EarliestDeadlineFirst {
+    deadlines: BTreeSet<(Instant, u64, u32)>,
+    rest: PriorityPolicy,
+    order: u64,
+}
+
+impl SchedulingPolicy for EarliestDeadlineFirst {
+    fn name(&self) -> &'static str {
+        "edf"
+    }
+
+    fn enqueue(&mut self, process: Runnable) {
+        match process.deadline {
+            Some(deadline) => {
+                self.deadlines.insert((deadline, self.order, process.pid));
+                self.order += 1;
+            }
+            None => self.rest.enqueue(process),
+        }
+    }
+
+    fn pick_next(&mut self) -> Option<u32> {
+        match self.deadlines.pop_first() {
+            Some((_, _, pid)) => Some(pid),
+            None => self.rest.pick_next(),
+        }
+    }
+
+    fn remove(&mut self, pid: u32) {
+        self.deadlines.retain(|(_, _, p)| *p != pid);
+        self.rest.remove(pid);
+    }
+
+    fn len(&self) -> usize {
+        self.deadlines.len() + self.rest.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runnable(pid: u32, priority: u32) -> Runnable {
+        Runnable {
+            pid,
+            priority,
+            deadline: None,
+        }
+    }
+
+    fn drain(policy: &mut dyn SchedulingPolicy) -> Vec<u32> {
+        std::iter::from_fn(|| policy.pick_next()).collect()
+    }
+
+    #[test]
+    fn test_priority_and_round_robin_order() {
+        let mut priority = PriorityPolicy::default();
+        let mut rr = RoundRobin::default();
+        for (pid, prio) in [(1, 1), (2, 5), (3, 1), (4, 5)] {
+            priority.enqueue(runnable(pid, prio));
+            rr.enqueue(runnable(pid, prio));
+        }
+        assert_eq!(drain(&mut priority), vec![2, 4, 1, 3]);
+        assert_eq!(drain(&mut rr), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_mlfq_demotes_cpu_hogs() {
+        let mut mlfq = Mlfq::default();
+        mlfq.enqueue(runnable(1, 1));
+        assert_eq!(mlfq.pick_next(), Some(1));
+        mlfq.account(1, DEFAULT_QUANTUM, Descheduled::Preempted);
+        assert_eq!(mlfq.level(1), 1);
+        assert_eq!(mlfq.time_slice(1), DEFAULT_QUANTUM * 2);
+
+        // An interactive newcomer runs before the demoted process
+        mlfq.enqueue(runnable(1, 1));
+        mlfq.enqueue(runnable(2, 1));
+        mlfq.account(2, Duration::from_millis(1), Descheduled::Blocked);
+        assert_eq!(drain(&mut mlfq), vec![2, 1]);
+
+        mlfq.last_boost -= MLFQ_BOOST_INTERVAL;
+        mlfq.enqueue(runnable(1, 1));
+        assert_eq!(mlfq.pick_next(), Some(1));
+        assert_eq!(mlfq.level(1), 0);
+    }
+
+    #[test]
+    fn test_fair_share_weights_cpu_time() {
+        let mut fair = FairShare::default();
+        fair.enqueue(runnable(1, 1));
+        fair.enqueue(runnable(2, 2));
+        let mut used = HashMap::from([(1, 0), (2, 0)]);
+        for _ in 0..30 {
+            let pid = fair.pick_next().unwrap();
+            *used.get_mut(&pid).unwrap() += 1;
+            fair.account(pid, DEFAULT_QUANTUM, Descheduled::Preempted);
+            fair.enqueue(runnable(pid, if pid == 1 { 1 } else { 2 }));
+        }
+        assert_eq!(used[&1], 10);
+        assert_eq!(used[&2], 20);
+    }
+
+    #[test]
+    fn test_edf_runs_earliest_deadline_first() {
+        let now = Instant::now();
+        let mut edf = EarliestDeadlineFirst::default();
+        edf.enqueue(runnable(1, 9));
+        for (pid, ms) in [(2, 30), (3, 10), (4, 20)] {
+            edf.enqueue(Runnable {
+                pid,
+                priority: 1,
+                deadline: Some(now + Duration::from_millis(ms)),
+            });
+        }
+        edf.remove(4);
+        assert_eq!(drain(&mut edf), vec![3, 2, 1]);
+    }
+}
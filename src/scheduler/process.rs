@@ -1,23 +1,118 @@
-use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::tty::{self, Tty};
+
+/// Where a process is in its life.
+///
+/// `Ready` processes wait for a CPU, `Running` ones hold one. `Blocked` and
+/// `Sleeping` processes gave their CPU up until an event or a deadline;
+/// `Stopped` ones were paused by `SIGSTOP` until `SIGCONT`. A finished
+/// process stays a `Zombie` until its parent reaps it with
+/// `Scheduler::wait`, then it is `Terminated`: gone from the process list,
+/// it is only seen through its handles. Processes nobody can reap skip
+/// straight to `Terminated`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessState {
+    Ready,
+    Running,
+    Blocked,
+    Sleeping,
+    Stopped,
+    Zombie,
+    Terminated,
+}
+
+impl ProcessState {
+    /// True until the job has finished.
+    pub fn is_alive(self) -> bool {
+        !matches!(self, ProcessState::Zombie | ProcessState::Terminated)
+    }
+
+    /// One-letter state, as `ps` and `/proc/<pid>/stat` show it.
+    pub fn code(self) -> char {
+        match self {
+            ProcessState::Ready | ProcessState::Running => 'R',
+            ProcessState::Sleeping | ProcessState::Blocked => 'S',
+            ProcessState::Stopped => 'T',
+            ProcessState::Zombie => 'Z',
+            ProcessState::Terminated => 'X',
+        }
+    }
+}
+
+impl fmt::Display for ProcessState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ProcessState::Ready => "ready",
+            ProcessState::Running => "running",
+            ProcessState::Blocked => "blocked",
+            ProcessState::Sleeping => "sleeping",
+            ProcessState::Stopped => "stopped",
+            ProcessState::Zombie => "zombie",
+            ProcessState::Terminated => "terminated",
+        };
+        f.pad(name)
+    }
+}
 
 #[derive(Clone, serde::Serialize)]
 pub struct ProcessInfo {
     pub id: u32,
     pub priority: u32,
-    pub status: String, // e.g., "running", "pending", "completed"
+    pub status: ProcessState,
     pub parent_pid: Option<u32>,
-    pub memory_usage: usize, // in bytes
+    pub memory_usage: usize, // resident bytes
+    /// Set once the job returns; 101 if it panicked, 128 + the signal number
+    /// if a signal killed it.
+    pub exit_code: Option<i32>,
+    /// Command name; `task` for processes started without a command line.
+    pub name: String,
+    pub argv: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub cwd: String,
+    /// UID of the user the process runs as.
+    pub uid: u32,
+    /// Set when the process is added to a scheduler.
+    pub start_time: SystemTime,
+    /// Time spent holding a CPU.
+    pub cpu_time: Duration,
+    /// Name of the terminal the process prints to.
+    pub tty: Option<String>,
 }
 
+impl ProcessInfo {
+    /// The command line as `ps` shows it: the arguments separated by
+    /// spaces, or the name in brackets when there are none.
+    pub fn command_line(&self) -> String {
+        if self.argv.is_empty() {
+            format!("[{}]", self.name)
+        } else {
+            self.argv.join(" ")
+        }
+    }
+}
+
+/// A job returns its exit code.
+pub type Job = Box<dyn FnOnce() -> i32 + Send + 'static>;
+
 pub struct Process {
     pub id: u32,
     pub priority: u32,
     pub timer: Instant,
-    pub job: Box<dyn FnOnce() + Send + 'static>,
+    /// When the job should be done by; only the EDF policy looks at it.
+    pub deadline: Option<Instant>,
+    pub job: Job,
     pub info: ProcessInfo,
+    /// Where the process prints; inherited from the process creating it.
+    pub tty: Option<Arc<Tty>>,
 }
 
 impl Process {
+    /// A process whose job always exits with 0.
     pub fn new(
         id: u32,
         priority: u32,
@@ -25,21 +120,87 @@ impl Process {
         job: Box<dyn FnOnce() + Send + 'static>,
         parent_pid: Option<u32>,
     ) -> Self {
+        Self::with_exit_code(
+            id,
+            priority,
+            delay,
+            Box::new(move || {
+                job();
+                0
+            }),
+            parent_pid,
+        )
+    }
+
+    pub fn with_exit_code(id: u32, priority: u32, delay: Duration, job: Job, parent_pid: Option<u32>) -> Self {
         let info = ProcessInfo {
             id,
             priority,
-            status: "ready".to_string(),
+            status: ProcessState::Ready,
             parent_pid,
-            memory_usage: 0, // placeholder
+            memory_usage: 0,
+            exit_code: None,
+            name: "task".to_string(),
+            argv: Vec::new(),
+            env: BTreeMap::new(),
+            cwd: "/".to_string(),
+            uid: 0,
+            start_time: SystemTime::now(),
+            cpu_time: Duration::ZERO,
+            tty: None,
         };
-        Process {
+        let process = Process {
             id,
             priority,
             timer: Instant::now() + delay,
+            deadline: None,
             job,
             info,
+            tty: None,
+        };
+        match tty::current() {
+            Some(tty) => process.with_tty(tty),
+            None => process,
         }
     }
+
+    /// Names the process after `argv[0]` and records its arguments.
+    pub fn with_command(mut self, argv: &[&str]) -> Self {
+        if let Some(name) = argv.first() {
+            self.info.name = name.rsplit('/').next().unwrap_or(name).to_string();
+        }
+        self.info.argv = argv.iter().map(|arg| arg.to_string()).collect();
+        self
+    }
+
+    pub fn with_env(mut self, env: BTreeMap<String, String>) -> Self {
+        self.info.env = env;
+        self
+    }
+
+    pub fn with_cwd(mut self, cwd: &str) -> Self {
+        self.info.cwd = cwd.to_string();
+        self
+    }
+
+    /// Runs the process as `uid`.
+    pub fn with_owner(mut self, uid: u32) -> Self {
+        self.info.uid = uid;
+        self
+    }
+
+    /// Runs the process on `tty`.
+    pub fn with_tty(mut self, tty: Arc<Tty>) -> Self {
+        self.info.tty = Some(tty.name().to_string());
+        self.tty = Some(tty);
+        self
+    }
+
+    /// Sets a deadline `within` from now.
+    pub fn with_deadline(mut self, within: Duration) -> Self {
+        self.deadline = Some(Instant::now() + within);
+        self
+    }
 }
 
 impl Eq for Process {}
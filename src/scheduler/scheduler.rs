@@ -1,72 +1,747 @@
-use super::process::{Process, ProcessInfo};
-use std::collections::BinaryHeap;
-use std::sync::Mutex;
-use std::thread;
-use std::time::Instant;
+use super::context;
+use super::load::{LoadAverage, LoadTracker};
+use crate::cgroup::Cgroups;
+use crate::ipc::Ipc;
+use crate::mm::MemoryManager;
+use crate::tty;
+use super::policy::{Descheduled, PriorityPolicy, Runnable, SchedulingPolicy};
+use super::process::{Job, Process, ProcessInfo, ProcessState};
+use super::signal::{Action, Signal, SignalHandle};
+use super::timer::TimerWheel;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
 
+/// How long a process may keep a CPU before a yield point hands it over.
+pub const DEFAULT_QUANTUM: Duration = Duration::from_millis(10);
+
+/// Exit code recorded for a job that panicked.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// Runs processes on a fixed number of simulated CPUs.
+///
+/// Every process gets its own thread, but only `cpus` of them hold a CPU
+/// (are `Running`) at a time; the rest wait parked. Jobs give their CPU up
+/// at the yield points in `scheduler::context` (`yield_now`, `sleep`,
+/// `blocking`) or by finishing, and a yield point after the time slice ran
+/// out puts the process back in the ready queue. The `SchedulingPolicy`
+/// decides who runs next and how long a slice is; sleeping and delayed
+/// processes wait on a timer wheel.
+///
+/// A scheduler is a small kernel: it also owns the IPC namespace its
+/// processes share, the memory manager they allocate from and the
+/// resource groups that limit them.
 pub struct Scheduler {
-    processes: Mutex<BinaryHeap<Process>>,
-    all_processes: Mutex<Vec<ProcessInfo>>,
-    next_pid: Mutex<u32>,
+    shared: Arc<Shared>,
+    ipc: Ipc,
+    memory: MemoryManager,
+    cgroups: Cgroups,
+    started: Instant,
+}
+
+struct Task {
+    info: ProcessInfo,
+    job: Option<Job>,
+    deadline: Option<Instant>,
+    wake_at: Option<Instant>,
+    slice_start: Instant,
+    signals: SignalHandle,
+    // Set by SIGSTOP: the process goes `Stopped` instead of `Ready`
+    stopped: bool,
+    // What its handles still see once the task is removed
+    last: Arc<OnceLock<ProcessInfo>>,
+}
+
+// Unwinds the thread of a process a signal killed
+struct Killed;
+
+struct State {
+    tasks: BTreeMap<u32, Task>,
+    policy: Box<dyn SchedulingPolicy>,
+    timers: TimerWheel<u32>,
+    running: usize,
+    next_pid: u32,
+    threads: Vec<JoinHandle<()>>,
+    // Frees the memory of processes as they end
+    memory: MemoryManager,
+    cgroups: Cgroups,
+    load: LoadTracker,
+}
+
+pub(crate) struct Shared {
+    state: Mutex<State>,
+    // Signalled on every state change
+    changed: Condvar,
+    cpus: usize,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scheduler {
+    /// One simulated CPU per host CPU.
     pub fn new() -> Self {
-        Scheduler {
-            processes: Mutex::new(BinaryHeap::new()),
-            all_processes: Mutex::new(Vec::new()),
-            next_pid: Mutex::new(1),
-        }
+        Self::with_cpus(thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+    }
+
+    /// `cpus` simulated CPUs, highest priority first.
+    pub fn with_cpus(cpus: usize) -> Self {
+        Self::with_policy(cpus, Box::new(PriorityPolicy::default()))
+    }
+
+    pub fn with_policy(cpus: usize, policy: Box<dyn SchedulingPolicy>) -> Self {
+        let memory = MemoryManager::default();
+        let cgroups = Cgroups::default();
+        let scheduler = Scheduler {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    tasks: BTreeMap::new(),
+                    policy,
+                    timers: TimerWheel::default(),
+                    running: 0,
+                    next_pid: 1,
+                    threads: Vec::new(),
+                    memory: memory.clone(),
+                    cgroups: cgroups.clone(),
+                    load: LoadTracker::new(),
+                }),
+                changed: Condvar::new(),
+                cpus: cpus.max(1),
+            }),
+            ipc: Ipc::default(),
+            memory,
+            cgroups,
+            started: Instant::now(),
+        };
+        scheduler.connect_memory();
+        scheduler
+    }
+
+    /// Replaces the default memory manager, e.g. with one that has swap.
+    pub fn with_memory(mut self, memory: MemoryManager) -> Self {
+        self.shared.lock().memory = memory.clone();
+        self.memory = memory;
+        self.connect_memory();
+        self
+    }
+
+    // Lets the memory manager kill processes and see their groups
+    fn connect_memory(&self) {
+        self.memory.set_cgroups(self.cgroups.clone());
+        let shared: Weak<Shared> = Arc::downgrade(&self.shared);
+        self.memory.set_oom_killer(Arc::new(move |pid| {
+            if let Some(shared) = shared.upgrade() {
+                let _ = shared.kill(pid, Signal::Kill);
+            }
+        }));
+    }
+
+    /// PID of the calling process, if it runs under this scheduler.
+    pub fn current_pid(&self) -> Option<u32> {
+        context::current_pid_in(&self.shared)
+    }
+
+    pub fn cpus(&self) -> usize {
+        self.shared.cpus
     }
 
-    pub fn add_process(&self, mut process: Process) {
-        let mut next_pid = self.next_pid.lock().unwrap();
-        process.id = *next_pid;
-        let info = process.info.clone();
-        self.all_processes.lock().unwrap().push(info);
-        *next_pid += 1;
-        self.processes.lock().unwrap().push(process);
+    pub fn ipc(&self) -> &Ipc {
+        &self.ipc
     }
 
+    pub fn memory(&self) -> &MemoryManager {
+        &self.memory
+    }
+
+    pub fn cgroups(&self) -> &Cgroups {
+        &self.cgroups
+    }
+
+    pub fn policy_name(&self) -> &'static str {
+        self.shared.lock().policy.name()
+    }
+
+    /// Starts `process` like `try_add_process` and returns a handle to it,
+    /// which knows the PID the process was given.
+    pub fn spawn(&self, process: Process) -> Result<ProcessHandle, String> {
+        self.insert(process)
+    }
+
+    /// A handle to the process `pid`, if there is one.
+    pub fn handle(&self, pid: u32) -> Option<ProcessHandle> {
+        self.shared.lock().tasks.get(&pid).map(|task| ProcessHandle {
+            shared: self.shared.clone(),
+            pid,
+            last: task.last.clone(),
+        })
+    }
+
+    /// Queues `process` under a fresh PID, which it returns. Panics if its
+    /// resource group is full; see `try_add_process`.
+    pub fn add_process(&self, process: Process) -> u32 {
+        self.try_add_process(process).expect("failed to add process")
+    }
+
+    /// Queues `process` in its parent's resource group (the root group
+    /// without a parent) and returns its PID. Fails with `Resource
+    /// temporarily unavailable`, as `fork` does, when that would take the
+    /// group past `pids.max`.
+    pub fn try_add_process(&self, process: Process) -> Result<u32, String> {
+        self.insert(process).map(|handle| handle.pid)
+    }
+
+    fn insert(&self, mut process: Process) -> Result<ProcessHandle, String> {
+        let mut st = self.shared.lock();
+        process.id = st.next_pid;
+        st.cgroups.admit(process.id, process.info.parent_pid)?;
+        st.next_pid += 1;
+        process.info.id = process.id;
+        process.info.start_time = SystemTime::now();
+
+        let now = Instant::now();
+        let delayed = process.timer > now;
+        process.info.status = if delayed {
+            ProcessState::Sleeping
+        } else {
+            ProcessState::Ready
+        };
+        let last = Arc::new(OnceLock::new());
+        st.tasks.insert(
+            process.id,
+            Task {
+                info: process.info,
+                job: Some(match process.tty {
+                    Some(tty) => {
+                        let job = process.job;
+                        Box::new(move || {
+                            tty::set_current(Some(tty));
+                            job()
+                        })
+                    }
+                    None => process.job,
+                }),
+                deadline: process.deadline,
+                wake_at: delayed.then_some(process.timer),
+                slice_start: now,
+                signals: SignalHandle::default(),
+                stopped: false,
+                last: last.clone(),
+            },
+        );
+        if delayed {
+            st.timers.insert(process.timer, process.id);
+        } else {
+            st.enqueue(process.id);
+        }
+        self.shared.changed.notify_all();
+        Ok(ProcessHandle {
+            shared: self.shared.clone(),
+            pid: process.id,
+            last,
+        })
+    }
+
+    /// Dispatches processes until none is left alive, then waits for their
+    /// threads.
     pub fn run(&self) {
-        let mut handles = vec![];
-        while let Some(process) = self.processes.lock().unwrap().pop() {
-            if process.timer <= Instant::now() {
-                // Update status in all_processes
-                if let Some(info) = self
-                    .all_processes
-                    .lock()
-                    .unwrap()
-                    .iter_mut()
-                    .find(|i| i.id == process.id)
-                {
-                    info.status = "running".to_string();
-                }
-                let handle = thread::spawn(move || {
-                    (process.job)();
-                    // Note: status update not possible here since process is moved
-                });
-                handles.push(handle);
-            } else {
-                // Put back
-                self.processes.lock().unwrap().push(process);
+        let mut st = self.shared.lock();
+        loop {
+            for (deadline, pid) in st.timers.expire(Instant::now()) {
+                // Skip timers of processes that were woken some other way
+                let task = match st.tasks.get_mut(&pid) {
+                    Some(task) if task.info.status == ProcessState::Sleeping && task.wake_at == Some(deadline) => task,
+                    _ => continue,
+                };
+                task.wake_at = None;
+                st.enqueue(pid);
             }
+
+            if !st.tasks.values().any(|t| t.info.status.is_alive()) {
+                break;
+            }
+
+            while st.running < self.shared.cpus {
+                let pid = match st.policy.pick_next() {
+                    Some(pid) => pid,
+                    None => break,
+                };
+                self.dispatch(&mut st, pid);
+            }
+
+            st = match st.timers.next_deadline() {
+                Some(wake) => {
+                    let timeout = wake.saturating_duration_since(Instant::now());
+                    self.shared.changed.wait_timeout(st, timeout).unwrap().0
+                }
+                None => self.shared.changed.wait(st).unwrap(),
+            };
         }
-        // Wait for all
-        for handle in handles {
-            handle.join().unwrap();
+        let threads = std::mem::take(&mut st.threads);
+        drop(st);
+        for handle in threads {
+            let _ = handle.join();
+        }
+    }
+
+    fn dispatch(&self, st: &mut State, pid: u32) {
+        let task = match st.tasks.get_mut(&pid) {
+            Some(task) if task.info.status == ProcessState::Ready => task,
+            _ => return,
+        };
+        task.info.status = ProcessState::Running;
+        task.slice_start = Instant::now();
+        let job = task.job.take();
+        st.running += 1;
+
+        match job {
+            Some(job) => {
+                let shared = self.shared.clone();
+                let handle = thread::Builder::new()
+                    .name(format!("pid-{}", pid))
+                    .spawn(move || {
+                        context::enter(shared.clone(), pid);
+                        let code = panic::catch_unwind(AssertUnwindSafe(job)).unwrap_or(PANIC_EXIT_CODE);
+                        shared.exit(pid, code);
+                    })
+                    .expect("failed to spawn process thread");
+                // Threads that are done need no joining
+                st.threads.retain(|thread| !thread.is_finished());
+                st.threads.push(handle);
+            }
+            // A parked thread is waiting for its CPU back
+            None => self.shared.changed.notify_all(),
         }
     }
 
     pub fn list_processes(&self) -> Vec<ProcessInfo> {
-        self.all_processes.lock().unwrap().clone()
+        let mut processes: Vec<ProcessInfo> = {
+            let st = self.shared.lock();
+            st.tasks
+                .values()
+                .map(|t| {
+                    let mut info = t.info.clone();
+                    // Include the slice a running process is in
+                    if info.status == ProcessState::Running {
+                        info.cpu_time += t.slice_start.elapsed();
+                    }
+                    info
+                })
+                .collect()
+        };
+        for process in &mut processes {
+            process.memory_usage = self.memory.usage(process.id).resident_bytes;
+        }
+        processes
+    }
+
+    /// Time since the scheduler was created.
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn load_average(&self) -> LoadAverage {
+        let st = self.shared.lock();
+        let [one, five, fifteen] = st.load.averages();
+        LoadAverage {
+            one,
+            five,
+            fifteen,
+            runnable: st.runnable(),
+            total: st.tasks.values().filter(|t| t.info.status.is_alive()).count(),
+            last_pid: st.next_pid - 1,
+        }
+    }
+
+    /// The signal handle of a live process.
+    pub fn signals(&self, pid: u32) -> Option<SignalHandle> {
+        self.shared.signals(pid)
+    }
+
+    /// Sends `signal` to `pid`. A terminated process ends at once: its
+    /// thread unwinds at its next yield point.
+    pub fn kill(&self, pid: u32, signal: Signal) -> Result<(), String> {
+        self.shared.kill(pid, signal)
+    }
+
+    /// Waits until a child of `parent` (`pid`, or any child) has exited,
+    /// reaps it and returns its PID and exit code. Called from the parent's
+    /// own job, the parent is `Blocked` meanwhile.
+    pub fn wait(&self, parent: u32, pid: Option<u32>) -> Result<(u32, i32), String> {
+        let in_parent = context::current_pid_in(&self.shared) == Some(parent);
+        if in_parent {
+            self.shared.suspend(parent, ProcessState::Blocked);
+        }
+        let mut st = self.shared.lock();
+        let result = loop {
+            match st.reap(parent, pid) {
+                Ok(Some(reaped)) => break Ok(reaped),
+                Ok(None) if !st.tasks.get(&parent).is_some_and(|p| p.info.status.is_alive()) => {
+                    break Err("Interrupted system call".into())
+                }
+                Ok(None) => st = self.shared.changed.wait(st).unwrap(),
+                Err(e) => break Err(e),
+            }
+        };
+        self.shared.changed.notify_all();
+        drop(st);
+        if in_parent {
+            self.shared.resume(parent);
+        }
+        result
+    }
+
+    /// Like `wait`, but returns `None` at once if no child has exited yet.
+    pub fn try_wait(&self, parent: u32, pid: Option<u32>) -> Result<Option<(u32, i32)>, String> {
+        let result = self.shared.lock().reap(parent, pid);
+        self.shared.changed.notify_all();
+        result
+    }
+}
+
+/// A process started by `Scheduler::spawn`: its PID, its state, and ways
+/// to wait for it or end it. Clones refer to the same process.
+#[derive(Clone)]
+pub struct ProcessHandle {
+    shared: Arc<Shared>,
+    pid: u32,
+    last: Arc<OnceLock<ProcessInfo>>,
+}
+
+impl ProcessHandle {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn info(&self) -> ProcessInfo {
+        self.with_info(&self.shared.lock(), ProcessInfo::clone)
+    }
+
+    pub fn status(&self) -> ProcessState {
+        self.with_info(&self.shared.lock(), |info| info.status)
+    }
+
+    // The task while it is there, then what it left behind
+    fn with_info<T>(&self, st: &State, f: impl FnOnce(&ProcessInfo) -> T) -> T {
+        match st.tasks.get(&self.pid) {
+            Some(task) => f(&task.info),
+            None => f(self.last.get().expect("a removed task leaves its info")),
+        }
+    }
+
+    /// True until the process has finished.
+    pub fn is_alive(&self) -> bool {
+        self.status().is_alive()
+    }
+
+    pub fn kill(&self, signal: Signal) -> Result<(), String> {
+        self.shared.kill(self.pid, signal)
+    }
+
+    /// Waits until the process has finished and returns its exit code. It
+    /// stays a zombie until its parent reaps it.
+    pub fn join(&self) -> i32 {
+        loop {
+            if let Some(code) = self.join_timeout(Duration::from_secs(1)) {
+                return code;
+            }
+        }
+    }
+
+    /// Like `join`, but gives up after `timeout`.
+    pub fn join_timeout(&self, timeout: Duration) -> Option<i32> {
+        let deadline = Instant::now() + timeout;
+        // A process of a scheduler gives its CPU up while it waits
+        context::blocking(|| {
+            let mut st = self.shared.lock();
+            loop {
+                let (status, exit_code) = self.with_info(&st, |info| (info.status, info.exit_code));
+                if !status.is_alive() {
+                    return exit_code;
+                }
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return None;
+                }
+                st = self.shared.changed.wait_timeout(st, left).unwrap().0;
+            }
+        })
+    }
+
+    /// Kills the process and returns its exit code once it has ended.
+    pub fn stop(&self) -> i32 {
+        let _ = self.kill(Signal::Kill);
+        self.join()
+    }
+}
+
+impl State {
+    // Processes holding a CPU or waiting in the ready queue
+    fn runnable(&self) -> usize {
+        self.running + self.policy.len()
+    }
+
+    fn enqueue(&mut self, pid: u32) {
+        let task = self.tasks.get_mut(&pid).unwrap();
+        if task.stopped {
+            task.info.status = ProcessState::Stopped;
+            return;
+        }
+        task.info.status = ProcessState::Ready;
+        self.policy.enqueue(Runnable {
+            pid,
+            priority: task.info.priority,
+            deadline: task.deadline,
+        });
+    }
+
+    /// Takes the CPU away from `pid` and charges the policy for the slice.
+    fn deschedule(&mut self, pid: u32, why: Descheduled) {
+        self.running -= 1;
+        let ran = self.tasks[&pid].slice_start.elapsed();
+        self.policy.account(pid, ran, why);
+        self.charge_cpu(pid, ran);
+    }
+
+    fn charge_cpu(&mut self, pid: u32, ran: Duration) {
+        self.tasks.get_mut(&pid).unwrap().info.cpu_time += ran;
+        self.cgroups.charge_cpu(pid, ran);
+    }
+
+    /// The policy's time slice, scaled by the weights of `pid`'s groups.
+    fn time_slice(&self, pid: u32) -> Duration {
+        self.policy.time_slice(pid).mul_f64(self.cgroups.cpu_factor(pid))
+    }
+
+    /// Reaps an exited child of `parent`; `None` while the children it
+    /// could reap are still alive.
+    fn reap(&mut self, parent: u32, pid: Option<u32>) -> Result<Option<(u32, i32)>, String> {
+        let mut children = self
+            .tasks
+            .values()
+            .filter(|t| t.info.parent_pid == Some(parent) && pid.is_none_or(|pid| pid == t.info.id))
+            .peekable();
+        if children.peek().is_none() {
+            return Err("No child processes".into());
+        }
+        let zombie = children.find(|t| t.info.status == ProcessState::Zombie).map(|t| t.info.id);
+        Ok(zombie.map(|zombie| (zombie, self.remove(zombie).exit_code.unwrap_or(0))))
+    }
+
+    /// Records the exit of `pid`, which no longer holds a CPU.
+    fn finish(&mut self, pid: u32, code: i32) {
+        let parent = self.tasks[&pid].info.parent_pid;
+        let reapable = parent
+            .and_then(|parent| self.tasks.get(&parent))
+            .is_some_and(|p| p.info.status.is_alive());
+        self.memory.release(pid);
+        self.cgroups.exit(pid);
+        let task = self.tasks.get_mut(&pid).unwrap();
+        task.info.exit_code = Some(code);
+        task.info.status = ProcessState::Zombie;
+        if !reapable {
+            self.remove(pid);
+        }
+        // Nobody is left to reap this process's zombie children
+        let orphans: Vec<u32> = self
+            .tasks
+            .values()
+            .filter(|t| t.info.parent_pid == Some(pid) && t.info.status == ProcessState::Zombie)
+            .map(|t| t.info.id)
+            .collect();
+        for orphan in orphans {
+            self.remove(orphan);
+        }
+    }
+
+    /// Drops the task of a finished process; its handles keep what it was
+    /// last, `Terminated`.
+    fn remove(&mut self, pid: u32) -> ProcessInfo {
+        let mut task = self.tasks.remove(&pid).unwrap();
+        task.info.status = ProcessState::Terminated;
+        let _ = task.last.set(task.info.clone());
+        task.info
+    }
+
+    /// Ends `pid`, whatever it was doing.
+    fn terminate(&mut self, pid: u32, code: i32) {
+        if self.tasks[&pid].info.status == ProcessState::Running {
+            self.deschedule(pid, Descheduled::Exited);
+        } else {
+            self.policy.remove(pid);
+            self.policy.account(pid, Duration::ZERO, Descheduled::Exited);
+            self.timers.cancel(pid);
+        }
+        self.tasks.get_mut(&pid).unwrap().job = None;
+        self.finish(pid, code);
+    }
+
+    fn stop(&mut self, pid: u32) {
+        let status = self.tasks[&pid].info.status;
+        match status {
+            ProcessState::Running => self.deschedule(pid, Descheduled::Blocked),
+            ProcessState::Ready => self.policy.remove(pid),
+            // Blocked and sleeping processes stop once they could run again
+            _ => {}
+        }
+        let task = self.tasks.get_mut(&pid).unwrap();
+        task.stopped = true;
+        if matches!(status, ProcessState::Running | ProcessState::Ready) {
+            task.info.status = ProcessState::Stopped;
+        }
+    }
+
+    fn cont(&mut self, pid: u32) {
+        let task = self.tasks.get_mut(&pid).unwrap();
+        task.stopped = false;
+        if task.info.status == ProcessState::Stopped {
+            self.enqueue(pid);
+        }
+    }
+
+    /// A caught signal cuts a sleep short so the process can handle it.
+    fn interrupt_sleep(&mut self, pid: u32) {
+        let task = self.tasks.get_mut(&pid).unwrap();
+        if task.info.status == ProcessState::Sleeping {
+            task.wake_at = None;
+            self.timers.cancel(pid);
+            self.enqueue(pid);
+        }
+    }
+}
+
+impl Shared {
+    // Every critical section starts by accounting the load since the last
+    fn lock(&self) -> MutexGuard<'_, State> {
+        let mut st = self.state.lock().unwrap();
+        let runnable = st.runnable();
+        st.load.sample(runnable);
+        st
+    }
+
+    /// Parks the calling process until it holds a CPU; unwinds its thread
+    /// if it was killed.
+    fn wait_for_cpu<'a>(&'a self, mut st: MutexGuard<'a, State>, pid: u32) -> MutexGuard<'a, State> {
+        loop {
+            // A removed task has terminated
+            match st.tasks.get(&pid).map_or(ProcessState::Terminated, |t| t.info.status) {
+                ProcessState::Running => return st,
+                status if !status.is_alive() => {
+                    drop(st);
+                    panic::resume_unwind(Box::new(Killed));
+                }
+                _ => st = self.changed.wait(st).unwrap(),
+            }
+        }
+    }
+
+    pub(crate) fn signals(&self, pid: u32) -> Option<SignalHandle> {
+        let st = self.lock();
+        st.tasks.get(&pid).filter(|t| t.info.status.is_alive()).map(|t| t.signals.clone())
+    }
+
+    /// Runs the handlers of the signals `pid` received, on its own thread.
+    pub(crate) fn run_handlers(&self, pid: u32) {
+        if let Some(signals) = self.signals(pid) {
+            signals.run_handlers();
+        }
+    }
+
+    /// Gives the CPU up if the time slice ran out and someone else is ready.
+    pub(crate) fn yield_now(&self, pid: u32) {
+        // A stopped process parks here until it is continued
+        let mut st = self.wait_for_cpu(self.lock(), pid);
+        let ran = st.tasks[&pid].slice_start.elapsed();
+        if ran < st.time_slice(pid) {
+            return;
+        }
+        if st.policy.is_empty() {
+            // Nobody to hand over to: start a fresh slice
+            st.policy.account(pid, ran, Descheduled::Preempted);
+            st.charge_cpu(pid, ran);
+            st.tasks.get_mut(&pid).unwrap().slice_start = Instant::now();
+            return;
+        }
+        st.deschedule(pid, Descheduled::Preempted);
+        st.enqueue(pid);
+        self.changed.notify_all();
+        drop(self.wait_for_cpu(st, pid));
+    }
+
+    pub(crate) fn sleep(&self, pid: u32, duration: Duration) {
+        let mut st = self.wait_for_cpu(self.lock(), pid);
+        st.deschedule(pid, Descheduled::Blocked);
+        let wake_at = Instant::now() + duration;
+        let task = st.tasks.get_mut(&pid).unwrap();
+        task.info.status = ProcessState::Sleeping;
+        task.wake_at = Some(wake_at);
+        st.timers.insert(wake_at, pid);
+        self.changed.notify_all();
+        drop(self.wait_for_cpu(st, pid));
+    }
+
+    /// Releases the CPU while the process waits outside the scheduler.
+    pub(crate) fn suspend(&self, pid: u32, state: ProcessState) {
+        let mut st = self.wait_for_cpu(self.lock(), pid);
+        st.deschedule(pid, Descheduled::Blocked);
+        st.tasks.get_mut(&pid).unwrap().info.status = state;
+        self.changed.notify_all();
+    }
+
+    /// Queues a suspended process again and waits until it gets a CPU.
+    pub(crate) fn resume(&self, pid: u32) {
+        let mut st = self.lock();
+        if !st.tasks.get(&pid).is_some_and(|t| t.info.status.is_alive()) {
+            drop(st);
+            panic::resume_unwind(Box::new(Killed));
+        }
+        st.enqueue(pid);
+        self.changed.notify_all();
+        drop(self.wait_for_cpu(st, pid));
+    }
+
+    fn kill(&self, pid: u32, signal: Signal) -> Result<(), String> {
+        let mut st = self.lock();
+        let task = match st.tasks.get(&pid) {
+            Some(task) if task.info.status.is_alive() => task,
+            _ => return Err("No such process".into()),
+        };
+        match task.signals.deliver(signal) {
+            Action::Terminate => st.terminate(pid, signal.exit_code()),
+            Action::Stop => st.stop(pid),
+            Action::Continue => st.cont(pid),
+            Action::Queued => st.interrupt_sleep(pid),
+            Action::Ignored => {}
+        }
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    fn exit(&self, pid: u32, code: i32) {
+        let mut st = self.lock();
+        // A killed process was already accounted for
+        if !st.tasks.get(&pid).is_some_and(|t| t.info.status.is_alive()) {
+            return;
+        }
+        st.terminate(pid, code);
+        self.changed.notify_all();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
+    use crate::scheduler::context;
+    use crate::scheduler::Signal;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn status(scheduler: &Scheduler, pid: u32) -> ProcessInfo {
+        scheduler.list_processes().into_iter().find(|p| p.id == pid).unwrap()
+    }
 
     #[test]
     fn test_add_process() {
@@ -76,5 +751,305 @@ mod tests {
         let processes = scheduler.list_processes();
         assert_eq!(processes.len(), 1);
         assert_eq!(processes[0].priority, 1);
+        assert_eq!(processes[0].status, ProcessState::Sleeping);
+    }
+
+    #[test]
+    fn test_exit_codes_are_recorded() {
+        let scheduler = Scheduler::with_cpus(2);
+        let three = scheduler.spawn(Process::with_exit_code(0, 1, Duration::ZERO, Box::new(|| 3), None)).unwrap();
+        let boom = scheduler
+            .spawn(Process::new(0, 1, Duration::from_millis(20), Box::new(|| panic!("boom")), None))
+            .unwrap();
+        scheduler.run();
+        // Without a parent to reap them, finished processes are terminated
+        // and gone; only their handles remember them
+        assert!(scheduler.list_processes().is_empty());
+        assert!(scheduler.handle(1).is_none());
+        assert_eq!(three.status(), ProcessState::Terminated);
+        assert_eq!(three.info().exit_code, Some(3));
+        assert_eq!(boom.info().exit_code, Some(PANIC_EXIT_CODE));
+        assert_eq!(boom.join(), PANIC_EXIT_CODE);
+    }
+
+    #[test]
+    fn test_parent_waits_for_children() {
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let inner = scheduler.clone();
+        let parent = Process::with_exit_code(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                let me = context::current_pid().unwrap();
+                for code in [4, 5] {
+                    inner.add_process(Process::with_exit_code(0, 1, Duration::ZERO, Box::new(move || code), Some(me)));
+                }
+                let total = inner.wait(me, None).unwrap().1 + inner.wait(me, None).unwrap().1;
+                assert_eq!(inner.wait(me, None), Err("No child processes".into()));
+                total
+            }),
+            None,
+        );
+        let parent = scheduler.spawn(parent).unwrap();
+        scheduler.run();
+        assert_eq!(parent.info().exit_code, Some(9));
+        // Reaped children are gone
+        assert!(scheduler.list_processes().is_empty());
+    }
+
+    #[test]
+    fn test_unreaped_child_is_a_zombie() {
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let inner = scheduler.clone();
+        scheduler.add_process(Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                let me = context::current_pid().unwrap();
+                inner.add_process(Process::new(0, 9, Duration::ZERO, Box::new(|| {}), Some(me)));
+                // The child has the higher priority: sleeping lets it run
+                context::sleep(Duration::from_millis(20));
+                assert_eq!(status(&inner, 2).status, ProcessState::Zombie);
+            }),
+            None,
+        ));
+        scheduler.run();
+        // Its parent exited without waiting, so it was reaped for it
+        assert!(scheduler.list_processes().is_empty());
+    }
+
+    #[test]
+    fn test_time_slices_interleave_jobs() {
+        let scheduler = Scheduler::with_cpus(1);
+        let turns = Arc::new(Mutex::new(Vec::new()));
+        for name in [1, 2] {
+            let turns = turns.clone();
+            scheduler.add_process(Process::new(
+                0,
+                1,
+                Duration::ZERO,
+                Box::new(move || {
+                    let deadline = Instant::now() + Duration::from_millis(60);
+                    while Instant::now() < deadline {
+                        let mut turns = turns.lock().unwrap();
+                        if turns.last() != Some(&name) {
+                            turns.push(name);
+                        }
+                        drop(turns);
+                        context::yield_now();
+                    }
+                }),
+                None,
+            ));
+        }
+        scheduler.run();
+        // With one CPU, the jobs took turns instead of running side by side
+        assert!(turns.lock().unwrap().len() > 2);
+    }
+
+    #[test]
+    fn test_blocking_releases_the_cpu() {
+        let scheduler = Scheduler::with_cpus(1);
+        let ran = Arc::new(AtomicU32::new(0));
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let seen = ran.clone();
+        let waiter = scheduler.spawn(Process::new(
+            0,
+            5,
+            Duration::ZERO,
+            Box::new(move || {
+                // Waits for the other process while holding no CPU
+                context::blocking(|| rx.recv().unwrap());
+                assert_eq!(seen.load(Ordering::SeqCst), 1);
+            }),
+            None,
+        ))
+        .unwrap();
+        let counter = ran.clone();
+        scheduler.add_process(Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tx.send(()).unwrap();
+            }),
+            None,
+        ));
+        scheduler.run();
+        assert_eq!(waiter.info().exit_code, Some(0));
+    }
+
+    #[test]
+    fn test_stop_continue_and_kill() {
+        let scheduler = Arc::new(Scheduler::with_cpus(2));
+        let spins = Arc::new(AtomicU32::new(0));
+        let counter = spins.clone();
+        let spinner = scheduler
+            .spawn(Process::new(
+                0,
+                1,
+                Duration::ZERO,
+                Box::new(move || {
+                    context::signals().unwrap().ignore(Signal::Term).unwrap();
+                    loop {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        context::yield_now();
+                    }
+                }),
+                None,
+            ))
+            .unwrap();
+        let inner = scheduler.clone();
+        let killer = scheduler.spawn(Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                let pause = || context::sleep(Duration::from_millis(20));
+                pause();
+                inner.kill(1, Signal::Stop).unwrap();
+                assert_eq!(status(&inner, 1).status, ProcessState::Stopped);
+                // Let the spinner reach its next yield point
+                pause();
+                let frozen = spins.load(Ordering::SeqCst);
+                pause();
+                assert_eq!(spins.load(Ordering::SeqCst), frozen);
+
+                inner.kill(1, Signal::Cont).unwrap();
+                pause();
+                assert!(spins.load(Ordering::SeqCst) > frozen);
+
+                inner.kill(1, Signal::Term).unwrap();
+                assert!(status(&inner, 1).status.is_alive());
+                inner.kill(1, Signal::Kill).unwrap();
+                assert_eq!(inner.kill(1, Signal::Kill), Err("No such process".into()));
+            }),
+            None,
+        ))
+        .unwrap();
+        scheduler.run();
+        assert_eq!(spinner.info().exit_code, Some(Signal::Kill.exit_code()));
+        assert_eq!(killer.info().exit_code, Some(0));
+    }
+
+    #[test]
+    fn test_caught_signals_cut_sleep_short() {
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let handled = Arc::new(AtomicU32::new(0));
+        let seen = handled.clone();
+        let sleeper = scheduler.spawn(Process::with_exit_code(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                let signals = context::signals().unwrap();
+                signals
+                    .on(Signal::Usr1, move |_| {
+                        seen.fetch_add(1, Ordering::SeqCst);
+                    })
+                    .unwrap();
+                signals.catch(Signal::Int).unwrap();
+                let start = Instant::now();
+                while signals.poll() != Some(Signal::Int) {
+                    context::sleep(Duration::from_secs(5));
+                }
+                assert!(start.elapsed() < Duration::from_secs(5));
+                7
+            }),
+            None,
+        ))
+        .unwrap();
+        let inner = scheduler.clone();
+        scheduler.add_process(Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                context::sleep(Duration::from_millis(10));
+                inner.kill(1, Signal::Usr1).unwrap();
+                context::sleep(Duration::from_millis(10));
+                inner.kill(1, Signal::Int).unwrap();
+            }),
+            None,
+        ));
+        scheduler.run();
+        assert_eq!(sleeper.info().exit_code, Some(7));
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_policy_decides_the_order() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let add = |scheduler: &Scheduler, priority: u32, deadline_ms: Option<u64>| {
+            let order = order.clone();
+            let mut process = Process::new(
+                0,
+                priority,
+                Duration::ZERO,
+                Box::new(move || order.lock().unwrap().push(context::current_pid().unwrap())),
+                None,
+            );
+            if let Some(ms) = deadline_ms {
+                process = process.with_deadline(Duration::from_millis(ms));
+            }
+            scheduler.add_process(process);
+        };
+
+        let edf = Scheduler::with_policy(1, crate::scheduler::policy_by_name("edf").unwrap());
+        assert_eq!(edf.policy_name(), "edf");
+        add(&edf, 9, None);
+        add(&edf, 1, Some(500));
+        add(&edf, 1, Some(100));
+        edf.run();
+        assert_eq!(std::mem::take(&mut *order.lock().unwrap()), vec![3, 2, 1]);
+
+        let rr = Scheduler::with_policy(1, crate::scheduler::policy_by_name("rr").unwrap());
+        add(&rr, 1, None);
+        add(&rr, 9, None);
+        rr.run();
+        assert_eq!(*order.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_delayed_processes_do_not_spin() {
+        let scheduler = Scheduler::with_cpus(1);
+        let start = Instant::now();
+        scheduler.add_process(Process::new(0, 1, Duration::from_millis(50), Box::new(|| {}), None));
+        scheduler.add_process(Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(|| context::sleep(Duration::from_millis(30))),
+            None,
+        ));
+        scheduler.run();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(scheduler.shared.lock().timers.is_empty());
+    }
+
+    #[test]
+    fn test_spawn_returns_a_handle_with_the_real_pid() {
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        scheduler.add_process(Process::new(0, 1, Duration::ZERO, Box::new(|| {}), None));
+        let quick = scheduler
+            .spawn(Process::with_exit_code(0, 1, Duration::ZERO, Box::new(|| 7), None))
+            .unwrap();
+        let forever = scheduler
+            .spawn(Process::new(0, 1, Duration::ZERO, Box::new(|| loop { context::sleep(Duration::from_millis(5)) }), None))
+            .unwrap();
+        assert_eq!((quick.pid(), forever.pid()), (2, 3));
+        assert_eq!(quick.status(), ProcessState::Ready);
+
+        let runner = scheduler.clone();
+        let dispatcher = thread::spawn(move || runner.run());
+        assert_eq!(quick.join(), 7);
+        assert_eq!(forever.join_timeout(Duration::from_millis(30)), None);
+        assert!(forever.is_alive());
+        assert_eq!(forever.stop(), Signal::Kill.exit_code());
+        assert_eq!(forever.info().exit_code, Some(Signal::Kill.exit_code()));
+        dispatcher.join().unwrap();
     }
 }
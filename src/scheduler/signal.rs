@@ -0,0 +1,298 @@
+//! Signals sent to scheduled processes.
+//!
+//! Every process has a `SignalHandle`. `SIGKILL` and `SIGSTOP` are always
+//! acted on by the scheduler; for the others the process picks a
+//! disposition: the default action, ignoring them, catching them to `poll`
+//! later, or a handler. Handlers run on the process's own thread, at its
+//! next yield point or `poll`.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Signal {
+    Int,
+    Kill,
+    Usr1,
+    Usr2,
+    Term,
+    Cont,
+    Stop,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 7] = [
+        Signal::Int,
+        Signal::Kill,
+        Signal::Usr1,
+        Signal::Usr2,
+        Signal::Term,
+        Signal::Cont,
+        Signal::Stop,
+    ];
+
+    /// Linux signal number.
+    pub fn number(self) -> i32 {
+        match self {
+            Signal::Int => 2,
+            Signal::Kill => 9,
+            Signal::Usr1 => 10,
+            Signal::Usr2 => 12,
+            Signal::Term => 15,
+            Signal::Cont => 18,
+            Signal::Stop => 19,
+        }
+    }
+
+    pub fn from_number(number: i32) -> Option<Signal> {
+        Signal::ALL.into_iter().find(|s| s.number() == number)
+    }
+
+    /// Name without the `SIG` prefix.
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Int => "INT",
+            Signal::Kill => "KILL",
+            Signal::Usr1 => "USR1",
+            Signal::Usr2 => "USR2",
+            Signal::Term => "TERM",
+            Signal::Cont => "CONT",
+            Signal::Stop => "STOP",
+        }
+    }
+
+    /// `SIGKILL` and `SIGSTOP` cannot be caught, ignored or handled.
+    pub fn is_catchable(self) -> bool {
+        !matches!(self, Signal::Kill | Signal::Stop)
+    }
+
+    /// What a shell prints for a job the signal terminated.
+    pub fn description(self) -> &'static str {
+        match self {
+            Signal::Int => "Interrupt",
+            Signal::Kill => "Killed",
+            Signal::Usr1 => "User defined signal 1",
+            Signal::Usr2 => "User defined signal 2",
+            Signal::Term => "Terminated",
+            Signal::Cont => "Continued",
+            Signal::Stop => "Stopped",
+        }
+    }
+
+    /// The signal that terminated a process with exit code `code`.
+    pub fn from_exit_code(code: i32) -> Option<Signal> {
+        code.checked_sub(128).and_then(Signal::from_number)
+    }
+
+    /// Exit code of a process the signal terminated, as a shell reports it.
+    pub fn exit_code(self) -> i32 {
+        128 + self.number()
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SIG{}", self.name())
+    }
+}
+
+impl FromStr for Signal {
+    type Err = String;
+
+    /// Accepts `TERM`, `SIGTERM`, `term` or `15`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(number) = s.parse::<i32>() {
+            return Signal::from_number(number).ok_or_else(|| format!("{}: invalid signal specification", s));
+        }
+        let upper = s.to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+        Signal::ALL
+            .into_iter()
+            .find(|sig| sig.name() == name)
+            .ok_or_else(|| format!("{}: invalid signal specification", s))
+    }
+}
+
+pub type SignalHandler = Arc<dyn Fn(Signal) + Send + Sync>;
+
+/// What a process does with a catchable signal.
+#[derive(Clone, Default)]
+pub enum Disposition {
+    /// Terminate, except `SIGCONT`, which just continues the process.
+    #[default]
+    Default,
+    Ignore,
+    /// Keep it pending until the process polls for it.
+    Catch,
+    Handler(SignalHandler),
+}
+
+/// What the scheduler must do with a process a signal was delivered to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Action {
+    Terminate,
+    Stop,
+    Continue,
+    /// The process caught it and should get a chance to look at it.
+    Queued,
+    Ignored,
+}
+
+#[derive(Default)]
+struct Signals {
+    dispositions: HashMap<Signal, Disposition>,
+    pending: VecDeque<Signal>,
+}
+
+/// A process's signal state, shared between the scheduler and the job.
+#[derive(Clone, Default)]
+pub struct SignalHandle {
+    inner: Arc<Mutex<Signals>>,
+}
+
+impl SignalHandle {
+    fn set(&self, signal: Signal, disposition: Disposition) -> Result<(), String> {
+        if !signal.is_catchable() {
+            return Err(format!("{} cannot be caught or ignored", signal));
+        }
+        self.inner.lock().unwrap().dispositions.insert(signal, disposition);
+        Ok(())
+    }
+
+    /// Runs `handler` on the process's thread whenever `signal` arrives.
+    pub fn on(&self, signal: Signal, handler: impl Fn(Signal) + Send + Sync + 'static) -> Result<(), String> {
+        self.set(signal, Disposition::Handler(Arc::new(handler)))
+    }
+
+    /// Keeps `signal` pending for `poll` instead of acting on it.
+    pub fn catch(&self, signal: Signal) -> Result<(), String> {
+        self.set(signal, Disposition::Catch)
+    }
+
+    pub fn ignore(&self, signal: Signal) -> Result<(), String> {
+        self.set(signal, Disposition::Ignore)
+    }
+
+    /// Goes back to the default action.
+    pub fn reset(&self, signal: Signal) {
+        self.inner.lock().unwrap().dispositions.remove(&signal);
+    }
+
+    pub fn disposition(&self, signal: Signal) -> Disposition {
+        let signals = self.inner.lock().unwrap();
+        signals.dispositions.get(&signal).cloned().unwrap_or_default()
+    }
+
+    pub fn pending(&self) -> Vec<Signal> {
+        self.inner.lock().unwrap().pending.iter().copied().collect()
+    }
+
+    pub fn is_pending(&self, signal: Signal) -> bool {
+        self.inner.lock().unwrap().pending.contains(&signal)
+    }
+
+    /// Runs the handlers of pending signals, then returns the oldest caught
+    /// signal still pending.
+    pub fn poll(&self) -> Option<Signal> {
+        self.run_handlers();
+        self.inner.lock().unwrap().pending.pop_front()
+    }
+
+    /// Runs the handlers of pending signals that have one.
+    pub fn run_handlers(&self) {
+        loop {
+            // Handlers may send signals themselves: never call one locked
+            let next = {
+                let mut signals = self.inner.lock().unwrap();
+                let found = signals.pending.iter().enumerate().find_map(|(i, sig)| {
+                    match signals.dispositions.get(sig) {
+                        Some(Disposition::Handler(handler)) => Some((i, *sig, handler.clone())),
+                        _ => None,
+                    }
+                });
+                if let Some((i, _, _)) = found {
+                    signals.pending.remove(i);
+                }
+                found
+            };
+            match next {
+                Some((_, signal, handler)) => handler(signal),
+                None => break,
+            }
+        }
+    }
+
+    pub(crate) fn deliver(&self, signal: Signal) -> Action {
+        let mut signals = self.inner.lock().unwrap();
+        let disposition = signals.dispositions.get(&signal).cloned().unwrap_or_default();
+        let caught = matches!(disposition, Disposition::Catch | Disposition::Handler(_));
+        match signal {
+            Signal::Kill => Action::Terminate,
+            Signal::Stop => Action::Stop,
+            Signal::Cont => {
+                if caught {
+                    signals.pending.push_back(signal);
+                }
+                Action::Continue
+            }
+            _ if caught => {
+                signals.pending.push_back(signal);
+                Action::Queued
+            }
+            _ => match disposition {
+                Disposition::Ignore => Action::Ignored,
+                _ => Action::Terminate,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_parse_signals() {
+        assert_eq!("TERM".parse(), Ok(Signal::Term));
+        assert_eq!("sigkill".parse(), Ok(Signal::Kill));
+        assert_eq!("10".parse(), Ok(Signal::Usr1));
+        assert_eq!("FOO".parse::<Signal>(), Err("FOO: invalid signal specification".into()));
+        assert_eq!(Signal::Int.to_string(), "SIGINT");
+        assert_eq!(Signal::Term.exit_code(), 143);
+    }
+
+    #[test]
+    fn test_dispositions() {
+        let handle = SignalHandle::default();
+        assert_eq!(handle.deliver(Signal::Term), Action::Terminate);
+        assert!(handle.ignore(Signal::Kill).is_err());
+
+        handle.ignore(Signal::Term).unwrap();
+        assert_eq!(handle.deliver(Signal::Term), Action::Ignored);
+
+        let seen = Arc::new(AtomicU32::new(0));
+        let counter = seen.clone();
+        handle
+            .on(Signal::Usr1, move |sig| {
+                counter.fetch_add(sig.number() as u32, Ordering::SeqCst);
+            })
+            .unwrap();
+        handle.catch(Signal::Int).unwrap();
+        assert_eq!(handle.deliver(Signal::Int), Action::Queued);
+        assert_eq!(handle.deliver(Signal::Usr1), Action::Queued);
+        assert_eq!(handle.pending(), vec![Signal::Int, Signal::Usr1]);
+
+        // Polling runs the handler and hands out the caught signal
+        assert_eq!(handle.poll(), Some(Signal::Int));
+        assert_eq!(seen.load(Ordering::SeqCst), 10);
+        assert_eq!(handle.poll(), None);
+
+        handle.reset(Signal::Term);
+        assert_eq!(handle.deliver(Signal::Term), Action::Terminate);
+        assert_eq!(handle.deliver(Signal::Stop), Action::Stop);
+        assert_eq!(handle.deliver(Signal::Cont), Action::Continue);
+    }
+}
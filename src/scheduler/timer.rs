@@ -0,0 +1,140 @@
+//! Hashed timer wheel for processes waiting on a deadline.
+//!
+//! Deadlines are rounded down to `tick`s and hashed into `slots` by tick;
+//! a slot holds the timers of every rotation that lands on it, so expiring
+//! only looks at the slots the clock moved past and leaves the others alone.
+
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TICK: Duration = Duration::from_millis(1);
+pub const DEFAULT_SLOTS: usize = 256;
+
+pub struct TimerWheel<T> {
+    slots: Vec<Vec<(Instant, T)>>,
+    tick: Duration,
+    origin: Instant,
+    // Every timer of a tick before this one has expired
+    cursor: u64,
+    len: usize,
+}
+
+impl<T: Copy + PartialEq> Default for TimerWheel<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK, DEFAULT_SLOTS)
+    }
+}
+
+impl<T: Copy + PartialEq> TimerWheel<T> {
+    pub fn new(tick: Duration, slots: usize) -> Self {
+        TimerWheel {
+            slots: (0..slots.max(1)).map(|_| Vec::new()).collect(),
+            tick: tick.max(Duration::from_nanos(1)),
+            origin: Instant::now(),
+            cursor: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn tick_of(&self, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.origin).as_nanos() / self.tick.as_nanos()) as u64
+    }
+
+    fn slot(&self, tick: u64) -> usize {
+        (tick % self.slots.len() as u64) as usize
+    }
+
+    /// Fires `item` once `deadline` has passed.
+    pub fn insert(&mut self, deadline: Instant, item: T) {
+        let tick = self.tick_of(deadline).max(self.cursor);
+        let slot = self.slot(tick);
+        self.slots[slot].push((deadline, item));
+        self.len += 1;
+    }
+
+    /// Drops every timer of `item`.
+    pub fn cancel(&mut self, item: T) {
+        for slot in &mut self.slots {
+            slot.retain(|(_, i)| *i != item);
+        }
+        self.len = self.slots.iter().map(|s| s.len()).sum();
+    }
+
+    /// Removes and returns the timers due at `now`, earliest first.
+    pub fn expire(&mut self, now: Instant) -> Vec<(Instant, T)> {
+        let end = self.tick_of(now);
+        let mut due = Vec::new();
+        if self.len > 0 && end >= self.cursor {
+            // After a full rotation every slot has been visited
+            let ticks = (end - self.cursor + 1).min(self.slots.len() as u64);
+            for tick in self.cursor..self.cursor + ticks {
+                let slot = self.slot(tick);
+                let (fired, kept) = std::mem::take(&mut self.slots[slot])
+                    .into_iter()
+                    .partition(|(deadline, _)| *deadline <= now);
+                self.slots[slot] = kept;
+                due.extend::<Vec<_>>(fired);
+            }
+        }
+        // Timers of the current tick that are not due yet stay put
+        self.cursor = self.cursor.max(end);
+        self.len -= due.len();
+        due.sort_by_key(|(deadline, _)| *deadline);
+        due
+    }
+
+    /// The earliest pending deadline.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.len == 0 {
+            return None;
+        }
+        // The first slot with a timer from this rotation holds the answer
+        let rotation = self.slots.len() as u64;
+        for tick in self.cursor..self.cursor + rotation {
+            let earliest = self.slots[self.slot(tick)]
+                .iter()
+                .filter(|(deadline, _)| self.tick_of(*deadline).max(self.cursor) == tick)
+                .map(|(deadline, _)| *deadline)
+                .min();
+            if earliest.is_some() {
+                return earliest;
+            }
+        }
+        self.slots.iter().flatten().map(|(deadline, _)| *deadline).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timers_expire_in_order() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        // 3 and 11 share a slot, a rotation apart
+        for (ms, pid) in [(11, 4), (3, 1), (5, 2), (30, 5), (3, 3)] {
+            wheel.insert(at(ms), pid);
+        }
+        wheel.cancel(5);
+        assert_eq!(wheel.len(), 4);
+        assert_eq!(wheel.next_deadline(), Some(at(3)));
+
+        assert!(wheel.expire(at(2)).is_empty());
+        let due: Vec<u32> = wheel.expire(at(6)).into_iter().map(|(_, pid)| pid).collect();
+        assert_eq!(due, vec![1, 3, 2]);
+        assert_eq!(wheel.next_deadline(), Some(at(11)));
+
+        assert_eq!(wheel.expire(at(100)), vec![(at(11), 4)]);
+        assert!(wheel.is_empty());
+        assert_eq!(wheel.next_deadline(), None);
+    }
+}
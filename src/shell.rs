@@ -1,17 +1,61 @@
-use crate::scheduler::Scheduler;
-use crate::vfs::{Disk, FileSystem};
+use crate::ipc::PipeReader;
+use crate::net::Interface;
+use crate::scheduler::{self, Job, Process, Scheduler, Signal};
+use crate::vfs::{Credentials, Disk, FileSystem, UserDb};
 use crate::vps::manager::VpsManager;
+use std::collections::BTreeMap;
 use std::io::{self, Write};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// The shell and its commands print to the terminal of the process running
+// them (see `crate::tty`): these shadow the std macros in this module and
+// the command modules below.
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::tty::write_fmt(format_args!($($arg)*))
+    };
+}
+
+macro_rules! println {
+    () => {
+        print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::tty::write_fmt(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
 
 mod commands;
 
+// The job Ctrl-C goes to: set while a shell waits for a foreground job
+static FOREGROUND: Mutex<Option<(Arc<Scheduler>, u32)>> = Mutex::new(None);
+
+/// Sends `SIGINT` to the foreground job of the terminal. Returns false if
+/// no shell is waiting for one.
+pub fn interrupt_foreground() -> bool {
+    match FOREGROUND.lock().unwrap().as_ref() {
+        Some((scheduler, pid)) => scheduler.kill(*pid, Signal::Int).is_ok(),
+        None => false,
+    }
+}
+
 pub struct Shell {
     fs: FileSystem,
     disk: Arc<dyn Disk + Send + Sync>,
     scheduler: Arc<Scheduler>,
     vps_manager: Arc<Mutex<VpsManager>>,
     cwd: String,
+    // Identities saved by `su`, restored by `exit`
+    user_stack: Vec<Credentials>,
+    // Background jobs not reaped yet: PID and command line
+    jobs: Vec<(u32, String)>,
+    // Where commands come from; the terminal if unset
+    input: Option<PipeReader>,
+    // Print a prompt before reading each command
+    show_prompt: bool,
+    // Network interface `ping` and `ip` use
+    interface: Option<Arc<Interface>>,
 }
 
 impl Shell {
@@ -27,26 +71,94 @@ impl Shell {
             scheduler,
             vps_manager,
             cwd: "/".to_string(),
+            user_stack: Vec::new(),
+            jobs: Vec::new(),
+            input: None,
+            show_prompt: true,
+            interface: None,
         }
     }
 
+    /// Reads commands from `input` instead of the terminal. Such a shell
+    /// prints no banner or prompt, and ends when the pipe's writers are
+    /// gone.
+    pub fn with_input(mut self, input: PipeReader) -> Self {
+        self.input = Some(input);
+        self.show_prompt = false;
+        self
+    }
+
+    /// Prints the prompt even though commands come from a pipe, for a
+    /// shell someone types into through a terminal.
+    pub fn with_prompt(mut self) -> Self {
+        self.show_prompt = true;
+        self
+    }
+
+    /// Connects the shell to the network through `interface`.
+    pub fn with_interface(mut self, interface: Arc<Interface>) -> Self {
+        self.interface = Some(interface);
+        self
+    }
+
     pub fn run(&mut self) {
-        println!("VBOX Shell - Type 'help' for commands, 'exit' to quit.");
+        let interactive = self.input.is_none();
+        if interactive {
+            println!("VBOX Shell - Type 'help' for commands, 'exit' to quit.");
+            // Like an interactive shell, survive Ctrl-C and a plain `kill`
+            if let Some(signals) = scheduler::signals() {
+                let _ = signals.ignore(Signal::Int);
+                let _ = signals.ignore(Signal::Term);
+            }
+        }
         loop {
-            print!("{}> ", self.cwd);
-            io::stdout().flush().unwrap();
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            let input = input.trim();
-            if input.is_empty() {
-                continue;
+            self.reap_jobs();
+            if self.show_prompt {
+                print!("{}:{}> ", self.username(), self.cwd);
+                io::stdout().flush().unwrap();
+            }
+            let exit = match self.read_line() {
+                // End of input
+                None => true,
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => self.execute(line.trim()),
+            };
+            if let Err(e) = self.fs.sync() {
+                println!("Warning: could not sync filesystem to disk: {}", e);
             }
-            if self.execute(input) {
+            if exit {
+                if let Err(e) = self.disk.flush() {
+                    println!("Warning: could not flush disk: {}", e);
+                }
                 break;
             }
         }
     }
 
+    fn read_line(&self) -> Option<String> {
+        let mut line = String::new();
+        match &self.input {
+            None => match scheduler::blocking(|| io::stdin().read_line(&mut line)) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            },
+            Some(input) => {
+                let mut byte = [0u8];
+                let mut bytes = Vec::new();
+                loop {
+                    match input.read(&mut byte) {
+                        Ok(1) if byte[0] == b'\n' => break,
+                        Ok(1) => bytes.push(byte[0]),
+                        _ if bytes.is_empty() => return None,
+                        _ => break,
+                    }
+                }
+                line = String::from_utf8_lossy(&bytes).into_owned();
+            }
+        }
+        Some(line)
+    }
+
     fn execute(&mut self, command: &str) -> bool {
         let parts: Vec<&str> = command.split_whitespace().collect();
         if parts.is_empty() {
@@ -54,20 +166,53 @@ impl Shell {
         }
         let args = &parts[1..];
         match parts[0] {
-            "exit" => return true,
+            "exit" => match self.user_stack.pop() {
+                Some(cred) => self.fs.set_credentials(cred),
+                None => return true,
+            },
             "help" => self.help(),
             "clear" => self.clear(),
             "calc" => commands::calc::execute(self, args),
             "ls" => commands::ls::execute(self, args),
             "cd" => commands::cd::execute(self, args),
             "mkdir" => commands::mkdir::execute(self, args),
+            "mkfifo" => commands::mkfifo::execute(self, args),
+            "mount" => commands::mount::execute(self, args),
+            "umount" => commands::umount::execute(self, args),
             "touch" => commands::touch::execute(self, args),
+            "rm" => commands::rm::execute(self, args),
+            "rmdir" => commands::rmdir::execute(self, args),
+            "mv" => commands::mv::execute(self, args),
+            "cp" => commands::cp::execute(self, args),
             "cat" => commands::cat::execute(self, args),
+            "stat" => commands::stat::execute(self, args),
             "echo" => commands::echo_cmd::execute(self, args),
             "mem" | "memory" => commands::memory::execute(self, args),
             "clearmem" | "freeram" => commands::clearmem::execute(self, args),
+            "sync" => commands::sync::execute(self, args),
+            "df" => commands::df::execute(self, args),
+            "disk" => commands::disk::execute(self, args),
             "ps" => commands::ps::execute(self, args),
+            "top" => commands::top::execute(self, args),
+            "kill" => commands::kill::execute(self, args),
+            "pause" => commands::pause::execute(self, args),
+            "resume" => commands::resume::execute(self, args),
+            "sleep" => commands::sleep::execute(self, args),
             "route" => commands::route::execute(self, args),
+            "ping" => commands::ping::execute(self, args),
+            "ip" => commands::ip::execute(self, args),
+            "nc" => commands::nc::execute(self, args),
+            "netstat" => commands::netstat::execute(self, args),
+            "ss" => commands::ss::execute(self, args),
+            "whoami" => commands::whoami::execute(self, args),
+            "id" => commands::id::execute(self, args),
+            "login" => commands::login::execute(self, args),
+            "su" => commands::su::execute(self, args),
+            "chmod" => commands::chmod::execute(self, args),
+            "chown" => commands::chown::execute(self, args),
+            "useradd" => commands::useradd::execute(self, args),
+            "groupadd" => commands::groupadd::execute(self, args),
+            "passwd" => commands::passwd::execute(self, args),
             "browse" => commands::browse::execute(self, args),
             "vps" => commands::vps::execute(self, args),
             _ => println!("Unknown command: {}", parts[0]),
@@ -77,19 +222,46 @@ impl Shell {
 
     fn help(&self) {
         println!("Commands:");
-        println!("  ls [dir]     - List directory contents");
+        println!("  ls [-l] [dir] - List directory contents");
         println!("  cd <dir>     - Change directory");
         println!("  mkdir <dir>  - Create directory");
+        println!("  mkfifo <path> - Create a named pipe");
+        println!("  mount [-t tmpfs <dir>] - List or add mounts");
+        println!("  umount <dir> - Remove a mount");
         println!("  touch <file> - Create file");
+        println!("  rm [-r] <path> - Remove files or directories");
+        println!("  rmdir <dir>  - Remove empty directory");
+        println!("  mv <src> <dst> - Move or rename");
+        println!("  cp [-r] <src> <dst> - Copy files or directories");
         println!("  cat <file>   - Display file contents");
+        println!("  stat <path>  - Show file metadata");
         println!("  echo <text> > <file> - Write text to file");
         println!("  mem/memory   - Show memory usage");
         println!("  clearmem/freeram - Clear RAM memory");
-        println!("  ps           - List running tasks");
+        println!("  sync         - Flush the filesystem to disk");
+        println!("  df [-h]      - Show disk space usage");
+        println!("  disk <snapshot|list|restore|delete> - Manage disk snapshots");
+        println!("  ps [-ef|aux] [--sort=key] - List processes (keys: pid, cpu, mem, time, start, name; '-' reverses)");
+        println!("  top [-d secs] [-n count] - Live process view (Ctrl-C quits)");
+        println!("  kill [-SIG] <pid> - Send a signal to a process ('kill -l' lists them)");
+        println!("  pause/resume <pid> - Stop or continue a process");
+        println!("  sleep <secs> [&] - Sleep in a child process");
         println!("  calc <num1> <op> <num2> - Simple calculator");
-        println!("  route <list|add> - Manage network routes");
+        println!("  route <list|add|del|get> - Show and change the routing table");
+        println!("  ping [-c count] [-i secs] [-W secs] <ip> - Send ICMP echo requests");
+        println!("  ip <addr|neigh> - Show network interfaces and the ARP cache");
+        println!("  nc [-u] [-z] [-w secs] <ip> <port> [message] [&] - Connect to a port; nc -l <port> listens");
+        println!("  netstat [-tulanp], ss [-tulanp] - Show open sockets");
+        println!("  whoami / id  - Show the current user");
+        println!("  login <user> - Log in as another user");
+        println!("  su [user]    - Switch user ('exit' returns)");
+        println!("  chmod <mode> <path> - Change permission bits");
+        println!("  chown <user[:group]> <path> - Change ownership");
+        println!("  useradd [-G groups] <user> - Create a user");
+        println!("  groupadd <group> - Create a group");
+        println!("  passwd [user] - Set a password");
         println!("  browse <url> - Browse web pages");
-        println!("  vps <create|list|start|stop|delete> - Manage virtual private servers");
+        println!("  vps <create|templates|clone|snapshot|list|status|start|stop|restart|kill|autostart|console|exec|delete> - Manage virtual private servers");
         println!("  clear        - Clear the screen");
         println!("  help         - Show this help");
         println!("  exit         - Exit shell");
@@ -100,6 +272,109 @@ impl Shell {
         std::io::stdout().flush().unwrap();
     }
 
+    pub fn username(&self) -> String {
+        let uid = self.fs.credentials().uid;
+        UserDb::load(&self.fs)
+            .user_by_uid(uid)
+            .map(|u| u.name.clone())
+            .unwrap_or_else(|| uid.to_string())
+    }
+
+    /// Environment jobs inherit: who runs them and where.
+    pub fn environment(&self) -> BTreeMap<String, String> {
+        let uid = self.fs.credentials().uid;
+        let user = self.username();
+        let home = UserDb::load(&self.fs)
+            .user_by_uid(uid)
+            .map(|u| u.home.clone())
+            .unwrap_or_else(|| "/".into());
+        BTreeMap::from([
+            ("HOME".to_string(), home),
+            ("LOGNAME".to_string(), user.clone()),
+            ("PWD".to_string(), self.cwd.clone()),
+            ("USER".to_string(), user),
+        ])
+    }
+
+    /// Switches the shell to `cred`; with `save` the current identity is
+    /// kept so `exit` can return to it.
+    pub fn switch_user(&mut self, cred: Credentials, save: bool) {
+        if save {
+            self.user_stack.push(self.fs.credentials().clone());
+        } else {
+            self.user_stack.clear();
+        }
+        self.fs.set_credentials(cred);
+    }
+
+    pub fn prompt(&self, text: &str) -> String {
+        print!("{}", text);
+        io::stdout().flush().unwrap();
+        let input = self.read_line().unwrap_or_default();
+        input.trim_end_matches(['\r', '\n']).to_string()
+    }
+
+    /// Runs `job` as a child process of the shell. A foreground job is
+    /// waited for, and Ctrl-C interrupts it; a background one is reported
+    /// when it ends. A shell outside the scheduler runs the job in place.
+    pub fn spawn_job(&mut self, command: &str, job: Job, background: bool) {
+        let me = match self.scheduler.current_pid() {
+            Some(pid) => pid,
+            None => {
+                report_exit(command, job());
+                return;
+            }
+        };
+        let argv: Vec<&str> = command.split_whitespace().collect();
+        let process = Process::with_exit_code(0, 1, Duration::ZERO, job, Some(me))
+            .with_command(&argv)
+            .with_env(self.environment())
+            .with_cwd(&self.cwd)
+            .with_owner(self.fs.credentials().uid);
+        let pid = match self.scheduler.try_add_process(process) {
+            Ok(pid) => pid,
+            Err(e) => {
+                println!("{}: fork: {}", command, e);
+                return;
+            }
+        };
+        if background {
+            println!("[{}] {}", pid, command);
+            self.jobs.push((pid, command.to_string()));
+            return;
+        }
+
+        *FOREGROUND.lock().unwrap() = Some((self.scheduler.clone(), pid));
+        let result = self.scheduler.wait(me, Some(pid));
+        *FOREGROUND.lock().unwrap() = None;
+        match result {
+            Ok((_, code)) => report_exit(command, code),
+            Err(e) => println!("{}: {}", command, e),
+        }
+    }
+
+    /// Reports background jobs that have ended.
+    fn reap_jobs(&mut self) {
+        let me = match self.scheduler.current_pid() {
+            Some(pid) => pid,
+            None => return,
+        };
+        let scheduler = self.scheduler.clone();
+        self.jobs.retain(|(pid, command)| match scheduler.try_wait(me, Some(*pid)) {
+            Ok(None) => true,
+            Ok(Some((_, code))) => {
+                let status = match Signal::from_exit_code(code) {
+                    Some(sig) => sig.description().to_string(),
+                    None if code == 0 => "Done".to_string(),
+                    None => format!("Exit {}", code),
+                };
+                println!("[{}] {:<24} {}", pid, status, command);
+                false
+            }
+            Err(_) => false,
+        });
+    }
+
     pub fn resolve_path(&self, path: &str) -> String {
         if path.starts_with('/') {
             path.to_string()
@@ -107,4 +382,28 @@ impl Shell {
             format!("{}/{}", self.cwd.trim_end_matches('/'), path)
         }
     }
+
+    /// Resolves the destination of `mv`/`cp`: an existing directory means
+    /// "put the source inside it" under its current name.
+    pub fn target_path(&self, source: &str, dest: &str) -> String {
+        let dest = self.resolve_path(dest);
+        match self.fs.get(&dest) {
+            Some(node) if node.as_directory().is_some() => {
+                let name = source.rsplit('/').find(|p| !p.is_empty()).unwrap_or("");
+                format!("{}/{}", dest.trim_end_matches('/'), name)
+            }
+            _ => dest,
+        }
+    }
+}
+
+/// Reports how a foreground job ended, the way a shell does.
+fn report_exit(command: &str, code: i32) {
+    match Signal::from_exit_code(code) {
+        // The terminal already echoed ^C
+        Some(Signal::Int) => println!(),
+        Some(sig) => println!("{}", sig.description()),
+        None if code != 0 => println!("{}: exit {}", command, code),
+        None => {}
+    }
 }
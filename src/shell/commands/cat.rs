@@ -1,30 +1,48 @@
 use crate::shell::Shell;
-use crate::vfs::NodeType;
+use crate::vfs::{NodeType, R_OK};
 
 pub fn execute(shell: &mut Shell, args: &[&str]) {
     if let Some(name) = args.first() {
         let path = shell.resolve_path(name);
-        if let Some(node) = shell.fs.get(&path) {
-            if node.node_type() == NodeType::File {
-                // Check if it's a regular file
-                if let Some(file) = node.as_any().downcast_ref::<crate::vfs::File>() {
-                    println!("{}", String::from_utf8_lossy(&file.read()));
-                } else if let Some(null_dev) =
-                    node.as_any().downcast_ref::<crate::vfs::NullDevice>()
-                {
-                    println!("{}", null_dev.read());
-                } else if let Some(random_dev) =
-                    node.as_any().downcast_ref::<crate::vfs::RandomDevice>()
-                {
-                    println!("{}", random_dev.read());
+        match shell.fs.access(&path, R_OK) {
+            Ok(node) => {
+                if node.node_type() == NodeType::File {
+                    // Check if it's a regular file
+                    if let Some(file) = node.as_any().downcast_ref::<crate::vfs::File>() {
+                        println!("{}", String::from_utf8_lossy(&file.read()));
+                    } else if let Some(null_dev) =
+                        node.as_any().downcast_ref::<crate::vfs::NullDevice>()
+                    {
+                        println!("{}", null_dev.read());
+                    } else if let Some(random_dev) =
+                        node.as_any().downcast_ref::<crate::vfs::RandomDevice>()
+                    {
+                        println!("{}", random_dev.read());
+                    } else if let Some(proc_file) =
+                        node.as_any().downcast_ref::<crate::vfs::ProcFile>()
+                    {
+                        print!("{}", proc_file.read());
+                    } else if let Some(control) =
+                        node.as_any().downcast_ref::<crate::vfs::CgroupFile>()
+                    {
+                        print!("{}", control.read());
+                    } else {
+                        println!("Unsupported file type");
+                    }
+                } else if let Some(fifo) = node.as_any().downcast_ref::<crate::ipc::Fifo>() {
+                    // The shell cannot wait for a writer: show what is buffered
+                    let reader = fifo.open_read_nonblock();
+                    let mut buf = vec![0u8; fifo.buffered()];
+                    match reader.try_read(&mut buf) {
+                        Ok(n) => println!("{}", String::from_utf8_lossy(&buf[..n])),
+                        Err(_) => println!(),
+                    }
                 } else {
-                    println!("Unsupported file type");
+                    println!("{} is not a file", path);
                 }
-            } else {
-                println!("{} is not a file", path);
             }
-        } else {
-            println!("File not found: {}", path);
+            Err(e) if e == "No such file or directory" => println!("File not found: {}", path),
+            Err(e) => println!("cat: {}: {}", path, e),
         }
     } else {
         println!("Usage: cat <file>");
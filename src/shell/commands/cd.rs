@@ -1,17 +1,16 @@
 use crate::shell::Shell;
-use crate::vfs::NodeType;
+use crate::vfs::{NodeType, X_OK};
 
 pub fn execute(shell: &mut Shell, args: &[&str]) {
     if let Some(path) = args.first() {
         let full_path = shell.resolve_path(path);
-        if let Some(node) = shell.fs.get(&full_path) {
-            if node.node_type() == NodeType::Directory {
-                shell.cwd = full_path;
-            } else {
-                println!("{} is not a directory", full_path);
+        match shell.fs.access(&full_path, X_OK) {
+            Ok(node) if node.node_type() == NodeType::Directory => shell.cwd = full_path,
+            Ok(_) => println!("{} is not a directory", full_path),
+            Err(e) if e == "No such file or directory" => {
+                println!("Directory not found: {}", full_path)
             }
-        } else {
-            println!("Directory not found: {}", full_path);
+            Err(e) => println!("cd: {}: {}", full_path, e),
         }
     } else {
         println!("Usage: cd <directory>");
@@ -0,0 +1,22 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.len() < 2 {
+        println!("Usage: chmod <octal-mode> <path>...");
+        return;
+    }
+
+    let mode = match u32::from_str_radix(args[0], 8) {
+        Ok(mode) if mode <= 0o7777 => mode,
+        _ => {
+            println!("chmod: invalid mode: '{}'", args[0]);
+            return;
+        }
+    };
+    for target in &args[1..] {
+        let path = shell.resolve_path(target);
+        if let Err(e) = shell.fs.chmod(&path, mode) {
+            println!("chmod: changing permissions of '{}': {}", path, e);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::shell::Shell;
+use crate::vfs::UserDb;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.len() < 2 {
+        println!("Usage: chown <user>[:<group>] <path>...");
+        return;
+    }
+
+    let db = UserDb::load(&shell.fs);
+    let (user, group) = match args[0].split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (args[0], None),
+    };
+    let uid = if user.is_empty() {
+        None
+    } else {
+        match db.user(user).map(|u| u.uid).or_else(|| user.parse().ok()) {
+            Some(uid) => Some(uid),
+            None => {
+                println!("chown: invalid user: '{}'", user);
+                return;
+            }
+        }
+    };
+    let gid = match group.filter(|g| !g.is_empty()) {
+        Some(group) => match db.group(group).map(|g| g.gid).or_else(|| group.parse().ok()) {
+            Some(gid) => Some(gid),
+            None => {
+                println!("chown: invalid group: '{}'", group);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    for target in &args[1..] {
+        let path = shell.resolve_path(target);
+        if let Err(e) = shell.fs.chown(&path, uid, gid) {
+            println!("chown: changing ownership of '{}': {}", path, e);
+        }
+    }
+}
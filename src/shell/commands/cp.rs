@@ -0,0 +1,20 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let recursive = args.iter().any(|a| matches!(*a, "-r" | "-R"));
+    let paths: Vec<&str> = args.iter().filter(|a| !a.starts_with('-')).copied().collect();
+    if paths.len() < 2 {
+        println!("Usage: cp [-r] <source> <destination>");
+        return;
+    }
+
+    let from = shell.resolve_path(paths[0]);
+    let to = shell.target_path(&from, paths[1]);
+    if let Err(e) = shell.fs.copy(&from, &to, recursive) {
+        if e == "Is a directory" && !recursive {
+            println!("cp: -r not specified; omitting directory '{}'", from);
+        } else {
+            println!("cp: cannot copy '{}' to '{}': {}", from, to, e);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let human = args.contains(&"-h");
+    let size = |bytes: u64| {
+        if human {
+            human_size(bytes)
+        } else {
+            (bytes / 1024).to_string()
+        }
+    };
+
+    println!(
+        "{:<12} {:>10} {:>10} {:>10} {:>5} Mounted on",
+        "Filesystem",
+        if human { "Size" } else { "1K-blocks" },
+        "Used",
+        "Avail",
+        "Use%"
+    );
+    for mount in shell.fs.list_mounts() {
+        // Only the root filesystem lives on the disk; the other mounts are
+        // in-memory providers
+        let (total, used) = if mount.path == "/" && shell.fs.is_persistent() {
+            let block_size = shell.disk.block_size() as u64;
+            (
+                shell.disk.capacity() * block_size,
+                shell.disk.get_allocated_blocks() as u64 * block_size,
+            )
+        } else {
+            (0, 0)
+        };
+        let percent = if total == 0 {
+            "-".to_string()
+        } else {
+            format!("{}%", (used * 100).div_ceil(total))
+        };
+        println!(
+            "{:<12} {:>10} {:>10} {:>10} {:>5} {}",
+            mount.fs_type,
+            size(total),
+            size(used),
+            size(total - used),
+            percent,
+            mount.path
+        );
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if bytes == 0 {
+        "0".to_string()
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,71 @@
+use crate::shell::Shell;
+use crate::vfs::format_time;
+
+const USAGE: &str = "Usage: disk <snapshot|list|restore <id>|delete <id>>";
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let action = match args.first() {
+        Some(action) => *action,
+        None => {
+            println!("{}", USAGE);
+            return;
+        }
+    };
+    if !shell.fs.is_persistent() {
+        println!("disk: filesystem is not backed by a disk");
+        return;
+    }
+
+    if action == "list" {
+        let snapshots = shell.disk.list_snapshots();
+        if snapshots.is_empty() {
+            println!("No snapshots.");
+            return;
+        }
+        println!("{:<6} {:<20} {:>8}", "ID", "Created", "Blocks");
+        for snapshot in snapshots {
+            println!("{:<6} {:<20} {:>8}", snapshot.id, format_time(snapshot.created), snapshot.blocks);
+        }
+        return;
+    }
+
+    if !shell.fs.credentials().is_root() {
+        println!("disk: Permission denied");
+        return;
+    }
+    match action {
+        "snapshot" => {
+            // Snapshot what the shell sees, not what was last synced
+            let result = shell.fs.sync().and_then(|_| shell.disk.snapshot().map_err(String::from));
+            match result {
+                Ok(id) => println!("Snapshot {} created", id),
+                Err(e) => println!("disk: {}", e),
+            }
+        }
+        "restore" | "delete" => {
+            let id = match args.get(1).and_then(|id| id.parse::<u64>().ok()) {
+                Some(id) => id,
+                None => {
+                    println!("{}", USAGE);
+                    return;
+                }
+            };
+            if action == "delete" {
+                match shell.disk.delete_snapshot(id) {
+                    Ok(()) => println!("Snapshot {} deleted", id),
+                    Err(e) => println!("disk: {}", e),
+                }
+                return;
+            }
+            if let Err(e) = shell.disk.restore(id).map_err(String::from).and_then(|_| shell.fs.reload()) {
+                println!("disk: {}", e);
+                return;
+            }
+            if shell.fs.lookup(&shell.cwd).is_err() {
+                shell.cwd = "/".to_string();
+            }
+            println!("Restored snapshot {}", id);
+        }
+        _ => println!("{}", USAGE),
+    }
+}
@@ -1,21 +1,29 @@
+use crate::ipc::Fifo;
 use crate::shell::Shell;
-use crate::vfs::NodeType;
+use crate::vfs::{NodeType, VfsNode, W_OK};
 
 pub fn execute(shell: &mut Shell, args: &[&str]) {
     if args.len() >= 3 && args[args.len() - 2] == ">" {
         let text = args[..args.len() - 2].join(" ");
         let file_path = shell.resolve_path(args[args.len() - 1]);
-        if let Some(node) = shell.fs.get(&file_path) {
-            if node.node_type() == NodeType::File {
-                let file = node.as_any().downcast_ref::<crate::vfs::File>().unwrap();
-                file.write(text.as_bytes());
-            } else {
-                println!("{} is not a file", file_path);
-            }
-        } else {
-            println!("File not found: {}", file_path);
+        let result = match shell.fs.access(&file_path, W_OK) {
+            Ok(node) if node.node_type() == NodeType::Fifo => write_fifo(node.as_ref(), text.as_bytes()),
+            _ => shell.fs.write_file(&file_path, text.as_bytes()),
+        };
+        match result {
+            Ok(()) => {}
+            Err(e) if e == "No such file or directory" => println!("File not found: {}", file_path),
+            Err(e) if e == "Is a directory" => println!("{} is not a file", file_path),
+            Err(e) => println!("echo: {}: {}", file_path, e),
         }
     } else {
         println!("Usage: echo <text> > <file>");
     }
 }
+
+// Writing to a FIFO nobody reads would block the shell forever, so it fails
+// like a non-blocking open does
+fn write_fifo(node: &dyn VfsNode, data: &[u8]) -> Result<(), String> {
+    let fifo = node.as_any().downcast_ref::<Fifo>().ok_or("Not a regular file")?;
+    fifo.open_write_nonblock()?.try_write(data).map(|_| ())
+}
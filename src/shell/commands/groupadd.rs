@@ -0,0 +1,21 @@
+use crate::shell::Shell;
+use crate::vfs::UserDb;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let name = match args.first() {
+        Some(name) => name,
+        None => {
+            println!("Usage: groupadd <group>");
+            return;
+        }
+    };
+    if !shell.fs.credentials().is_root() {
+        println!("groupadd: Permission denied");
+        return;
+    }
+
+    let mut db = UserDb::load(&shell.fs);
+    if let Err(e) = db.add_group(name, None).and_then(|_| db.save(&shell.fs)) {
+        println!("groupadd: {}", e);
+    }
+}
@@ -0,0 +1,32 @@
+use crate::shell::Shell;
+use crate::vfs::UserDb;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let db = UserDb::load(&shell.fs);
+    let cred = match args.first() {
+        Some(name) => match db.credentials(name) {
+            Some(cred) => cred,
+            None => {
+                println!("id: '{}': no such user", name);
+                return;
+            }
+        },
+        None => shell.fs.credentials().clone(),
+    };
+
+    let user_name = |uid: u32| db.user_by_uid(uid).map(|u| u.name.clone()).unwrap_or_default();
+    let group_name = |gid: u32| db.group_by_gid(gid).map(|g| g.name.clone()).unwrap_or_default();
+    let groups: Vec<String> = cred
+        .groups
+        .iter()
+        .map(|gid| format!("{}({})", gid, group_name(*gid)))
+        .collect();
+    println!(
+        "uid={}({}) gid={}({}) groups={}",
+        cred.uid,
+        user_name(cred.uid),
+        cred.gid,
+        group_name(cred.gid),
+        groups.join(",")
+    );
+}
@@ -0,0 +1,30 @@
+use crate::shell::Shell;
+
+const USAGE: &str = "Usage: ip <addr|neigh>";
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let iface = match &shell.interface {
+        Some(iface) => iface.clone(),
+        None => {
+            println!("ip: no network interface");
+            return;
+        }
+    };
+    match args.first().copied().unwrap_or("addr") {
+        "a" | "addr" | "address" => {
+            let state = if iface.is_up() { "UP" } else { "DOWN" };
+            let cidr = iface.cidr();
+            let stats = iface.stats();
+            println!("1: {}: <BROADCAST,MULTICAST> state {}", iface.name(), state);
+            println!("    link/ether {} brd ff:ff:ff:ff:ff:ff", iface.mac());
+            println!("    inet {} brd {} scope global {}", cidr, cidr.broadcast(), iface.name());
+            println!("    RX: packets {}  TX: packets {}", stats.rx_packets, stats.tx_packets);
+        }
+        "n" | "neigh" | "neighbour" => {
+            for (ip, mac) in iface.neighbours() {
+                println!("{} dev {} lladdr {} REACHABLE", ip, iface.name(), mac);
+            }
+        }
+        _ => println!("{}", USAGE),
+    }
+}
@@ -0,0 +1,45 @@
+use crate::scheduler::Signal;
+use crate::shell::Shell;
+
+const USAGE: &str = "usage: kill [-s sigspec | -sigspec] pid ... or kill -l";
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.first() == Some(&"-l") {
+        for sig in Signal::ALL {
+            println!("{:>2}) SIG{}", sig.number(), sig.name());
+        }
+        return;
+    }
+    match parse_args(args) {
+        Ok((signal, pids)) => send(shell, "kill", signal, pids),
+        Err(e) => println!("kill: {}", e),
+    }
+}
+
+fn parse_args<'a>(args: &'a [&'a str]) -> Result<(Signal, &'a [&'a str]), String> {
+    let (signal, pids) = match args {
+        ["-s", spec, pids @ ..] => (spec.parse()?, pids),
+        [flag, pids @ ..] if flag.starts_with('-') => (flag[1..].parse()?, pids),
+        pids => (Signal::Term, pids),
+    };
+    if pids.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    Ok((signal, pids))
+}
+
+/// Sends `signal` to every PID in `pids`, reporting failures as `command`.
+pub fn send(shell: &Shell, command: &str, signal: Signal, pids: &[&str]) {
+    for arg in pids {
+        let pid: u32 = match arg.parse() {
+            Ok(pid) => pid,
+            Err(_) => {
+                println!("{}: {}: arguments must be process IDs", command, arg);
+                continue;
+            }
+        };
+        if let Err(e) = shell.scheduler.kill(pid, signal) {
+            println!("{}: ({}) - {}", command, pid, e);
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::shell::Shell;
+use crate::vfs::UserDb;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let name = match args.first() {
+        Some(name) => name.to_string(),
+        None => shell.prompt("login: "),
+    };
+    let password = shell.prompt("Password: ");
+
+    let db = UserDb::load(&shell.fs);
+    if !db.authenticate(&name, &password) {
+        println!("Login incorrect");
+        return;
+    }
+    let cred = db.credentials(&name).unwrap();
+    let home = db.user(&name).map(|u| u.home.clone()).unwrap_or_default();
+    shell.switch_user(cred, false);
+    shell.cwd = if shell.fs.get(&home).is_some() { home } else { "/".to_string() };
+}
@@ -1,28 +1,63 @@
 use crate::shell::Shell;
-use crate::vfs::NodeType;
+use crate::vfs::{format_time, Metadata, NodeType, UserDb};
 
 pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let long = args.iter().any(|a| a.starts_with('-') && a.contains('l'));
     let path = args
-        .first()
+        .iter()
+        .find(|a| !a.starts_with('-'))
         .map(|s| shell.resolve_path(s))
         .unwrap_or_else(|| shell.cwd.clone());
     if let Some(node) = shell.fs.get(&path) {
         if node.node_type() == NodeType::Directory {
-            let names = if let Some(dir) = node.as_any().downcast_ref::<crate::vfs::Directory>() {
-                dir.list()
-            } else if let Some(proc_dir) = node.as_any().downcast_ref::<crate::vfs::ProcDirectory>()
-            {
-                proc_dir.list()
-            } else {
-                vec![]
+            let names = match shell.fs.list_dir(&path) {
+                Ok(names) => names,
+                Err(e) => {
+                    println!("ls: cannot open directory '{}': {}", path, e);
+                    return;
+                }
             };
+            let db = UserDb::load(&shell.fs);
             for name in names {
-                println!("{}", name);
+                if long {
+                    let child = format!("{}/{}", path.trim_end_matches('/'), name);
+                    if let Ok(meta) = shell.fs.stat(&child) {
+                        println!("{}", long_entry(&db, &meta, &name));
+                    }
+                } else {
+                    println!("{}", name);
+                }
             }
+        } else if long {
+            let db = UserDb::load(&shell.fs);
+            println!("{}", long_entry(&db, &node.metadata(), &path));
         } else {
-            println!("{} is not a directory", path);
+            println!("{}", path);
         }
     } else {
         println!("Path not found: {}", path);
     }
 }
+
+fn long_entry(db: &UserDb, meta: &Metadata, name: &str) -> String {
+    // Drop the seconds: "YYYY-MM-DD HH:MM"
+    let mtime = format_time(meta.mtime);
+    let owner = db
+        .user_by_uid(meta.uid)
+        .map(|u| u.name.clone())
+        .unwrap_or_else(|| meta.uid.to_string());
+    let group = db
+        .group_by_gid(meta.gid)
+        .map(|g| g.name.clone())
+        .unwrap_or_else(|| meta.gid.to_string());
+    format!(
+        "{} {:>3} {:<8} {:<8} {:>8} {} {}",
+        meta.mode_string(),
+        meta.nlink,
+        owner,
+        group,
+        meta.size,
+        &mtime[..16],
+        name
+    )
+}
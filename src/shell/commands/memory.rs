@@ -5,7 +5,7 @@ pub fn execute(shell: &mut Shell, _args: &[&str]) {
     let num_blocks = shell.disk.get_allocated_blocks();
     let total_size = shell.disk.get_total_size();
     println!("Disk Memory:");
-    println!("  Blocks allocated: {}", num_blocks);
+    println!("  Blocks allocated: {} of {}", num_blocks, shell.disk.capacity());
     println!("  Total data size: {} bytes", total_size);
 
     // VFS stats
@@ -23,9 +23,10 @@ pub fn execute(shell: &mut Shell, _args: &[&str]) {
             crate::vfs::NodeType::File => {
                 *files += 1;
                 if let Some(file) = node.as_any().downcast_ref::<crate::vfs::File>() {
-                    *size += file.read().len();
+                    *size += file.size();
                 }
             }
+            crate::vfs::NodeType::Fifo => *files += 1,
             crate::vfs::NodeType::Directory => {
                 *dirs += 1;
                 if let Some(dir) = node.as_any().downcast_ref::<crate::vfs::Directory>() {
@@ -50,5 +51,28 @@ pub fn execute(shell: &mut Shell, _args: &[&str]) {
     println!("  Files: {}", file_count);
     println!("  Total file data: {} bytes", total_file_size);
 
-    println!("Note: All data is in-memory.");
+    // Virtual memory of the scheduler's processes
+    let vm = shell.scheduler.memory().stats();
+    println!("Virtual Memory ({} replacement):", vm.replacement);
+    println!(
+        "  Frames: {} used, {} free ({} KiB pages)",
+        vm.frames - vm.free_frames,
+        vm.free_frames,
+        crate::mm::PAGE_SIZE / 1024
+    );
+    if vm.swap_pages > 0 {
+        println!("  Swap: {} of {} pages", vm.swap_used, vm.swap_pages);
+    } else {
+        println!("  Swap: none");
+    }
+    println!(
+        "  Page faults: {}, swapped in: {}, swapped out: {}, OOM kills: {}",
+        vm.page_faults, vm.swap_ins, vm.swap_outs, vm.oom_kills
+    );
+
+    if shell.fs.is_persistent() {
+        println!("Note: VFS is persisted to the virtual disk.");
+    } else {
+        println!("Note: All data is in-memory.");
+    }
 }
@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.is_empty() {
+        println!("Usage: mkfifo <path> ...");
+        return;
+    }
+    for name in args {
+        let path = shell.resolve_path(name);
+        let ipc = shell.scheduler.ipc().clone();
+        if let Err(e) = shell.fs.mknod(&path, |_| Arc::new(ipc.fifo(&path))) {
+            println!("mkfifo: cannot create fifo '{}': {}", path, e);
+        }
+    }
+}
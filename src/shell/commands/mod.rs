@@ -2,15 +2,45 @@ pub mod browse;
 pub mod calc;
 pub mod cat;
 pub mod cd;
+pub mod chmod;
+pub mod chown;
 pub mod clearmem;
+pub mod cp;
+pub mod df;
+pub mod disk;
 pub mod echo_cmd;
+pub mod groupadd;
+pub mod id;
+pub mod ip;
+pub mod kill;
+pub mod login;
 pub mod ls;
 pub mod memory;
 pub mod mkdir;
+pub mod mkfifo;
+pub mod mount;
+pub mod mv;
+pub mod nc;
+pub mod netstat;
+pub mod passwd;
+pub mod pause;
+pub mod ping;
 pub mod ps;
+pub mod resume;
+pub mod rm;
+pub mod rmdir;
 pub mod route;
+pub mod sleep;
+pub mod ss;
+pub mod stat;
+pub mod su;
+pub mod sync;
+pub mod top;
 pub mod touch;
+pub mod umount;
+pub mod useradd;
 pub mod vps;
+pub mod whoami;
 
 #[cfg(test)]
 mod tests {
@@ -18,16 +48,99 @@ mod tests {
     use crate::shell::Shell;
     use crate::vfs::{FileSystem, Disk};
     use crate::scheduler::Scheduler;
-    use std::sync::Arc;
+    use crate::vps::manager::VpsManager;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_calc_command() {
         let fs = FileSystem::new();
         let disk: Arc<dyn Disk + Send + Sync> = Arc::new(crate::vfs::RamDisk::new());
         let scheduler = Arc::new(Scheduler::new());
-        let mut shell = Shell::new(fs, disk, scheduler);
+        let vps_manager = Arc::new(Mutex::new(VpsManager::in_memory()));
+        let mut shell = Shell::new(fs, disk, scheduler, vps_manager);
         // Capture stdout, but for simplicity, just call and assume no panic
         calc::execute(&mut shell, &["10", "+", "5"]);
         // In real test, check output, but for now, just ensure no panic
     }
+
+    #[test]
+    fn test_kill_pause_and_resume_commands() {
+        use crate::scheduler::{Process, ProcessState};
+        use std::time::Duration;
+
+        let fs = FileSystem::new();
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(crate::vfs::RamDisk::new());
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let vps_manager = Arc::new(Mutex::new(VpsManager::in_memory()));
+        let mut shell = Shell::new(fs, disk, scheduler.clone(), vps_manager);
+        let process = scheduler.spawn(Process::new(0, 1, Duration::ZERO, Box::new(|| {}), None)).unwrap();
+        let pid = process.pid();
+        let state = || process.info();
+
+        pause::execute(&mut shell, &[&pid.to_string()]);
+        assert_eq!(state().status, ProcessState::Stopped);
+        resume::execute(&mut shell, &[&pid.to_string()]);
+        assert_eq!(state().status, ProcessState::Ready);
+        kill::execute(&mut shell, &["-KILL", &pid.to_string()]);
+        assert_eq!(state().status, ProcessState::Terminated);
+        assert_eq!(state().exit_code, Some(137));
+    }
+
+    #[test]
+    fn test_su_to_root_needs_a_password() {
+        use crate::vfs::UserDb;
+
+        let fs = FileSystem::new();
+        UserDb::bootstrap(&fs).unwrap();
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(crate::vfs::RamDisk::new());
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let vps_manager = Arc::new(Mutex::new(VpsManager::in_memory()));
+        let (input, typed) = scheduler.ipc().pipe();
+        let mut shell = Shell::new(fs, disk, scheduler, vps_manager).with_input(input);
+        useradd::execute(&mut shell, &["alice"]);
+        let alice = UserDb::load(&shell.fs).credentials("alice").unwrap();
+        shell.switch_user(alice, false);
+
+        // Neither an empty password nor the lock itself lets alice in
+        for password in ["\n", "!\n"] {
+            typed.write(password.as_bytes()).unwrap();
+            su::execute(&mut shell, &[]);
+            assert_eq!(shell.fs.credentials().uid, 1000);
+        }
+    }
+
+    #[test]
+    fn test_ps_rows_carry_the_command_line_and_sort() {
+        use crate::scheduler::Process;
+        use std::collections::BTreeMap;
+        use std::time::Duration;
+
+        let fs = FileSystem::new();
+        let scheduler = Scheduler::with_cpus(1);
+        let env = BTreeMap::from([("USER".to_string(), "root".to_string())]);
+        let job = || Box::new(|| {}) as Box<dyn FnOnce() + Send>;
+        scheduler.add_process(Process::new(0, 1, Duration::ZERO, job(), None).with_command(&["/bin/zsh", "-l"]));
+        scheduler.add_process(
+            Process::new(0, 1, Duration::ZERO, job(), None)
+                .with_command(&["sleep", "5"])
+                .with_env(env.clone())
+                .with_cwd("/tmp")
+                .with_owner(1000),
+        );
+        scheduler.add_process(Process::new(0, 1, Duration::ZERO, job(), None));
+
+        let mut rows = ps::rows(&scheduler, &fs);
+        ps::sort_rows(&mut rows, "name").unwrap();
+        let names: Vec<String> = rows.iter().map(|row| row.info.command_line()).collect();
+        assert_eq!(names, ["sleep 5", "[task]", "/bin/zsh -l"]);
+        assert_eq!(rows[2].info.name, "zsh");
+        assert_eq!((rows[0].info.cwd.as_str(), rows[0].info.uid), ("/tmp", 1000));
+        assert_eq!(rows[0].info.env, env);
+        // Unknown users show as their UID
+        assert_eq!(rows[0].user, "1000");
+
+        ps::sort_rows(&mut rows, "-pid").unwrap();
+        assert_eq!(rows.iter().map(|row| row.info.id).collect::<Vec<_>>(), [3, 2, 1]);
+        assert!(ps::sort_rows(&mut rows, "colour").is_err());
+    }
 }
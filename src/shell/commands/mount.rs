@@ -0,0 +1,34 @@
+use crate::shell::Shell;
+use crate::vfs::Directory;
+use std::sync::Arc;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.is_empty() {
+        for mount in shell.fs.list_mounts() {
+            println!("{} on {} type {}", mount.fs_type, mount.path, mount.fs_type);
+        }
+        return;
+    }
+
+    if args.len() < 3 || args[0] != "-t" {
+        println!("Usage: mount [-t tmpfs <path>]");
+        return;
+    }
+
+    let fs_type = args[1];
+    let path = shell.resolve_path(args[2]);
+    let provider = match fs_type {
+        "tmpfs" => {
+            let name = path.rsplit('/').find(|p| !p.is_empty()).unwrap_or("/");
+            Arc::new(Directory::new(name))
+        }
+        _ => {
+            println!("Unknown filesystem type: {}", fs_type);
+            return;
+        }
+    };
+    match shell.fs.mount(&path, fs_type, provider) {
+        Ok(()) => println!("Mounted {} on {}", fs_type, path),
+        Err(e) => println!("Error mounting: {}", e),
+    }
+}
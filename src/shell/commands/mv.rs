@@ -0,0 +1,14 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.len() < 2 {
+        println!("Usage: mv <source> <destination>");
+        return;
+    }
+
+    let from = shell.resolve_path(args[0]);
+    let to = shell.target_path(&from, args[1]);
+    if let Err(e) = shell.fs.rename(&from, &to) {
+        println!("mv: cannot move '{}' to '{}': {}", from, to, e);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::net::{Interface, Protocol, TcpListener, TcpStream, UdpSocket};
+use crate::shell::Shell;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: nc [-u] [-z] [-w secs] <ip> <port> [message] [&]\n       nc -l [-u] [-w secs] <port> [message] [&]";
+
+// How long connecting waits when -w does not say
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+struct Options {
+    listen: bool,
+    protocol: Protocol,
+    zero_io: bool,
+    timeout: Option<Duration>,
+    host: Option<Ipv4Addr>,
+    port: u16,
+    // Sent, with a newline, once connected
+    message: Option<String>,
+}
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let (args, background) = match args.split_last() {
+        Some((&"&", rest)) => (rest, true),
+        _ => (args, false),
+    };
+    let options = match parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                println!("nc: {}", e);
+            }
+            println!("{}", USAGE);
+            return;
+        }
+    };
+    let iface = match &shell.interface {
+        Some(iface) => iface.clone(),
+        None => {
+            println!("nc: no network interface");
+            return;
+        }
+    };
+
+    let command = format!("nc {}", args.join(" "));
+    shell.spawn_job(
+        &command,
+        Box::new(move || {
+            let result = match (options.listen, options.protocol) {
+                (true, Protocol::Tcp) => serve_tcp(&iface, &options),
+                (true, Protocol::Udp) => serve_udp(&iface, &options),
+                (false, Protocol::Tcp) => connect_tcp(&iface, &options),
+                (false, Protocol::Udp) => send_udp(&iface, &options),
+            };
+            match result {
+                Ok(()) => 0,
+                Err(e) => {
+                    println!("nc: {}", e);
+                    1
+                }
+            }
+        }),
+        background,
+    );
+}
+
+fn parse(args: &[&str]) -> Result<Options, String> {
+    let mut options = Options {
+        listen: false,
+        protocol: Protocol::Tcp,
+        zero_io: false,
+        timeout: None,
+        host: None,
+        port: 0,
+        message: None,
+    };
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-l" => options.listen = true,
+            "-u" => options.protocol = Protocol::Udp,
+            "-z" => options.zero_io = true,
+            "-w" => {
+                let secs = args.next().ok_or("")?;
+                let secs = secs.parse::<f64>().ok().filter(|s| s.is_finite() && *s > 0.0);
+                options.timeout = Some(Duration::from_secs_f64(secs.ok_or("invalid timeout")?));
+            }
+            _ if positional.is_empty() && arg.starts_with('-') => return Err(format!("invalid option '{}'", arg)),
+            _ => positional.push(*arg),
+        }
+    }
+    let rest = match (options.listen, positional.as_slice()) {
+        (true, [port, rest @ ..]) => {
+            options.port = parse_port(port)?;
+            rest
+        }
+        (false, [host, port, rest @ ..]) => {
+            options.host = Some(host.parse().map_err(|_| format!("{}: Name or service not known", host))?);
+            options.port = parse_port(port)?;
+            rest
+        }
+        _ => return Err(String::new()),
+    };
+    if !rest.is_empty() {
+        options.message = Some(rest.join(" "));
+    }
+    Ok(options)
+}
+
+fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse::<u16>().ok().filter(|port| *port != 0).ok_or_else(|| format!("invalid port '{}'", port))
+}
+
+// Accepts one connection, sends the message, and prints what arrives until
+// the peer closes
+fn serve_tcp(iface: &Arc<Interface>, options: &Options) -> Result<(), String> {
+    let listener = TcpListener::bind(iface, options.port)?;
+    let (mut conn, _) = listener.accept()?;
+    drop(listener);
+    if let Some(message) = &options.message {
+        conn.send(format!("{}\n", message).as_bytes())?;
+    }
+    conn.set_read_timeout(options.timeout);
+    print_stream(&conn)
+}
+
+// Prints the datagrams that arrive, answering each with the message
+fn serve_udp(iface: &Arc<Interface>, options: &Options) -> Result<(), String> {
+    let mut socket = UdpSocket::bind(iface, options.port)?;
+    socket.set_read_timeout(options.timeout);
+    let mut buf = [0; 65507];
+    loop {
+        let (n, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            // Quiet for as long as -w said
+            Err(_) if options.timeout.is_some() => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        print!("{}", String::from_utf8_lossy(&buf[..n]));
+        crate::tty::flush();
+        if let Some(message) = &options.message {
+            socket.send_to(format!("{}\n", message).as_bytes(), from)?;
+        }
+    }
+}
+
+// Connects, sends the message, and prints the answer until the peer closes
+fn connect_tcp(iface: &Arc<Interface>, options: &Options) -> Result<(), String> {
+    let addr = SocketAddrV4::new(options.host.unwrap(), options.port);
+    let mut conn = TcpStream::connect(iface, addr, options.timeout.unwrap_or(CONNECT_TIMEOUT))
+        .map_err(|e| format!("connect to {} port {} (tcp) failed: {}", addr.ip(), addr.port(), e))?;
+    if options.zero_io {
+        println!("Connection to {} {} port [tcp/*] succeeded!", addr.ip(), addr.port());
+        return Ok(());
+    }
+    if let Some(message) = &options.message {
+        conn.send(format!("{}\n", message).as_bytes())?;
+    }
+    conn.shutdown()?;
+    conn.set_read_timeout(options.timeout);
+    print_stream(&conn)
+}
+
+// Sends the message and, with -w, prints an answer if one comes in time
+fn send_udp(iface: &Arc<Interface>, options: &Options) -> Result<(), String> {
+    let addr = SocketAddrV4::new(options.host.unwrap(), options.port);
+    let mut socket = UdpSocket::bind(iface, 0)?;
+    let message = options.message.as_ref().map(|m| format!("{}\n", m)).unwrap_or_default();
+    socket.send_to(message.as_bytes(), addr)?;
+    if options.zero_io {
+        println!("Connection to {} {} port [udp/*] succeeded!", addr.ip(), addr.port());
+        return Ok(());
+    }
+    if let Some(timeout) = options.timeout {
+        socket.set_read_timeout(Some(timeout));
+        let mut buf = [0; 65507];
+        if let Ok((n, _)) = socket.recv_from(&mut buf) {
+            print!("{}", String::from_utf8_lossy(&buf[..n]));
+        }
+    }
+    Ok(())
+}
+
+// Prints what arrives on `conn` as it arrives, until the peer closes or,
+// with a read timeout, goes quiet
+fn print_stream(conn: &TcpStream) -> Result<(), String> {
+    let mut buf = [0; 4096];
+    loop {
+        match conn.recv(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                print!("{}", String::from_utf8_lossy(&buf[..n]));
+                crate::tty::flush();
+            }
+            Err(e) if e == "Resource temporarily unavailable" => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
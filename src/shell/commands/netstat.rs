@@ -0,0 +1,130 @@
+use crate::net::{Protocol, SocketInfo, TcpState};
+use crate::shell::Shell;
+
+/// Which sockets `netstat` and `ss` show, from `-t -u -l -a -n -p` (which
+/// may be combined, as in `-tulpn`).
+pub(super) struct Filter {
+    tcp: bool,
+    udp: bool,
+    listening: bool,
+    all: bool,
+    pub(super) processes: bool,
+}
+
+impl Filter {
+    pub(super) fn parse(command: &str, args: &[&str]) -> Result<Filter, String> {
+        let mut filter = Filter {
+            tcp: false,
+            udp: false,
+            listening: false,
+            all: false,
+            processes: false,
+        };
+        for arg in args {
+            let flags = match arg.strip_prefix('-') {
+                Some(flags) if !flags.is_empty() => flags,
+                _ => return Err(format!("{}: invalid argument '{}'", command, arg)),
+            };
+            for flag in flags.chars() {
+                match flag {
+                    't' => filter.tcp = true,
+                    'u' => filter.udp = true,
+                    'l' => filter.listening = true,
+                    'a' => filter.all = true,
+                    'p' => filter.processes = true,
+                    // Addresses are always numeric
+                    'n' => {}
+                    _ => return Err(format!("{}: invalid option -- '{}'", command, flag)),
+                }
+            }
+        }
+        if !filter.tcp && !filter.udp {
+            filter.tcp = true;
+            filter.udp = true;
+        }
+        Ok(filter)
+    }
+
+    /// Servers only with `-l`, everything with `-a`, and otherwise
+    /// everything but servers.
+    pub(super) fn matches(&self, socket: &SocketInfo) -> bool {
+        let wanted = match socket.protocol {
+            Protocol::Tcp => self.tcp,
+            Protocol::Udp => self.udp,
+        };
+        wanted && (self.all || self.listening == is_server(socket))
+    }
+
+    fn title(&self) -> &'static str {
+        match (self.all, self.listening) {
+            (true, _) => "servers and established",
+            (false, true) => "only servers",
+            (false, false) => "w/o servers",
+        }
+    }
+}
+
+/// Listening TCP sockets, and UDP sockets with no peer.
+pub(super) fn is_server(socket: &SocketInfo) -> bool {
+    match socket.protocol {
+        Protocol::Tcp => socket.state == Some(TcpState::Listen),
+        Protocol::Udp => socket.peer.is_none(),
+    }
+}
+
+/// The sockets of the shell's host that `filter` lets through.
+pub(super) fn sockets(shell: &Shell, command: &str, filter: &Filter) -> Option<Vec<SocketInfo>> {
+    match &shell.interface {
+        Some(iface) => Some(iface.sockets().into_iter().filter(|s| filter.matches(s)).collect()),
+        None => {
+            println!("{}: no network interface", command);
+            None
+        }
+    }
+}
+
+/// Name of the process `pid`, if it is still around.
+pub(super) fn process_name(shell: &Shell, pid: u32) -> Option<String> {
+    shell.scheduler.list_processes().into_iter().find(|p| p.id == pid).map(|p| p.name)
+}
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let filter = match Filter::parse("netstat", args) {
+        Ok(filter) => filter,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: netstat [-tulanp]");
+            return;
+        }
+    };
+    let sockets = match sockets(shell, "netstat", &filter) {
+        Some(sockets) => sockets,
+        None => return,
+    };
+
+    println!("Active Internet connections ({})", filter.title());
+    let mut header = format!(
+        "{:<5} {:>6} {:>6} {:<23} {:<23} {:<11}",
+        "Proto", "Recv-Q", "Send-Q", "Local Address", "Foreign Address", "State"
+    );
+    if filter.processes {
+        header.push_str(" PID/Program name");
+    }
+    println!("{}", header.trim_end());
+    for socket in sockets {
+        let peer = match socket.peer {
+            Some(peer) => peer.to_string(),
+            None => "0.0.0.0:*".to_string(),
+        };
+        let state = socket.state.map(|state| state.to_string()).unwrap_or_default();
+        let mut line = format!(
+            "{:<5} {:>6} {:>6} {:<23} {:<23} {:<11}",
+            socket.protocol, socket.recv_queue, 0, socket.local, peer, state
+        );
+        if filter.processes {
+            let name = process_name(shell, socket.pid).unwrap_or_else(|| "-".into());
+            line.push_str(&format!(" {}/{}", socket.pid, name));
+        }
+        println!("{}", line.trim_end());
+    }
+}
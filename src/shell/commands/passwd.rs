@@ -0,0 +1,30 @@
+use crate::shell::Shell;
+use crate::vfs::UserDb;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let current = shell.username();
+    let name = args.first().map(|n| n.to_string()).unwrap_or(current.clone());
+    if name != current && !shell.fs.credentials().is_root() {
+        println!("passwd: You may not view or modify password information for {}.", name);
+        return;
+    }
+
+    let mut db = UserDb::load(&shell.fs);
+    if !shell.fs.credentials().is_root() {
+        let old = shell.prompt("Current password: ");
+        if !db.authenticate(&name, &old) {
+            println!("passwd: Authentication token manipulation error");
+            return;
+        }
+    }
+    let new = shell.prompt("New password: ");
+    let retyped = shell.prompt("Retype new password: ");
+    if new != retyped {
+        println!("passwd: passwords do not match");
+        return;
+    }
+    match db.set_password(&name, &new).and_then(|_| db.save(&shell.fs)) {
+        Ok(()) => println!("passwd: password updated successfully"),
+        Err(e) => println!("passwd: {}", e),
+    }
+}
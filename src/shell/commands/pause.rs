@@ -0,0 +1,11 @@
+use crate::scheduler::Signal;
+use crate::shell::commands::kill;
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.is_empty() {
+        println!("Usage: pause <pid>...");
+        return;
+    }
+    kill::send(shell, "pause", Signal::Stop, args);
+}
@@ -0,0 +1,138 @@
+use crate::scheduler;
+use crate::shell::Shell;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: ping [-c count] [-i interval] [-W timeout] <ip>";
+
+// ICMP header bytes on top of the echoed data
+const ICMP_HEADER: usize = 8;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let mut count = 4;
+    let mut interval = Duration::from_secs(1);
+    let mut timeout = Duration::from_secs(1);
+    let mut target = None;
+    let mut opts = args.iter();
+    while let Some(opt) = opts.next() {
+        if !opt.starts_with('-') {
+            if target.replace(*opt).is_some() {
+                println!("{}", USAGE);
+                return;
+            }
+            continue;
+        }
+        let value = match opts.next() {
+            Some(value) => *value,
+            None => {
+                println!("{}", USAGE);
+                return;
+            }
+        };
+        let seconds = value.parse::<f64>().ok().filter(|secs| secs.is_finite() && *secs >= 0.0);
+        match (*opt, seconds) {
+            ("-c", _) => match value.parse::<u16>() {
+                Ok(n) if n > 0 => count = n,
+                _ => {
+                    println!("ping: invalid count '{}'", value);
+                    return;
+                }
+            },
+            ("-i", Some(secs)) => interval = Duration::from_secs_f64(secs),
+            ("-W", Some(secs)) => timeout = Duration::from_secs_f64(secs),
+            ("-i" | "-W", None) => {
+                println!("ping: invalid time '{}'", value);
+                return;
+            }
+            _ => {
+                println!("{}", USAGE);
+                return;
+            }
+        }
+    }
+    let target = match target {
+        Some(target) => target,
+        None => {
+            println!("{}", USAGE);
+            return;
+        }
+    };
+    let dst = match target.parse::<Ipv4Addr>() {
+        Ok(dst) => dst,
+        Err(_) => {
+            println!("ping: {}: Name or service not known", target);
+            return;
+        }
+    };
+    let iface = match &shell.interface {
+        Some(iface) => iface.clone(),
+        None => {
+            println!("ping: connect: Network is unreachable");
+            return;
+        }
+    };
+
+    let command = format!("ping {}", args.join(" "));
+    shell.spawn_job(
+        &command,
+        Box::new(move || {
+            println!("PING {} ({}) 56({}) bytes of data.", dst, dst, 56 + ICMP_HEADER + 20);
+            let start = Instant::now();
+            let mut received = 0;
+            let mut sent = 0;
+            let mut times = Vec::new();
+            for seq in 1..=count {
+                sent += 1;
+                match iface.ping(dst, seq, timeout) {
+                    Ok(reply) => {
+                        received += 1;
+                        let ms = reply.time.as_secs_f64() * 1000.0;
+                        times.push(ms);
+                        println!(
+                            "{} bytes from {}: icmp_seq={} ttl={} time={:.3} ms",
+                            reply.bytes + ICMP_HEADER,
+                            reply.from,
+                            reply.seq,
+                            reply.ttl,
+                            ms
+                        );
+                    }
+                    Err(e) if e == "No route to host" => {
+                        println!("From {} icmp_seq={} Destination Host Unreachable", iface.addr(), seq)
+                    }
+                    // Lost: nothing to say until the summary
+                    Err(e) if e == "Request timed out" => {}
+                    Err(e) => {
+                        println!("ping: sendmsg: {}", e);
+                        return 2;
+                    }
+                }
+                if seq < count {
+                    scheduler::sleep(interval);
+                }
+            }
+
+            println!();
+            println!("--- {} ping statistics ---", dst);
+            println!(
+                "{} packets transmitted, {} received, {}% packet loss, time {}ms",
+                sent,
+                received,
+                (sent - received) * 100 / sent,
+                start.elapsed().as_millis()
+            );
+            if !times.is_empty() {
+                let min = times.iter().copied().fold(f64::MAX, f64::min);
+                let max = times.iter().copied().fold(0.0, f64::max);
+                let avg = times.iter().sum::<f64>() / times.len() as f64;
+                println!("rtt min/avg/max = {:.3}/{:.3}/{:.3} ms", min, avg, max);
+            }
+            if received > 0 {
+                0
+            } else {
+                1
+            }
+        }),
+        false,
+    );
+}
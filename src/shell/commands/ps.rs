@@ -1,12 +1,209 @@
+use crate::mm::PAGE_SIZE;
+use crate::scheduler::{ProcessInfo, ProcessState, Scheduler};
 use crate::shell::Shell;
+use crate::vfs::{FileSystem, UserDb};
+use std::cmp::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-pub fn execute(shell: &mut Shell, _args: &[&str]) {
-    println!("PID\tPriority\tStatus\tParent PID\tMemory");
-    let processes = shell.scheduler.list_processes();
-    for process in processes {
-        println!(
-            "{}\t{}\t{}\t{:?}\t{} bytes",
-            process.id, process.priority, process.status, process.parent_pid, process.memory_usage
-        );
+/// A process with what `ps` and `top` show about it.
+pub struct Row {
+    pub info: ProcessInfo,
+    pub user: String,
+    /// Virtual and resident size in kB.
+    pub vsz: usize,
+    pub rss: usize,
+    /// Share of one CPU used since the process started.
+    pub cpu: f64,
+    /// Share of physical memory resident.
+    pub mem: f64,
+}
+
+/// Every process of `scheduler`, zombies included.
+pub fn rows(scheduler: &Scheduler, fs: &FileSystem) -> Vec<Row> {
+    let users = UserDb::load(fs);
+    let memory = scheduler.memory();
+    let total = (memory.stats().frames * PAGE_SIZE).max(1) as f64;
+    let now = SystemTime::now();
+    scheduler
+        .list_processes()
+        .into_iter()
+        .map(|info| {
+            let usage = memory.usage(info.id);
+            let lifetime = now.duration_since(info.start_time).unwrap_or_default();
+            Row {
+                user: users
+                    .user_by_uid(info.uid)
+                    .map(|u| u.name.clone())
+                    .unwrap_or_else(|| info.uid.to_string()),
+                vsz: usage.virtual_bytes / 1024,
+                rss: usage.resident_bytes / 1024,
+                cpu: percent(info.cpu_time, lifetime),
+                mem: usage.resident_bytes as f64 * 100.0 / total,
+                info,
+            }
+        })
+        .collect()
+}
+
+/// `part` as a percentage of `whole`.
+pub fn percent(part: Duration, whole: Duration) -> f64 {
+    if whole.is_zero() {
+        0.0
+    } else {
+        part.as_secs_f64() * 100.0 / whole.as_secs_f64()
+    }
+}
+
+/// Sorts by `key` (pid, cpu, mem, time, start or name), in descending
+/// order if it starts with `-`.
+pub fn sort_rows(rows: &mut [Row], key: &str) -> Result<(), String> {
+    let (key, descending) = match key.strip_prefix('-') {
+        Some(key) => (key, true),
+        None => (key.strip_prefix('+').unwrap_or(key), false),
+    };
+    let compare: fn(&Row, &Row) -> Ordering = match key {
+        "pid" => |a, b| a.info.id.cmp(&b.info.id),
+        "cpu" | "%cpu" | "pcpu" => |a, b| a.cpu.total_cmp(&b.cpu),
+        "mem" | "%mem" | "pmem" | "rss" => |a, b| a.rss.cmp(&b.rss),
+        "time" => |a, b| a.info.cpu_time.cmp(&b.info.cpu_time),
+        "start" => |a, b| a.info.start_time.cmp(&b.info.start_time),
+        "name" | "comm" | "cmd" => |a, b| a.info.name.cmp(&b.info.name),
+        _ => return Err(format!("unknown sort key '{}'", key)),
+    };
+    rows.sort_by(|a, b| {
+        let order = compare(a, b).then(a.info.id.cmp(&b.info.id));
+        if descending {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+    Ok(())
+}
+
+/// Wall-clock `HH:MM` (UTC) of `time`.
+pub fn clock(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    format!("{:02}:{:02}", secs / 3600 % 24, secs / 60 % 60)
+}
+
+/// CPU time as `M:SS`.
+pub fn minutes(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+fn command(info: &ProcessInfo) -> String {
+    if info.status == ProcessState::Zombie {
+        format!("[{}] <defunct>", info.name)
+    } else {
+        info.command_line()
+    }
+}
+
+// Controlling terminal, `?` for none
+fn tty(info: &ProcessInfo) -> &str {
+    info.tty.as_deref().unwrap_or("?")
+}
+
+enum Format {
+    // The scheduler's view: priority, state and exit code
+    Tasks,
+    // -ef
+    Full,
+    // aux
+    User,
+}
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let mut format = Format::Tasks;
+    let mut sort = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-ef" | "-fe" | "-e" | "-f" => format = Format::Full,
+            "aux" | "-aux" | "au" | "ax" => format = Format::User,
+            "--sort" => match args.next() {
+                Some(key) => sort = Some(key.to_string()),
+                None => {
+                    println!("ps: option '--sort' requires an argument");
+                    return;
+                }
+            },
+            _ => match arg.strip_prefix("--sort=") {
+                Some(key) => sort = Some(key.to_string()),
+                None => {
+                    println!("ps: unknown option '{}'", arg);
+                    println!("Usage: ps [-ef|aux] [--sort=[-]key]");
+                    return;
+                }
+            },
+        }
+    }
+
+    let mut rows = rows(&shell.scheduler, &shell.fs);
+    if let Some(key) = sort {
+        if let Err(e) = sort_rows(&mut rows, &key) {
+            println!("ps: {}", e);
+            return;
+        }
+    }
+    match format {
+        Format::Tasks => {
+            println!("PID\tPriority\tStatus\tParent PID\tMemory\tExit\tCommand");
+            for Row { info, .. } in rows {
+                println!(
+                    "{}\t{}\t{}\t{:?}\t{} bytes\t{}\t{}",
+                    info.id,
+                    info.priority,
+                    info.status,
+                    info.parent_pid,
+                    info.memory_usage,
+                    info.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "-".into()),
+                    info.command_line()
+                );
+            }
+        }
+        Format::Full => {
+            println!("{:<8} {:>5} {:>5} {:>2} {:<5} {:<7} {:>8} CMD", "UID", "PID", "PPID", "C", "STIME", "TTY", "TIME");
+            for row in rows.iter().filter(|row| row.info.status != ProcessState::Terminated) {
+                let secs = row.info.cpu_time.as_secs();
+                println!(
+                    "{:<8} {:>5} {:>5} {:>2} {:<5} {:<7} {:02}:{:02}:{:02} {}",
+                    row.user,
+                    row.info.id,
+                    row.info.parent_pid.unwrap_or(0),
+                    row.cpu.min(99.0) as u32,
+                    clock(row.info.start_time),
+                    tty(&row.info),
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60,
+                    command(&row.info)
+                );
+            }
+        }
+        Format::User => {
+            println!(
+                "{:<8} {:>5} {:>4} {:>4} {:>7} {:>6} {:<7} {:<4} {:<5} {:>6} COMMAND",
+                "USER", "PID", "%CPU", "%MEM", "VSZ", "RSS", "TTY", "STAT", "START", "TIME"
+            );
+            for row in rows.iter().filter(|row| row.info.status != ProcessState::Terminated) {
+                println!(
+                    "{:<8} {:>5} {:>4.1} {:>4.1} {:>7} {:>6} {:<7} {:<4} {:<5} {:>6} {}",
+                    row.user,
+                    row.info.id,
+                    row.cpu,
+                    row.mem,
+                    row.vsz,
+                    row.rss,
+                    tty(&row.info),
+                    row.info.status.code(),
+                    clock(row.info.start_time),
+                    minutes(row.info.cpu_time),
+                    command(&row.info)
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,11 @@
+use crate::scheduler::Signal;
+use crate::shell::commands::kill;
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.is_empty() {
+        println!("Usage: resume <pid>...");
+        return;
+    }
+    kill::send(shell, "resume", Signal::Cont, args);
+}
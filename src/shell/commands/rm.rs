@@ -0,0 +1,29 @@
+use crate::shell::Shell;
+use crate::vfs::NodeType;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let recursive = args.iter().any(|a| matches!(*a, "-r" | "-rf" | "-fr" | "-R"));
+    let targets: Vec<&str> = args.iter().filter(|a| !a.starts_with('-')).copied().collect();
+    if targets.is_empty() {
+        println!("Usage: rm [-r] <path>...");
+        return;
+    }
+
+    for target in targets {
+        let path = shell.resolve_path(target);
+        let result = match shell.fs.get(&path) {
+            Some(node) if node.node_type() == NodeType::Directory => {
+                if recursive {
+                    shell.fs.remove_all(&path)
+                } else {
+                    Err("Is a directory".to_string())
+                }
+            }
+            Some(_) => shell.fs.remove_file(&path),
+            None => Err("No such file or directory".to_string()),
+        };
+        if let Err(e) = result {
+            println!("rm: cannot remove '{}': {}", path, e);
+        }
+    }
+}
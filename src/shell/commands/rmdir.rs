@@ -0,0 +1,15 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.is_empty() {
+        println!("Usage: rmdir <directory>...");
+        return;
+    }
+
+    for target in args {
+        let path = shell.resolve_path(target);
+        if let Err(e) = shell.fs.remove_dir(&path) {
+            println!("rmdir: failed to remove '{}': {}", path, e);
+        }
+    }
+}
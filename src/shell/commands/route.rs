@@ -1,43 +1,148 @@
+use crate::net::{Cidr, Route, RoutingTable};
 use crate::shell::Shell;
 use crate::vfs::NodeType;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: route <list|add|del|get> ...";
+const ADD_USAGE: &str = "Usage: route add <prefix|default> [via] <gateway> [dev <iface>] [metric <n>]";
+const DEL_USAGE: &str = "Usage: route del <prefix|default> [metric <n>]";
 
 pub fn execute(shell: &mut Shell, args: &[&str]) {
-    if args.is_empty() {
-        println!("Usage: route <add|list> [destination] [gateway]");
-        return;
-    }
+    let table = match routing_table(shell) {
+        Some(table) => table,
+        None => {
+            println!("route: /network is not mounted");
+            return;
+        }
+    };
 
-    match args[0] {
-        "list" => {
-            if let Some(node) = shell.fs.get("/network") {
-                if node.node_type() == NodeType::Directory {
-                    if let Some(dir) = node.as_any().downcast_ref::<crate::vfs::NetworkDirectory>() {
-                        let routes = dir.get_routes();
-                        for (dest, gateway) in routes {
-                            println!("{} -> {}", dest, gateway);
-                        }
+    match args.first().copied().unwrap_or("list") {
+        "list" | "show" => {
+            for route in table.routes() {
+                println!("{}", route);
+            }
+        }
+        "add" => match parse_route(&args[1..]) {
+            Ok(route) => match table.add(route) {
+                Ok(route) => println!("Route added: {}", route),
+                Err(e) => println!("route: {}", e),
+            },
+            Err(e) => println!("{}", e),
+        },
+        "del" | "delete" => {
+            let (destination, metric) = match args[1..] {
+                [destination] => (destination, None),
+                [destination, "metric", metric] => match metric.parse::<u32>() {
+                    Ok(metric) => (destination, Some(metric)),
+                    Err(_) => {
+                        println!("route: invalid metric '{}'", metric);
+                        return;
                     }
+                },
+                _ => {
+                    println!("{}", DEL_USAGE);
+                    return;
                 }
+            };
+            let result = parse_destination(destination).and_then(|dst| table.remove(dst, metric));
+            match result {
+                Ok(route) => println!("Route deleted: {}", route),
+                Err(e) => println!("route: {}", e),
             }
         }
-        "add" => {
-            if args.len() < 3 {
-                println!("Usage: route add <destination> <gateway>");
-                return;
-            }
-            let destination = args[1];
-            let gateway = args[2];
-            if let Some(node) = shell.fs.get("/network") {
-                if node.node_type() == NodeType::Directory {
-                    if let Some(dir) = node.as_any().downcast_ref::<crate::vfs::NetworkDirectory>() {
-                        dir.add_route(destination, gateway);
-                        println!("Route added: {} -> {}", destination, gateway);
+        "get" => {
+            let addr = match args.get(1).map(|addr| addr.parse::<Ipv4Addr>()) {
+                Some(Ok(addr)) => addr,
+                Some(Err(_)) => {
+                    println!("route: invalid address '{}'", args[1]);
+                    return;
+                }
+                None => {
+                    println!("Usage: route get <ip>");
+                    return;
+                }
+            };
+            match table.lookup(addr) {
+                Some(route) => {
+                    let mut line = addr.to_string();
+                    if let Some(gateway) = route.gateway {
+                        line.push_str(&format!(" via {}", gateway));
                     }
+                    line.push_str(&format!(" dev {}", route.iface));
+                    if let Some(src) = table.link_addr(&route.iface) {
+                        line.push_str(&format!(" src {}", src));
+                    }
+                    println!("{}", line);
                 }
+                None => println!("route: Network is unreachable"),
+            }
+        }
+        other => {
+            println!("Unknown subcommand: {}", other);
+            println!("{}", USAGE);
+        }
+    }
+}
+
+// The routing table mounted at /network
+fn routing_table(shell: &Shell) -> Option<Arc<RoutingTable>> {
+    let node = shell.fs.get("/network")?;
+    if node.node_type() != NodeType::Directory {
+        return None;
+    }
+    let dir = node.as_any().downcast_ref::<crate::vfs::NetworkDirectory>()?;
+    Some(dir.routes().clone())
+}
+
+fn parse_destination(destination: &str) -> Result<Cidr, String> {
+    match destination {
+        "default" => Ok(Cidr {
+            addr: Ipv4Addr::UNSPECIFIED,
+            prefix: 0,
+        }),
+        _ => destination.parse().map_err(|e| format!("route: {}", e)),
+    }
+}
+
+// `<prefix> [via] <gateway> [dev <iface>] [metric <n>]`, or
+// `<prefix> dev <iface>` for a route on the link
+fn parse_route(args: &[&str]) -> Result<Route, String> {
+    let (destination, mut rest) = match args.split_first() {
+        Some((destination, rest)) => (parse_destination(destination)?, rest),
+        None => return Err(ADD_USAGE.into()),
+    };
+    let mut route = Route {
+        destination,
+        gateway: None,
+        iface: String::new(),
+        metric: 0,
+        src: None,
+    };
+    while let Some((key, tail)) = rest.split_first() {
+        rest = tail;
+        if !matches!(*key, "via" | "dev" | "metric") {
+            // A bare address is the gateway, as in `route add <dest> <gateway>`
+            if route.gateway.is_some() {
+                return Err(ADD_USAGE.into());
             }
+            route.gateway = Some(parse_gateway(key)?);
+            continue;
         }
-        _ => {
-            println!("Unknown subcommand: {}", args[0]);
+        let (value, tail) = rest.split_first().ok_or(ADD_USAGE)?;
+        rest = tail;
+        match *key {
+            "via" => route.gateway = Some(parse_gateway(value)?),
+            "dev" => route.iface = value.to_string(),
+            _ => route.metric = value.parse().map_err(|_| format!("route: invalid metric '{}'", value))?,
         }
     }
-}
\ No newline at end of file
+    if route.gateway.is_none() && route.iface.is_empty() {
+        return Err(ADD_USAGE.into());
+    }
+    Ok(route)
+}
+
+fn parse_gateway(gateway: &str) -> Result<Ipv4Addr, String> {
+    gateway.parse().map_err(|_| format!("route: invalid gateway '{}'", gateway))
+}
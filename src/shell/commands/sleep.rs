@@ -0,0 +1,33 @@
+use crate::scheduler;
+use crate::shell::Shell;
+use std::time::Duration;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let (args, background) = match args.split_last() {
+        Some((&"&", rest)) => (rest, true),
+        _ => (args, false),
+    };
+    let seconds = match args {
+        [secs] => secs.parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0),
+        _ => {
+            println!("Usage: sleep <seconds> [&]");
+            return;
+        }
+    };
+    let duration = match seconds {
+        Some(secs) => Duration::from_secs_f64(secs),
+        None => {
+            println!("sleep: invalid time interval '{}'", args[0]);
+            return;
+        }
+    };
+    let command = format!("sleep {}", args[0]);
+    shell.spawn_job(
+        &command,
+        Box::new(move || {
+            scheduler::sleep(duration);
+            0
+        }),
+        background,
+    );
+}
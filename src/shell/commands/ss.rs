@@ -0,0 +1,60 @@
+use super::netstat::{self, Filter};
+use crate::net::{SocketInfo, TcpState};
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let filter = match Filter::parse("ss", args) {
+        Ok(filter) => filter,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: ss [-tulanp]");
+            return;
+        }
+    };
+    let sockets = match netstat::sockets(shell, "ss", &filter) {
+        Some(sockets) => sockets,
+        None => return,
+    };
+
+    let mut header = format!(
+        "{:<5} {:<10} {:>6} {:>6} {:<21} {:<21}",
+        "Netid", "State", "Recv-Q", "Send-Q", "Local Address:Port", "Peer Address:Port"
+    );
+    if filter.processes {
+        header.push_str(" Process");
+    }
+    println!("{}", header.trim_end());
+    for socket in sockets {
+        let peer = match socket.peer {
+            Some(peer) => peer.to_string(),
+            None => "0.0.0.0:*".to_string(),
+        };
+        let mut line = format!(
+            "{:<5} {:<10} {:>6} {:>6} {:<21} {:<21}",
+            socket.protocol,
+            state(&socket),
+            socket.recv_queue,
+            0,
+            socket.local,
+            peer
+        );
+        if filter.processes {
+            if let Some(name) = netstat::process_name(shell, socket.pid) {
+                line.push_str(&format!(" users:((\"{}\",pid={}))", name, socket.pid));
+            }
+        }
+        println!("{}", line.trim_end());
+    }
+}
+
+// The state as ss names it
+fn state(socket: &SocketInfo) -> &'static str {
+    match socket.state {
+        Some(TcpState::Listen) => "LISTEN",
+        Some(TcpState::SynSent) => "SYN-SENT",
+        Some(TcpState::Established) => "ESTAB",
+        Some(TcpState::FinWait) => "FIN-WAIT-2",
+        Some(TcpState::CloseWait) => "CLOSE-WAIT",
+        Some(TcpState::Closed) | None => "UNCONN",
+    }
+}
@@ -0,0 +1,36 @@
+use crate::shell::Shell;
+use crate::vfs::{format_time, NodeType};
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if args.is_empty() {
+        println!("Usage: stat <path>...");
+        return;
+    }
+
+    for target in args {
+        let path = shell.resolve_path(target);
+        match shell.fs.stat(&path) {
+            Ok(meta) => {
+                let kind = match meta.kind {
+                    NodeType::File => "regular file",
+                    NodeType::Directory => "directory",
+                    NodeType::Fifo => "fifo",
+                };
+                println!("  File: {}", path);
+                println!("  Size: {:<10} Type: {}", meta.size, kind);
+                println!(" Inode: {:<10} Links: {}", meta.ino, meta.nlink);
+                println!(
+                    "Access: ({:04o}/{})  Uid: {}  Gid: {}",
+                    meta.mode,
+                    meta.mode_string(),
+                    meta.uid,
+                    meta.gid
+                );
+                println!("Access: {}", format_time(meta.atime));
+                println!("Modify: {}", format_time(meta.mtime));
+                println!("Change: {}", format_time(meta.ctime));
+            }
+            Err(e) => println!("stat: cannot stat '{}': {}", path, e),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use crate::shell::Shell;
+use crate::vfs::UserDb;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let name = args.first().copied().unwrap_or("root");
+    let db = UserDb::load(&shell.fs);
+    let cred = match db.credentials(name) {
+        Some(cred) => cred,
+        None => {
+            println!("su: user {} does not exist", name);
+            return;
+        }
+    };
+
+    // Root may switch to anyone without a password
+    if !shell.fs.credentials().is_root() {
+        let password = shell.prompt("Password: ");
+        if !db.authenticate(name, &password) {
+            println!("su: Authentication failure");
+            return;
+        }
+    }
+    shell.switch_user(cred, true);
+}
@@ -0,0 +1,11 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, _args: &[&str]) {
+    if !shell.fs.is_persistent() {
+        println!("sync: filesystem is not backed by a disk");
+        return;
+    }
+    if let Err(e) = shell.fs.sync().and_then(|_| shell.disk.flush()) {
+        println!("sync: {}", e);
+    }
+}
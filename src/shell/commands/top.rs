@@ -0,0 +1,142 @@
+use super::ps::{self, Row};
+use crate::mm::PAGE_SIZE;
+use crate::scheduler::{self, ProcessState, Scheduler};
+use crate::shell::Shell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Processes shown per screen
+const ROWS: usize = 20;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let mut delay = Duration::from_secs(2);
+    let mut count: Option<usize> = None;
+    let mut opts = args.iter();
+    while let Some(opt) = opts.next() {
+        let value = match (*opt, opts.next()) {
+            ("-d" | "-n", Some(value)) => value,
+            _ => {
+                println!("Usage: top [-d <seconds>] [-n <iterations>]");
+                return;
+            }
+        };
+        match *opt {
+            "-d" => match value.parse::<f64>().ok().filter(|secs| secs.is_finite() && *secs > 0.0) {
+                Some(secs) => delay = Duration::from_secs_f64(secs),
+                None => {
+                    println!("top: bad delay interval '{}'", value);
+                    return;
+                }
+            },
+            _ => match value.parse::<usize>() {
+                Ok(n) => count = Some(n),
+                Err(_) => {
+                    println!("top: bad iterations argument '{}'", value);
+                    return;
+                }
+            },
+        }
+    }
+
+    let scheduler = shell.scheduler.clone();
+    let fs = shell.fs.clone();
+    let command = format!("top {}", args.join(" "));
+    shell.spawn_job(
+        command.trim_end(),
+        Box::new(move || {
+            // CPU time of every process at the previous screen
+            let mut previous: HashMap<u32, Duration> = HashMap::new();
+            let mut last = Instant::now();
+            let mut frame = 0;
+            while count.is_none_or(|n| frame < n) {
+                let mut rows = ps::rows(&scheduler, &fs);
+                let elapsed = last.elapsed();
+                for row in &mut rows {
+                    if let Some(before) = previous.get(&row.info.id) {
+                        row.cpu = ps::percent(row.info.cpu_time.saturating_sub(*before), elapsed);
+                    }
+                }
+                previous = rows.iter().map(|row| (row.info.id, row.info.cpu_time)).collect();
+                last = Instant::now();
+                let _ = ps::sort_rows(&mut rows, "-cpu");
+                print!("\x1B[2J\x1B[1;1H{}", render(&scheduler, &rows));
+                std::io::stdout().flush().unwrap();
+                frame += 1;
+                if count.is_none_or(|n| frame < n) {
+                    scheduler::sleep(delay);
+                }
+            }
+            0
+        }),
+        false,
+    );
+}
+
+fn render(scheduler: &Arc<Scheduler>, rows: &[Row]) -> String {
+    let up = scheduler.uptime().as_secs();
+    let load = scheduler.load_average();
+    let mut out = format!(
+        "top - up {}:{:02}:{:02}, load average: {:.2}, {:.2}, {:.2}\n",
+        up / 3600,
+        up / 60 % 60,
+        up % 60,
+        load.one,
+        load.five,
+        load.fifteen
+    );
+
+    let live: Vec<&Row> = rows.iter().filter(|row| row.info.status != ProcessState::Terminated).collect();
+    let count = |codes: &[char]| live.iter().filter(|row| codes.contains(&row.info.status.code())).count();
+    out.push_str(&format!(
+        "Tasks: {} total, {} running, {} sleeping, {} stopped, {} zombie\n",
+        live.len(),
+        count(&['R']),
+        count(&['S']),
+        count(&['T']),
+        count(&['Z'])
+    ));
+    let busy: f64 = live.iter().map(|row| row.cpu).sum::<f64>() / scheduler.cpus() as f64;
+    out.push_str(&format!(
+        "%Cpu(s): {:.1} us, {:.1} id\n",
+        busy.min(100.0),
+        (100.0 - busy).max(0.0)
+    ));
+    let stats = scheduler.memory().stats();
+    let mib = |pages: usize| (pages * PAGE_SIZE) as f64 / (1024.0 * 1024.0);
+    out.push_str(&format!(
+        "MiB Mem : {:.1} total, {:.1} free, {:.1} used\n",
+        mib(stats.frames),
+        mib(stats.free_frames),
+        mib(stats.frames - stats.free_frames)
+    ));
+    out.push_str(&format!(
+        "MiB Swap: {:.1} total, {:.1} free, {:.1} used\n\n",
+        mib(stats.swap_pages),
+        mib(stats.swap_pages - stats.swap_used),
+        mib(stats.swap_used)
+    ));
+
+    out.push_str(&format!(
+        "{:>5} {:<8} {:>3} {:>7} {:>6} {:<1} {:>5} {:>5} {:>7} COMMAND\n",
+        "PID", "USER", "PR", "VIRT", "RES", "S", "%CPU", "%MEM", "TIME+"
+    ));
+    for row in live.iter().take(ROWS) {
+        let centis = row.info.cpu_time.as_millis() / 10;
+        out.push_str(&format!(
+            "{:>5} {:<8} {:>3} {:>7} {:>6} {:<1} {:>5.1} {:>5.1} {:>7} {}\n",
+            row.info.id,
+            row.user,
+            row.info.priority,
+            row.vsz,
+            row.rss,
+            row.info.status.code(),
+            row.cpu,
+            row.mem,
+            format!("{}:{:02}.{:02}", centis / 6000, centis / 100 % 60, centis % 100),
+            row.info.name
+        ));
+    }
+    out
+}
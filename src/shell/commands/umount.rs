@@ -0,0 +1,12 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    if let Some(target) = args.first() {
+        let path = shell.resolve_path(target);
+        if let Err(e) = shell.fs.unmount(&path) {
+            println!("Error unmounting: {}", e);
+        }
+    } else {
+        println!("Usage: umount <path>");
+    }
+}
@@ -0,0 +1,56 @@
+use crate::shell::Shell;
+use crate::vfs::UserDb;
+
+pub fn execute(shell: &mut Shell, args: &[&str]) {
+    let mut groups: Vec<&str> = Vec::new();
+    let mut name = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match *arg {
+            "-G" => groups.extend(iter.next().map(|g| g.split(',')).into_iter().flatten()),
+            other => name = Some(other),
+        }
+    }
+    let name = match name {
+        Some(name) => name,
+        None => {
+            println!("Usage: useradd [-G group[,group...]] <user>");
+            return;
+        }
+    };
+    if !shell.fs.credentials().is_root() {
+        println!("useradd: Permission denied");
+        return;
+    }
+
+    let mut db = UserDb::load(&shell.fs);
+    let user = match db.add_user(name, None) {
+        Ok(user) => user,
+        Err(e) => {
+            println!("useradd: {}", e);
+            return;
+        }
+    };
+    for group in groups {
+        if let Err(e) = db.add_member(group, name) {
+            println!("useradd: {}", e);
+        }
+    }
+    if let Err(e) = db.save(&shell.fs) {
+        println!("useradd: {}", e);
+        return;
+    }
+
+    if shell.fs.get("/home").is_none() {
+        let _ = shell.fs.create_dir("/home");
+    }
+    if shell.fs.get(&user.home).is_none() {
+        let result = shell
+            .fs
+            .create_dir(&user.home)
+            .and_then(|_| shell.fs.chown(&user.home, Some(user.uid), Some(user.gid)));
+        if let Err(e) = result {
+            println!("useradd: cannot create {}: {}", user.home, e);
+        }
+    }
+}
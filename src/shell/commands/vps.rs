@@ -1,15 +1,23 @@
 use crate::shell::Shell;
+use crate::tty::Tty;
+use crate::vfs::format_time;
+use crate::vps::template::TEMPLATES;
 
 pub fn execute(shell: &mut Shell, args: &[&str]) {
     if args.is_empty() {
-        println!("Usage: vps <create|list|start|stop|delete> [args...]");
+        println!("Usage: vps <create|templates|clone|snapshot|list|status|start|stop|restart|kill|autostart|console|exec|delete> [args...]");
         return;
     }
 
     match args[0] {
         "create" => {
+            if args.len() >= 4 && args[2] == "--from-template" {
+                create_from_template(shell, args[1], args[3], &args[4..]);
+                return;
+            }
             if args.len() < 5 {
                 println!("Usage: vps create <name> <memory_mb> <disk_mb> <cpu_cores>");
+                println!("       vps create <name> --from-template <template> [<memory_mb> <disk_mb> <cpu_cores>]");
                 return;
             }
             let name = args[1];
@@ -23,6 +31,31 @@ pub fn execute(shell: &mut Shell, args: &[&str]) {
                 Err(e) => println!("Error creating VPS: {}", e),
             }
         }
+        "templates" => {
+            println!("{:<10} {:<8} {:<8} {:<4} Description", "Template", "Memory", "Disk", "CPU");
+            for template in TEMPLATES {
+                println!(
+                    "{:<10} {:<8} {:<8} {:<4} {}",
+                    template.name,
+                    format!("{}MB", template.memory_mb),
+                    format!("{}MB", template.disk_mb),
+                    template.cpu_cores,
+                    template.description
+                );
+            }
+        }
+        "clone" => {
+            if args.len() != 3 {
+                println!("Usage: vps clone <source id or name> <new name>");
+                return;
+            }
+            let mut manager = shell.vps_manager.lock().unwrap();
+            match manager.clone_vps(args[1], args[2]) {
+                Ok(id) => println!("VPS '{}' cloned from {} with ID: {}", args[2], args[1], id),
+                Err(e) => println!("Error cloning VPS: {}", e),
+            }
+        }
+        "snapshot" => snapshot(shell, &args[1..]),
         "list" => {
             let manager = shell.vps_manager.lock().unwrap();
             let vps_list = manager.list_vps();
@@ -65,6 +98,108 @@ pub fn execute(shell: &mut Shell, args: &[&str]) {
                 Err(e) => println!("Error stopping VPS: {}", e),
             }
         }
+        "restart" => {
+            if args.len() < 2 {
+                println!("Usage: vps restart <id or name>");
+                return;
+            }
+            let identifier = args[1];
+            let mut manager = shell.vps_manager.lock().unwrap();
+            match manager.restart_vps(identifier) {
+                Ok(()) => println!("VPS {} restarted successfully", identifier),
+                Err(e) => println!("Error restarting VPS: {}", e),
+            }
+        }
+        "kill" => {
+            if args.len() < 2 {
+                println!("Usage: vps kill <id or name>");
+                return;
+            }
+            let identifier = args[1];
+            let mut manager = shell.vps_manager.lock().unwrap();
+            match manager.kill_vps(identifier) {
+                Ok(()) => println!("VPS {} killed", identifier),
+                Err(e) => println!("Error killing VPS: {}", e),
+            }
+        }
+        "status" => {
+            if args.len() < 2 {
+                println!("Usage: vps status <id or name>");
+                return;
+            }
+            let manager = shell.vps_manager.lock().unwrap();
+            let instance = match manager.find(args[1]) {
+                Ok(instance) => instance,
+                Err(e) => {
+                    println!("Error: {}", e);
+                    return;
+                }
+            };
+            let mut instance = instance.lock().unwrap();
+            let status = instance.get_status();
+            println!("{}: {}", instance.config.name, status);
+            if instance.config.autostart {
+                println!("Autostart: on");
+            }
+            if let Some(shell) = &instance.shell {
+                println!("Shell PID: {}", shell.pid());
+            }
+            for (time, state) in instance.transitions() {
+                println!("  {}  {}", super::ps::clock(*time), state);
+            }
+        }
+        "autostart" => {
+            let autostart = match args.get(2) {
+                Some(&"on") if args.len() == 3 => true,
+                Some(&"off") if args.len() == 3 => false,
+                _ => {
+                    println!("Usage: vps autostart <id or name> <on|off>");
+                    return;
+                }
+            };
+            let mut manager = shell.vps_manager.lock().unwrap();
+            match manager.set_autostart(args[1], autostart) {
+                Ok(()) => println!("Autostart {} for VPS {}", if autostart { "enabled" } else { "disabled" }, args[1]),
+                Err(e) => println!("Error: {}", e),
+            }
+        }
+        "console" => {
+            if args.len() != 2 {
+                println!("Usage: vps console <id or name>");
+                return;
+            }
+            let console = shell
+                .vps_manager
+                .lock()
+                .unwrap()
+                .find(args[1])
+                .map(|instance| instance.lock().unwrap().console());
+            match console {
+                Ok(Some(console)) => attach(shell, &console, args[1]),
+                Ok(None) => println!("Error: VPS {} is not running", args[1]),
+                Err(e) => println!("Error: {}", e),
+            }
+        }
+        "exec" => {
+            if args.len() < 4 || args[2] != "--" {
+                println!("Usage: vps exec <id or name> -- <command>");
+                return;
+            }
+            let command = args[3..].join(" ");
+            let session = shell
+                .vps_manager
+                .lock()
+                .unwrap()
+                .find(args[1])
+                .and_then(|instance| instance.lock().unwrap().exec(&command));
+            match session {
+                Ok((sh, tty)) => {
+                    sh.join();
+                    tty.detach();
+                }
+                Err(e) => println!("Error: {}", e),
+            }
+        }
         "delete" => {
             if args.len() < 2 {
                 println!("Usage: vps delete <id or name>");
@@ -79,7 +214,116 @@ pub fn execute(shell: &mut Shell, args: &[&str]) {
         }
         _ => {
             println!("Unknown VPS subcommand: {}", args[0]);
-            println!("Available subcommands: create, list, start, stop, delete");
+            println!("Available subcommands: create, templates, clone, snapshot, list, status, start, stop, restart, kill, autostart, console, exec, delete");
+        }
+    }
+}
+fn create_from_template(shell: &mut Shell, name: &str, template: &str, resources: &[&str]) {
+    let resources: Vec<Option<usize>> = match resources.len() {
+        0 => vec![None; 3],
+        3 => resources.iter().map(|value| value.parse::<usize>().ok()).collect(),
+        _ => {
+            println!("Usage: vps create <name> --from-template <template> [<memory_mb> <disk_mb> <cpu_cores>]");
+            return;
+        }
+    };
+    let mut manager = shell.vps_manager.lock().unwrap();
+    match manager.create_from_template(name, template, resources[0], resources[1], resources[2]) {
+        Ok(id) => println!("VPS '{}' created from template {} with ID: {}", name, template, id),
+        Err(e) => println!("Error creating VPS: {}", e),
+    }
+}
+
+// Ctrl-]: a line containing it detaches the console
+const DETACH_KEY: char = '\x1d';
+
+// Connects the host terminal to `console` until the detach key, end of
+// input, or the VPS shell going away
+fn attach(shell: &mut Shell, console: &Tty, name: &str) {
+    let scrollback = match console.attach() {
+        Ok(scrollback) => scrollback,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    println!("Connected to {} ({}). Press Ctrl-] and Enter to detach.", name, console.name());
+    print!("{}", String::from_utf8_lossy(&scrollback));
+    crate::tty::flush();
+    while let Some(line) = shell.read_line() {
+        if line.contains(DETACH_KEY) {
+            break;
+        }
+        if let Err(e) = console.send(line.trim_end_matches(['\r', '\n'])) {
+            println!("Console closed: {}", e);
+            break;
+        }
+    }
+    console.detach();
+    println!();
+    println!("Detached from {}", name);
+}
+
+const SNAPSHOT_USAGE: &str = "Usage: vps snapshot <id or name> [list|restore <snapshot>|delete <snapshot>]";
+
+fn snapshot(shell: &mut Shell, args: &[&str]) {
+    let identifier = match args.first() {
+        Some(identifier) => *identifier,
+        None => {
+            println!("{}", SNAPSHOT_USAGE);
+            return;
+        }
+    };
+    let mut manager = shell.vps_manager.lock().unwrap();
+    let action = args.get(1).copied();
+    if action.is_none() {
+        match manager.snapshot_vps(identifier) {
+            Ok(id) => println!("Snapshot {} of VPS {} created", id, identifier),
+            Err(e) => println!("Error: {}", e),
+        }
+        return;
+    }
+    if action == Some("list") {
+        let instance = match manager.find(identifier) {
+            Ok(instance) => instance,
+            Err(e) => {
+                println!("Error: {}", e);
+                return;
+            }
+        };
+        let snapshots = instance.lock().unwrap().disk.list_snapshots();
+        if snapshots.is_empty() {
+            println!("No snapshots.");
+            return;
+        }
+        println!("{:<6} {:<20} {:>8}", "ID", "Created", "Blocks");
+        for snapshot in snapshots {
+            println!("{:<6} {:<20} {:>8}", snapshot.id, format_time(snapshot.created), snapshot.blocks);
+        }
+        return;
+    }
+
+    let id = match args.get(2).and_then(|id| id.parse::<u64>().ok()) {
+        Some(id) if args.len() == 3 => id,
+        _ => {
+            println!("{}", SNAPSHOT_USAGE);
+            return;
+        }
+    };
+    match action {
+        Some("restore") => match manager.restore_vps(identifier, id) {
+            Ok(()) => println!("VPS {} restored to snapshot {}", identifier, id),
+            Err(e) => println!("Error: {}", e),
+        },
+        Some("delete") => {
+            let result = manager
+                .find(identifier)
+                .and_then(|instance| Ok(instance.lock().unwrap().disk.delete_snapshot(id)?));
+            match result {
+                Ok(()) => println!("Snapshot {} deleted", id),
+                Err(e) => println!("Error: {}", e),
+            }
         }
+        _ => println!("{}", SNAPSHOT_USAGE),
     }
-}
\ No newline at end of file
+}
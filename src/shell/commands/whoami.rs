@@ -0,0 +1,5 @@
+use crate::shell::Shell;
+
+pub fn execute(shell: &mut Shell, _args: &[&str]) {
+    println!("{}", shell.username());
+}
@@ -2,20 +2,21 @@
 mod integration_tests {
     use std::fs;
     use std::sync::Arc;
-    use crate::vfs::{FileDisk, Disk};
+    use crate::vfs::{FileDisk, Disk, FileSystem, RamDisk};
 
     #[test]
     fn test_file_disk_persistence() {
         let disk_path = "integration_test_disk.bin";
         // Clean up
         let _ = fs::remove_file(disk_path);
+        let _ = fs::remove_file(format!("{}.journal", disk_path));
 
         // Create disk
         let disk: Arc<dyn Disk + Send + Sync> = Arc::new(FileDisk::new(disk_path));
 
         // Write data
-        let block_id = disk.allocate_block();
-        disk.write_block(block_id, b"Hello, persistent world!");
+        let block_id = disk.allocate_block().unwrap();
+        disk.write_block(block_id, b"Hello, persistent world!").unwrap();
 
         // Drop to save
         drop(disk);
@@ -28,6 +29,720 @@ mod integration_tests {
         assert_eq!(data, b"Hello, persistent world!");
 
         // Clean up
-        fs::remove_file(disk_path).unwrap();
+        let _ = fs::remove_file(disk_path);
+        fs::remove_file(format!("{}.journal", disk_path)).unwrap();
+    }
+
+    #[test]
+    fn test_filesystem_survives_restart() {
+        let disk_path = "integration_test_fs_disk.bin";
+        let _ = fs::remove_file(disk_path);
+        let _ = fs::remove_file(format!("{}.journal", disk_path));
+
+        {
+            let disk: Arc<dyn Disk + Send + Sync> = Arc::new(FileDisk::new(disk_path));
+            let mut vfs = FileSystem::new();
+            vfs.attach_disk(disk).unwrap();
+            vfs.create_dir("/docs").unwrap();
+            vfs.create_file("/docs/readme.txt").unwrap().write(b"still here");
+            vfs.sync().unwrap();
+        }
+
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(FileDisk::new(disk_path));
+        let mut vfs = FileSystem::new();
+        vfs.attach_disk(disk).unwrap();
+        assert_eq!(vfs.read_file("/docs/readme.txt").unwrap(), b"still here");
+
+        let _ = fs::remove_file(disk_path);
+        fs::remove_file(format!("{}.journal", disk_path)).unwrap();
+    }
+
+    #[test]
+    fn test_filesystem_reports_enospc() {
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(RamDisk::with_geometry(512, 16));
+        let mut fs = FileSystem::new();
+        fs.attach_disk(disk.clone()).unwrap();
+        fs.create_file("/small").unwrap();
+        fs.write_file("/small", b"fits").unwrap();
+
+        let big = vec![1u8; 512 * 16];
+        fs.create_file("/big").unwrap();
+        assert_eq!(fs.write_file("/big", &big).unwrap_err(), "No space left on device");
+        // The failed write was rolled back and its blocks released
+        assert!(fs.read_file("/big").unwrap().is_empty());
+        assert!(disk.get_allocated_blocks() < 16);
+
+        fs.remove_file("/small").unwrap();
+        fs.sync().unwrap();
+        fs.write_file("/big", b"reused").unwrap();
+
+        let mut reopened = FileSystem::new();
+        reopened.attach_disk(disk).unwrap();
+        assert_eq!(reopened.read_file("/big").unwrap(), b"reused");
+        assert!(reopened.get("/small").is_none());
+    }
+
+    #[test]
+    fn test_fifo_in_the_filesystem_and_proc_ipc() {
+        use crate::ipc::Fifo;
+        use crate::scheduler::Scheduler;
+        use crate::vfs::{NodeType, ProcDirectory, ProcFile};
+
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(RamDisk::with_geometry(512, 64));
+        let mut fs = FileSystem::new();
+        fs.attach_disk(disk.clone()).unwrap();
+        fs.mount("/proc", "proc", Arc::new(ProcDirectory::new(scheduler.clone()))).unwrap();
+
+        let ipc = scheduler.ipc().clone();
+        fs.mknod("/chan", |_| Arc::new(ipc.fifo("/chan"))).unwrap();
+        assert_eq!(fs.mknod("/chan", |_| Arc::new(ipc.fifo("/chan"))).err(), Some("File exists".into()));
+        let node = fs.lookup("/chan").unwrap();
+        assert_eq!(node.node_type(), NodeType::Fifo);
+
+        let fifo = node.as_any().downcast_ref::<Fifo>().unwrap();
+        assert_eq!(fifo.open_write_nonblock().err(), Some("No such device or address".into()));
+        let reader = fifo.open_read_nonblock();
+        fifo.open_write_nonblock().unwrap().write(b"ping").unwrap();
+
+        let pipes = fs.lookup("/proc/ipc/pipes").unwrap();
+        let table = pipes.as_any().downcast_ref::<ProcFile>().unwrap().read();
+        assert!(table.lines().any(|line| line.contains("fifo") && line.ends_with("/chan")));
+        assert_eq!(reader.read_to_end().unwrap(), b"ping");
+
+        // FIFOs are not stored on disk
+        fs.sync().unwrap();
+        let mut reopened = FileSystem::new();
+        reopened.attach_disk(disk).unwrap();
+        assert!(reopened.lookup("/chan").is_err());
+    }
+
+    #[test]
+    fn test_cgroup_limits_through_sys_fs_cgroup() {
+        use crate::scheduler::{Process, Scheduler};
+        use crate::vfs::{CgroupFile, SysDirectory};
+        use std::time::Duration;
+
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let fs = FileSystem::new();
+        fs.mount("/sys", "sysfs", Arc::new(SysDirectory::new(scheduler.clone()))).unwrap();
+
+        fs.create_dir("/sys/fs/cgroup/web").unwrap();
+        assert!(scheduler.cgroups().exists("/web"));
+        fs.write_file("/sys/fs/cgroup/web/pids.max", b"1\n").unwrap();
+        assert_eq!(fs.write_file("/sys/fs/cgroup/web/pids.max", b"lots").err(), Some("Invalid argument".into()));
+
+        let pid = scheduler.add_process(Process::new(0, 1, Duration::ZERO, Box::new(|| {}), None));
+        fs.write_file("/sys/fs/cgroup/web/cgroup.procs", pid.to_string().as_bytes()).unwrap();
+        let child = Process::new(0, 1, Duration::ZERO, Box::new(|| {}), Some(pid));
+        assert_eq!(scheduler.try_add_process(child).err(), Some("Resource temporarily unavailable".into()));
+
+        let current = fs.lookup("/sys/fs/cgroup/web/pids.current").unwrap();
+        assert_eq!(current.as_any().downcast_ref::<CgroupFile>().unwrap().read(), "1\n");
+        assert_eq!(fs.remove_dir("/sys/fs/cgroup/web").err(), Some("Device or resource busy".into()));
+        scheduler.run();
+        fs.remove_dir("/sys/fs/cgroup/web").unwrap();
+        assert!(!scheduler.cgroups().exists("/web"));
+    }
+
+    #[test]
+    fn test_proc_pid_directories_and_system_files() {
+        use crate::scheduler::{Process, Scheduler};
+        use crate::vfs::{ProcDirectory, ProcFile};
+        use std::sync::Mutex;
+        use std::time::Duration;
+
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let fs = FileSystem::new();
+        let proc_dir = ProcDirectory::new(scheduler.clone()).with_mounts(&fs.mounts);
+        fs.mount("/proc", "proc", Arc::new(proc_dir)).unwrap();
+        let read = |fs: &FileSystem, path: &str| {
+            let node = fs.lookup(path).unwrap();
+            node.as_any().downcast_ref::<ProcFile>().unwrap().read()
+        };
+
+        let parent = scheduler.add_process(Process::new(0, 1, Duration::ZERO, Box::new(|| {}), None));
+        let child = scheduler.add_process(Process::new(0, 1, Duration::ZERO, Box::new(|| {}), Some(parent)));
+        assert!(fs.list_dir("/proc").unwrap().contains(&parent.to_string()));
+        let mut files = fs.list_dir(&format!("/proc/{}", parent)).unwrap();
+        files.sort();
+        assert_eq!(files, ["children", "cmdline", "environ", "fd", "stat", "status"]);
+        let status = read(&fs, &format!("/proc/{}/status", child));
+        assert!(status.contains(&format!("PPid:\t{}\n", parent)));
+        assert!(status.contains("State:\tR (running)"));
+        assert_eq!(read(&fs, &format!("/proc/{}/children", parent)), format!("{}\n", child));
+        assert!(read(&fs, "/proc/mounts").contains("proc /proc proc rw 0 0\n"));
+        assert!(read(&fs, "/proc/loadavg").ends_with(&format!(" 2/2 {}\n", child)));
+
+        // A running process sees the pipe ends it holds
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (job_fs, job_scheduler, job_seen) = (fs.clone(), scheduler.clone(), seen.clone());
+        let pid = scheduler.add_process(Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                let _ends = job_scheduler.ipc().pipe();
+                let me = job_scheduler.current_pid().unwrap();
+                for fd in job_fs.list_dir(&format!("/proc/{}/fd", me)).unwrap() {
+                    job_seen.lock().unwrap().push(read(&job_fs, &format!("/proc/{}/fd/{}", me, fd)));
+                }
+            }),
+            None,
+        ));
+        scheduler.run();
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert!(seen.iter().all(|target| target.starts_with("pipe:[")));
+        assert!(fs.lookup(&format!("/proc/{}", pid)).is_err());
+    }
+
+    #[test]
+    fn test_vps_shell_has_a_real_pid_and_stops() {
+        use crate::vps::instance::{VpsConfig, VpsInstance, VpsState};
+
+        let config = VpsConfig {
+            id: "integration-shell".to_string(),
+            name: "shell".to_string(),
+            memory_mb: 4,
+            disk_mb: 4,
+            cpu_cores: 1,
+            ip_address: "192.168.1.100".to_string(),
+            status: VpsState::Stopped,
+            autostart: false,
+        };
+        let disk_path = format!("vps_{}_disk.bin", config.id);
+        let _ = fs::remove_file(&disk_path);
+        let mut vps = VpsInstance::new(config);
+
+        vps.start().unwrap();
+        let shell = vps.shell.clone().unwrap();
+        assert_eq!(shell.pid(), 1);
+        assert_eq!(shell.info().name, "sh");
+        assert!(shell.is_alive());
+        assert!(vps.start().is_err());
+
+        vps.stop().unwrap();
+        assert!(!shell.is_alive());
+        assert!(vps.shell.is_none());
+        assert_eq!(vps.get_status(), VpsState::Stopped);
+
+        // Starting again gives a new shell
+        vps.start().unwrap();
+        assert_eq!(vps.shell.as_ref().unwrap().pid(), 2);
+        vps.stop().unwrap();
+
+        drop(vps);
+        let _ = fs::remove_file(&disk_path);
+        let _ = fs::remove_file(format!("{}.journal", disk_path));
+    }
+
+    #[test]
+    fn test_vps_shell_runs_after_a_quick_stop_and_start() {
+        use crate::scheduler::ProcessState;
+        use crate::vps::instance::{VpsConfig, VpsInstance, VpsState};
+        use std::time::{Duration, Instant};
+
+        let config = VpsConfig {
+            id: "integration-cycle".to_string(),
+            name: "cycle".to_string(),
+            memory_mb: 4,
+            disk_mb: 4,
+            cpu_cores: 1,
+            ip_address: "192.168.1.104".to_string(),
+            status: VpsState::Stopped,
+            autostart: false,
+        };
+        let disk_path = format!("vps_{}_disk.bin", config.id);
+        let _ = fs::remove_file(&disk_path);
+        let mut vps = VpsInstance::new(config);
+
+        // Each new shell must be picked up by a dispatcher, even when the
+        // previous one was still winding down
+        for _ in 0..50 {
+            vps.start().unwrap();
+            let shell = vps.shell.clone().unwrap();
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while shell.status() == ProcessState::Ready && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            assert_ne!(shell.status(), ProcessState::Ready);
+            vps.stop().unwrap();
+        }
+
+        drop(vps);
+        let _ = fs::remove_file(&disk_path);
+        let _ = fs::remove_file(format!("{}.journal", disk_path));
+    }
+
+    #[test]
+    fn test_vps_swap_stays_off_its_disk() {
+        use crate::mm::PAGE_SIZE;
+        use crate::scheduler::{self, Process};
+        use crate::vps::instance::{VpsConfig, VpsInstance, VpsState};
+        use std::time::Duration;
+
+        let config = VpsConfig {
+            id: "integration-swap".to_string(),
+            name: "swap".to_string(),
+            memory_mb: 1,
+            disk_mb: 4,
+            cpu_cores: 1,
+            ip_address: "192.168.1.105".to_string(),
+            status: VpsState::Stopped,
+            autostart: false,
+        };
+        let disk_path = format!("vps_{}_disk.bin", config.id);
+        let _ = fs::remove_file(&disk_path);
+        let mut vps = VpsInstance::new(config);
+        vps.start().unwrap();
+        let blocks = vps.disk.get_allocated_blocks();
+
+        // More pages than the VPS has RAM: some go to swap, and stay there
+        // while the process lives
+        let memory = vps.scheduler.memory().clone();
+        let pages = memory.stats().frames / 2 * 3;
+        let process = vps
+            .scheduler
+            .spawn(Process::new(
+                0,
+                1,
+                Duration::ZERO,
+                Box::new(move || {
+                    let addr = memory.alloc(pages * PAGE_SIZE).unwrap();
+                    for page in 0..pages {
+                        memory.write(addr + (page * PAGE_SIZE) as u64, b"page").unwrap();
+                    }
+                    loop {
+                        scheduler::sleep(Duration::from_millis(5));
+                    }
+                }),
+                None,
+            ))
+            .unwrap();
+        let mut waited = 0;
+        while vps.scheduler.memory().stats().swap_used == 0 && waited < 200 {
+            scheduler::sleep(Duration::from_millis(10));
+            waited += 1;
+        }
+        assert!(vps.scheduler.memory().stats().swap_used > 0);
+        assert_eq!(vps.disk.get_allocated_blocks(), blocks);
+        process.stop();
+        vps.stop().unwrap();
+
+        drop(vps);
+        let _ = fs::remove_file(&disk_path);
+        let _ = fs::remove_file(format!("{}.journal", disk_path));
+    }
+
+    #[test]
+    fn test_vps_stop_kills_stragglers_and_crashes_are_noticed() {
+        use crate::scheduler::{self, Process, Signal};
+        use crate::vps::instance::{VpsConfig, VpsInstance, VpsState};
+        use std::time::{Duration, Instant};
+
+        let config = VpsConfig {
+            id: "integration-lifecycle".to_string(),
+            name: "lifecycle".to_string(),
+            memory_mb: 4,
+            disk_mb: 4,
+            cpu_cores: 1,
+            ip_address: "192.168.1.101".to_string(),
+            status: VpsState::Stopped,
+            autostart: false,
+        };
+        let disk_path = format!("vps_{}_disk.bin", config.id);
+        let _ = fs::remove_file(&disk_path);
+        let mut vps = VpsInstance::new(config);
+        assert!(vps.stop().is_err());
+
+        // A shell killed behind the VPS's back is a crash
+        vps.start().unwrap();
+        vps.shell.as_ref().unwrap().kill(Signal::Kill).unwrap();
+        assert_eq!(vps.get_status(), VpsState::Crashed);
+        vps.restart().unwrap();
+        assert_eq!(vps.get_status(), VpsState::Running);
+
+        // A process that ignores SIGTERM is killed once the grace period ends
+        let stubborn = vps
+            .scheduler
+            .spawn(Process::new(
+                0,
+                1,
+                Duration::ZERO,
+                Box::new(|| {
+                    scheduler::signals().unwrap().ignore(Signal::Term).unwrap();
+                    loop {
+                        scheduler::sleep(Duration::from_millis(5));
+                    }
+                }),
+                None,
+            ))
+            .unwrap();
+        scheduler::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        vps.stop_within(Duration::from_millis(100)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(stubborn.info().exit_code, Some(Signal::Kill.exit_code()));
+        assert_eq!(vps.get_status(), VpsState::Stopped);
+
+        let states: Vec<VpsState> = vps.transitions().iter().map(|(_, state)| *state).collect();
+        use VpsState::*;
+        // Restarting a crashed VPS clears out what it left behind first
+        assert_eq!(states, [Starting, Running, Crashed, Stopping, Stopped, Starting, Running, Stopping, Stopped]);
+
+        drop(vps);
+        let _ = fs::remove_file(&disk_path);
+        let _ = fs::remove_file(format!("{}.journal", disk_path));
+    }
+
+    #[test]
+    fn test_vps_registry_survives_restarts() {
+        use crate::vps::instance::{VpsInstance, VpsState};
+        use crate::vps::manager::VpsManager;
+        use std::path::Path;
+
+        let dir = Path::new("integration_vps_registry");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let registry = dir.join("vps_registry.json");
+        let orphan = VpsInstance::disk_path(dir, "orphan");
+        fs::write(&orphan, b"").unwrap();
+        fs::write(format!("{}.journal", orphan.display()), b"").unwrap();
+
+        // Without a registry nobody knows whose images these are
+        let mut manager = VpsManager::open(&registry);
+        assert!(orphan.exists());
+        let id = manager.create_vps("web", 4, 4, 1).unwrap();
+        {
+            let instance = manager.find("web").unwrap();
+            let instance = instance.lock().unwrap();
+            let fs = instance.filesystem.lock().unwrap();
+            fs.create_file("/etc/motd").unwrap();
+            fs.write_file("/etc/motd", b"hello").unwrap();
+            fs.sync().unwrap();
+        }
+        drop(manager);
+
+        // The VPS comes back stopped, on its old disk; the orphan is gone
+        let mut manager = VpsManager::open(&registry);
+        assert!(!orphan.exists());
+        assert!(!dir.join("vps_orphan_disk.bin.journal").exists());
+        let vps = manager.list_vps();
+        assert_eq!(vps.len(), 1);
+        assert_eq!((vps[0].id.as_str(), vps[0].status), (id.as_str(), VpsState::Stopped));
+        let instance = manager.find(&id).unwrap();
+        assert_eq!(instance.lock().unwrap().filesystem.lock().unwrap().read_file("/etc/motd").unwrap(), b"hello");
+        drop(instance);
+        manager.set_autostart("web", true).unwrap();
+        drop(manager);
+
+        let mut manager = VpsManager::open(&registry);
+        assert_eq!(manager.list_vps()[0].status, VpsState::Running);
+        manager.delete_vps("web").unwrap();
+        assert!(!VpsInstance::disk_path(dir, &id).exists());
+        drop(manager);
+
+        assert!(VpsManager::open(&registry).list_vps().is_empty());
+        let left: Vec<_> = fs::read_dir(dir).unwrap().filter_map(|e| e.ok()).map(|e| e.file_name()).collect();
+        assert_eq!(left, [std::ffi::OsString::from("vps_registry.json")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_vps_templates_snapshots_and_clones() {
+        use crate::scheduler;
+        use crate::vps::instance::VpsState;
+        use crate::vps::manager::VpsManager;
+        use std::path::Path;
+        use std::time::Duration;
+
+        let dir = Path::new("integration_vps_templates");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let mut manager = VpsManager::open(&dir.join("vps_registry.json"));
+
+        assert!(manager.create_from_template("www", "mail", None, None, None).is_err());
+        manager.create_from_template("www", "web", Some(8), Some(8), None).unwrap();
+        assert!(manager.create_vps("www", 8, 8, 1).is_err());
+        let config = &manager.list_vps()[0];
+        assert_eq!((config.memory_mb, config.disk_mb, config.cpu_cores), (8, 8, 2));
+        let read = |manager: &VpsManager, vps: &str, path: &str| {
+            let instance = manager.find(vps).unwrap();
+            let instance = instance.lock().unwrap();
+            let data = instance.filesystem.lock().unwrap().read_file(path);
+            data.map(|data| String::from_utf8(data).unwrap())
+        };
+        assert!(read(&manager, "www", "/var/www/html/index.html").unwrap().contains("It works!"));
+        assert!(read(&manager, "www", "/etc/rc.local").is_ok());
+
+        // The startup script runs when the VPS starts
+        manager.start_vps("www").unwrap();
+        let mut waited = 0;
+        while read(&manager, "www", "/var/log/boot.log").unwrap_or_default().is_empty() && waited < 200 {
+            scheduler::sleep(Duration::from_millis(10));
+            waited += 1;
+        }
+        assert_eq!(read(&manager, "www", "/var/log/boot.log").unwrap(), "web server up");
+        assert!(read(&manager, "www", "/var/log/nginx/access.log").is_ok());
+
+        // Snapshots need the VPS stopped to be restored
+        let snapshot = manager.snapshot_vps("www").unwrap();
+        assert!(manager.restore_vps("www", snapshot).is_err());
+        manager.stop_vps("www").unwrap();
+        {
+            let instance = manager.find("www").unwrap();
+            let instance = instance.lock().unwrap();
+            let fs = instance.filesystem.lock().unwrap();
+            fs.write_file("/etc/motd", b" (changed)").unwrap();
+            fs.sync().unwrap();
+        }
+        assert_eq!(read(&manager, "www", "/etc/motd").unwrap(), "Web server VPS (changed)");
+        manager.restore_vps("www", snapshot).unwrap();
+        assert_eq!(read(&manager, "www", "/etc/motd").unwrap(), "Web server VPS");
+
+        // So can a crashed one, once what it left running is killed
+        manager.start_vps("www").unwrap();
+        {
+            let instance = manager.find("www").unwrap();
+            let mut instance = instance.lock().unwrap();
+            instance.shell.as_ref().unwrap().kill(scheduler::Signal::Kill).unwrap();
+            assert_eq!(instance.get_status(), VpsState::Crashed);
+            instance.filesystem.lock().unwrap().write_file("/etc/motd", b" (crashed)").unwrap();
+        }
+        manager.restore_vps("www", snapshot).unwrap();
+        assert_eq!(read(&manager, "www", "/etc/motd").unwrap(), "Web server VPS");
+        assert_eq!(manager.find("www").unwrap().lock().unwrap().get_status(), VpsState::Stopped);
+
+        // A clone gets the files and resources but not the snapshots
+        let clone = manager.clone_vps("www", "www2").unwrap();
+        assert_eq!(read(&manager, "www2", "/var/log/boot.log").unwrap(), "web server up");
+        let instance = manager.find(&clone).unwrap();
+        assert_eq!(instance.lock().unwrap().config.cpu_cores, 2);
+        assert!(instance.lock().unwrap().disk.list_snapshots().is_empty());
+        assert_eq!(instance.lock().unwrap().get_status(), VpsState::Stopped);
+        drop(instance);
+
+        manager.delete_vps("www").unwrap();
+        manager.delete_vps("www2").unwrap();
+        drop(manager);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_vps_console_and_exec_have_their_own_terminals() {
+        use crate::scheduler;
+        use crate::vps::instance::{VpsConfig, VpsInstance, VpsState};
+        use std::time::Duration;
+
+        let config = VpsConfig {
+            id: "integration-console".to_string(),
+            name: "console".to_string(),
+            memory_mb: 4,
+            disk_mb: 4,
+            cpu_cores: 1,
+            ip_address: "192.168.1.102".to_string(),
+            status: VpsState::Stopped,
+            autostart: false,
+        };
+        let disk_path = format!("vps_{}_disk.bin", config.id);
+        let _ = fs::remove_file(&disk_path);
+        let mut vps = VpsInstance::new(config);
+        assert!(vps.console().is_none());
+        assert!(vps.exec("ls").is_err());
+
+        vps.start().unwrap();
+        let console = vps.console().unwrap();
+        assert_eq!(vps.shell.as_ref().unwrap().info().tty.as_deref(), Some("console"));
+        console.send("whoami").unwrap();
+        let mut waited = 0;
+        while !String::from_utf8_lossy(&console.scrollback()).contains("root\n") && waited < 200 {
+            scheduler::sleep(Duration::from_millis(10));
+            waited += 1;
+        }
+        // The shell prompts on its console, not on the host terminal
+        let shown = String::from_utf8(console.scrollback()).unwrap();
+        assert!(shown.starts_with("root:/> root\n"), "{:?}", shown);
+
+        // exec runs one command in a shell of its own and ends
+        let (sh, tty) = vps.exec("ls /").unwrap();
+        assert_eq!(tty.name(), "pts/1");
+        assert_eq!(sh.info().tty.as_deref(), Some("pts/1"));
+        assert_eq!(sh.join(), 0);
+        tty.detach();
+        assert!(String::from_utf8_lossy(&tty.scrollback()).contains("etc"));
+        assert!(!String::from_utf8_lossy(&console.scrollback()).contains("etc"));
+
+        vps.stop().unwrap();
+        assert!(vps.console().is_none());
+        assert!(console.send("whoami").is_err());
+
+        drop(vps);
+        let _ = fs::remove_file(&disk_path);
+        let _ = fs::remove_file(format!("{}.journal", disk_path));
+    }
+
+    #[test]
+    fn test_vps_ping_each_other_over_the_virtual_network() {
+        use crate::net::Network;
+        use crate::scheduler;
+        use crate::vps::manager::VpsManager;
+        use std::path::Path;
+        use std::time::Duration;
+
+        let dir = Path::new("integration_vps_network");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let network = Network::new("10.9.0.0/29".parse().unwrap()).unwrap();
+        let mut manager = VpsManager::open_on(&dir.join("vps_registry.json"), network);
+        manager.create_vps("a", 4, 4, 1).unwrap();
+        manager.create_vps("b", 4, 4, 1).unwrap();
+        let ip = |manager: &VpsManager, name: &str| manager.find(name).unwrap().lock().unwrap().config.ip_address.clone();
+        assert_eq!((ip(&manager, "a").as_str(), ip(&manager, "b").as_str()), ("10.9.0.2", "10.9.0.3"));
+        let nic = |manager: &VpsManager, name: &str| manager.find(name).unwrap().lock().unwrap().nic.clone();
+
+        // A stopped VPS is off the network
+        let timeout = Duration::from_millis(100);
+        assert!(nic(&manager, "a").ping(nic(&manager, "b").addr(), 1, timeout).is_err());
+        manager.start_vps("a").unwrap();
+        manager.start_vps("b").unwrap();
+        assert!(nic(&manager, "a").ping(nic(&manager, "b").addr(), 2, timeout).is_ok());
+        assert!(manager.network().bridge().ping(nic(&manager, "a").addr(), 1, timeout).is_ok());
+
+        // ping from a's shell reaches b
+        let console = manager.find("a").unwrap().lock().unwrap().console().unwrap();
+        console.send("ping -c 1 10.9.0.3").unwrap();
+        let mut waited = 0;
+        while !String::from_utf8_lossy(&console.scrollback()).contains("packet loss") && waited < 200 {
+            scheduler::sleep(Duration::from_millis(10));
+            waited += 1;
+        }
+        let shown = String::from_utf8(console.scrollback()).unwrap();
+        assert!(shown.contains("64 bytes from 10.9.0.3: icmp_seq=1 ttl=64"), "{:?}", shown);
+        assert!(shown.contains("1 packets transmitted, 1 received, 0% packet loss"), "{:?}", shown);
+
+        // Addresses run out, and are only handed out again once freed
+        manager.create_vps("c", 4, 4, 1).unwrap();
+        manager.create_vps("d", 4, 4, 1).unwrap();
+        manager.create_vps("e", 4, 4, 1).unwrap();
+        assert!(manager.create_vps("f", 4, 4, 1).is_err());
+        manager.delete_vps("b").unwrap();
+        manager.create_vps("f", 4, 4, 1).unwrap();
+        assert_eq!(ip(&manager, "f"), "10.9.0.3");
+
+        for name in ["a", "c", "d", "e", "f"] {
+            manager.delete_vps(name).unwrap();
+        }
+        drop(manager);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_each_vps_has_its_own_routing_table() {
+        use crate::net::Network;
+        use crate::vps::manager::VpsManager;
+        use std::path::Path;
+
+        let dir = Path::new("integration_vps_routes");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let network = Network::new("10.8.0.0/24".parse().unwrap()).unwrap();
+        let mut manager = VpsManager::open_on(&dir.join("vps_registry.json"), network);
+        manager.create_vps("a", 4, 4, 1).unwrap();
+        manager.create_vps("b", 4, 4, 1).unwrap();
+        let routes = |manager: &VpsManager, name: &str| {
+            let instance = manager.find(name).unwrap();
+            let instance = instance.lock().unwrap();
+            let data = instance.filesystem.lock().unwrap().read_file("/network/routes").unwrap();
+            String::from_utf8(data).unwrap()
+        };
+        assert_eq!(
+            routes(&manager, "a"),
+            "default via 10.8.0.1 dev eth0\n10.8.0.0/24 dev eth0 proto kernel scope link src 10.8.0.2\n"
+        );
+
+        // route runs inside the VPS and only changes its table
+        manager.start_vps("a").unwrap();
+        let instance = manager.find("a").unwrap();
+        let run = |cmd: &str| {
+            let (sh, tty) = instance.lock().unwrap().exec(cmd).unwrap();
+            sh.join();
+            tty.detach();
+            String::from_utf8(tty.scrollback()).unwrap()
+        };
+        assert!(run("route add 172.16.0.0/12 via 10.8.0.3 metric 5").contains("Route added"));
+        assert!(run("route add 172.16.0.1/12 via 10.8.0.3").contains("Invalid prefix for given prefix length"));
+        assert!(run("route add 172.16.0.0/12 via 10.9.0.3 dev eth0").contains("Nexthop has invalid gateway"));
+        assert!(run("route get 172.16.4.4").contains("172.16.4.4 via 10.8.0.3 dev eth0 src 10.8.0.2"));
+        assert!(run("route get 8.8.8.8").contains("8.8.8.8 via 10.8.0.1 dev eth0 src 10.8.0.2"));
+        assert!(routes(&manager, "a").contains("172.16.0.0/12 via 10.8.0.3 dev eth0 metric 5\n"));
+        assert!(!routes(&manager, "b").contains("172.16.0.0/12"));
+        assert!(run("route del 172.16.0.0/12").contains("Route deleted"));
+        assert!(run("route del 172.16.0.0/12").contains("No such process"));
+        drop(instance);
+
+        manager.delete_vps("a").unwrap();
+        manager.delete_vps("b").unwrap();
+        drop(manager);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_vps_talk_over_sockets_with_nc() {
+        use crate::net::{Network, TcpState};
+        use crate::scheduler;
+        use crate::vps::manager::VpsManager;
+        use std::path::Path;
+        use std::time::Duration;
+
+        let dir = Path::new("integration_vps_sockets");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let network = Network::new("10.7.0.0/24".parse().unwrap()).unwrap();
+        let mut manager = VpsManager::open_on(&dir.join("vps_registry.json"), network);
+        manager.create_vps("server", 4, 4, 1).unwrap();
+        manager.create_vps("client", 4, 4, 1).unwrap();
+        manager.start_vps("server").unwrap();
+        manager.start_vps("client").unwrap();
+        let server = manager.find("server").unwrap();
+        let client = manager.find("client").unwrap();
+        let run = |vps: &std::sync::Mutex<crate::vps::instance::VpsInstance>, cmd: &str| {
+            let (sh, tty) = vps.lock().unwrap().exec(cmd).unwrap();
+            sh.join();
+            tty.detach();
+            tty
+        };
+        let output = |tty: &crate::tty::Tty| String::from_utf8(tty.scrollback()).unwrap();
+
+        // A listener in the background of one VPS...
+        let listener = run(&server, "nc -l 7000 pong &");
+        let nic = server.lock().unwrap().nic.clone();
+        let mut waited = 0;
+        while nic.sockets().is_empty() && waited < 200 {
+            scheduler::sleep(Duration::from_millis(10));
+            waited += 1;
+        }
+        assert_eq!(nic.sockets()[0].state, Some(TcpState::Listen));
+        let netstat = output(&run(&server, "netstat -tlp"));
+        assert!(netstat.contains("10.7.0.2:7000") && netstat.contains("LISTEN"), "{}", netstat);
+        // ...ports are per VPS: nothing listens on 7000 in the other one
+        assert!(output(&run(&client, "nc -z 10.7.0.3 7000")).contains("Connection refused"));
+
+        // ...answers a client in the other
+        let answer = output(&run(&client, "nc 10.7.0.2 7000 ping"));
+        assert!(answer.contains("pong"), "{}", answer);
+        let mut waited = 0;
+        while !output(&listener).contains("ping") && waited < 200 {
+            scheduler::sleep(Duration::from_millis(10));
+            waited += 1;
+        }
+        assert!(output(&listener).contains("ping"));
+        assert!(output(&run(&client, "nc -z 10.7.0.2 7000")).contains("Connection refused"));
+
+        drop((server, client));
+        manager.delete_vps("server").unwrap();
+        manager.delete_vps("client").unwrap();
+        drop(manager);
+        fs::remove_dir_all(dir).unwrap();
     }
-}
\ No newline at end of file
+}
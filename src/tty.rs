@@ -0,0 +1,175 @@
+//! Virtual terminals.
+//!
+//! A `Tty` joins a line-oriented input channel, the write end of the pipe a
+//! shell reads its commands from, with an output buffer. Processes started
+//! on a terminal print to it, and so do the processes they start: whatever
+//! the shell and its commands print lands in the terminal's scrollback, and
+//! on the host terminal too while someone is attached. Processes without a
+//! terminal print straight to the host's stdout.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::ipc::PipeWriter;
+
+/// Bytes of output a terminal keeps for whoever attaches next.
+pub const SCROLLBACK: usize = 16 * 1024;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Tty>>> = const { RefCell::new(None) };
+}
+
+/// Terminal of the calling thread's process, if it has one.
+pub fn current() -> Option<Arc<Tty>> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Makes `tty` the terminal of the calling thread.
+pub(crate) fn set_current(tty: Option<Arc<Tty>>) {
+    CURRENT.with(|current| *current.borrow_mut() = tty);
+}
+
+/// Prints to the calling process's terminal, or to stdout without one.
+pub fn write_fmt(args: fmt::Arguments) {
+    match current() {
+        Some(tty) => tty.write(fmt::format(args).as_bytes()),
+        None => {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_fmt(args);
+        }
+    }
+}
+
+/// Flushes what `write_fmt` printed to the host terminal.
+pub fn flush() {
+    let _ = io::stdout().flush();
+}
+
+struct Output {
+    scrollback: VecDeque<u8>,
+    attached: bool,
+}
+
+pub struct Tty {
+    name: String,
+    input: Mutex<Option<PipeWriter>>,
+    output: Mutex<Output>,
+}
+
+impl Tty {
+    /// A terminal feeding its input to `input`.
+    pub fn new(name: &str, input: PipeWriter) -> Self {
+        Tty {
+            name: name.to_string(),
+            input: Mutex::new(Some(input)),
+            output: Mutex::new(Output {
+                scrollback: VecDeque::new(),
+                attached: false,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sends one line of input.
+    pub fn send(&self, line: &str) -> Result<(), String> {
+        let input = self.input.lock().unwrap();
+        let input = input.as_ref().ok_or("terminal hung up")?;
+        input.write(format!("{}\n", line).as_bytes()).map(|_| ())
+    }
+
+    /// Closes the input: the reader sees end of file once it has read what
+    /// was sent.
+    pub fn hangup(&self) {
+        self.input.lock().unwrap().take();
+    }
+
+    /// Output of the processes on this terminal.
+    pub fn write(&self, data: &[u8]) {
+        let mut output = self.output.lock().unwrap();
+        if output.attached {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(data);
+            let _ = stdout.flush();
+        }
+        output.scrollback.extend(data);
+        let excess = output.scrollback.len().saturating_sub(SCROLLBACK);
+        output.scrollback.drain(..excess);
+    }
+
+    /// Connects the host terminal: from now on output is shown as it is
+    /// written. Returns the scrollback, what was written before.
+    pub fn attach(&self) -> Result<Vec<u8>, String> {
+        let mut output = self.output.lock().unwrap();
+        if output.attached {
+            return Err(format!("{}: already attached", self.name));
+        }
+        output.attached = true;
+        Ok(output.scrollback.iter().copied().collect())
+    }
+
+    pub fn detach(&self) {
+        self.output.lock().unwrap().attached = false;
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.output.lock().unwrap().attached
+    }
+
+    /// What the terminal has shown, oldest first.
+    pub fn scrollback(&self) -> Vec<u8> {
+        self.output.lock().unwrap().scrollback.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::{Process, Scheduler};
+    use std::time::Duration;
+
+    #[test]
+    fn test_processes_print_to_their_terminal_and_children_inherit_it() {
+        let scheduler = Arc::new(Scheduler::with_cpus(1));
+        let (reader, writer) = scheduler.ipc().pipe();
+        let tty = Arc::new(Tty::new("pts/0", writer));
+
+        let inner = scheduler.clone();
+        let parent = Process::new(
+            0,
+            1,
+            Duration::ZERO,
+            Box::new(move || {
+                write_fmt(format_args!("line {}\n", 1));
+                let child = inner
+                    .spawn(Process::new(0, 1, Duration::ZERO, Box::new(|| write_fmt(format_args!("child\n"))), None))
+                    .unwrap();
+                child.join();
+            }),
+            None,
+        )
+        .with_tty(tty.clone());
+        assert_eq!(parent.info.tty.as_deref(), Some("pts/0"));
+        scheduler.add_process(parent);
+        scheduler.run();
+        assert_eq!(tty.scrollback(), b"line 1\nchild\n");
+
+        tty.send("ls").unwrap();
+        tty.hangup();
+        assert!(tty.send("ls").is_err());
+        assert_eq!(reader.read_to_end().unwrap(), b"ls\n");
+
+        // Only the most recent output is kept
+        tty.write(&vec![b'x'; SCROLLBACK + 10]);
+        assert_eq!(tty.scrollback().len(), SCROLLBACK);
+        assert!(tty.attach().is_ok());
+        assert!(tty.attach().is_err());
+        tty.detach();
+        assert!(!tty.is_attached());
+    }
+}
@@ -0,0 +1,119 @@
+/// One bit per block: set means allocated. Allocation always hands out the
+/// lowest free block, so freed blocks are reused before the disk grows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockBitmap {
+    words: Vec<u64>,
+    capacity: u64,
+    used: u64,
+    // No free block exists below this word
+    hint: usize,
+}
+
+impl BlockBitmap {
+    pub fn new(capacity: u64) -> Self {
+        BlockBitmap {
+            words: vec![0; capacity.div_ceil(64) as usize],
+            capacity,
+            used: 0,
+            hint: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    pub fn free_count(&self) -> u64 {
+        self.capacity - self.used
+    }
+
+    pub fn is_allocated(&self, id: u64) -> bool {
+        id < self.capacity && self.words[(id / 64) as usize] & (1 << (id % 64)) != 0
+    }
+
+    pub fn allocate(&mut self) -> Option<u64> {
+        for index in self.hint..self.words.len() {
+            let word = self.words[index];
+            if word == u64::MAX {
+                continue;
+            }
+            let id = index as u64 * 64 + (!word).trailing_zeros() as u64;
+            if id >= self.capacity {
+                break;
+            }
+            self.hint = index;
+            self.mark(id);
+            return Some(id);
+        }
+        self.hint = self.words.len();
+        None
+    }
+
+    /// Marks `id` allocated; false if it is out of range.
+    pub fn mark(&mut self, id: u64) -> bool {
+        if id >= self.capacity {
+            return false;
+        }
+        let word = &mut self.words[(id / 64) as usize];
+        if *word & (1 << (id % 64)) == 0 {
+            *word |= 1 << (id % 64);
+            self.used += 1;
+        }
+        true
+    }
+
+    /// Frees `id`; false if it was not allocated.
+    pub fn free(&mut self, id: u64) -> bool {
+        if !self.is_allocated(id) {
+            return false;
+        }
+        self.words[(id / 64) as usize] &= !(1 << (id % 64));
+        self.used -= 1;
+        self.hint = self.hint.min((id / 64) as usize);
+        true
+    }
+
+    /// Extends the bitmap to `capacity` blocks; never shrinks it.
+    pub fn grow(&mut self, capacity: u64) {
+        if capacity > self.capacity {
+            self.words.resize(capacity.div_ceil(64) as usize, 0);
+            self.capacity = capacity;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = 0);
+        self.used = 0;
+        self.hint = 0;
+    }
+
+    /// Iterates over allocated block ids in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.capacity).filter(|id| self.is_allocated(*id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_reuses_freed_blocks() {
+        let mut bitmap = BlockBitmap::new(130);
+        for expected in 0..130 {
+            assert_eq!(bitmap.allocate(), Some(expected));
+        }
+        assert_eq!(bitmap.allocate(), None);
+        assert!(bitmap.free(70));
+        assert!(bitmap.free(3));
+        assert!(!bitmap.free(3));
+        assert_eq!(bitmap.allocate(), Some(3));
+        assert_eq!(bitmap.allocate(), Some(70));
+        assert_eq!(bitmap.used(), 130);
+        assert!(!bitmap.mark(130));
+    }
+}
@@ -8,6 +8,12 @@ pub struct DevDirectory {
     devices: HashMap<String, Arc<dyn VfsNode>>,
 }
 
+impl Default for DevDirectory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DevDirectory {
     pub fn new() -> Self {
         let mut devices = HashMap::new();
@@ -36,6 +42,10 @@ impl VfsNode for DevDirectory {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
 }
 
 impl DirectoryOps for DevDirectory {
@@ -47,14 +57,28 @@ impl DirectoryOps for DevDirectory {
         self.devices.get(name).cloned()
     }
 
-    fn add(&self, _node: Arc<dyn VfsNode>) {
-        // Read-only
+    fn add(&self, _node: Arc<dyn VfsNode>) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+
+    fn remove(&self, _name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        Err("Read-only file system".into())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Read-only file system".into())
     }
 }
 
 // /dev/null - discards all writes, reads return empty
 pub struct NullDevice;
 
+impl Default for NullDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NullDevice {
     pub fn new() -> Self {
         NullDevice
@@ -86,6 +110,12 @@ impl VfsNode for NullDevice {
 // /dev/random - returns random bytes
 pub struct RandomDevice;
 
+impl Default for RandomDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RandomDevice {
     pub fn new() -> Self {
         RandomDevice
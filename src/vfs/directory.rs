@@ -1,12 +1,15 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use super::{DirectoryOps, NodeType, VfsNode};
+use super::metadata::Generation;
+use super::{DirectoryOps, Metadata, NodeType, VfsNode};
 
 #[derive(Clone)]
 pub struct Directory {
     pub name: String,
     pub children: Arc<Mutex<HashMap<String, Arc<dyn VfsNode>>>>,
+    pub meta: Arc<Mutex<Metadata>>,
+    pub(crate) generation: Arc<Generation>,
 }
 
 impl Directory {
@@ -14,11 +17,16 @@ impl Directory {
         Directory {
             name: name.to_string(),
             children: Arc::new(Mutex::new(HashMap::new())),
+            meta: Arc::new(Mutex::new(Metadata::new(NodeType::Directory, 0o755))),
+            generation: Arc::new(Generation::default()),
         }
     }
 
     pub fn add(&self, node: Arc<dyn VfsNode>) {
-        self.children.lock().unwrap().insert(node.name(), node);
+        let mut children = self.children.lock().unwrap();
+        children.insert(node.name(), node);
+        self.meta.lock().unwrap().touch_modify();
+        self.generation.data_changed();
     }
 
     pub fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
@@ -28,6 +36,20 @@ impl Directory {
     pub fn list(&self) -> Vec<String> {
         self.children.lock().unwrap().keys().cloned().collect()
     }
+
+    pub fn remove(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        let mut children = self.children.lock().unwrap();
+        let removed = children.remove(name);
+        if removed.is_some() {
+            self.meta.lock().unwrap().touch_modify();
+            self.generation.data_changed();
+        }
+        removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.lock().unwrap().is_empty()
+    }
 }
 
 impl DirectoryOps for Directory {
@@ -39,8 +61,24 @@ impl DirectoryOps for Directory {
         self.get(name)
     }
 
-    fn add(&self, node: Arc<dyn VfsNode>) {
+    fn add(&self, node: Arc<dyn VfsNode>) -> Result<(), String> {
         self.add(node);
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        self.remove(name).ok_or_else(|| "No such file or directory".to_string())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<(), String> {
+        let mut children = self.children.lock().unwrap();
+        let node = children.get(from).ok_or("No such file or directory")?;
+        let renamed = node.with_name(to).ok_or("Operation not supported")?;
+        children.remove(from);
+        children.insert(to.to_string(), renamed);
+        self.meta.lock().unwrap().touch_modify();
+        self.generation.data_changed();
+        Ok(())
     }
 }
 
@@ -56,4 +94,38 @@ impl VfsNode for Directory {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
+
+    fn with_name(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        let mut dir = self.clone();
+        dir.name = name.to_string();
+        dir.meta.lock().unwrap().touch_change();
+        dir.generation.meta_changed();
+        Some(Arc::new(dir))
+    }
+
+    fn metadata(&self) -> Metadata {
+        let children = self.children.lock().unwrap();
+        let subdirs = children
+            .values()
+            .filter(|c| c.node_type() == NodeType::Directory)
+            .count();
+        let mut meta = *self.meta.lock().unwrap();
+        meta.size = children.len() as u64;
+        meta.nlink = 2 + subdirs as u32;
+        meta
+    }
+
+    fn set_permissions(&self, mode: u32, uid: u32, gid: u32) -> Result<(), String> {
+        let mut meta = self.meta.lock().unwrap();
+        meta.mode = mode & 0o7777;
+        meta.uid = uid;
+        meta.gid = gid;
+        meta.touch_change();
+        self.generation.meta_changed();
+        Ok(())
+    }
 }
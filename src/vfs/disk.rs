@@ -1,166 +1,666 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
+use std::io::Write;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct DiskData {
-    blocks: HashMap<u64, Vec<u8>>,
-    checksum: u64,
+use super::allocator::BlockBitmap;
+use super::image::{self, Image, ImageBlocks, IMAGE_VERSION};
+use super::journal::{Journal, JournalOp, Recovered};
+use super::metadata::now;
+
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+/// 64 MiB with the default block size.
+pub const DEFAULT_CAPACITY_BLOCKS: u64 = 16 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskError {
+    /// ENOSPC: every block is allocated.
+    NoSpace,
+    /// The block is outside the disk or not allocated.
+    InvalidBlock(u64),
+    /// A write larger than the block size.
+    TooLarge { len: usize, block_size: usize },
+    NoSuchSnapshot(u64),
+    Io(String),
+}
+
+impl fmt::Display for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskError::NoSpace => write!(f, "No space left on device"),
+            DiskError::InvalidBlock(id) => write!(f, "block {} is not allocated", id),
+            DiskError::TooLarge { len, block_size } => {
+                write!(f, "write of {} bytes exceeds block size {}", len, block_size)
+            }
+            DiskError::NoSuchSnapshot(id) => write!(f, "no such snapshot: {}", id),
+            DiskError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl From<DiskError> for String {
+    fn from(e: DiskError) -> Self {
+        e.to_string()
+    }
 }
 
+/// A block device with `capacity()` blocks of at most `block_size()` bytes.
+/// Blocks must be allocated before they are written; an allocated block
+/// that was never written reads back empty.
 pub trait Disk: Send + Sync {
-    fn read_block(&self, block_id: u64) -> Option<Vec<u8>>;
-    fn write_block(&self, block_id: u64, data: &[u8]);
-    fn allocate_block(&self) -> u64;
-    fn free_block(&self, block_id: u64);
+    fn read_block(&self, block_id: u64) -> Result<Vec<u8>, DiskError>;
+    fn write_block(&self, block_id: u64, data: &[u8]) -> Result<(), DiskError>;
+    fn allocate_block(&self) -> Result<u64, DiskError>;
+    fn free_block(&self, block_id: u64) -> Result<(), DiskError>;
     fn get_allocated_blocks(&self) -> usize;
     fn get_total_size(&self) -> usize;
     fn clear_all(&self);
+    fn block_size(&self) -> usize;
+    fn capacity(&self) -> u64;
+
+    /// Records the current contents and returns the snapshot id.
+    fn snapshot(&self) -> Result<u64, DiskError>;
+    fn list_snapshots(&self) -> Vec<SnapshotInfo>;
+    /// Rolls the disk back to snapshot `id`. The snapshot is kept.
+    fn restore(&self, id: u64) -> Result<(), DiskError>;
+    fn delete_snapshot(&self, id: u64) -> Result<(), DiskError>;
+    /// An independent in-memory disk with the same geometry and contents.
+    /// Blocks are shared until either side writes them.
+    fn clone_cow(&self) -> Arc<dyn Disk + Send + Sync>;
+
+    /// Makes everything written so far durable. In-memory disks have
+    /// nothing to do.
+    fn flush(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub id: u64,
+    /// Seconds since the epoch.
+    pub created: u64,
+    pub blocks: usize,
+}
+
+/// Block contents plus allocation state. Contents are reference counted so
+/// snapshots and clones share them: a write replaces a block's `Arc`, it
+/// never mutates one.
+#[derive(Clone)]
+pub(crate) struct BlockMap {
+    blocks: HashMap<u64, Arc<Vec<u8>>>,
+    bitmap: BlockBitmap,
+}
+
+impl BlockMap {
+    fn new(capacity: u64) -> Self {
+        BlockMap {
+            blocks: HashMap::new(),
+            bitmap: BlockBitmap::new(capacity),
+        }
+    }
+
+    /// Rebuilds the allocation state from stored blocks, growing the
+    /// capacity to fit them.
+    pub(crate) fn from_blocks(blocks: ImageBlocks, capacity: u64) -> Self {
+        let highest = blocks.keys().max().map(|&k| k + 1).unwrap_or(0);
+        let mut bitmap = BlockBitmap::new(capacity.max(highest));
+        for id in blocks.keys() {
+            bitmap.mark(*id);
+        }
+        BlockMap {
+            blocks: blocks.into_iter().map(|(id, data)| (id, Arc::new(data))).collect(),
+            bitmap,
+        }
+    }
+
+    fn read(&self, block_id: u64) -> Result<Vec<u8>, DiskError> {
+        if !self.bitmap.is_allocated(block_id) {
+            return Err(DiskError::InvalidBlock(block_id));
+        }
+        Ok(self.blocks.get(&block_id).map(|b| b.to_vec()).unwrap_or_default())
+    }
+
+    fn check_write(&self, block_size: usize, block_id: u64, data: &[u8]) -> Result<(), DiskError> {
+        if !self.bitmap.is_allocated(block_id) {
+            return Err(DiskError::InvalidBlock(block_id));
+        }
+        if data.len() > block_size {
+            return Err(DiskError::TooLarge {
+                len: data.len(),
+                block_size,
+            });
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, op: &JournalOp) {
+        match op {
+            JournalOp::Write(id, data) => {
+                self.bitmap.mark(*id);
+                self.blocks.insert(*id, Arc::new(data.clone()));
+            }
+            JournalOp::Free(id) => {
+                self.bitmap.free(*id);
+                self.blocks.remove(id);
+            }
+            JournalOp::Clear => {
+                self.bitmap.clear();
+                self.blocks.clear();
+            }
+        }
+    }
+
+    /// Applies journal records on top of a loaded image; writes past the
+    /// capacity grow it.
+    pub(crate) fn replay(&mut self, ops: &[JournalOp]) {
+        for op in ops {
+            if let JournalOp::Write(id, _) = op {
+                self.bitmap.grow(id + 1);
+            }
+            self.apply(op);
+        }
+    }
+
+    pub(crate) fn allocated(&self) -> Vec<u64> {
+        self.bitmap.iter().collect()
+    }
+
+    fn total_size(&self) -> usize {
+        self.blocks.values().map(|v| v.len()).sum()
+    }
+
+    /// Every allocated block, written or not, in id order.
+    fn to_sorted(&self) -> ImageBlocks {
+        self.bitmap
+            .iter()
+            .map(|id| (id, self.blocks.get(&id).map(|b| b.to_vec()).unwrap_or_default()))
+            .collect()
+    }
+}
+
+struct Snapshot {
+    created: u64,
+    image: BlockMap,
+}
+
+#[derive(Default)]
+struct Snapshots {
+    taken: BTreeMap<u64, Snapshot>,
+    next_id: u64,
+}
+
+impl Snapshots {
+    fn add(&mut self, image: BlockMap) -> u64 {
+        self.next_id = self.next_id.max(self.taken.keys().max().copied().unwrap_or(0)) + 1;
+        self.taken.insert(
+            self.next_id,
+            Snapshot {
+                created: now(),
+                image,
+            },
+        );
+        self.next_id
+    }
+
+    fn get(&self, id: u64) -> Result<BlockMap, DiskError> {
+        self.taken
+            .get(&id)
+            .map(|s| s.image.clone())
+            .ok_or(DiskError::NoSuchSnapshot(id))
+    }
+
+    fn list(&self) -> Vec<SnapshotInfo> {
+        self.taken
+            .iter()
+            .map(|(id, s)| SnapshotInfo {
+                id: *id,
+                created: s.created,
+                blocks: s.image.bitmap.used() as usize,
+            })
+            .collect()
+    }
 }
 
 #[derive(Clone)]
 pub struct RamDisk {
-    pub(crate) blocks: Arc<Mutex<HashMap<u64, Vec<u8>>>>,
-    pub(crate) next_block: Arc<Mutex<u64>>,
+    state: Arc<Mutex<BlockMap>>,
+    snapshots: Arc<Mutex<Snapshots>>,
+    block_size: usize,
+}
+
+impl Default for RamDisk {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RamDisk {
     pub fn new() -> Self {
+        Self::with_geometry(DEFAULT_BLOCK_SIZE, DEFAULT_CAPACITY_BLOCKS)
+    }
+
+    pub fn with_geometry(block_size: usize, capacity: u64) -> Self {
+        Self::from_state(BlockMap::new(capacity), block_size)
+    }
+
+    pub(crate) fn from_state(state: BlockMap, block_size: usize) -> Self {
         RamDisk {
-            blocks: Arc::new(Mutex::new(HashMap::new())),
-            next_block: Arc::new(Mutex::new(0)),
+            state: Arc::new(Mutex::new(state)),
+            snapshots: Arc::new(Mutex::new(Snapshots::default())),
+            block_size,
         }
     }
 }
 
 impl Disk for RamDisk {
-    fn read_block(&self, block_id: u64) -> Option<Vec<u8>> {
-        self.blocks.lock().unwrap().get(&block_id).cloned()
+    fn read_block(&self, block_id: u64) -> Result<Vec<u8>, DiskError> {
+        self.state.lock().unwrap().read(block_id)
     }
 
-    fn write_block(&self, block_id: u64, data: &[u8]) {
-        self.blocks.lock().unwrap().insert(block_id, data.to_vec());
+    fn write_block(&self, block_id: u64, data: &[u8]) -> Result<(), DiskError> {
+        let mut state = self.state.lock().unwrap();
+        state.check_write(self.block_size, block_id, data)?;
+        state.blocks.insert(block_id, Arc::new(data.to_vec()));
+        Ok(())
     }
 
-    fn allocate_block(&self) -> u64 {
-        let mut next = self.next_block.lock().unwrap();
-        let id = *next;
-        *next += 1;
-        id
+    fn allocate_block(&self) -> Result<u64, DiskError> {
+        self.state.lock().unwrap().bitmap.allocate().ok_or(DiskError::NoSpace)
     }
 
-    fn free_block(&self, block_id: u64) {
-        self.blocks.lock().unwrap().remove(&block_id);
+    fn free_block(&self, block_id: u64) -> Result<(), DiskError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.bitmap.free(block_id) {
+            return Err(DiskError::InvalidBlock(block_id));
+        }
+        state.blocks.remove(&block_id);
+        Ok(())
     }
 
     fn get_allocated_blocks(&self) -> usize {
-        self.blocks.lock().unwrap().len()
+        self.state.lock().unwrap().bitmap.used() as usize
     }
 
     fn get_total_size(&self) -> usize {
-        self.blocks.lock().unwrap().values().map(|v| v.len()).sum()
+        self.state.lock().unwrap().total_size()
     }
 
     fn clear_all(&self) {
-        self.blocks.lock().unwrap().clear();
-        *self.next_block.lock().unwrap() = 0;
+        self.state.lock().unwrap().apply(&JournalOp::Clear);
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn capacity(&self) -> u64 {
+        self.state.lock().unwrap().bitmap.capacity()
+    }
+
+    fn snapshot(&self) -> Result<u64, DiskError> {
+        let image = self.state.lock().unwrap().clone();
+        Ok(self.snapshots.lock().unwrap().add(image))
+    }
+
+    fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.snapshots.lock().unwrap().list()
+    }
+
+    fn restore(&self, id: u64) -> Result<(), DiskError> {
+        let image = self.snapshots.lock().unwrap().get(id)?;
+        *self.state.lock().unwrap() = image;
+        Ok(())
+    }
+
+    fn delete_snapshot(&self, id: u64) -> Result<(), DiskError> {
+        self.snapshots
+            .lock()
+            .unwrap()
+            .taken
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(DiskError::NoSuchSnapshot(id))
+    }
+
+    fn clone_cow(&self) -> Arc<dyn Disk + Send + Sync> {
+        Arc::new(RamDisk::from_state(self.state.lock().unwrap().clone(), self.block_size))
     }
 }
 
+/// Journal size that triggers a checkpoint.
+pub const JOURNAL_COMPACT_BYTES: u64 = 1 << 20;
+
+/// A disk image on the host. Mutations are appended to a write-ahead
+/// journal (`<image>.journal`) and folded into the image by periodic
+/// checkpoints, which write `<image>.tmp` and atomically rename it over the
+/// image. Opening replays whatever the journal holds.
+///
+/// Allocating a block journals an empty write, so every allocated block is
+/// present in the image and the bitmap is rebuilt from the stored block ids.
+/// Snapshots are full images stored next to it as `<image>.snap-<id>`.
 #[derive(Clone)]
 pub struct FileDisk {
-    pub(crate) blocks: Arc<Mutex<HashMap<u64, Vec<u8>>>>,
-    pub(crate) next_block: Arc<Mutex<u64>>,
+    state: Arc<Mutex<BlockMap>>,
+    snapshots: Arc<Mutex<Snapshots>>,
     pub(crate) file_path: String,
+    block_size: usize,
+    journal: Arc<Mutex<Journal>>,
 }
 
 impl FileDisk {
     pub fn new(file_path: &str) -> Self {
+        Self::with_geometry(file_path, DEFAULT_BLOCK_SIZE, DEFAULT_CAPACITY_BLOCKS)
+    }
+
+    /// Opens (or creates) an image of `capacity` blocks of `block_size`
+    /// bytes. An existing image keeps its own block size, and its capacity
+    /// only ever grows. Images in an older format are converted, keeping the
+    /// original as `<image>.v0`. A journal that cannot be opened is reported
+    /// and the disk runs without one, saving changes only at checkpoints.
+    pub fn with_geometry(file_path: &str, block_size: usize, capacity: u64) -> Self {
         let path = Path::new(file_path);
-        let (blocks, next_block) = if path.exists() {
-            // Load from file
-            let data = fs::read(path).unwrap_or_default();
-            if let Ok(disk_data) = bincode::deserialize::<DiskData>(&data) {
-                // Verify checksum
-                let serialized_blocks = bincode::serialize(&disk_data.blocks).unwrap();
-                let mut hasher = DefaultHasher::new();
-                serialized_blocks.hash(&mut hasher);
-                if hasher.finish() == disk_data.checksum {
-                    let next_block = disk_data.blocks.keys().max().map(|&k| k + 1).unwrap_or(0);
-                    (disk_data.blocks, next_block)
-                } else {
-                    eprintln!("Checksum mismatch, initializing empty disk");
-                    (HashMap::new(), 0)
+        let mut block_size = block_size;
+        let mut capacity = capacity;
+        let mut migrate = false;
+        let blocks = if path.exists() {
+            let (image, intact) = Self::load_checkpoint(path);
+            if let Some(stored) = image.block_size {
+                if stored != block_size {
+                    eprintln!(
+                        "{}: image uses {}-byte blocks, not {}",
+                        file_path, stored, block_size
+                    );
+                    block_size = stored;
                 }
-            } else {
-                (HashMap::new(), 0)
             }
+            capacity = capacity.max(image.capacity.unwrap_or(0));
+            migrate = intact && image.version < IMAGE_VERSION;
+            image.blocks
         } else {
-            (HashMap::new(), 0)
+            BTreeMap::new()
+        };
+        let mut state = BlockMap::from_blocks(blocks, capacity);
+
+        let journal_path = format!("{}.journal", file_path);
+        let (journal, recovered) = match Journal::open(Path::new(&journal_path)) {
+            Ok(opened) => opened,
+            Err(e) => {
+                eprintln!(
+                    "{}: cannot open journal: {}; changes are only saved at checkpoints",
+                    journal_path, e
+                );
+                let recovered = Recovered {
+                    ops: Vec::new(),
+                    dropped_bytes: 0,
+                };
+                (Journal::detached(Path::new(&journal_path)), recovered)
+            }
         };
-        FileDisk {
-            blocks: Arc::new(Mutex::new(blocks)),
-            next_block: Arc::new(Mutex::new(next_block)),
+        if recovered.dropped_bytes > 0 {
+            eprintln!(
+                "{}: discarded {} bytes of incomplete journal records",
+                journal_path, recovered.dropped_bytes
+            );
+        }
+        let replayed = recovered.ops.len();
+        state.replay(&recovered.ops);
+        if state.bitmap.capacity() > capacity {
+            eprintln!(
+                "{}: image holds {} blocks, more than the configured {}",
+                file_path,
+                state.bitmap.capacity(),
+                capacity
+            );
+        }
+
+        let disk = FileDisk {
+            state: Arc::new(Mutex::new(state)),
+            snapshots: Arc::new(Mutex::new(Self::load_snapshots(path, block_size, capacity))),
             file_path: file_path.to_string(),
+            block_size,
+            journal: Arc::new(Mutex::new(journal)),
+        };
+        if migrate {
+            eprintln!("{}: converting to image format v{}", file_path, IMAGE_VERSION);
+            let _ = fs::copy(path, format!("{}.v0", file_path));
+        }
+        if replayed > 0 || migrate {
+            if let Err(e) = disk.checkpoint() {
+                eprintln!("{}: checkpoint after recovery failed: {}", file_path, e);
+            }
         }
+        disk
     }
 
-    fn save(&self) {
-        let blocks = self.blocks.lock().unwrap();
-        let serialized_blocks = bincode::serialize(&*blocks).unwrap();
-        let mut hasher = DefaultHasher::new();
-        serialized_blocks.hash(&mut hasher);
-        let checksum = hasher.finish();
-        let disk_data = DiskData {
-            blocks: blocks.clone(),
-            checksum,
+    /// Reads the last checkpoint and whether it was intact. A damaged
+    /// checkpoint is kept aside as `<image>.corrupt` and its readable blocks
+    /// are still used, since the journal replay may repair them.
+    fn load_checkpoint(path: &Path) -> (Image, bool) {
+        let decoded = image::decode(&fs::read(path).unwrap_or_default());
+        if decoded.problems.is_empty() {
+            return (decoded.image, true);
+        }
+        eprintln!(
+            "{}: {}; keeping a copy and recovering what is readable",
+            path.display(),
+            decoded.problems.join(", ")
+        );
+        let _ = fs::copy(path, format!("{}.corrupt", path.display()));
+        (decoded.image, false)
+    }
+
+    /// Writes an image to `<path>.tmp` and atomically renames it to `path`.
+    fn write_image(path: &str, block_size: usize, capacity: u64, blocks: &ImageBlocks) -> Result<(), String> {
+        let data = image::encode(block_size, capacity, blocks);
+        let tmp_path = format!("{}.tmp", path);
+        let mut tmp = fs::File::create(&tmp_path).map_err(|e| e.to_string())?;
+        tmp.write_all(&data).map_err(|e| e.to_string())?;
+        tmp.sync_all().map_err(|e| e.to_string())?;
+        drop(tmp);
+        fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+    }
+
+    fn snapshot_path(&self, id: u64) -> String {
+        format!("{}.snap-{}", self.file_path, id)
+    }
+
+    /// Loads every `<image>.snap-<id>` next to the image. Damaged snapshots
+    /// are skipped; old-format ones are converted.
+    fn load_snapshots(path: &Path, block_size: usize, capacity: u64) -> Snapshots {
+        let mut snapshots = Snapshots::default();
+        let prefix = match path.file_name() {
+            Some(name) => format!("{}.snap-", name.to_string_lossy()),
+            None => return snapshots,
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return snapshots,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let id = match name.strip_prefix(&prefix).and_then(|id| id.parse::<u64>().ok()) {
+                Some(id) => id,
+                None => continue,
+            };
+            let decoded = image::decode(&fs::read(entry.path()).unwrap_or_default());
+            if !decoded.problems.is_empty() {
+                eprintln!(
+                    "{}: {}; ignoring snapshot",
+                    entry.path().display(),
+                    decoded.problems.join(", ")
+                );
+                continue;
+            }
+            let created = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let capacity = capacity.max(decoded.image.capacity.unwrap_or(0));
+            if decoded.image.version < IMAGE_VERSION {
+                let entry_path = entry.path().to_string_lossy().to_string();
+                let _ = Self::write_image(&entry_path, block_size, capacity, &decoded.image.blocks);
+            }
+            snapshots.taken.insert(
+                id,
+                Snapshot {
+                    created,
+                    image: BlockMap::from_blocks(decoded.image.blocks, capacity),
+                },
+            );
+        }
+        snapshots.next_id = snapshots.taken.keys().max().copied().unwrap_or(0);
+        snapshots
+    }
+
+    /// Writes the current blocks to a new image, atomically replaces the old
+    /// one and empties the journal.
+    pub fn checkpoint(&self) -> Result<(), String> {
+        let mut journal = self.journal.lock().unwrap();
+        self.checkpoint_locked(&mut journal)
+    }
+
+    fn checkpoint_locked(&self, journal: &mut Journal) -> Result<(), String> {
+        let (blocks, capacity) = {
+            let state = self.state.lock().unwrap();
+            (state.to_sorted(), state.bitmap.capacity())
+        };
+        Self::write_image(&self.file_path, self.block_size, capacity, &blocks)?;
+        journal.reset().map_err(|e| e.to_string())
+    }
+
+    /// Ids of every allocated block, in order.
+    pub(crate) fn allocated(&self) -> Vec<u64> {
+        self.state.lock().unwrap().allocated()
+    }
+
+    pub fn journal_size(&self) -> u64 {
+        self.journal.lock().unwrap().len()
+    }
+
+    /// Logs `op` and applies it in memory; checkpoints when the journal
+    /// grows past `JOURNAL_COMPACT_BYTES`. Nothing changes if the journal
+    /// write fails.
+    fn commit(&self, op: JournalOp) -> Result<(), DiskError> {
+        let compact = {
+            let mut journal = self.journal.lock().unwrap();
+            journal
+                .append(&op)
+                .map_err(|e| DiskError::Io(format!("{}: {}", journal.path().display(), e)))?;
+            self.state.lock().unwrap().apply(&op);
+            journal.len() >= JOURNAL_COMPACT_BYTES
         };
-        let data = bincode::serialize(&disk_data).unwrap();
-        fs::write(&self.file_path, data).unwrap();
+        if compact {
+            if let Err(e) = self.checkpoint() {
+                eprintln!("{}: checkpoint failed: {}", self.file_path, e);
+            }
+        }
+        Ok(())
     }
 }
 
 impl Disk for FileDisk {
-    fn read_block(&self, block_id: u64) -> Option<Vec<u8>> {
-        self.blocks.lock().unwrap().get(&block_id).cloned()
+    fn read_block(&self, block_id: u64) -> Result<Vec<u8>, DiskError> {
+        self.state.lock().unwrap().read(block_id)
     }
 
-    fn write_block(&self, block_id: u64, data: &[u8]) {
-        {
-            self.blocks.lock().unwrap().insert(block_id, data.to_vec());
-        }
-        self.save();
+    fn write_block(&self, block_id: u64, data: &[u8]) -> Result<(), DiskError> {
+        self.state.lock().unwrap().check_write(self.block_size, block_id, data)?;
+        self.commit(JournalOp::Write(block_id, data.to_vec()))
     }
 
-    fn allocate_block(&self) -> u64 {
-        let mut next = self.next_block.lock().unwrap();
-        let id = *next;
-        *next += 1;
-        id
+    fn allocate_block(&self) -> Result<u64, DiskError> {
+        let id = self.state.lock().unwrap().bitmap.allocate().ok_or(DiskError::NoSpace)?;
+        if let Err(e) = self.commit(JournalOp::Write(id, Vec::new())) {
+            self.state.lock().unwrap().bitmap.free(id);
+            return Err(e);
+        }
+        Ok(id)
     }
 
-    fn free_block(&self, block_id: u64) {
-        {
-            self.blocks.lock().unwrap().remove(&block_id);
+    fn free_block(&self, block_id: u64) -> Result<(), DiskError> {
+        if !self.state.lock().unwrap().bitmap.is_allocated(block_id) {
+            return Err(DiskError::InvalidBlock(block_id));
         }
-        self.save();
+        self.commit(JournalOp::Free(block_id))
     }
 
     fn get_allocated_blocks(&self) -> usize {
-        self.blocks.lock().unwrap().len()
+        self.state.lock().unwrap().bitmap.used() as usize
     }
 
     fn get_total_size(&self) -> usize {
-        self.blocks.lock().unwrap().values().map(|v| v.len()).sum()
+        self.state.lock().unwrap().total_size()
     }
 
     fn clear_all(&self) {
-        self.blocks.lock().unwrap().clear();
-        *self.next_block.lock().unwrap() = 0;
-        self.save();
+        if let Err(e) = self.commit(JournalOp::Clear) {
+            eprintln!("{}: {}", self.file_path, e);
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn capacity(&self) -> u64 {
+        self.state.lock().unwrap().bitmap.capacity()
+    }
+
+    fn snapshot(&self) -> Result<u64, DiskError> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let image = self.state.lock().unwrap().clone();
+        let id = snapshots.add(image.clone());
+        let capacity = image.bitmap.capacity();
+        if let Err(e) = Self::write_image(&self.snapshot_path(id), self.block_size, capacity, &image.to_sorted()) {
+            snapshots.taken.remove(&id);
+            return Err(DiskError::Io(e));
+        }
+        Ok(id)
+    }
+
+    fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.snapshots.lock().unwrap().list()
+    }
+
+    /// Swaps the contents and checkpoints while holding the journal, so the
+    /// rollback is atomic on the host too.
+    fn restore(&self, id: u64) -> Result<(), DiskError> {
+        let image = self.snapshots.lock().unwrap().get(id)?;
+        let mut journal = self.journal.lock().unwrap();
+        let previous = std::mem::replace(&mut *self.state.lock().unwrap(), image);
+        if let Err(e) = self.checkpoint_locked(&mut journal) {
+            *self.state.lock().unwrap() = previous;
+            return Err(DiskError::Io(e));
+        }
+        Ok(())
+    }
+
+    fn delete_snapshot(&self, id: u64) -> Result<(), DiskError> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        if !snapshots.taken.contains_key(&id) {
+            return Err(DiskError::NoSuchSnapshot(id));
+        }
+        fs::remove_file(self.snapshot_path(id)).map_err(|e| DiskError::Io(e.to_string()))?;
+        snapshots.taken.remove(&id);
+        Ok(())
+    }
+
+    fn clone_cow(&self) -> Arc<dyn Disk + Send + Sync> {
+        Arc::new(RamDisk::from_state(self.state.lock().unwrap().clone(), self.block_size))
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        self.checkpoint()
     }
 }
 
@@ -171,23 +671,193 @@ mod tests {
     #[test]
     fn test_ram_disk() {
         let disk = RamDisk::new();
-        let block_id = disk.allocate_block();
-        disk.write_block(block_id, b"test data");
+        let block_id = disk.allocate_block().unwrap();
+        disk.write_block(block_id, b"test data").unwrap();
         let data = disk.read_block(block_id).unwrap();
         assert_eq!(data, b"test data");
-        disk.free_block(block_id);
-        assert!(disk.read_block(block_id).is_none());
+        disk.free_block(block_id).unwrap();
+        assert_eq!(disk.read_block(block_id), Err(DiskError::InvalidBlock(block_id)));
+    }
+
+    #[test]
+    fn test_disk_capacity() {
+        let disk = RamDisk::with_geometry(16, 2);
+        let a = disk.allocate_block().unwrap();
+        let b = disk.allocate_block().unwrap();
+        assert_eq!(disk.allocate_block(), Err(DiskError::NoSpace));
+        assert!(matches!(disk.write_block(a, &[0; 17]), Err(DiskError::TooLarge { .. })));
+        assert_eq!(disk.write_block(5, b"x"), Err(DiskError::InvalidBlock(5)));
+
+        disk.free_block(a).unwrap();
+        assert_eq!(disk.allocate_block(), Ok(a));
+        assert_eq!(disk.read_block(a).unwrap(), b"");
+        assert_eq!(disk.get_allocated_blocks(), 2);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_snapshot_restore_and_clone() {
+        let disk = RamDisk::new();
+        let block = disk.allocate_block().unwrap();
+        disk.write_block(block, b"before").unwrap();
+        let id = disk.snapshot().unwrap();
+
+        let clone = disk.clone_cow();
+        disk.write_block(block, b"after").unwrap();
+        let extra = disk.allocate_block().unwrap();
+        assert_eq!(clone.read_block(block).unwrap(), b"before");
+        clone.write_block(block, b"clone").unwrap();
+        assert_eq!(disk.read_block(block).unwrap(), b"after");
+
+        disk.restore(id).unwrap();
+        assert_eq!(disk.read_block(block).unwrap(), b"before");
+        assert!(disk.read_block(extra).is_err());
+        assert_eq!(disk.list_snapshots().len(), 1);
+        assert_eq!(disk.list_snapshots()[0].blocks, 1);
+        assert_eq!(disk.restore(id + 1), Err(DiskError::NoSuchSnapshot(id + 1)));
+        disk.delete_snapshot(id).unwrap();
+        assert!(disk.list_snapshots().is_empty());
     }
 
     #[test]
     fn test_file_disk() {
         let path = "test_disk.bin";
         let disk = FileDisk::new(path);
-        let block_id = disk.allocate_block();
-        disk.write_block(block_id, b"file test");
+        let block_id = disk.allocate_block().unwrap();
+        disk.write_block(block_id, b"file test").unwrap();
         let data = disk.read_block(block_id).unwrap();
         assert_eq!(data, b"file test");
-        disk.free_block(block_id);
+        disk.free_block(block_id).unwrap();
+        let _ = fs::remove_file(path);
+        fs::remove_file(format!("{}.journal", path)).unwrap();
+    }
+
+    #[test]
+    fn test_file_disk_replays_journal() {
+        let path = "test_disk_replay.bin";
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(format!("{}.journal", path));
+        {
+            let disk = FileDisk::new(path);
+            let a = disk.allocate_block().unwrap();
+            let b = disk.allocate_block().unwrap();
+            let c = disk.allocate_block().unwrap();
+            disk.write_block(a, b"first").unwrap();
+            disk.write_block(b, b"second").unwrap();
+            disk.free_block(a).unwrap();
+            assert_eq!(c, 2);
+            // No checkpoint: the image does not exist yet, only the journal
+            assert!(!Path::new(path).exists());
+        }
+
+        let disk = FileDisk::new(path);
+        assert!(disk.read_block(0).is_err());
+        assert_eq!(disk.read_block(1).unwrap(), b"second");
+        // Allocated but never written blocks survive too
+        assert_eq!(disk.read_block(2).unwrap(), b"");
+        // Recovery checkpoints and empties the journal
+        assert!(Path::new(path).exists());
+        assert_eq!(disk.journal_size(), 0);
+        // The bitmap was rebuilt: the freed block is handed out again
+        assert_eq!(disk.allocate_block(), Ok(0));
+
         fs::remove_file(path).unwrap();
+        fs::remove_file(format!("{}.journal", path)).unwrap();
+    }
+
+    #[test]
+    fn test_file_disk_compacts_journal() {
+        let path = "test_disk_compact.bin";
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(format!("{}.journal", path));
+        let disk = FileDisk::with_geometry(path, 64 * 1024, 16);
+        let block = disk.allocate_block().unwrap();
+        let data = vec![0xAB; 64 * 1024];
+        for _ in 0..20 {
+            disk.write_block(block, &data).unwrap();
+        }
+        assert!(disk.journal_size() < JOURNAL_COMPACT_BYTES);
+        drop(disk);
+
+        let disk = FileDisk::new(path);
+        assert_eq!(disk.read_block(block).unwrap(), data);
+        fs::remove_file(path).unwrap();
+        fs::remove_file(format!("{}.journal", path)).unwrap();
+    }
+
+    #[test]
+    fn test_file_disk_runs_without_a_journal_it_cannot_open() {
+        let path = "test_disk_no_journal.bin";
+        let journal = format!("{}.journal", path);
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_dir(&journal);
+        fs::create_dir(&journal).unwrap();
+
+        let disk = FileDisk::with_geometry(path, 64, 8);
+        let block = disk.allocate_block().unwrap();
+        disk.write_block(block, b"kept").unwrap();
+        assert_eq!(disk.journal_size(), 0);
+        disk.checkpoint().unwrap();
+        drop(disk);
+
+        let disk = FileDisk::with_geometry(path, 64, 8);
+        assert_eq!(disk.read_block(block).unwrap(), b"kept");
+        fs::remove_file(path).unwrap();
+        fs::remove_dir(&journal).unwrap();
+    }
+
+    #[test]
+    fn test_file_disk_keeps_a_damaged_image_aside() {
+        let path = "test_disk_damaged.bin";
+        let cleanup = || {
+            for suffix in ["", ".journal", ".corrupt"] {
+                let _ = fs::remove_file(format!("{}{}", path, suffix));
+            }
+        };
+        cleanup();
+        let disk = FileDisk::with_geometry(path, 64, 8);
+        let block = disk.allocate_block().unwrap();
+        disk.write_block(block, b"data").unwrap();
+        disk.checkpoint().unwrap();
+        drop(disk);
+        let mut bytes = fs::read(path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(path, &bytes).unwrap();
+
+        // The copy keeps the image's whole name, so it stays with its owner
+        drop(FileDisk::with_geometry(path, 64, 8));
+        assert_eq!(fs::read(format!("{}.corrupt", path)).unwrap(), bytes);
+        assert!(!Path::new("test_disk_damaged.corrupt").exists());
+        cleanup();
+    }
+
+    #[test]
+    fn test_file_disk_snapshots_persist() {
+        let path = "test_disk_snapshots.bin";
+        let cleanup = || {
+            for suffix in ["", ".journal", ".snap-1"] {
+                let _ = fs::remove_file(format!("{}{}", path, suffix));
+            }
+        };
+        cleanup();
+        let disk = FileDisk::new(path);
+        let block = disk.allocate_block().unwrap();
+        disk.write_block(block, b"v1").unwrap();
+        let id = disk.snapshot().unwrap();
+        disk.write_block(block, b"v2").unwrap();
+        drop(disk);
+
+        let disk = FileDisk::new(path);
+        assert_eq!(disk.read_block(block).unwrap(), b"v2");
+        assert_eq!(disk.list_snapshots().iter().map(|s| s.id).collect::<Vec<_>>(), vec![id]);
+        disk.restore(id).unwrap();
+        drop(disk);
+
+        let disk = FileDisk::new(path);
+        assert_eq!(disk.read_block(block).unwrap(), b"v1");
+        assert_eq!(disk.snapshot().unwrap(), id + 1);
+        disk.delete_snapshot(id + 1).unwrap();
+        cleanup();
     }
 }
@@ -0,0 +1,655 @@
+//! On-disk layout for the VFS tree.
+//!
+//! Everything is stored as *chains*: a sequence of blocks where each block
+//! starts with `next: u64` (`NO_BLOCK` ends the chain) and `len: u32`,
+//! followed by `len` payload bytes. On top of chains:
+//!
+//! * block 0 is the superblock: magic, version, block size, root inode and
+//!   the head of the inode table chain;
+//! * the inode table is a list of `(ino, inode block)` pairs;
+//! * an inode block holds the node's metadata plus the head and length of
+//!   its data chain;
+//! * a directory's data is its entries, `(ino, name)` pairs sorted by name;
+//! * a file's data is its raw content.
+//!
+//! Chains fill whole disk blocks, so the layout follows the disk's block
+//! size. All integers are little-endian. Only the root tree is stored;
+//! mounted providers (/proc, tmpfs, ...) are not.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use super::metadata::{reserve_ino, Generation};
+use super::{Directory, Disk, File, Metadata, NodeType, VfsNode};
+
+pub const FS_MAGIC: &[u8; 8] = b"VBOXFS\0\0";
+pub const FS_VERSION: u32 = 1;
+
+const SUPERBLOCK: u64 = 0;
+const NO_BLOCK: u64 = u64::MAX;
+const CHAIN_HEADER: usize = 12;
+
+/// What was last written for an inode, and the node's change counters at
+/// that point, so unchanged nodes are skipped.
+struct CachedInode {
+    inode_block: u64,
+    inode_bytes: Vec<u8>,
+    data_blocks: Vec<u64>,
+    data_len: u64,
+    data_gen: u64,
+    meta_gen: u64,
+}
+
+/// Keeps a VFS tree in sync with a `Disk` using the layout above.
+pub struct DiskStore {
+    disk: Arc<dyn Disk + Send + Sync>,
+    inodes: HashMap<u64, CachedInode>,
+    table_blocks: Vec<u64>,
+    table_bytes: Vec<u8>,
+    superblock_bytes: Vec<u8>,
+}
+
+impl DiskStore {
+    /// Opens the filesystem on `disk`, loading the stored tree into `root`.
+    /// A disk without a valid superblock is formatted.
+    pub fn open(disk: Arc<dyn Disk + Send + Sync>, root: &Directory) -> Result<Self, String> {
+        if disk.block_size() <= CHAIN_HEADER || disk.capacity() == 0 {
+            return Err(format!("block size {} is too small for the filesystem", disk.block_size()));
+        }
+        let mut store = DiskStore {
+            disk,
+            inodes: HashMap::new(),
+            table_blocks: Vec::new(),
+            table_bytes: Vec::new(),
+            superblock_bytes: Vec::new(),
+        };
+        if store.has_superblock() {
+            if !root.is_empty() {
+                return Err("cannot load a stored tree over a non-empty root".into());
+            }
+            store.load(root)?;
+        } else {
+            store.format()?;
+            store.sync(root)?;
+        }
+        Ok(store)
+    }
+
+    /// Like `open`, but never formats: a disk without a filesystem is an
+    /// error.
+    pub fn open_existing(disk: Arc<dyn Disk + Send + Sync>, root: &Directory) -> Result<Self, String> {
+        if !has_superblock(&*disk) {
+            return Err("no filesystem on disk".into());
+        }
+        Self::open(disk, root)
+    }
+
+    /// Every block the stored tree uses.
+    pub fn blocks_in_use(&self) -> HashSet<u64> {
+        let mut used: HashSet<u64> = self.table_blocks.iter().copied().collect();
+        used.insert(SUPERBLOCK);
+        for cached in self.inodes.values() {
+            used.insert(cached.inode_block);
+            used.extend(&cached.data_blocks);
+        }
+        used
+    }
+
+    pub fn disk(&self) -> Arc<dyn Disk + Send + Sync> {
+        self.disk.clone()
+    }
+
+    /// Writes every node that changed since the last sync and frees the
+    /// blocks of nodes that are gone.
+    pub fn sync(&mut self, root: &Directory) -> Result<(), String> {
+        if !self.has_superblock() {
+            // Someone wiped the disk underneath us (e.g. `clearmem`)
+            self.format()?;
+        }
+
+        let mut seen = HashSet::new();
+        let root_ino = self.sync_node(root, &mut seen)?;
+
+        let stale: Vec<u64> = self.inodes.keys().filter(|i| !seen.contains(i)).copied().collect();
+        for ino in stale {
+            let cached = self.inodes.remove(&ino).unwrap();
+            for block in cached.data_blocks {
+                self.disk.free_block(block)?;
+            }
+            self.disk.free_block(cached.inode_block)?;
+        }
+
+        let mut table: Vec<(u64, u64)> = self.inodes.iter().map(|(i, c)| (*i, c.inode_block)).collect();
+        table.sort();
+        let mut w = Writer::default();
+        w.u64(table.len() as u64);
+        for (ino, block) in table {
+            w.u64(ino);
+            w.u64(block);
+        }
+        if w.buf != self.table_bytes {
+            self.table_blocks = self.write_chain(self.table_blocks.clone(), &w.buf)?;
+            self.table_bytes = w.buf;
+        }
+
+        let mut sb = Writer::default();
+        sb.bytes(FS_MAGIC);
+        sb.u32(FS_VERSION);
+        sb.u32(self.disk.block_size() as u32);
+        sb.u64(root_ino);
+        sb.u64(self.table_blocks.first().copied().unwrap_or(NO_BLOCK));
+        if sb.buf != self.superblock_bytes {
+            self.disk.write_block(SUPERBLOCK, &sb.buf)?;
+            self.superblock_bytes = sb.buf;
+        }
+        Ok(())
+    }
+
+    fn has_superblock(&self) -> bool {
+        has_superblock(&*self.disk)
+    }
+
+    fn format(&mut self) -> Result<(), String> {
+        self.disk.clear_all();
+        let block = self.disk.allocate_block()?;
+        if block != SUPERBLOCK {
+            return Err(format!("superblock allocated at block {}, expected 0", block));
+        }
+        self.inodes.clear();
+        self.table_blocks.clear();
+        self.table_bytes.clear();
+        self.superblock_bytes.clear();
+        Ok(())
+    }
+
+    fn sync_node(&mut self, node: &dyn VfsNode, seen: &mut HashSet<u64>) -> Result<u64, String> {
+        let file = node.as_any().downcast_ref::<File>();
+        let dir = node.as_any().downcast_ref::<Directory>();
+        let generation = match (file, dir) {
+            (Some(file), _) => &file.generation,
+            (_, Some(dir)) => &dir.generation,
+            _ => return Err(format!("{}: cannot store special node", node.name())),
+        };
+        // Read before the state it covers, so a change racing with the sync
+        // leaves the node dirty for the next one
+        let (data_gen, meta_gen) = generation.current();
+        let meta = node.metadata();
+        if !seen.insert(meta.ino) {
+            return Ok(meta.ino);
+        }
+
+        let cached = self.inodes.get(&meta.ino);
+        let data_dirty = cached.is_none_or(|c| c.data_gen != data_gen);
+        let meta_dirty = cached.is_none_or(|c| c.meta_gen != meta_gen);
+
+        let data = if let Some(file) = file {
+            data_dirty.then(|| file.content.lock().unwrap().clone())
+        } else {
+            let dir = dir.unwrap();
+            // Special nodes such as FIFOs live in memory only
+            let mut children: Vec<(String, Arc<dyn VfsNode>)> = dir
+                .children
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, child)| {
+                    child.as_any().downcast_ref::<File>().is_some()
+                        || child.as_any().downcast_ref::<Directory>().is_some()
+                })
+                .map(|(name, child)| (name.clone(), child.clone()))
+                .collect();
+            if data_dirty {
+                children.sort_by(|a, b| a.0.cmp(&b.0));
+                let mut w = Writer::default();
+                w.u32(children.len() as u32);
+                for (name, child) in children {
+                    let child_ino = self.sync_node(&*child, seen)?;
+                    w.u64(child_ino);
+                    w.string(&name);
+                }
+                Some(w.buf)
+            } else {
+                // Unchanged entries, but the children themselves may have changed
+                for (_, child) in children {
+                    self.sync_node(&*child, seen)?;
+                }
+                None
+            }
+        };
+        if !data_dirty && !meta_dirty {
+            return Ok(meta.ino);
+        }
+
+        // The cache entry is only replaced once the node is fully written,
+        // so a failed sync (e.g. ENOSPC) can simply be retried.
+        let (inode_block, data_blocks, data_len, inode_bytes, fresh) = match self.inodes.get(&meta.ino) {
+            Some(c) => (c.inode_block, c.data_blocks.clone(), c.data_len, c.inode_bytes.clone(), false),
+            None => (self.disk.allocate_block()?, Vec::new(), 0, Vec::new(), true),
+        };
+        let written = self.write_node(&meta, data.as_deref(), inode_block, data_blocks, data_len, &inode_bytes);
+        let (data_blocks, data_len, encoded) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                if fresh {
+                    let _ = self.disk.free_block(inode_block);
+                }
+                return Err(e);
+            }
+        };
+        self.inodes.insert(
+            meta.ino,
+            CachedInode {
+                inode_block,
+                inode_bytes: encoded,
+                data_blocks,
+                data_len,
+                data_gen,
+                meta_gen,
+            },
+        );
+        Ok(meta.ino)
+    }
+
+    /// Writes the inode of `meta`, and its data chain when `data` is given;
+    /// otherwise the stored chain of `data_len` bytes is kept.
+    fn write_node(
+        &self,
+        meta: &Metadata,
+        data: Option<&[u8]>,
+        inode_block: u64,
+        data_blocks: Vec<u64>,
+        data_len: u64,
+        inode_bytes: &[u8],
+    ) -> Result<(Vec<u64>, u64, Vec<u8>), String> {
+        let (data_blocks, data_len) = match data {
+            Some(data) => (self.write_chain(data_blocks, data)?, data.len() as u64),
+            None => (data_blocks, data_len),
+        };
+        let encoded = encode_inode(meta, data_blocks.first().copied().unwrap_or(NO_BLOCK), data_len);
+        if encoded != inode_bytes {
+            self.disk.write_block(inode_block, &encoded)?;
+        }
+        Ok((data_blocks, data_len, encoded))
+    }
+
+    fn load(&mut self, root: &Directory) -> Result<(), String> {
+        let sb = self.disk.read_block(SUPERBLOCK)?;
+        let mut r = Reader::new(&sb);
+        r.take(FS_MAGIC.len())?;
+        let version = r.u32()?;
+        if version != FS_VERSION {
+            return Err(format!("unsupported filesystem version {}", version));
+        }
+        let block_size = r.u32()? as usize;
+        if block_size != self.disk.block_size() {
+            return Err(format!(
+                "filesystem block size {} does not match the disk ({})",
+                block_size,
+                self.disk.block_size()
+            ));
+        }
+        let root_ino = r.u64()?;
+        let table_head = r.u64()?;
+        self.superblock_bytes = sb.clone();
+
+        let (table, table_blocks) = self.read_chain(table_head)?;
+        let mut r = Reader::new(&table);
+        let count = r.u64()?;
+        let mut locations = HashMap::new();
+        for _ in 0..count {
+            let ino = r.u64()?;
+            let block = r.u64()?;
+            locations.insert(ino, block);
+        }
+        self.table_blocks = table_blocks;
+        self.table_bytes = table;
+
+        let (meta, data) = self.load_inode(root_ino, &locations)?;
+        *root.meta.lock().unwrap() = meta;
+        self.load_children(root, &data, &locations, &mut HashSet::from([root_ino]))?;
+        self.mark_loaded(root_ino, &root.generation);
+        Ok(())
+    }
+
+    fn load_inode(&mut self, ino: u64, locations: &HashMap<u64, u64>) -> Result<(Metadata, Vec<u8>), String> {
+        let block = *locations.get(&ino).ok_or_else(|| format!("inode {} missing from table", ino))?;
+        let bytes = self
+            .disk
+            .read_block(block)
+            .map_err(|e| format!("inode {}: {}", ino, e))?;
+        let (meta, data_head, data_len) = decode_inode(&bytes)?;
+        let (data, data_blocks) = self.read_chain(data_head)?;
+        if data.len() as u64 != data_len {
+            return Err(format!("inode {}: data length mismatch", ino));
+        }
+        reserve_ino(ino);
+        self.inodes.insert(
+            ino,
+            CachedInode {
+                inode_block: block,
+                inode_bytes: bytes,
+                data_blocks,
+                data_len,
+                // Set by `mark_loaded` once the node exists
+                data_gen: u64::MAX,
+                meta_gen: u64::MAX,
+            },
+        );
+        Ok((meta, data))
+    }
+
+    /// Records that the node loaded for `ino` matches what is stored.
+    fn mark_loaded(&mut self, ino: u64, generation: &Generation) {
+        if let Some(cached) = self.inodes.get_mut(&ino) {
+            (cached.data_gen, cached.meta_gen) = generation.current();
+        }
+    }
+
+    fn load_children(
+        &mut self,
+        dir: &Directory,
+        entries: &[u8],
+        locations: &HashMap<u64, u64>,
+        visited: &mut HashSet<u64>,
+    ) -> Result<(), String> {
+        let mut r = Reader::new(entries);
+        let count = r.u32()?;
+        for _ in 0..count {
+            let ino = r.u64()?;
+            let name = r.string()?;
+            if !visited.insert(ino) {
+                return Err(format!("inode {} linked twice", ino));
+            }
+            let (meta, data) = self.load_inode(ino, locations)?;
+            match meta.kind {
+                NodeType::File => {
+                    let file = File::new(&name);
+                    *file.content.lock().unwrap() = data;
+                    *file.meta.lock().unwrap() = meta;
+                    self.mark_loaded(ino, &file.generation);
+                    dir.children.lock().unwrap().insert(name, Arc::new(file));
+                }
+                NodeType::Directory => {
+                    let child = Directory::new(&name);
+                    *child.meta.lock().unwrap() = meta;
+                    self.load_children(&child, &data, locations, visited)?;
+                    self.mark_loaded(ino, &child.generation);
+                    dir.children.lock().unwrap().insert(name, Arc::new(child));
+                }
+                NodeType::Fifo => return Err(format!("inode {}: FIFOs are not stored", ino)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores `data` in a chain, reusing `blocks` where possible. Every
+    /// missing block is allocated before anything is written, so running
+    /// out of space leaves the old chain untouched.
+    fn write_chain(&self, mut blocks: Vec<u64>, data: &[u8]) -> Result<Vec<u64>, String> {
+        let chunks: Vec<&[u8]> = data.chunks(self.disk.block_size() - CHAIN_HEADER).collect();
+        let mut added = Vec::new();
+        while blocks.len() + added.len() < chunks.len() {
+            match self.disk.allocate_block() {
+                Ok(block) => added.push(block),
+                Err(e) => {
+                    for block in added {
+                        let _ = self.disk.free_block(block);
+                    }
+                    return Err(e.into());
+                }
+            }
+        }
+        blocks.extend(added);
+        let surplus = blocks.split_off(chunks.len());
+        for (i, chunk) in chunks.iter().enumerate() {
+            let next = blocks.get(i + 1).copied().unwrap_or(NO_BLOCK);
+            let mut w = Writer::default();
+            w.u64(next);
+            w.u32(chunk.len() as u32);
+            w.bytes(chunk);
+            self.disk.write_block(blocks[i], &w.buf)?;
+        }
+        for block in surplus {
+            self.disk.free_block(block)?;
+        }
+        Ok(blocks)
+    }
+
+    fn read_chain(&self, head: u64) -> Result<(Vec<u8>, Vec<u64>), String> {
+        let mut data = Vec::new();
+        let mut blocks = Vec::new();
+        let mut current = head;
+        while current != NO_BLOCK {
+            if blocks.contains(&current) {
+                return Err(format!("block {}: chain loops", current));
+            }
+            let block = self.disk.read_block(current)?;
+            let mut r = Reader::new(&block);
+            let next = r.u64()?;
+            let len = r.u32()? as usize;
+            data.extend_from_slice(r.take(len)?);
+            blocks.push(current);
+            current = next;
+        }
+        Ok((data, blocks))
+    }
+}
+
+fn has_superblock(disk: &dyn Disk) -> bool {
+    disk.read_block(SUPERBLOCK)
+        .map(|b| b.starts_with(FS_MAGIC))
+        .unwrap_or(false)
+}
+
+fn encode_inode(meta: &Metadata, data_head: u64, data_len: u64) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.u64(meta.ino);
+    w.u32(match meta.kind {
+        NodeType::File => 1,
+        NodeType::Directory => 2,
+        // Never stored: FIFOs are skipped when syncing
+        NodeType::Fifo => 3,
+    });
+    w.u32(meta.mode);
+    w.u32(meta.uid);
+    w.u32(meta.gid);
+    w.u64(meta.atime);
+    w.u64(meta.mtime);
+    w.u64(meta.ctime);
+    w.u64(data_head);
+    w.u64(data_len);
+    w.buf
+}
+
+fn decode_inode(bytes: &[u8]) -> Result<(Metadata, u64, u64), String> {
+    let mut r = Reader::new(bytes);
+    let ino = r.u64()?;
+    let kind = match r.u32()? {
+        1 => NodeType::File,
+        2 => NodeType::Directory,
+        other => return Err(format!("inode {}: unknown type {}", ino, other)),
+    };
+    let mut meta = Metadata::synthetic(kind, 0);
+    meta.ino = ino;
+    meta.mode = r.u32()?;
+    meta.uid = r.u32()?;
+    meta.gid = r.u32()?;
+    meta.atime = r.u64()?;
+    meta.mtime = r.u64()?;
+    meta.ctime = r.u64()?;
+    let data_head = r.u64()?;
+    let data_len = r.u64()?;
+    meta.size = data_len;
+    Ok((meta, data_head, data_len))
+}
+
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub(crate) buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    pub(crate) fn string(&mut self, v: &str) {
+        self.u32(v.len() as u32);
+        self.bytes(v.as_bytes());
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.buf.len() - self.pos < n {
+            return Err("unexpected end of data".into());
+        }
+        let out = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "invalid UTF-8 name".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{FileDisk, FileSystem, RamDisk, DEFAULT_BLOCK_SIZE};
+
+    #[test]
+    fn test_round_trip() {
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(RamDisk::new());
+        let mut fs = FileSystem::new();
+        fs.attach_disk(disk.clone()).unwrap();
+        fs.create_dir("/home").unwrap();
+        fs.create_file("/home/a.txt").unwrap().write(b"persisted");
+        fs.chmod("/home/a.txt", 0o600).unwrap();
+        let big = vec![7u8; DEFAULT_BLOCK_SIZE * 3];
+        fs.create_file("/big.bin").unwrap().write(&big);
+        fs.sync().unwrap();
+
+        let mut reopened = FileSystem::new();
+        reopened.attach_disk(disk).unwrap();
+        assert_eq!(reopened.read_file("/home/a.txt").unwrap(), b"persisted");
+        assert_eq!(reopened.stat("/home/a.txt").unwrap().mode, 0o600);
+        assert_eq!(reopened.read_file("/big.bin").unwrap(), big);
+        assert_eq!(reopened.stat("/home").unwrap(), fs.stat("/home").unwrap());
+    }
+
+    #[test]
+    fn test_sync_is_incremental_and_frees_blocks() {
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(RamDisk::new());
+        let mut fs = FileSystem::new();
+        fs.attach_disk(disk.clone()).unwrap();
+        let empty = disk.get_allocated_blocks();
+
+        fs.create_file("/f").unwrap().write(&vec![1u8; DEFAULT_BLOCK_SIZE * 2]);
+        fs.sync().unwrap();
+        let with_file = disk.get_allocated_blocks();
+        assert!(with_file > empty);
+
+        fs.sync().unwrap();
+        assert_eq!(disk.get_allocated_blocks(), with_file);
+
+        fs.remove_file("/f").unwrap();
+        fs.sync().unwrap();
+        assert_eq!(disk.get_allocated_blocks(), empty);
+    }
+
+    #[test]
+    fn test_sync_writes_only_changed_nodes() {
+        let path = "test_diskfs_changed.bin";
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(format!("{}.journal", path));
+        let disk = Arc::new(FileDisk::new(path));
+        let mut fs = FileSystem::new();
+        fs.attach_disk(disk.clone()).unwrap();
+        fs.create_dir("/home").unwrap();
+        fs.create_file("/home/a.txt").unwrap().write(b"aaaa");
+        fs.sync().unwrap();
+
+        let journal = disk.journal_size();
+        fs.sync().unwrap();
+        assert_eq!(disk.journal_size(), journal);
+
+        // Same length, so only the change counter tells it apart
+        let node = fs.get("/home/a.txt").unwrap();
+        node.as_any().downcast_ref::<File>().unwrap().set_content(b"bbbb");
+        fs.sync().unwrap();
+        let mut reopened = FileSystem::new();
+        reopened.attach_disk(disk).unwrap();
+        assert_eq!(reopened.read_file("/home/a.txt").unwrap(), b"bbbb");
+        let _ = std::fs::remove_file(path);
+        std::fs::remove_file(format!("{}.journal", path)).unwrap();
+    }
+
+    #[test]
+    fn test_resync_after_disk_wipe() {
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(RamDisk::new());
+        let mut fs = FileSystem::new();
+        fs.attach_disk(disk.clone()).unwrap();
+        fs.create_file("/keep").unwrap().write(b"x");
+        fs.sync().unwrap();
+        disk.clear_all();
+        fs.sync().unwrap();
+
+        let mut reopened = FileSystem::new();
+        reopened.attach_disk(disk).unwrap();
+        assert_eq!(reopened.read_file("/keep").unwrap(), b"x");
+    }
+
+    #[test]
+    fn test_reload_after_restore() {
+        let disk: Arc<dyn Disk + Send + Sync> = Arc::new(RamDisk::new());
+        let mut fs = FileSystem::new();
+        fs.attach_disk(disk.clone()).unwrap();
+        fs.create_file("/kept").unwrap();
+        let id = disk.snapshot().unwrap();
+        fs.create_file("/dropped").unwrap();
+        fs.remove_file("/kept").unwrap();
+        fs.sync().unwrap();
+
+        disk.restore(id).unwrap();
+        fs.reload().unwrap();
+        assert!(fs.get("/kept").is_some());
+        assert!(fs.get("/dropped").is_none());
+        // The reloaded tree keeps syncing incrementally
+        fs.create_file("/new").unwrap();
+        let mut reopened = FileSystem::new();
+        reopened.attach_disk(disk).unwrap();
+        assert_eq!(reopened.list_dir("/").unwrap(), vec!["kept", "new"]);
+    }
+}
@@ -1,10 +1,13 @@
-use super::{NodeType, VfsNode};
+use super::metadata::Generation;
+use super::{Metadata, NodeType, VfsNode};
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 pub struct File {
     pub name: String,
     pub content: Arc<Mutex<Vec<u8>>>,
+    pub meta: Arc<Mutex<Metadata>>,
+    pub(crate) generation: Arc<Generation>,
 }
 
 impl File {
@@ -12,25 +15,62 @@ impl File {
         File {
             name: name.to_string(),
             content: Arc::new(Mutex::new(Vec::new())),
+            meta: Arc::new(Mutex::new(Metadata::new(NodeType::File, 0o644))),
+            generation: Arc::new(Generation::default()),
         }
     }
 
     pub fn new_with_content(name: &str, content: String) -> Self {
-        File {
-            name: name.to_string(),
-            content: Arc::new(Mutex::new(content.into_bytes())),
-        }
+        let file = File::new(name);
+        file.write(content.as_bytes());
+        file
     }
 
     pub fn write(&self, data: &[u8]) {
         let mut lock = self.content.lock().unwrap();
         lock.extend_from_slice(data);
+        let mut meta = self.meta.lock().unwrap();
+        meta.size = lock.len() as u64;
+        meta.touch_modify();
+        self.generation.data_changed();
+    }
+
+    /// Replaces the whole content.
+    pub fn set_content(&self, data: &[u8]) {
+        let mut lock = self.content.lock().unwrap();
+        *lock = data.to_vec();
+        let mut meta = self.meta.lock().unwrap();
+        meta.size = lock.len() as u64;
+        meta.touch_modify();
+        self.generation.data_changed();
     }
 
     pub fn read(&self) -> Vec<u8> {
         let lock = self.content.lock().unwrap();
+        self.meta.lock().unwrap().touch_access();
+        self.generation.meta_changed();
         lock.clone()
     }
+
+    /// Content length, without touching the access time.
+    pub fn size(&self) -> usize {
+        self.content.lock().unwrap().len()
+    }
+
+    /// Creates an independent copy of this file under `name`.
+    pub fn duplicate(&self, name: &str) -> Self {
+        let file = File::new(name);
+        let src = self.metadata();
+        {
+            let mut meta = file.meta.lock().unwrap();
+            meta.mode = src.mode;
+            meta.uid = src.uid;
+            meta.gid = src.gid;
+        }
+        let content = self.content.lock().unwrap().clone();
+        file.write(&content);
+        file
+    }
 }
 
 impl VfsNode for File {
@@ -45,4 +85,26 @@ impl VfsNode for File {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
+
+    fn with_name(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        let mut file = self.clone();
+        file.name = name.to_string();
+        file.meta.lock().unwrap().touch_change();
+        file.generation.meta_changed();
+        Some(Arc::new(file))
+    }
+
+    fn metadata(&self) -> Metadata {
+        *self.meta.lock().unwrap()
+    }
+
+    fn set_permissions(&self, mode: u32, uid: u32, gid: u32) -> Result<(), String> {
+        let mut meta = self.meta.lock().unwrap();
+        meta.mode = mode & 0o7777;
+        meta.uid = uid;
+        meta.gid = gid;
+        meta.touch_change();
+        self.generation.meta_changed();
+        Ok(())
+    }
 }
@@ -1,35 +1,130 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use super::{Directory, DirectoryOps, File, NodeType, VfsNode};
+use super::diskfs::DiskStore;
+use super::mount::{normalize, Mount, MountTable};
+use super::{Credentials, Directory, Disk, File, Metadata, NodeType, VfsNode, R_OK, W_OK, X_OK};
 
+/// A handle onto the VFS. Clones share the tree and mount table but each
+/// carries its own credentials, which every checked operation runs as.
 #[derive(Clone)]
 pub struct FileSystem {
     pub root: Arc<Directory>,
-    pub proc_dir: Option<Arc<dyn VfsNode>>,
-    pub dev_dir: Option<Arc<dyn VfsNode>>,
-    pub network_dir: Option<Arc<dyn VfsNode>>,
+    pub mounts: MountTable,
+    cred: Credentials,
+    store: Option<Arc<Mutex<DiskStore>>>,
+}
+
+impl Default for FileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FileSystem {
     pub fn new() -> Self {
+        let root = Arc::new(Directory::new("/"));
+        let mounts = MountTable::new();
+        mounts.mount("/", "rootfs", root.clone()).unwrap();
         FileSystem {
-            root: Arc::new(Directory::new("/")),
-            proc_dir: None,
-            dev_dir: None,
-            network_dir: None,
+            root,
+            mounts,
+            cred: Credentials::root(),
+            store: None,
         }
     }
 
-    pub fn set_proc(&mut self, proc: Arc<dyn VfsNode>) {
-        self.proc_dir = Some(proc);
+    /// Backs the root tree with `disk`: a formatted disk is loaded into the
+    /// (still empty) root, anything else is formatted. Call before cloning
+    /// so every handle shares the store.
+    pub fn attach_disk(&mut self, disk: Arc<dyn Disk + Send + Sync>) -> Result<(), String> {
+        let store = DiskStore::open(disk, &self.root)?;
+        self.store = Some(Arc::new(Mutex::new(store)));
+        Ok(())
+    }
+
+    /// Throws away the in-memory root tree and loads it again from the
+    /// attached disk, e.g. after the disk was restored from a snapshot.
+    pub fn reload(&self) -> Result<(), String> {
+        let store = self.store.as_ref().ok_or("filesystem is not backed by a disk")?;
+        let mut store = store.lock().unwrap();
+        let disk = store.disk();
+        let mut children = self.root.children.lock().unwrap();
+        children.clear();
+        self.root.generation.data_changed();
+        drop(children);
+        *store = DiskStore::open(disk, &self.root)?;
+        Ok(())
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.store.is_some()
+    }
+
+    /// Flushes changes of the root tree to the attached disk, if any.
+    pub fn sync(&self) -> Result<(), String> {
+        match &self.store {
+            Some(store) => store.lock().unwrap().sync(&self.root),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes a change that allocates space through to the disk right away,
+    /// so running out of space fails the operation itself; `undo` reverts
+    /// the in-memory change in that case.
+    fn persist(&self, undo: impl FnOnce()) -> Result<(), String> {
+        if let Err(e) = self.sync() {
+            undo();
+            // Drop whatever the failed sync managed to allocate
+            let _ = self.sync();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.cred
+    }
+
+    pub fn set_credentials(&mut self, cred: Credentials) {
+        self.cred = cred;
+    }
+
+    /// A handle onto the same tree acting as `cred`.
+    pub fn with_credentials(&self, cred: Credentials) -> Self {
+        let mut fs = self.clone();
+        fs.cred = cred;
+        fs
     }
 
-    pub fn set_dev(&mut self, dev: Arc<dyn VfsNode>) {
-        self.dev_dir = Some(dev);
+    pub fn as_root(&self) -> Self {
+        self.with_credentials(Credentials::root())
     }
 
-    pub fn set_network(&mut self, network: Arc<dyn VfsNode>) {
-        self.network_dir = Some(network);
+    /// Mounts a directory provider at `path`. The parent of the mount point
+    /// must already exist; the mount point itself shadows anything there.
+    pub fn mount(&self, path: &str, fs_type: &str, provider: Arc<dyn VfsNode>) -> Result<(), String> {
+        if !self.cred.is_root() {
+            return Err("Operation not permitted".into());
+        }
+        let path = normalize(path);
+        if path != "/" {
+            self.resolve_parent(&path)?;
+        }
+        self.mounts.mount(&path, fs_type, provider)
+    }
+
+    pub fn unmount(&self, path: &str) -> Result<(), String> {
+        if !self.cred.is_root() {
+            return Err("Operation not permitted".into());
+        }
+        if normalize(path) == "/" {
+            return Err("/: cannot unmount root filesystem".into());
+        }
+        self.mounts.unmount(path).map(|_| ())
+    }
+
+    pub fn list_mounts(&self) -> Vec<Mount> {
+        self.mounts.list()
     }
 
     fn split_path(path: &str) -> Vec<&str> {
@@ -40,77 +135,340 @@ impl FileSystem {
     }
 
     pub fn create_file(&self, path: &str) -> Result<Arc<File>, String> {
-        let (parent, name) = self.resolve_parent(path)?;
+        let (parent, name) = self.writable_parent(path)?;
+        let parent = parent.as_directory().unwrap();
+        if parent.get(&name).is_some() {
+            return Err("File exists".into());
+        }
         let file = Arc::new(File::new(&name));
-        parent.add(file.clone());
+        file.set_permissions(0o644, self.cred.uid, self.cred.gid)?;
+        parent.add(file.clone())?;
+        self.persist(|| {
+            let _ = parent.remove(&name);
+        })?;
         Ok(file)
     }
 
     pub fn create_dir(&self, path: &str) -> Result<Arc<Directory>, String> {
-        let (parent, name) = self.resolve_parent(path)?;
+        let (parent, name) = self.writable_parent(path)?;
+        let parent = parent.as_directory().unwrap();
+        if parent.get(&name).is_some() {
+            return Err("File exists".into());
+        }
         let dir = Arc::new(Directory::new(&name));
-        parent.add(dir.clone());
+        dir.set_permissions(0o755, self.cred.uid, self.cred.gid)?;
+        parent.add(dir.clone())?;
+        self.persist(|| {
+            let _ = parent.remove(&name);
+        })?;
         Ok(dir)
     }
 
+    /// Creates a special node such as a FIFO at `path`; `make` builds it
+    /// from its name. Special nodes live in memory only.
+    pub fn mknod(
+        &self,
+        path: &str,
+        make: impl FnOnce(&str) -> Arc<dyn VfsNode>,
+    ) -> Result<Arc<dyn VfsNode>, String> {
+        let (parent, name) = self.writable_parent(path)?;
+        let parent = parent.as_directory().unwrap();
+        if parent.get(&name).is_some() {
+            return Err("File exists".into());
+        }
+        let node = make(&name);
+        node.set_permissions(0o644, self.cred.uid, self.cred.gid)?;
+        parent.add(node.clone())?;
+        Ok(node)
+    }
+
+    /// Reads a regular file after checking read permission.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, String> {
+        let node = self.access(path, R_OK)?;
+        if node.node_type() == NodeType::Directory {
+            return Err("Is a directory".into());
+        }
+        let file = node.as_any().downcast_ref::<File>().ok_or("Not a regular file")?;
+        Ok(file.read())
+    }
+
+    /// Appends to an existing regular file after checking write permission.
+    /// Generated files handle the write themselves.
+    pub fn write_file(&self, path: &str, data: &[u8]) -> Result<(), String> {
+        let node = self.access(path, W_OK)?;
+        if node.node_type() == NodeType::Directory {
+            return Err("Is a directory".into());
+        }
+        let file = match node.as_any().downcast_ref::<File>() {
+            Some(file) => file,
+            None => return node.write_control(data),
+        };
+        let before = file.content.lock().unwrap().clone();
+        file.write(data);
+        self.persist(|| file.set_content(&before))
+    }
+
+    /// Only the owner (or root) may change the mode bits.
+    pub fn chmod(&self, path: &str, mode: u32) -> Result<(), String> {
+        let node = self.lookup(path)?;
+        let meta = node.metadata();
+        if !self.cred.is_root() && self.cred.uid != meta.uid {
+            return Err("Operation not permitted".into());
+        }
+        node.set_permissions(mode, meta.uid, meta.gid)
+    }
+
+    /// Only root may change ownership.
+    pub fn chown(&self, path: &str, uid: Option<u32>, gid: Option<u32>) -> Result<(), String> {
+        let node = self.lookup(path)?;
+        if !self.cred.is_root() {
+            return Err("Operation not permitted".into());
+        }
+        let meta = node.metadata();
+        node.set_permissions(meta.mode, uid.unwrap_or(meta.uid), gid.unwrap_or(meta.gid))
+    }
+
+    /// Resolves `path` after checking search permission on every directory
+    /// along the way.
+    pub fn lookup(&self, path: &str) -> Result<Arc<dyn VfsNode>, String> {
+        let parts = Self::split_path(path);
+        for depth in 0..parts.len() {
+            let dir_path = format!("/{}", parts[..depth].join("/"));
+            let dir = self.get(&dir_path).ok_or("No such file or directory")?;
+            if dir.as_directory().is_none() {
+                return Err("Not a directory".into());
+            }
+            if !dir.metadata().permits(&self.cred, X_OK) {
+                return Err("Permission denied".into());
+            }
+        }
+        self.get(path).ok_or_else(|| "No such file or directory".to_string())
+    }
+
+    /// Like `lookup`, then checks `mask` on the node itself.
+    pub fn access(&self, path: &str, mask: u32) -> Result<Arc<dyn VfsNode>, String> {
+        let node = self.lookup(path)?;
+        if !node.metadata().permits(&self.cred, mask) {
+            return Err("Permission denied".into());
+        }
+        Ok(node)
+    }
+
     pub fn get(&self, path: &str) -> Option<Arc<dyn VfsNode>> {
-        if path == "/" {
-            return Some(self.root.clone());
+        let parts = Self::split_path(path);
+        let (mount, depth) = self.mounts.resolve(&parts)?;
+        let mut current = mount.node;
+        for part in &parts[depth..] {
+            let next = current.as_directory()?.get(part)?;
+            current = next;
         }
-        if path == "/proc" {
-            if let Some(proc) = &self.proc_dir {
-                return Some(proc.clone());
+        Some(current)
+    }
+
+    pub fn stat(&self, path: &str) -> Result<Metadata, String> {
+        self.lookup(path).map(|node| node.metadata())
+    }
+
+    /// Lists a directory, including mount points attached directly below it.
+    pub fn list_dir(&self, path: &str) -> Result<Vec<String>, String> {
+        let node = self.access(path, R_OK)?;
+        let mut names = node.as_directory().ok_or("Not a directory")?.list();
+        for name in self.mounts.children_of(path) {
+            if !names.contains(&name) {
+                names.push(name);
             }
         }
-        if path.starts_with("/proc/") {
-            if let Some(proc) = &self.proc_dir {
-                let name = path.strip_prefix("/proc/").unwrap();
-                if let Some(dir) = proc.as_any().downcast_ref::<crate::vfs::ProcDirectory>() {
-                    return dir.get(name);
+        names.sort();
+        Ok(names)
+    }
+
+    /// Removes a file. Directories must go through `remove_dir` or `remove_all`.
+    pub fn remove_file(&self, path: &str) -> Result<(), String> {
+        let node = self.lookup(path)?;
+        if node.node_type() == NodeType::Directory {
+            return Err("Is a directory".into());
+        }
+        let (parent, name) = self.writable_parent(path)?;
+        parent.as_directory().unwrap().remove(&name)?;
+        Ok(())
+    }
+
+    /// Removes an empty directory.
+    pub fn remove_dir(&self, path: &str) -> Result<(), String> {
+        let node = self.lookup(path)?;
+        let dir = node.as_directory().ok_or("Not a directory")?;
+        if self.mounts.is_busy(path) {
+            return Err("Device or resource busy".into());
+        }
+        if !dir.is_empty() {
+            return Err("Directory not empty".into());
+        }
+        let (parent, name) = self.writable_parent(path)?;
+        parent.as_directory().unwrap().remove(&name)?;
+        Ok(())
+    }
+
+    /// Removes a file or a whole directory tree. Every non-empty directory
+    /// in the tree must be writable, as with `rm -r`.
+    pub fn remove_all(&self, path: &str) -> Result<(), String> {
+        let node = self.lookup(path)?;
+        if self.mounts.is_busy(path) {
+            return Err("Device or resource busy".into());
+        }
+        self.check_removable(&node)?;
+        let (parent, name) = self.writable_parent(path)?;
+        parent.as_directory().unwrap().remove(&name)?;
+        Ok(())
+    }
+
+    /// Moves or renames a node within a single mount. An existing file at
+    /// `to` is replaced, an existing directory only if it is empty.
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), String> {
+        let from_norm = normalize(from);
+        let to_norm = normalize(to);
+        let node = self.lookup(&from_norm)?;
+        if self.mounts.is_busy(&from_norm) {
+            return Err("Device or resource busy".into());
+        }
+        if from_norm == to_norm {
+            return Ok(());
+        }
+        if to_norm.starts_with(&format!("{}/", from_norm)) {
+            return Err("Cannot move a directory into itself".into());
+        }
+        if self.mount_point_of(&from_norm) != self.mount_point_of(&to_norm) {
+            return Err("Invalid cross-device link".into());
+        }
+
+        let (src_parent, src_name) = self.writable_parent(&from_norm)?;
+        let (dst_parent, dst_name) = self.writable_parent(&to_norm)?;
+        let dst_dir = dst_parent.as_directory().unwrap();
+        if let Some(existing) = dst_dir.get(&dst_name) {
+            match (node.node_type(), existing.node_type()) {
+                (NodeType::Directory, NodeType::Directory) if !existing.as_directory().unwrap().list().is_empty() => {
+                    return Err("Directory not empty".into())
                 }
+                (NodeType::Directory, NodeType::Directory) => {}
+                (NodeType::Directory, _) => return Err("Not a directory".into()),
+                (_, NodeType::Directory) => return Err("Is a directory".into()),
+                _ => {}
             }
         }
-        if path == "/dev" {
-            if let Some(dev) = &self.dev_dir {
-                return Some(dev.clone());
+
+        let src_dir = src_parent.as_directory().unwrap();
+        if Self::parent_path(&from_norm) == Self::parent_path(&to_norm) {
+            return src_dir.rename(&src_name, &dst_name);
+        }
+        let moved = node.with_name(&dst_name).ok_or("Operation not supported")?;
+        dst_dir.add(moved)?;
+        if let Err(e) = src_dir.remove(&src_name) {
+            let _ = dst_dir.remove(&dst_name);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Copies a file, or a directory tree when `recursive` is set. Unlike
+    /// `rename`, copies may cross mounts.
+    pub fn copy(&self, from: &str, to: &str, recursive: bool) -> Result<(), String> {
+        let from_norm = normalize(from);
+        let to_norm = normalize(to);
+        let node = self.lookup(&from_norm)?;
+        if node.node_type() == NodeType::Directory {
+            if !recursive {
+                return Err("Is a directory".into());
+            }
+            if to_norm == from_norm || to_norm.starts_with(&format!("{}/", from_norm)) {
+                return Err("Cannot copy a directory into itself".into());
             }
         }
-        if path.starts_with("/dev/") {
-            if let Some(dev) = &self.dev_dir {
-                let name = path.strip_prefix("/dev/").unwrap();
-                if let Some(dir) = dev.as_any().downcast_ref::<crate::vfs::DevDirectory>() {
-                    return dir.get(name);
-                }
+        let (dst_parent, dst_name) = self.writable_parent(&to_norm)?;
+        let dst_dir = dst_parent.as_directory().unwrap();
+        let existing = dst_dir.get(&dst_name);
+        if let Some(existing) = &existing {
+            if existing.node_type() == NodeType::Directory {
+                return Err("Is a directory".into());
+            }
+            if node.node_type() == NodeType::Directory {
+                return Err("Not a directory".into());
             }
         }
-        if path == "/network" {
-            if let Some(network) = &self.network_dir {
-                return Some(network.clone());
+        dst_dir.add(self.copy_node(&node, &dst_name)?)?;
+        self.persist(|| {
+            let _ = match existing {
+                Some(existing) => dst_dir.add(existing),
+                None => dst_dir.remove(&dst_name).map(|_| ()),
+            };
+        })
+    }
+
+    /// Deep-copies `node`; the copy is owned by the caller, as with `cp`.
+    fn copy_node(&self, node: &Arc<dyn VfsNode>, name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        let src_meta = node.metadata();
+        if let Some(file) = node.as_any().downcast_ref::<File>() {
+            if !src_meta.permits(&self.cred, R_OK) {
+                return Err("Permission denied".into());
             }
+            let copy = file.duplicate(name);
+            copy.set_permissions(src_meta.mode, self.cred.uid, self.cred.gid)?;
+            return Ok(Arc::new(copy));
         }
-        if path.starts_with("/network/") {
-            if let Some(network) = &self.network_dir {
-                let name = path.strip_prefix("/network/").unwrap();
-                if let Some(dir) = network.as_any().downcast_ref::<crate::vfs::NetworkDirectory>() {
-                    return dir.get(name);
+        if let Some(src) = node.as_directory() {
+            if !src_meta.permits(&self.cred, R_OK | X_OK) {
+                return Err("Permission denied".into());
+            }
+            let dir = Directory::new(name);
+            dir.set_permissions(src_meta.mode, self.cred.uid, self.cred.gid)?;
+            for child_name in src.list() {
+                if let Some(child) = src.get(&child_name) {
+                    dir.add(self.copy_node(&child, &child_name)?);
                 }
             }
+            return Ok(Arc::new(dir));
         }
+        Err(format!("{}: cannot copy special file", node.name()))
+    }
 
+    fn check_removable(&self, node: &Arc<dyn VfsNode>) -> Result<(), String> {
+        if let Some(dir) = node.as_directory() {
+            let children = dir.list();
+            if !children.is_empty() && !node.metadata().permits(&self.cred, W_OK | X_OK) {
+                return Err("Permission denied".into());
+            }
+            for name in children {
+                if let Some(child) = dir.get(&name) {
+                    self.check_removable(&child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `resolve_parent` plus the write and search permission that adding or
+    /// removing an entry requires.
+    fn writable_parent(&self, path: &str) -> Result<(Arc<dyn VfsNode>, String), String> {
+        let (parent, name) = self.resolve_parent(path)?;
+        let parent_path = Self::parent_path(&normalize(path)).to_string();
+        self.lookup(&parent_path)?;
+        if !parent.metadata().permits(&self.cred, W_OK | X_OK) {
+            return Err("Permission denied".into());
+        }
+        Ok((parent, name))
+    }
+
+    fn mount_point_of(&self, path: &str) -> Option<String> {
         let parts = Self::split_path(path);
-        let mut current: Arc<dyn VfsNode> = self.root.clone();
+        self.mounts.resolve(&parts).map(|(mount, _)| mount.path)
+    }
 
-        for part in parts {
-            if current.node_type() != NodeType::Directory {
-                return None;
-            }
-            let dir = current.as_any().downcast_ref::<Directory>().unwrap();
-            current = dir.get(part)?;
+    fn parent_path(path: &str) -> &str {
+        match path.rfind('/') {
+            Some(0) | None => "/",
+            Some(i) => &path[..i],
         }
-        Some(current)
     }
 
-    fn resolve_parent(&self, path: &str) -> Result<(Arc<Directory>, String), String> {
+    fn resolve_parent(&self, path: &str) -> Result<(Arc<dyn VfsNode>, String), String> {
         let mut parts = Self::split_path(path);
         let name = parts.pop().ok_or("Invalid path")?;
 
@@ -122,12 +480,11 @@ impl FileSystem {
 
         let parent = self.get(&parent_path).ok_or("Parent not found")?;
 
-        if parent.node_type() != NodeType::Directory {
+        if parent.as_directory().is_none() {
             return Err("Parent is not directory".into());
         }
 
-        let dir = parent.as_any().downcast_ref::<Directory>().unwrap().clone();
-        Ok((Arc::new(dir), name.to_string()))
+        Ok((parent, name.to_string()))
     }
 }
 
@@ -157,4 +514,150 @@ mod tests {
         assert_eq!(FileSystem::split_path("/a"), vec!["a"]);
         assert_eq!(FileSystem::split_path("/a/b/c"), vec!["a", "b", "c"]);
     }
+
+    #[test]
+    fn test_mount_resolves_nested_paths() {
+        let fs = FileSystem::new();
+        fs.create_dir("/mnt").unwrap();
+        let tmp = Arc::new(Directory::new("data"));
+        fs.mount("/mnt/data", "tmpfs", tmp).unwrap();
+        fs.create_dir("/mnt/data/sub").unwrap();
+        fs.create_file("/mnt/data/sub/file.txt").unwrap();
+
+        assert!(fs.get("/mnt/data/sub/file.txt").is_some());
+        assert_eq!(fs.list_dir("/mnt").unwrap(), vec!["data".to_string()]);
+
+        fs.unmount("/mnt/data").unwrap();
+        assert!(fs.get("/mnt/data/sub").is_none());
+    }
+
+    #[test]
+    fn test_remove() {
+        let fs = FileSystem::new();
+        fs.create_dir("/dir").unwrap();
+        fs.create_file("/dir/a.txt").unwrap();
+        assert_eq!(fs.remove_dir("/dir"), Err("Directory not empty".to_string()));
+        assert_eq!(fs.remove_file("/dir"), Err("Is a directory".to_string()));
+        fs.remove_file("/dir/a.txt").unwrap();
+        fs.remove_dir("/dir").unwrap();
+        assert!(fs.get("/dir").is_none());
+
+        fs.create_dir("/tree").unwrap();
+        fs.create_dir("/tree/sub").unwrap();
+        fs.create_file("/tree/sub/b.txt").unwrap();
+        fs.remove_all("/tree").unwrap();
+        assert!(fs.get("/tree").is_none());
+    }
+
+    #[test]
+    fn test_rename_and_move() {
+        let fs = FileSystem::new();
+        fs.create_dir("/a").unwrap();
+        fs.create_dir("/b").unwrap();
+        fs.create_file("/a/f.txt").unwrap().write(b"data");
+        fs.rename("/a/f.txt", "/a/g.txt").unwrap();
+        assert!(fs.get("/a/f.txt").is_none());
+        fs.rename("/a/g.txt", "/b/h.txt").unwrap();
+        let node = fs.get("/b/h.txt").unwrap();
+        assert_eq!(node.name(), "h.txt");
+        let file = node.as_any().downcast_ref::<File>().unwrap();
+        assert_eq!(file.read(), b"data");
+        assert!(fs.rename("/a", "/a/inner").is_err());
+    }
+
+    #[test]
+    fn test_rename_across_mounts_fails() {
+        let fs = FileSystem::new();
+        fs.create_dir("/mnt").unwrap();
+        fs.mount("/mnt", "tmpfs", Arc::new(Directory::new("mnt"))).unwrap();
+        fs.create_file("/f.txt").unwrap();
+        assert_eq!(
+            fs.rename("/f.txt", "/mnt/f.txt"),
+            Err("Invalid cross-device link".to_string())
+        );
+        fs.copy("/f.txt", "/mnt/f.txt", false).unwrap();
+        assert!(fs.get("/mnt/f.txt").is_some());
+        assert_eq!(fs.remove_dir("/mnt"), Err("Device or resource busy".to_string()));
+    }
+
+    #[test]
+    fn test_copy_recursive() {
+        let fs = FileSystem::new();
+        fs.create_dir("/src").unwrap();
+        fs.create_dir("/src/sub").unwrap();
+        fs.create_file("/src/sub/f.txt").unwrap().write(b"one");
+        assert!(fs.copy("/src", "/dst", false).is_err());
+        fs.copy("/src", "/dst", true).unwrap();
+
+        let copy = fs.get("/dst/sub/f.txt").unwrap();
+        let copy = copy.as_any().downcast_ref::<File>().unwrap();
+        copy.write(b"two");
+        let orig = fs.get("/src/sub/f.txt").unwrap();
+        assert_eq!(orig.as_any().downcast_ref::<File>().unwrap().read(), b"one");
+    }
+
+    #[test]
+    fn test_metadata_tracks_writes() {
+        let fs = FileSystem::new();
+        fs.create_dir("/d").unwrap();
+        let file = fs.create_file("/d/f.txt").unwrap();
+        file.write(b"hello");
+        let meta = fs.stat("/d/f.txt").unwrap();
+        assert_eq!(meta.size, 5);
+        assert_eq!(meta.mode, 0o644);
+        assert_eq!(meta.nlink, 1);
+
+        fs.create_dir("/d/sub").unwrap();
+        let dir_meta = fs.stat("/d").unwrap();
+        assert_eq!(dir_meta.kind, NodeType::Directory);
+        assert_eq!(dir_meta.size, 2);
+        assert_eq!(dir_meta.nlink, 3);
+
+        let ino = meta.ino;
+        fs.rename("/d/f.txt", "/g.txt").unwrap();
+        assert_eq!(fs.stat("/g.txt").unwrap().ino, ino);
+        fs.copy("/g.txt", "/h.txt", false).unwrap();
+        assert_ne!(fs.stat("/h.txt").unwrap().ino, ino);
+    }
+
+    #[test]
+    fn test_permissions() {
+        let fs = FileSystem::new();
+        fs.create_dir("/home").unwrap();
+        fs.create_dir("/home/alice").unwrap();
+        fs.chown("/home/alice", Some(1000), Some(1000)).unwrap();
+        fs.chmod("/home/alice", 0o700).unwrap();
+        fs.create_file("/public.txt").unwrap().write(b"hi");
+
+        let alice = fs.with_credentials(Credentials { uid: 1000, gid: 1000, groups: vec![1000] });
+        let bob = fs.with_credentials(Credentials { uid: 1001, gid: 1001, groups: vec![1001] });
+
+        let file = alice.create_file("/home/alice/notes.txt").unwrap();
+        assert_eq!(file.metadata().uid, 1000);
+        alice.write_file("/home/alice/notes.txt", b"mine").unwrap();
+
+        assert_eq!(bob.read_file("/home/alice/notes.txt"), Err("Permission denied".to_string()));
+        assert_eq!(bob.create_file("/bob.txt").err(), Some("Permission denied".to_string()));
+        assert_eq!(bob.read_file("/public.txt").unwrap(), b"hi");
+        assert!(bob.write_file("/public.txt", b"!").is_err());
+        assert!(bob.chmod("/public.txt", 0o777).is_err());
+        assert!(alice.chown("/home/alice/notes.txt", Some(0), None).is_err());
+        assert!(alice.mount("/home/alice/x", "tmpfs", Arc::new(Directory::new("x"))).is_err());
+    }
+
+    #[test]
+    fn test_create_existing_fails() {
+        let fs = FileSystem::new();
+        fs.create_file("/a").unwrap().write(b"keep");
+        assert_eq!(fs.create_file("/a").err(), Some("File exists".to_string()));
+        assert_eq!(fs.read_file("/a").unwrap(), b"keep");
+    }
+
+    #[test]
+    fn test_mount_requires_parent() {
+        let fs = FileSystem::new();
+        let tmp = Arc::new(Directory::new("x"));
+        assert!(fs.mount("/missing/x", "tmpfs", tmp).is_err());
+        assert!(fs.unmount("/").is_err());
+    }
 }
@@ -0,0 +1,271 @@
+//! Offline inspection, verification and repair of `FileDisk` images, used by
+//! the `vbox-disk` tool. Nothing here may run while a VBOX instance has the
+//! image open.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::disk::BlockMap;
+use super::image::{self, IMAGE_VERSION};
+use super::journal::Journal;
+use super::{Directory, Disk, DiskStore, FileDisk, RamDisk, DEFAULT_BLOCK_SIZE, DEFAULT_CAPACITY_BLOCKS};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub version: u32,
+    pub block_size: usize,
+    pub capacity: u64,
+    pub blocks: usize,
+    pub data_bytes: usize,
+    pub journal_records: usize,
+    pub snapshots: Vec<String>,
+}
+
+/// What `check` or `repair` found (and, for `repair`, did).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub problems: Vec<String>,
+    pub fixed: Vec<String>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+pub fn inspect(path: &str) -> Result<ImageInfo, String> {
+    let bytes = read(path)?;
+    let decoded = image::decode(&bytes);
+    let journal = Journal::inspect(&journal_path(path)).map_err(|e| e.to_string())?;
+    Ok(ImageInfo {
+        version: decoded.image.version,
+        block_size: decoded.image.block_size.unwrap_or(DEFAULT_BLOCK_SIZE),
+        capacity: decoded.image.capacity.unwrap_or(DEFAULT_CAPACITY_BLOCKS),
+        blocks: decoded.image.blocks.len(),
+        data_bytes: decoded.image.blocks.values().map(|b| b.len()).sum(),
+        journal_records: journal.ops.len(),
+        snapshots: snapshot_files(path),
+    })
+}
+
+/// Verifies the image, its journal and the filesystem stored on it without
+/// changing anything.
+pub fn check(path: &str) -> Result<Report, String> {
+    let bytes = read(path)?;
+    let decoded = image::decode(&bytes);
+    let mut report = Report {
+        problems: decoded.problems,
+        fixed: Vec::new(),
+    };
+    if decoded.image.version < IMAGE_VERSION && report.problems.is_empty() {
+        report
+            .problems
+            .push(format!("old image format (v{}), needs conversion", decoded.image.version));
+    }
+
+    let journal = Journal::inspect(&journal_path(path)).map_err(|e| e.to_string())?;
+    if journal.dropped_bytes > 0 {
+        report
+            .problems
+            .push(format!("journal: {} bytes of incomplete records", journal.dropped_bytes));
+    }
+
+    let block_size = decoded.image.block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+    let capacity = decoded.image.capacity.unwrap_or(DEFAULT_CAPACITY_BLOCKS);
+    let mut state = BlockMap::from_blocks(decoded.image.blocks, capacity);
+    state.replay(&journal.ops);
+    let allocated = state.allocated();
+    let disk: Arc<dyn Disk + Send + Sync> = Arc::new(RamDisk::from_state(state, block_size));
+    check_filesystem(disk, &allocated, &mut report);
+    Ok(report)
+}
+
+/// Rewrites the image in the current format with the journal folded in,
+/// dropping damaged blocks and blocks the filesystem does not use.
+pub fn repair(path: &str) -> Result<Report, String> {
+    let before = check(path)?;
+    let decoded = image::decode(&read(path)?);
+    let disk = FileDisk::with_geometry(
+        path,
+        decoded.image.block_size.unwrap_or(DEFAULT_BLOCK_SIZE),
+        decoded.image.capacity.unwrap_or(DEFAULT_CAPACITY_BLOCKS),
+    );
+
+    let mut report = Report::default();
+    let disk = Arc::new(disk);
+    match DiskStore::open_existing(disk.clone(), &Directory::new("/")) {
+        Ok(store) => {
+            let used = store.blocks_in_use();
+            for block in disk.allocated().into_iter().filter(|b| !used.contains(b)) {
+                disk.free_block(block).map_err(String::from)?;
+            }
+        }
+        Err(e) if disk.get_allocated_blocks() > 0 => {
+            report.problems.push(format!("filesystem: {}", e));
+        }
+        Err(_) => {}
+    }
+    disk.checkpoint()?;
+
+    let after = check(path)?;
+    report.fixed = before
+        .problems
+        .into_iter()
+        .filter(|p| !after.problems.contains(p))
+        .collect();
+    report.problems.extend(after.problems);
+    report.problems.dedup();
+    Ok(report)
+}
+
+fn check_filesystem(disk: Arc<dyn Disk + Send + Sync>, allocated: &[u64], report: &mut Report) {
+    if allocated.is_empty() {
+        return;
+    }
+    match DiskStore::open_existing(disk, &Directory::new("/")) {
+        Ok(store) => {
+            let used = store.blocks_in_use();
+            let leaked = allocated.iter().filter(|b| !used.contains(b)).count();
+            if leaked > 0 {
+                report
+                    .problems
+                    .push(format!("{} allocated blocks not used by the filesystem", leaked));
+            }
+            let missing: HashSet<u64> = used.difference(&allocated.iter().copied().collect()).copied().collect();
+            if !missing.is_empty() {
+                report
+                    .problems
+                    .push(format!("{} blocks used by the filesystem are not allocated", missing.len()));
+            }
+        }
+        Err(e) => report.problems.push(format!("filesystem: {}", e)),
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn journal_path(path: &str) -> std::path::PathBuf {
+    Path::new(&format!("{}.journal", path)).to_path_buf()
+}
+
+fn snapshot_files(path: &str) -> Vec<String> {
+    let image = Path::new(path);
+    let prefix = match image.file_name() {
+        Some(name) => format!("{}.snap-", name.to_string_lossy()),
+        None => return Vec::new(),
+    };
+    let dir = match image.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| name.starts_with(&prefix))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::FileSystem;
+
+    fn cleanup(path: &str) {
+        for suffix in ["", ".journal", ".tmp", ".corrupt", ".v0"] {
+            let _ = fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    #[test]
+    fn test_check_and_repair() {
+        let path = "test_fsck.img";
+        cleanup(path);
+        {
+            let disk = Arc::new(FileDisk::new(path));
+            let mut fs = FileSystem::new();
+            fs.attach_disk(disk.clone()).unwrap();
+            fs.create_file("/a").unwrap();
+            fs.write_file("/a", b"data").unwrap();
+            // A block nobody references
+            disk.allocate_block().unwrap();
+            disk.checkpoint().unwrap();
+        }
+        let report = check(path).unwrap();
+        assert_eq!(report.problems, vec!["1 allocated blocks not used by the filesystem"]);
+
+        let report = repair(path).unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+        assert_eq!(report.fixed.len(), 1);
+        assert!(check(path).unwrap().is_clean());
+
+        let mut fs = FileSystem::new();
+        fs.attach_disk(Arc::new(FileDisk::new(path))).unwrap();
+        assert_eq!(fs.read_file("/a").unwrap(), b"data");
+        cleanup(path);
+    }
+
+    #[test]
+    fn test_migrates_legacy_images() {
+        let path = "test_fsck_legacy.img";
+        cleanup(path);
+        {
+            let disk = RamDisk::new();
+            let mut fs = FileSystem::new();
+            let disk: Arc<dyn Disk + Send + Sync> = Arc::new(disk);
+            fs.attach_disk(disk.clone()).unwrap();
+            fs.create_file("/old").unwrap();
+            fs.write_file("/old", b"v0 data").unwrap();
+            let blocks = (0..disk.capacity())
+                .filter_map(|id| disk.read_block(id).ok().map(|data| (id, data)))
+                .collect();
+            fs::write(path, image::encode_legacy(&blocks)).unwrap();
+        }
+        assert_eq!(inspect(path).unwrap().version, 0);
+        assert!(!check(path).unwrap().is_clean());
+
+        let mut fs = FileSystem::new();
+        fs.attach_disk(Arc::new(FileDisk::new(path))).unwrap();
+        assert_eq!(fs.read_file("/old").unwrap(), b"v0 data");
+        assert_eq!(inspect(path).unwrap().version, IMAGE_VERSION);
+        assert!(check(path).unwrap().is_clean());
+        assert!(Path::new(&format!("{}.v0", path)).exists());
+        cleanup(path);
+    }
+
+    #[test]
+    fn test_repair_drops_damaged_blocks() {
+        let path = "test_fsck_damaged.img";
+        cleanup(path);
+        let disk = FileDisk::with_geometry(path, 64, 8);
+        let a = disk.allocate_block().unwrap();
+        disk.write_block(a, b"fine").unwrap();
+        let b = disk.allocate_block().unwrap();
+        disk.write_block(b, b"doomed").unwrap();
+        disk.checkpoint().unwrap();
+        drop(disk);
+
+        let mut bytes = fs::read(path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(path, &bytes).unwrap();
+        assert_eq!(check(path).unwrap().problems[0], format!("block {}: checksum mismatch", b));
+
+        let report = repair(path).unwrap();
+        assert!(report.fixed.contains(&format!("block {}: checksum mismatch", b)));
+        let disk = FileDisk::with_geometry(path, 64, 8);
+        assert_eq!(disk.read_block(a).unwrap(), b"fine");
+        assert!(disk.read_block(b).is_err());
+        cleanup(path);
+    }
+}
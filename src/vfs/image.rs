@@ -0,0 +1,248 @@
+//! Host file format of `FileDisk` images and their snapshots.
+//!
+//! ```text
+//! header:  magic "VBOXDISK" | version: u32 | block_size: u32 |
+//!          capacity: u64 | block_count: u64 | header_crc: u32
+//! records: block_count x (id: u64 | len: u32 | crc: u32 | data: [u8; len])
+//! ```
+//!
+//! All integers are little-endian and every CRC is CRC-32 (IEEE). The header
+//! CRC covers the header bytes before it; a record's CRC covers its data.
+//! Records are sorted by id and every allocated block has one, written or
+//! not. `capacity` is in blocks.
+//!
+//! Images written before this format (version 0) are bincode dumps of the
+//! block map with a `DefaultHasher` checksum. The checksum hashed the map in
+//! its `HashMap` iteration order, which cannot be reproduced, so a legacy
+//! image that parses is taken as intact. They are still read, and `FileDisk`
+//! rewrites them in the current format when it opens them.
+
+use std::collections::BTreeMap;
+
+use super::diskfs::{Reader, Writer};
+use crate::utils::checksum::crc32;
+
+pub const IMAGE_MAGIC: &[u8; 8] = b"VBOXDISK";
+pub const IMAGE_VERSION: u32 = 1;
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 8 + 4;
+const RECORD_HEADER_LEN: usize = 8 + 4 + 4;
+
+/// Blocks of an image, keyed by block id.
+pub type ImageBlocks = BTreeMap<u64, Vec<u8>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// 0 for legacy images, otherwise the header version.
+    pub version: u32,
+    /// Geometry from the header; legacy images have none.
+    pub block_size: Option<usize>,
+    pub capacity: Option<u64>,
+    pub blocks: ImageBlocks,
+}
+
+/// Result of decoding an image: whatever could be read, plus what was wrong
+/// with it. An image with no problems is intact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub image: Image,
+    pub problems: Vec<String>,
+}
+
+pub fn encode(block_size: usize, capacity: u64, blocks: &ImageBlocks) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.bytes(IMAGE_MAGIC);
+    w.u32(IMAGE_VERSION);
+    w.u32(block_size as u32);
+    w.u64(capacity);
+    w.u64(blocks.len() as u64);
+    let crc = crc32(&w.buf);
+    w.u32(crc);
+    for (id, data) in blocks {
+        w.u64(*id);
+        w.u32(data.len() as u32);
+        w.u32(crc32(data));
+        w.bytes(data);
+    }
+    w.buf
+}
+
+/// Decodes an image in either format. Damaged records are left out and
+/// reported; decoding stops at the first record that cannot be framed.
+pub fn decode(bytes: &[u8]) -> Decoded {
+    if !bytes.starts_with(IMAGE_MAGIC) {
+        return decode_legacy(bytes);
+    }
+
+    let mut decoded = Decoded {
+        image: Image {
+            version: 0,
+            block_size: None,
+            capacity: None,
+            blocks: BTreeMap::new(),
+        },
+        problems: Vec::new(),
+    };
+    if bytes.len() < HEADER_LEN {
+        decoded.problems.push("truncated header".into());
+        return decoded;
+    }
+    let mut r = Reader::new(bytes);
+    let (version, block_size, capacity, count) = match read_header(&mut r) {
+        Ok(header) => header,
+        Err(e) => {
+            decoded.problems.push(e);
+            return decoded;
+        }
+    };
+    if crc32(&bytes[..HEADER_LEN - 4]) != r.u32().unwrap() {
+        decoded.problems.push("header checksum mismatch".into());
+        return decoded;
+    }
+    if version != IMAGE_VERSION {
+        decoded.problems.push(format!("unsupported image version {}", version));
+        return decoded;
+    }
+    decoded.image.version = version;
+    decoded.image.block_size = Some(block_size as usize);
+    decoded.image.capacity = Some(capacity);
+
+    for index in 0..count {
+        let record = read_record(&mut r);
+        let (id, data, crc) = match record {
+            Ok(record) => record,
+            Err(_) => {
+                decoded.problems.push(format!(
+                    "image truncated after {} of {} blocks",
+                    index, count
+                ));
+                return decoded;
+            }
+        };
+        if crc32(data) != crc {
+            decoded.problems.push(format!("block {}: checksum mismatch", id));
+        } else if data.len() > block_size as usize {
+            decoded.problems.push(format!("block {}: larger than the block size", id));
+        } else if id >= capacity {
+            decoded.problems.push(format!("block {}: beyond the capacity", id));
+        } else if decoded.image.blocks.insert(id, data.to_vec()).is_some() {
+            decoded.problems.push(format!("block {}: stored twice", id));
+        }
+    }
+    if r.remaining() > 0 {
+        decoded.problems.push(format!("{} trailing bytes", r.remaining()));
+    }
+    decoded
+}
+
+fn read_header(r: &mut Reader) -> Result<(u32, u32, u64, u64), String> {
+    r.take(IMAGE_MAGIC.len())?;
+    Ok((r.u32()?, r.u32()?, r.u64()?, r.u64()?))
+}
+
+fn read_record<'a>(r: &mut Reader<'a>) -> Result<(u64, &'a [u8], u32), String> {
+    if r.remaining() < RECORD_HEADER_LEN {
+        return Err("unexpected end of data".into());
+    }
+    let id = r.u64()?;
+    let len = r.u32()? as usize;
+    let crc = r.u32()?;
+    Ok((id, r.take(len)?, crc))
+}
+
+// Version 0: bincode of { blocks: HashMap<u64, Vec<u8>>, checksum: u64 }.
+// A map is encoded the same whatever its type, so it is read straight into
+// sorted blocks; the checksum is read and ignored.
+#[derive(serde::Deserialize)]
+struct LegacyDiskData {
+    blocks: BTreeMap<u64, Vec<u8>>,
+    _checksum: u64,
+}
+
+fn decode_legacy(bytes: &[u8]) -> Decoded {
+    let mut decoded = Decoded {
+        image: Image {
+            version: 0,
+            block_size: None,
+            capacity: None,
+            blocks: BTreeMap::new(),
+        },
+        problems: Vec::new(),
+    };
+    match bincode::deserialize::<LegacyDiskData>(bytes) {
+        Ok(data) => decoded.image.blocks = data.blocks,
+        Err(_) => decoded.problems.push("not a disk image".into()),
+    }
+    decoded
+}
+
+/// A version 0 image, written the way `FileDisk::save` used to.
+#[cfg(test)]
+pub(crate) fn encode_legacy(blocks: &std::collections::HashMap<u64, Vec<u8>>) -> Vec<u8> {
+    use std::collections::hash_map::{DefaultHasher, HashMap};
+    use std::hash::{Hash, Hasher};
+
+    #[derive(serde::Serialize)]
+    struct DiskData<'a> {
+        blocks: &'a HashMap<u64, Vec<u8>>,
+        checksum: u64,
+    }
+    let serialized_blocks = bincode::serialize(blocks).unwrap();
+    let mut hasher = DefaultHasher::new();
+    serialized_blocks.hash(&mut hasher);
+    bincode::serialize(&DiskData {
+        blocks,
+        checksum: hasher.finish(),
+    })
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn sample() -> ImageBlocks {
+        BTreeMap::from([(0, b"super".to_vec()), (3, Vec::new()), (7, vec![9; 100])])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = encode(128, 16, &sample());
+        let decoded = decode(&bytes);
+        assert!(decoded.problems.is_empty(), "{:?}", decoded.problems);
+        assert_eq!(decoded.image.version, IMAGE_VERSION);
+        assert_eq!(decoded.image.block_size, Some(128));
+        assert_eq!(decoded.image.capacity, Some(16));
+        assert_eq!(decoded.image.blocks, sample());
+    }
+
+    #[test]
+    fn test_detects_damage() {
+        let mut bytes = encode(128, 16, &sample());
+        // Flip a byte of block 7's data, then cut the image short
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        let decoded = decode(&bytes);
+        assert_eq!(decoded.problems, vec!["block 7: checksum mismatch"]);
+        assert_eq!(decoded.image.blocks.len(), 2);
+
+        let decoded = decode(&bytes[..bytes.len() - 50]);
+        assert_eq!(decoded.problems, vec!["image truncated after 2 of 3 blocks"]);
+
+        bytes[9] ^= 0xFF;
+        assert_eq!(decode(&bytes).problems, vec!["header checksum mismatch"]);
+    }
+
+    #[test]
+    fn test_reads_legacy_images() {
+        // Enough blocks that the hash map's order is not the sorted one
+        let blocks: HashMap<u64, Vec<u8>> = (0..64).map(|id| (id, vec![id as u8; 4])).collect();
+        let decoded = decode(&encode_legacy(&blocks));
+        assert!(decoded.problems.is_empty(), "{:?}", decoded.problems);
+        assert_eq!(decoded.image.version, 0);
+        assert_eq!(decoded.image.block_size, None);
+        assert_eq!(decoded.image.blocks, blocks.into_iter().collect::<ImageBlocks>());
+
+        assert_eq!(decode(b"garbage").problems, vec!["not a disk image"]);
+    }
+}
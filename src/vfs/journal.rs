@@ -0,0 +1,198 @@
+//! Write-ahead journal for `FileDisk`.
+//!
+//! Every block mutation is appended as a record before it is acknowledged:
+//!
+//! ```text
+//! op: u8 | block: u64 | len: u32 | data: [u8; len] | crc32: u32
+//! ```
+//!
+//! (little-endian; the CRC covers everything before it). Replaying stops at
+//! the first truncated or corrupt record, which is what a crash mid-append
+//! leaves behind; that tail is cut off. Records are idempotent, so replaying
+//! a journal over a checkpoint that already contains it is harmless.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::utils::checksum::crc32;
+
+const OP_WRITE: u8 = 1;
+const OP_FREE: u8 = 2;
+const OP_CLEAR: u8 = 3;
+const RECORD_OVERHEAD: usize = 1 + 8 + 4 + 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum JournalOp {
+    Write(u64, Vec<u8>),
+    Free(u64),
+    Clear,
+}
+
+pub(crate) struct Journal {
+    // None for a journal that could not be opened: records are dropped and
+    // changes only reach the disk at checkpoints
+    file: Option<fs::File>,
+    path: PathBuf,
+    len: u64,
+}
+
+/// Result of opening a journal: the valid records and how many trailing
+/// bytes had to be discarded.
+pub(crate) struct Recovered {
+    pub ops: Vec<JournalOp>,
+    pub dropped_bytes: usize,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> io::Result<(Journal, Recovered)> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let (ops, valid) = Self::parse(&data);
+
+        let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        if valid < data.len() {
+            file.set_len(valid as u64)?;
+            file.sync_data()?;
+        }
+        let journal = Journal {
+            file: Some(file),
+            path: path.to_path_buf(),
+            len: valid as u64,
+        };
+        let recovered = Recovered {
+            ops,
+            dropped_bytes: data.len() - valid,
+        };
+        Ok((journal, recovered))
+    }
+
+    /// A journal at `path` that keeps nothing, for when `open` fails.
+    pub fn detached(path: &Path) -> Journal {
+        Journal {
+            file: None,
+            path: path.to_path_buf(),
+            len: 0,
+        }
+    }
+
+    pub fn append(&mut self, op: &JournalOp) -> io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let (kind, block, data): (u8, u64, &[u8]) = match op {
+            JournalOp::Write(block, data) => (OP_WRITE, *block, data),
+            JournalOp::Free(block) => (OP_FREE, *block, &[]),
+            JournalOp::Clear => (OP_CLEAR, 0, &[]),
+        };
+        let mut record = Vec::with_capacity(RECORD_OVERHEAD + data.len());
+        record.push(kind);
+        record.extend_from_slice(&block.to_le_bytes());
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(data);
+        let crc = crc32(&record);
+        record.extend_from_slice(&crc.to_le_bytes());
+
+        if let Err(e) = file.write_all(&record).and_then(|_| file.sync_data()) {
+            // Drop the partial record so later appends stay replayable
+            let _ = file.set_len(self.len);
+            return Err(e);
+        }
+        self.len += record.len() as u64;
+        Ok(())
+    }
+
+    /// Reads the records of a journal without opening it for writing or
+    /// cutting off a damaged tail. A missing journal is empty.
+    pub fn inspect(path: &Path) -> io::Result<Recovered> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let (ops, valid) = Self::parse(&data);
+        Ok(Recovered {
+            ops,
+            dropped_bytes: data.len() - valid,
+        })
+    }
+
+    /// Empties the journal once its records are part of a checkpoint.
+    pub fn reset(&mut self) -> io::Result<()> {
+        if let Some(file) = &self.file {
+            file.set_len(0)?;
+            file.sync_data()?;
+        }
+        self.len = 0;
+        Ok(())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn parse(data: &[u8]) -> (Vec<JournalOp>, usize) {
+        let mut ops = Vec::new();
+        let mut pos = 0;
+        while data.len() - pos >= RECORD_OVERHEAD {
+            let kind = data[pos];
+            let block = u64::from_le_bytes(data[pos + 1..pos + 9].try_into().unwrap());
+            let len = u32::from_le_bytes(data[pos + 9..pos + 13].try_into().unwrap()) as usize;
+            let end = pos + 13 + len;
+            if data.len() < end + 4 {
+                break;
+            }
+            let crc = u32::from_le_bytes(data[end..end + 4].try_into().unwrap());
+            if crc != crc32(&data[pos..end]) {
+                break;
+            }
+            let op = match kind {
+                OP_WRITE => JournalOp::Write(block, data[pos + 13..end].to_vec()),
+                OP_FREE => JournalOp::Free(block),
+                OP_CLEAR => JournalOp::Clear,
+                _ => break,
+            };
+            ops.push(op);
+            pos = end + 4;
+        }
+        (ops, pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_torn_tail_is_dropped() {
+        let path = PathBuf::from("test_journal_torn.journal");
+        let _ = fs::remove_file(&path);
+        {
+            let (mut journal, recovered) = Journal::open(&path).unwrap();
+            assert!(recovered.ops.is_empty());
+            journal.append(&JournalOp::Write(1, b"abc".to_vec())).unwrap();
+            journal.append(&JournalOp::Free(2)).unwrap();
+        }
+        // Simulate a crash in the middle of the next append
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[OP_WRITE, 9, 0, 0]).unwrap();
+        drop(file);
+
+        let (journal, recovered) = Journal::open(&path).unwrap();
+        assert_eq!(
+            recovered.ops,
+            vec![JournalOp::Write(1, b"abc".to_vec()), JournalOp::Free(2)]
+        );
+        assert_eq!(recovered.dropped_bytes, 4);
+        assert_eq!(journal.len(), fs::metadata(&path).unwrap().len());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Credentials, NodeType};
+
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+/// Inode-style attributes of a VFS node. Timestamps are seconds since the
+/// Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub ino: u64,
+    pub kind: NodeType,
+    pub size: u64,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+}
+
+impl Metadata {
+    /// Metadata for a freshly created node with a new inode number.
+    pub fn new(kind: NodeType, mode: u32) -> Self {
+        Metadata {
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            ..Self::synthetic(kind, mode)
+        }
+    }
+
+    /// Metadata for generated nodes (/proc, /dev, ...) that have no inode.
+    pub fn synthetic(kind: NodeType, mode: u32) -> Self {
+        let now = now();
+        Metadata {
+            ino: 0,
+            kind,
+            size: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            nlink: if kind == NodeType::Directory { 2 } else { 1 },
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    pub fn touch_access(&mut self) {
+        self.atime = now();
+    }
+
+    pub fn touch_modify(&mut self) {
+        let now = now();
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    pub fn touch_change(&mut self) {
+        self.ctime = now();
+    }
+
+    /// Checks `mask` (a combination of `R_OK`, `W_OK`, `X_OK`) against the
+    /// owner, group or other bits that apply to `cred`. Root bypasses checks.
+    pub fn permits(&self, cred: &Credentials, mask: u32) -> bool {
+        if cred.is_root() {
+            return true;
+        }
+        let bits = if self.uid == cred.uid {
+            self.mode >> 6
+        } else if cred.in_group(self.gid) {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        bits & mask & 0o7 == mask
+    }
+
+    /// `ls -l` style mode string, e.g. `drwxr-xr-x`.
+    pub fn mode_string(&self) -> String {
+        let mut s = String::with_capacity(10);
+        s.push(match self.kind {
+            NodeType::Directory => 'd',
+            NodeType::File => '-',
+            NodeType::Fifo => 'p',
+        });
+        for shift in [6, 3, 0] {
+            let bits = (self.mode >> shift) & 0o7;
+            s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+            s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+            s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+        }
+        s
+    }
+}
+
+/// Makes sure inode numbers handed out later never collide with `ino`,
+/// e.g. after loading a tree from disk.
+pub(crate) fn reserve_ino(ino: u64) {
+    NEXT_INO.fetch_max(ino + 1, Ordering::Relaxed);
+}
+
+/// Change counters of a stored node, so syncing only re-encodes what
+/// changed. Bumped while the changed state is still locked.
+#[derive(Debug, Default)]
+pub(crate) struct Generation {
+    data: AtomicU64,
+    meta: AtomicU64,
+}
+
+impl Generation {
+    /// Content or directory entries changed.
+    pub fn data_changed(&self) {
+        self.data.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Only attributes (mode, owner, times) changed.
+    pub fn meta_changed(&self) {
+        self.meta.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// `(data, meta)` counters.
+    pub fn current(&self) -> (u64, u64) {
+        (self.data.load(Ordering::SeqCst), self.meta.load(Ordering::SeqCst))
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Formats epoch seconds as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil-from-days, Howard Hinnant's algorithm.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_string() {
+        let meta = Metadata::synthetic(NodeType::Directory, 0o755);
+        assert_eq!(meta.mode_string(), "drwxr-xr-x");
+        let meta = Metadata::synthetic(NodeType::File, 0o640);
+        assert_eq!(meta.mode_string(), "-rw-r-----");
+    }
+
+    #[test]
+    fn test_permits() {
+        let mut meta = Metadata::synthetic(NodeType::File, 0o640);
+        meta.uid = 1000;
+        meta.gid = 100;
+        let owner = Credentials { uid: 1000, gid: 1000, groups: vec![1000] };
+        let member = Credentials { uid: 1001, gid: 1001, groups: vec![1001, 100] };
+        let other = Credentials { uid: 1002, gid: 1002, groups: vec![1002] };
+        assert!(meta.permits(&owner, 6));
+        assert!(meta.permits(&member, 4));
+        assert!(!meta.permits(&member, 2));
+        assert!(!meta.permits(&other, 4));
+        assert!(meta.permits(&Credentials::root(), 7));
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00");
+        assert_eq!(format_time(1_700_000_000), "2023-11-14 22:13:20");
+    }
+}
@@ -1,17 +1,37 @@
+mod allocator;
 mod dev;
 mod directory;
 mod disk;
+mod diskfs;
 mod file;
 mod filesystem;
+pub mod fsck;
+pub mod image;
+mod journal;
+mod metadata;
+mod mount;
 mod network;
 mod node;
 mod proc;
+mod sys;
+mod throttle;
+mod users;
 
+pub use allocator::BlockBitmap;
 pub use dev::{DevDirectory, NullDevice, RandomDevice};
 pub use directory::Directory;
-pub use disk::{Disk, FileDisk, RamDisk};
+pub use disk::{
+    Disk, DiskError, FileDisk, RamDisk, SnapshotInfo, DEFAULT_BLOCK_SIZE, DEFAULT_CAPACITY_BLOCKS,
+    JOURNAL_COMPACT_BYTES,
+};
+pub use diskfs::{DiskStore, FS_MAGIC, FS_VERSION};
 pub use file::File;
 pub use filesystem::FileSystem;
+pub use metadata::{format_time, Metadata};
+pub use mount::{Mount, MountTable};
 pub use network::NetworkDirectory;
 pub use node::{DirectoryOps, NodeType, VfsNode};
-pub use proc::ProcDirectory;
+pub use proc::{FdDirectory, PidDirectory, ProcDirectory, ProcFile};
+pub use sys::{CgroupDirectory, CgroupFile, SysDirectory};
+pub use throttle::ThrottledDisk;
+pub use users::{Credentials, Group, User, UserDb, R_OK, W_OK, X_OK};
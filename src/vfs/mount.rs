@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use super::VfsNode;
+
+#[derive(Clone)]
+pub struct Mount {
+    pub path: String,
+    pub fs_type: String,
+    pub node: Arc<dyn VfsNode>,
+}
+
+/// Mount points keyed by normalized absolute path. Lookups pick the
+/// longest mounted prefix of a path, so mounts can be nested.
+#[derive(Clone, Default)]
+pub struct MountTable {
+    mounts: Arc<Mutex<BTreeMap<String, Mount>>>,
+}
+
+impl MountTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mount(&self, path: &str, fs_type: &str, node: Arc<dyn VfsNode>) -> Result<(), String> {
+        if node.as_directory().is_none() {
+            return Err(format!("{}: provider is not a directory", path));
+        }
+        let path = normalize(path);
+        let mut mounts = self.mounts.lock().unwrap();
+        if mounts.contains_key(&path) {
+            return Err(format!("{}: already mounted", path));
+        }
+        mounts.insert(
+            path.clone(),
+            Mount {
+                path,
+                fs_type: fs_type.to_string(),
+                node,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn unmount(&self, path: &str) -> Result<Mount, String> {
+        let path = normalize(path);
+        let mut mounts = self.mounts.lock().unwrap();
+        if !mounts.contains_key(&path) {
+            return Err(format!("{}: not mounted", path));
+        }
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        if mounts.keys().any(|p| *p != path && p.starts_with(&prefix)) {
+            return Err(format!("{}: target is busy", path));
+        }
+        Ok(mounts.remove(&path).unwrap())
+    }
+
+    pub fn list(&self) -> Vec<Mount> {
+        self.mounts.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn get(&self, path: &str) -> Option<Mount> {
+        self.mounts.lock().unwrap().get(&normalize(path)).cloned()
+    }
+
+    /// Finds the mount with the longest prefix of `parts` and returns it
+    /// with the number of path components it covers.
+    pub fn resolve(&self, parts: &[&str]) -> Option<(Mount, usize)> {
+        let mounts = self.mounts.lock().unwrap();
+        (0..=parts.len()).rev().find_map(|depth| {
+            let path = format!("/{}", parts[..depth].join("/"));
+            mounts.get(&path).map(|m| (m.clone(), depth))
+        })
+    }
+
+    /// True if `path` or anything below it is a mount point.
+    pub fn is_busy(&self, path: &str) -> bool {
+        let path = normalize(path);
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        self.mounts
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|p| *p == path || p.starts_with(&prefix))
+    }
+
+    /// Names of mount points that sit directly below `path`.
+    pub fn children_of(&self, path: &str) -> Vec<String> {
+        let path = normalize(path);
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        self.mounts
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|p| p.strip_prefix(&prefix))
+            .filter(|rest| !rest.is_empty() && !rest.contains('/'))
+            .map(|rest| rest.to_string())
+            .collect()
+    }
+
+    /// A handle that does not keep the table alive, for providers mounted
+    /// in the very table they describe.
+    pub(crate) fn downgrade(&self) -> WeakMountTable {
+        WeakMountTable(Arc::downgrade(&self.mounts))
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct WeakMountTable(Weak<Mutex<BTreeMap<String, Mount>>>);
+
+impl WeakMountTable {
+    pub(crate) fn upgrade(&self) -> Option<MountTable> {
+        self.0.upgrade().map(|mounts| MountTable { mounts })
+    }
+}
+
+pub(crate) fn normalize(path: &str) -> String {
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::Directory;
+
+    #[test]
+    fn test_longest_prefix() {
+        let table = MountTable::new();
+        table.mount("/", "rootfs", Arc::new(Directory::new("/"))).unwrap();
+        table.mount("/a", "tmpfs", Arc::new(Directory::new("a"))).unwrap();
+        table.mount("/a/b", "tmpfs", Arc::new(Directory::new("b"))).unwrap();
+
+        let (mount, depth) = table.resolve(&["a", "b", "c"]).unwrap();
+        assert_eq!(mount.path, "/a/b");
+        assert_eq!(depth, 2);
+        let (mount, depth) = table.resolve(&["x"]).unwrap();
+        assert_eq!(mount.path, "/");
+        assert_eq!(depth, 0);
+    }
+
+    #[test]
+    fn test_unmount_busy() {
+        let table = MountTable::new();
+        table.mount("/a", "tmpfs", Arc::new(Directory::new("a"))).unwrap();
+        table.mount("/a/b", "tmpfs", Arc::new(Directory::new("b"))).unwrap();
+        assert!(table.unmount("/a").is_err());
+        assert!(table.unmount("/a/b").is_ok());
+        assert!(table.unmount("/a/").is_ok());
+        assert_eq!(table.children_of("/"), Vec::<String>::new());
+    }
+}
@@ -1,49 +1,50 @@
 use super::{DirectoryOps, File, NodeType, VfsNode};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use crate::net::RoutingTable;
+use std::sync::Arc;
 
+/// `/network`: the routing table of a host. `routes` lists it the way
+/// `ip route` does, rendered when it is looked up.
 #[derive(Clone)]
 pub struct NetworkDirectory {
-    routes: Arc<Mutex<HashMap<String, String>>>,
+    routes: Arc<RoutingTable>,
 }
 
 impl NetworkDirectory {
-    pub fn new() -> Self {
-        let mut routes = HashMap::new();
-        routes.insert("default".to_string(), "0.0.0.0/0 via 192.168.1.1".to_string());
-        NetworkDirectory {
-            routes: Arc::new(Mutex::new(routes)),
-        }
+    pub fn new(routes: Arc<RoutingTable>) -> Self {
+        NetworkDirectory { routes }
     }
 
-    pub fn add_route(&self, destination: &str, gateway: &str) {
-        let mut routes = self.routes.lock().unwrap();
-        routes.insert(destination.to_string(), gateway.to_string());
+    pub fn routes(&self) -> &Arc<RoutingTable> {
+        &self.routes
     }
 
-    pub fn get_routes(&self) -> HashMap<String, String> {
-        self.routes.lock().unwrap().clone()
+    fn render_routes(&self) -> String {
+        self.routes.routes().iter().map(|route| format!("{}\n", route)).collect()
     }
 }
 
 impl DirectoryOps for NetworkDirectory {
     fn list(&self) -> Vec<String> {
-        let routes = self.routes.lock().unwrap();
-        routes.keys().cloned().collect()
+        vec!["routes".into()]
     }
 
     fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
-        let routes = self.routes.lock().unwrap();
-        if let Some(route) = routes.get(name) {
-            let content = format!("Destination: {}\nGateway: {}", name, route);
-            Some(Arc::new(File::new_with_content(name, content)))
-        } else {
-            None
+        match name {
+            "routes" => Some(Arc::new(File::new_with_content(name, self.render_routes()))),
+            _ => None,
         }
     }
 
-    fn add(&self, _node: Arc<dyn VfsNode>) {
-        // Not implemented for routes
+    fn add(&self, _node: Arc<dyn VfsNode>) -> Result<(), String> {
+        Err("Use 'route add' to add routes".into())
+    }
+
+    fn remove(&self, _name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        Err("Read-only file system".into())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Read-only file system".into())
     }
 }
 
@@ -59,4 +60,8 @@ impl VfsNode for NetworkDirectory {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
 }
\ No newline at end of file
@@ -1,20 +1,64 @@
 use std::any::Any;
 use std::sync::Arc;
 
+use super::Metadata;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NodeType {
     File,
     Directory,
+    /// A named pipe (see `ipc::Fifo`).
+    Fifo,
 }
 
 pub trait VfsNode: Send + Sync {
     fn name(&self) -> String;
     fn node_type(&self) -> NodeType;
     fn as_any(&self) -> &dyn Any;
+
+    /// Directory nodes return themselves here so paths can be walked
+    /// through any provider, not only the built-in `Directory`.
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        None
+    }
+
+    /// Returns a handle to the same node under a new name, sharing its
+    /// contents. Used by rename and move; `None` means renaming is unsupported.
+    fn with_name(&self, _name: &str) -> Option<Arc<dyn VfsNode>> {
+        None
+    }
+
+    /// Generated nodes default to read-only, root-owned attributes.
+    fn metadata(&self) -> Metadata {
+        match self.node_type() {
+            NodeType::Directory => Metadata::synthetic(NodeType::Directory, 0o555),
+            kind => Metadata::synthetic(kind, 0o444),
+        }
+    }
+
+    /// Changes mode bits and ownership; generated nodes refuse.
+    fn set_permissions(&self, _mode: u32, _uid: u32, _gid: u32) -> Result<(), String> {
+        Err("Operation not permitted".into())
+    }
+
+    /// Handles a write to a generated file, such as a control file under
+    /// `/sys`. Regular files are written by `FileSystem` itself.
+    fn write_control(&self, _data: &[u8]) -> Result<(), String> {
+        Err("Not a regular file".into())
+    }
 }
 
-pub trait DirectoryOps {
+pub trait DirectoryOps: Send + Sync {
     fn list(&self) -> Vec<String>;
     fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>>;
-    fn add(&self, node: Arc<dyn VfsNode>);
+    fn add(&self, node: Arc<dyn VfsNode>) -> Result<(), String>;
+    fn remove(&self, name: &str) -> Result<Arc<dyn VfsNode>, String>;
+    fn rename(&self, from: &str, to: &str) -> Result<(), String>;
+
+    /// Whether `rmdir` may remove the directory. Providers whose entries
+    /// are generated (e.g. a resource group's control files) can say yes
+    /// while listing some.
+    fn is_empty(&self) -> bool {
+        self.list().is_empty()
+    }
 }
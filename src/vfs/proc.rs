@@ -1,44 +1,176 @@
 use std::any::Any;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use super::{DirectoryOps, NodeType, VfsNode};
+use super::mount::WeakMountTable;
+use super::{Disk, DirectoryOps, MountTable, NodeType, VfsNode};
+use crate::ipc::Ipc;
+use crate::mm::PAGE_SIZE;
+use crate::scheduler::{ProcessInfo, ProcessState, Scheduler};
+use crate::vps::manager::VpsManager;
 
+/// `/proc`: a directory per live process plus system-wide files, all
+/// rendered from the scheduler when they are looked up. `mounts` and `vps`
+/// only appear once the mount table and VPS manager are attached.
 pub struct ProcDirectory {
-    pub scheduler: Arc<crate::scheduler::Scheduler>,
+    pub scheduler: Arc<Scheduler>,
+    mounts: Option<WeakMountTable>,
+    disk: Option<Arc<dyn Disk + Send + Sync>>,
+    vps: Option<Arc<Mutex<VpsManager>>>,
 }
 
 impl ProcDirectory {
-    pub fn new(scheduler: Arc<crate::scheduler::Scheduler>) -> Self {
-        ProcDirectory { scheduler }
+    pub fn new(scheduler: Arc<Scheduler>) -> Self {
+        ProcDirectory {
+            scheduler,
+            mounts: None,
+            disk: None,
+            vps: None,
+        }
     }
 
-    pub fn add(&self, _node: Arc<dyn VfsNode>) {
-        // Read-only
+    /// Shows the mounts of `mounts` in `/proc/mounts`.
+    pub fn with_mounts(mut self, mounts: &MountTable) -> Self {
+        self.mounts = Some(mounts.downgrade());
+        self
+    }
+
+    /// The disk behind the root filesystem, shown as its device.
+    pub fn with_disk(mut self, disk: Arc<dyn Disk + Send + Sync>) -> Self {
+        self.disk = Some(disk);
+        self
+    }
+
+    /// Lists the servers of `manager` in `/proc/vps`.
+    pub fn with_vps(mut self, manager: Arc<Mutex<VpsManager>>) -> Self {
+        self.vps = Some(manager);
+        self
     }
 
     pub fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
-        if let Ok(id) = name.parse::<u32>() {
-            if let Some(process) = self.scheduler.list_processes().iter().find(|p| p.id == id) {
-                // Return a ProcFile with process info
-                Some(Arc::new(ProcFile {
-                    content: format!("PID: {}\nPriority: {}\nStatus: {}\nParent PID: {:?}\nMemory Usage: {} bytes\n", 
-                                     process.id, process.priority, process.status, process.parent_pid, process.memory_usage),
+        let content = match name {
+            "ipc" => {
+                return Some(Arc::new(IpcDirectory {
+                    ipc: self.scheduler.ipc().clone(),
                 }))
-            } else {
-                None
             }
-        } else {
-            None
-        }
+            "loadavg" => self.loadavg(),
+            "meminfo" => self.meminfo(),
+            "mounts" => self.render_mounts()?,
+            "uptime" => self.uptime(),
+            "vps" => self.render_vps()?,
+            _ => {
+                let id = name.parse::<u32>().ok()?;
+                let process = find_process(&self.scheduler, id)?;
+                return Some(Arc::new(PidDirectory {
+                    scheduler: self.scheduler.clone(),
+                    id: process.id,
+                }));
+            }
+        };
+        Some(Arc::new(ProcFile::new(name, content)))
     }
 
     pub fn list(&self) -> Vec<String> {
-        // List process IDs as files
-        self.scheduler
+        // Process directories first, as on Linux
+        let mut names: Vec<String> = self
+            .scheduler
             .list_processes()
             .iter()
+            .filter(|p| p.status != ProcessState::Terminated)
             .map(|p| p.id.to_string())
-            .collect()
+            .collect();
+        names.extend(["ipc", "loadavg", "meminfo"].map(String::from));
+        if self.mounts.is_some() {
+            names.push("mounts".into());
+        }
+        names.push("uptime".into());
+        if self.vps.is_some() {
+            names.push("vps".into());
+        }
+        names
+    }
+
+    // Seconds since boot and seconds the CPUs spent idle, summed over CPUs
+    fn uptime(&self) -> String {
+        let uptime = self.scheduler.uptime().as_secs_f64();
+        let busy = self.scheduler.cgroups().usage("/").unwrap_or_default().cpu_time.as_secs_f64();
+        let idle = (uptime * self.scheduler.cpus() as f64 - busy).max(0.0);
+        format!("{:.2} {:.2}\n", uptime, idle)
+    }
+
+    fn loadavg(&self) -> String {
+        let load = self.scheduler.load_average();
+        format!(
+            "{:.2} {:.2} {:.2} {}/{} {}\n",
+            load.one, load.five, load.fifteen, load.runnable, load.total, load.last_pid
+        )
+    }
+
+    fn meminfo(&self) -> String {
+        let memory = self.scheduler.memory();
+        let stats = memory.stats();
+        let committed: usize = self
+            .scheduler
+            .list_processes()
+            .iter()
+            .map(|p| memory.usage(p.id).virtual_bytes)
+            .sum();
+        let kb = |pages: usize| pages * PAGE_SIZE / 1024;
+        let mut out = String::new();
+        for (field, value) in [
+            ("MemTotal", kb(stats.frames)),
+            ("MemFree", kb(stats.free_frames)),
+            ("MemAvailable", kb(stats.free_frames)),
+            ("SwapTotal", kb(stats.swap_pages)),
+            ("SwapFree", kb(stats.swap_pages - stats.swap_used)),
+            ("Committed_AS", committed / 1024),
+        ] {
+            out.push_str(&format!("{:<16}{:>8} kB\n", format!("{}:", field), value));
+        }
+        out
+    }
+
+    // device, mount point, type and options, as in /etc/fstab
+    fn render_mounts(&self) -> Option<String> {
+        let mounts = self.mounts.as_ref()?.upgrade()?;
+        let mut out = String::new();
+        for mount in mounts.list() {
+            let device = match (mount.path.as_str(), &self.disk) {
+                ("/", Some(_)) => "/dev/vda".to_string(),
+                _ => mount.fs_type.clone(),
+            };
+            out.push_str(&format!("{} {} {} rw 0 0\n", device, mount.path, mount.fs_type));
+        }
+        Some(out)
+    }
+
+    fn render_vps(&self) -> Option<String> {
+        let manager = self.vps.as_ref()?.lock().unwrap();
+        let mut servers = manager.list_vps();
+        servers.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut out = format!(
+            "{:<16} {:<8} {:>6} {:>7} {:>4} {:<15} {:>5}  {}\n",
+            "name", "status", "mem_mb", "disk_mb", "cpus", "ip", "procs", "id"
+        );
+        for config in servers {
+            let procs = manager.get_vps(&config.id).map_or(0, |instance| {
+                let scheduler = instance.lock().unwrap().scheduler.clone();
+                scheduler.list_processes().iter().filter(|p| p.status.is_alive()).count()
+            });
+            out.push_str(&format!(
+                "{:<16} {:<8} {:>6} {:>7} {:>4} {:<15} {:>5}  {}\n",
+                config.name,
+                config.status,
+                config.memory_mb,
+                config.disk_mb,
+                config.cpu_cores,
+                config.ip_address,
+                procs,
+                config.id
+            ));
+        }
+        Some(out)
     }
 }
 
@@ -51,8 +183,16 @@ impl DirectoryOps for ProcDirectory {
         self.get(name)
     }
 
-    fn add(&self, _node: Arc<dyn VfsNode>) {
-        // Read-only
+    fn add(&self, _node: Arc<dyn VfsNode>) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+
+    fn remove(&self, _name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        Err("Read-only file system".into())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Read-only file system".into())
     }
 }
 
@@ -68,13 +208,223 @@ impl VfsNode for ProcDirectory {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
+}
+
+// Processes stay in the scheduler's table after they end; /proc stops
+// showing them once they have been reaped
+fn find_process(scheduler: &Scheduler, id: u32) -> Option<ProcessInfo> {
+    scheduler
+        .list_processes()
+        .into_iter()
+        .find(|p| p.id == id && p.status != ProcessState::Terminated)
+}
+
+// Clock ticks per second of the times in `stat`, as on Linux
+const CLOCK_TICKS: u128 = 100;
+
+fn ticks(time: Duration) -> u128 {
+    time.as_millis() * CLOCK_TICKS / 1000
+}
+
+// State line of `status`
+fn state_name(status: ProcessState) -> &'static str {
+    match status {
+        ProcessState::Ready | ProcessState::Running => "running",
+        ProcessState::Sleeping | ProcessState::Blocked => "sleeping",
+        ProcessState::Stopped => "stopped",
+        ProcessState::Zombie => "zombie",
+        ProcessState::Terminated => "dead",
+    }
+}
+
+/// `/proc/<pid>`. Files are rendered when looked up, so they always show
+/// the process as it is now.
+pub struct PidDirectory {
+    scheduler: Arc<Scheduler>,
+    id: u32,
+}
+
+impl PidDirectory {
+    fn render(&self, name: &str) -> Option<String> {
+        let process = find_process(&self.scheduler, self.id)?;
+        let memory = self.scheduler.memory().usage(self.id);
+        let (code, state) = (process.status.code(), state_name(process.status));
+        let ppid = process.parent_pid.unwrap_or(0);
+        let content = match name {
+            "status" => {
+                let mut out = format!(
+                    "Name:\t{}\nState:\t{} ({})\nPid:\t{}\nPPid:\t{}\nUid:\t{}\nThreads:\t1\n",
+                    process.name,
+                    code,
+                    state,
+                    process.id,
+                    ppid,
+                    process.uid
+                );
+                for (field, bytes) in [
+                    ("VmSize", memory.virtual_bytes),
+                    ("VmRSS", memory.resident_bytes),
+                    ("VmSwap", memory.swapped_bytes),
+                ] {
+                    out.push_str(&format!("{}:\t{:>8} kB\n", field, bytes / 1024));
+                }
+                out
+            }
+            // The fields of Linux's stat up to rss; what the scheduler does
+            // not track is 0
+            "stat" => {
+                let booted = SystemTime::now() - self.scheduler.uptime();
+                let started = process.start_time.duration_since(booted).unwrap_or_default();
+                format!(
+                    "{} ({}) {} {} 0 0 0 -1 0 0 0 0 0 {} 0 0 0 {} 0 1 0 {} {} {}\n",
+                    process.id,
+                    process.name,
+                    code,
+                    ppid,
+                    ticks(process.cpu_time),
+                    process.priority,
+                    ticks(started),
+                    memory.virtual_bytes,
+                    memory.resident_bytes / PAGE_SIZE
+                )
+            }
+            // NUL-terminated strings, as on Linux
+            "cmdline" => process.argv.iter().map(|arg| format!("{}\0", arg)).collect(),
+            "environ" => process.env.iter().map(|(key, value)| format!("{}={}\0", key, value)).collect(),
+            "children" => {
+                let children: Vec<String> = self
+                    .scheduler
+                    .list_processes()
+                    .iter()
+                    .filter(|p| p.parent_pid == Some(self.id) && p.status != ProcessState::Terminated)
+                    .map(|p| p.id.to_string())
+                    .collect();
+                if children.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}\n", children.join(" "))
+                }
+            }
+            _ => return None,
+        };
+        Some(content)
+    }
+}
+
+impl DirectoryOps for PidDirectory {
+    fn list(&self) -> Vec<String> {
+        ["children", "cmdline", "environ", "fd", "stat", "status"].map(String::from).to_vec()
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        if name == "fd" {
+            find_process(&self.scheduler, self.id)?;
+            return Some(Arc::new(FdDirectory {
+                ipc: self.scheduler.ipc().clone(),
+                id: self.id,
+            }));
+        }
+        let content = self.render(name)?;
+        Some(Arc::new(ProcFile::new(name, content)))
+    }
+
+    fn add(&self, _node: Arc<dyn VfsNode>) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+
+    fn remove(&self, _name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        Err("Read-only file system".into())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+}
+
+impl VfsNode for PidDirectory {
+    fn name(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Directory
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
+}
+
+/// `/proc/<pid>/fd`: one file per pipe end the process holds, numbered in
+/// the order it opened them and holding what the end points at.
+pub struct FdDirectory {
+    ipc: Ipc,
+    id: u32,
+}
+
+impl DirectoryOps for FdDirectory {
+    fn list(&self) -> Vec<String> {
+        (0..self.ipc.descriptors(self.id).len()).map(|fd| fd.to_string()).collect()
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        let fd = name.parse::<usize>().ok()?;
+        let target = self.ipc.descriptors(self.id).into_iter().nth(fd)?;
+        Some(Arc::new(ProcFile::new(name, format!("{}\n", target))))
+    }
+
+    fn add(&self, _node: Arc<dyn VfsNode>) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+
+    fn remove(&self, _name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        Err("Read-only file system".into())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+}
+
+impl VfsNode for FdDirectory {
+    fn name(&self) -> String {
+        "fd".to_string()
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Directory
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
 }
 
 pub struct ProcFile {
+    name: String,
     content: String,
 }
 
 impl ProcFile {
+    pub fn new(name: &str, content: String) -> Self {
+        ProcFile {
+            name: name.to_string(),
+            content,
+        }
+    }
+
     pub fn read(&self) -> String {
         self.content.clone()
     }
@@ -86,7 +436,7 @@ impl ProcFile {
 
 impl VfsNode for ProcFile {
     fn name(&self) -> String {
-        "task".to_string()
+        self.name.clone()
     }
 
     fn node_type(&self) -> NodeType {
@@ -97,3 +447,117 @@ impl VfsNode for ProcFile {
         self
     }
 }
+
+/// `/proc/ipc`: the IPC objects of the scheduler's processes, one table per
+/// kind.
+pub struct IpcDirectory {
+    ipc: Ipc,
+}
+
+impl IpcDirectory {
+    fn render(&self, name: &str) -> Option<String> {
+        let mut out = String::new();
+        match name {
+            "pipes" => {
+                out.push_str(&format!(
+                    "{:>5} {:<5} {:>8} {:>7} {:>7} {:>7}  {}\n",
+                    "id", "type", "buffered", "readers", "writers", "creator", "path"
+                ));
+                for pipe in self.ipc.pipes() {
+                    out.push_str(&format!(
+                        "{:>5} {:<5} {:>8} {:>7} {:>7} {:>7}  {}\n",
+                        pipe.id,
+                        if pipe.path.is_some() { "fifo" } else { "pipe" },
+                        pipe.buffered,
+                        pipe.readers,
+                        pipe.writers,
+                        pipe.creator,
+                        pipe.path.as_deref().unwrap_or("-")
+                    ));
+                }
+            }
+            "msg" => {
+                out.push_str(&format!(
+                    "{:<16} {:>5} {:>8} {:>8} {:>6} {:>6}\n",
+                    "name", "owner", "messages", "bytes", "lspid", "lrpid"
+                ));
+                for queue in self.ipc.queues() {
+                    out.push_str(&format!(
+                        "{:<16} {:>5} {:>8} {:>8} {:>6} {:>6}\n",
+                        queue.name,
+                        queue.owner,
+                        queue.messages,
+                        queue.bytes,
+                        pid_or_dash(queue.last_sender),
+                        pid_or_dash(queue.last_receiver)
+                    ));
+                }
+            }
+            "shm" => {
+                out.push_str(&format!(
+                    "{:<16} {:>8} {:>7} {:>6} {:>6}  {}\n",
+                    "name", "size", "creator", "lpid", "nattch", "attached"
+                ));
+                for segment in self.ipc.segments() {
+                    let attached: Vec<String> = segment.attached.iter().map(|pid| pid.to_string()).collect();
+                    out.push_str(&format!(
+                        "{:<16} {:>8} {:>7} {:>6} {:>6}  {}\n",
+                        segment.name,
+                        segment.size,
+                        segment.creator,
+                        pid_or_dash(segment.last_pid),
+                        segment.attached.len(),
+                        if attached.is_empty() { "-".to_string() } else { attached.join(",") }
+                    ));
+                }
+            }
+            _ => return None,
+        }
+        Some(out)
+    }
+}
+
+fn pid_or_dash(pid: Option<u32>) -> String {
+    pid.map(|pid| pid.to_string()).unwrap_or_else(|| "-".into())
+}
+
+impl DirectoryOps for IpcDirectory {
+    fn list(&self) -> Vec<String> {
+        vec!["msg".into(), "pipes".into(), "shm".into()]
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        let content = self.render(name)?;
+        Some(Arc::new(ProcFile::new(name, content)))
+    }
+
+    fn add(&self, _node: Arc<dyn VfsNode>) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+
+    fn remove(&self, _name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        Err("Read-only file system".into())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+}
+
+impl VfsNode for IpcDirectory {
+    fn name(&self) -> String {
+        "ipc".to_string()
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Directory
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
+}
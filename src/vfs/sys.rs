@@ -0,0 +1,287 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use super::{DirectoryOps, Metadata, NodeType, VfsNode};
+use crate::cgroup::Limits;
+use crate::scheduler::Scheduler;
+
+/// `/sys` and `/sys/fs`, leading to the scheduler's resource groups at
+/// `/sys/fs/cgroup`.
+pub struct SysDirectory {
+    name: &'static str,
+    scheduler: Arc<Scheduler>,
+}
+
+impl SysDirectory {
+    pub fn new(scheduler: Arc<Scheduler>) -> Self {
+        SysDirectory { name: "sys", scheduler }
+    }
+}
+
+impl VfsNode for SysDirectory {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Directory
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
+}
+
+impl DirectoryOps for SysDirectory {
+    fn list(&self) -> Vec<String> {
+        match self.name {
+            "sys" => vec!["fs".into()],
+            _ => vec!["cgroup".into()],
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        match (self.name, name) {
+            ("sys", "fs") => Some(Arc::new(SysDirectory {
+                name: "fs",
+                scheduler: self.scheduler.clone(),
+            })),
+            ("fs", "cgroup") => Some(Arc::new(CgroupDirectory {
+                scheduler: self.scheduler.clone(),
+                path: "/".into(),
+            })),
+            _ => None,
+        }
+    }
+
+    fn add(&self, _node: Arc<dyn VfsNode>) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+
+    fn remove(&self, _name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        Err("Read-only file system".into())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Read-only file system".into())
+    }
+}
+
+/// Control files of every group, and whether they can be written.
+const CONTROL_FILES: [(&str, bool); 10] = [
+    ("cgroup.procs", true),
+    ("cpu.stat", false),
+    ("cpu.weight", true),
+    ("io.max", true),
+    ("io.stat", false),
+    ("memory.current", false),
+    ("memory.events", false),
+    ("memory.max", true),
+    ("pids.current", false),
+    ("pids.max", true),
+];
+
+/// A resource group. `mkdir` in it creates a child group and `rmdir`
+/// removes one that has no processes left.
+pub struct CgroupDirectory {
+    scheduler: Arc<Scheduler>,
+    path: String,
+}
+
+impl CgroupDirectory {
+    fn child_path(&self, name: &str) -> String {
+        match self.path.as_str() {
+            "/" => format!("/{}", name),
+            path => format!("{}/{}", path, name),
+        }
+    }
+}
+
+impl VfsNode for CgroupDirectory {
+    fn name(&self) -> String {
+        match self.path.rsplit('/').next() {
+            Some("") | None => "cgroup".to_string(),
+            Some(name) => name.to_string(),
+        }
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::Directory
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_directory(&self) -> Option<&dyn DirectoryOps> {
+        Some(self)
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata::synthetic(NodeType::Directory, 0o755)
+    }
+}
+
+impl DirectoryOps for CgroupDirectory {
+    fn list(&self) -> Vec<String> {
+        let mut names: Vec<String> = CONTROL_FILES.iter().map(|(name, _)| name.to_string()).collect();
+        names.extend(self.scheduler.cgroups().children(&self.path));
+        names
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn VfsNode>> {
+        if let Some((file, writable)) = CONTROL_FILES.iter().find(|(file, _)| *file == name) {
+            return Some(Arc::new(CgroupFile {
+                scheduler: self.scheduler.clone(),
+                path: self.path.clone(),
+                name: file,
+                writable: *writable,
+            }));
+        }
+        let path = self.child_path(name);
+        self.scheduler.cgroups().exists(&path).then(|| {
+            Arc::new(CgroupDirectory {
+                scheduler: self.scheduler.clone(),
+                path,
+            }) as Arc<dyn VfsNode>
+        })
+    }
+
+    fn add(&self, node: Arc<dyn VfsNode>) -> Result<(), String> {
+        if node.node_type() != NodeType::Directory {
+            return Err("Operation not permitted".into());
+        }
+        self.scheduler.cgroups().create(&self.child_path(&node.name()))
+    }
+
+    fn remove(&self, name: &str) -> Result<Arc<dyn VfsNode>, String> {
+        let node = self.get(name).ok_or("No such file or directory")?;
+        if node.node_type() != NodeType::Directory {
+            return Err("Operation not permitted".into());
+        }
+        self.scheduler.cgroups().remove(&self.child_path(name))?;
+        Ok(node)
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> Result<(), String> {
+        Err("Operation not permitted".into())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.scheduler.cgroups().children(&self.path).is_empty()
+    }
+}
+
+/// One control file of a resource group. Reads render the current value;
+/// writes to the writable ones change it.
+pub struct CgroupFile {
+    scheduler: Arc<Scheduler>,
+    path: String,
+    name: &'static str,
+    writable: bool,
+}
+
+impl CgroupFile {
+    pub fn read(&self) -> String {
+        let cgroups = self.scheduler.cgroups();
+        let limits = cgroups.limits(&self.path).unwrap_or_default();
+        let usage = cgroups.usage(&self.path).unwrap_or_default();
+        let max = |value: Option<u64>| value.map_or("max".to_string(), |v| v.to_string());
+        match self.name {
+            "cgroup.procs" => cgroups
+                .procs(&self.path)
+                .unwrap_or_default()
+                .iter()
+                .map(|pid| format!("{}\n", pid))
+                .collect(),
+            "cpu.stat" => format!("usage_usec {}\n", usage.cpu_time.as_micros()),
+            "cpu.weight" => format!("{}\n", limits.cpu_weight),
+            "io.max" => format!("{}\n", max(limits.io_max)),
+            "io.stat" => format!(
+                "rbytes={} wbytes={} rios={} wios={} throttled_usec={}\n",
+                usage.read_bytes,
+                usage.write_bytes,
+                usage.reads,
+                usage.writes,
+                usage.io_throttled.as_micros()
+            ),
+            "memory.current" => {
+                let memory = self.scheduler.memory();
+                let bytes: usize = cgroups
+                    .subtree_procs(&self.path)
+                    .iter()
+                    .map(|pid| memory.usage(*pid).resident_bytes)
+                    .sum();
+                format!("{}\n", bytes)
+            }
+            "memory.events" => format!("oom_kill {}\n", usage.oom_kills),
+            "memory.max" => format!("{}\n", max(limits.memory_max.map(|v| v as u64))),
+            "pids.current" => format!("{}\n", cgroups.subtree_procs(&self.path).len()),
+            "pids.max" => format!("{}\n", max(limits.pids_max.map(|v| v as u64))),
+            _ => String::new(),
+        }
+    }
+}
+
+/// `max` for no limit, else a number with an optional K, M or G suffix.
+fn parse_limit(value: &str) -> Result<Option<u64>, String> {
+    if value == "max" {
+        return Ok(None);
+    }
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .map(Some)
+        .ok_or_else(|| "Invalid argument".into())
+}
+
+impl VfsNode for CgroupFile {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::File
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata::synthetic(NodeType::File, if self.writable { 0o644 } else { 0o444 })
+    }
+
+    fn write_control(&self, data: &[u8]) -> Result<(), String> {
+        if !self.writable {
+            return Err("Permission denied".into());
+        }
+        let text = String::from_utf8_lossy(data);
+        let value = text.trim();
+        let cgroups = self.scheduler.cgroups();
+        if self.name == "cgroup.procs" {
+            let pid = value.parse::<u32>().map_err(|_| "Invalid argument")?;
+            return cgroups.attach(pid, &self.path);
+        }
+        let mut limits: Limits = cgroups.limits(&self.path)?;
+        match self.name {
+            "cpu.weight" => limits.cpu_weight = value.parse().map_err(|_| "Invalid argument")?,
+            "io.max" => limits.io_max = parse_limit(value)?,
+            "memory.max" => limits.memory_max = parse_limit(value)?.map(|v| v as usize),
+            "pids.max" => limits.pids_max = parse_limit(value)?.map(|v| v as usize),
+            _ => return Err("Permission denied".into()),
+        }
+        cgroups.set_limits(&self.path, limits)
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use super::{Disk, DiskError, SnapshotInfo};
+use crate::cgroup::Cgroups;
+use crate::scheduler;
+
+/// A disk that charges block transfers to the calling process's resource
+/// group and makes the process wait while the group is over its `io.max`
+/// budget. Snapshots and clones go straight to the inner disk.
+pub struct ThrottledDisk {
+    inner: Arc<dyn Disk + Send + Sync>,
+    cgroups: Cgroups,
+}
+
+impl ThrottledDisk {
+    pub fn new(inner: Arc<dyn Disk + Send + Sync>, cgroups: Cgroups) -> Self {
+        ThrottledDisk { inner, cgroups }
+    }
+
+    pub fn inner(&self) -> &Arc<dyn Disk + Send + Sync> {
+        &self.inner
+    }
+
+    fn charge(&self, bytes: usize, write: bool) {
+        let pid = scheduler::current_pid().unwrap_or(0);
+        let wait = self.cgroups.charge_io(pid, bytes, write);
+        if !wait.is_zero() {
+            scheduler::sleep(wait);
+        }
+    }
+}
+
+impl Disk for ThrottledDisk {
+    fn read_block(&self, block_id: u64) -> Result<Vec<u8>, DiskError> {
+        self.charge(self.inner.block_size(), false);
+        self.inner.read_block(block_id)
+    }
+
+    fn write_block(&self, block_id: u64, data: &[u8]) -> Result<(), DiskError> {
+        self.charge(data.len(), true);
+        self.inner.write_block(block_id, data)
+    }
+
+    fn allocate_block(&self) -> Result<u64, DiskError> {
+        self.inner.allocate_block()
+    }
+
+    fn free_block(&self, block_id: u64) -> Result<(), DiskError> {
+        self.inner.free_block(block_id)
+    }
+
+    fn get_allocated_blocks(&self) -> usize {
+        self.inner.get_allocated_blocks()
+    }
+
+    fn get_total_size(&self) -> usize {
+        self.inner.get_total_size()
+    }
+
+    fn clear_all(&self) {
+        self.inner.clear_all()
+    }
+
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    fn capacity(&self) -> u64 {
+        self.inner.capacity()
+    }
+
+    fn snapshot(&self) -> Result<u64, DiskError> {
+        self.inner.snapshot()
+    }
+
+    fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.inner.list_snapshots()
+    }
+
+    fn restore(&self, id: u64) -> Result<(), DiskError> {
+        self.inner.restore(id)
+    }
+
+    fn delete_snapshot(&self, id: u64) -> Result<(), DiskError> {
+        self.inner.delete_snapshot(id)
+    }
+
+    fn clone_cow(&self) -> Arc<dyn Disk + Send + Sync> {
+        self.inner.clone_cow()
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgroup::Limits;
+    use crate::vfs::RamDisk;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_writes_wait_for_the_io_budget() {
+        let cgroups = Cgroups::default();
+        cgroups
+            .set_limits("/", Limits { io_max: Some(4 * 512), ..Limits::default() })
+            .unwrap();
+        let disk = ThrottledDisk::new(Arc::new(RamDisk::with_geometry(512, 16)), cgroups.clone());
+        let start = Instant::now();
+        for _ in 0..6 {
+            let block = disk.allocate_block().unwrap();
+            disk.write_block(block, &[7; 512]).unwrap();
+        }
+        // Four blocks fit in the first second, two more take half of the next
+        assert!(start.elapsed() >= Duration::from_millis(450));
+        let usage = cgroups.usage("/").unwrap();
+        assert_eq!((usage.writes, usage.write_bytes), (6, 6 * 512));
+        assert!(usage.io_throttled >= Duration::from_millis(450));
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use super::{File, FileSystem};
+
+pub const R_OK: u32 = 4;
+pub const W_OK: u32 = 2;
+pub const X_OK: u32 = 1;
+
+/// Shadow entry of an account nobody can log into until it gets a password.
+pub const LOCKED: &str = "!";
+
+/// Identity a `FileSystem` handle acts as when checking permissions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    pub fn root() -> Self {
+        Credentials {
+            uid: 0,
+            gid: 0,
+            groups: vec![0],
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.gid == gid || self.groups.contains(&gid)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: String,
+    pub shell: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub gid: u32,
+    pub members: Vec<String>,
+}
+
+/// User and group database stored in `/etc/passwd`, `/etc/group` and
+/// `/etc/shadow`. The files are the source of truth: load, modify, save.
+#[derive(Debug, Clone, Default)]
+pub struct UserDb {
+    pub users: Vec<User>,
+    pub groups: Vec<Group>,
+    shadow: HashMap<String, String>,
+}
+
+impl UserDb {
+    /// Creates `/etc` with a root-only database if it does not exist yet.
+    pub fn bootstrap(fs: &FileSystem) -> Result<(), String> {
+        let fs = fs.as_root();
+        if fs.get("/etc").is_none() {
+            fs.create_dir("/etc")?;
+        }
+        if fs.get("/etc/passwd").is_some() {
+            return Ok(());
+        }
+        let db = UserDb {
+            users: vec![User {
+                name: "root".to_string(),
+                uid: 0,
+                gid: 0,
+                home: "/root".to_string(),
+                shell: "/bin/vsh".to_string(),
+            }],
+            groups: vec![Group {
+                name: "root".to_string(),
+                gid: 0,
+                members: Vec::new(),
+            }],
+            // Root has no password until `passwd` gives it one
+            shadow: HashMap::from([("root".to_string(), LOCKED.to_string())]),
+        };
+        db.save(&fs)
+    }
+
+    pub fn load(fs: &FileSystem) -> Self {
+        let read = |path: &str| -> String {
+            fs.get(path)
+                .and_then(|n| n.as_any().downcast_ref::<File>().map(|f| f.read()))
+                .map(|b| String::from_utf8_lossy(&b).into_owned())
+                .unwrap_or_default()
+        };
+
+        let users = read("/etc/passwd")
+            .lines()
+            .filter_map(|line| {
+                let f: Vec<&str> = line.split(':').collect();
+                if f.len() < 7 {
+                    return None;
+                }
+                Some(User {
+                    name: f[0].to_string(),
+                    uid: f[2].parse().ok()?,
+                    gid: f[3].parse().ok()?,
+                    home: f[5].to_string(),
+                    shell: f[6].to_string(),
+                })
+            })
+            .collect();
+
+        let groups = read("/etc/group")
+            .lines()
+            .filter_map(|line| {
+                let f: Vec<&str> = line.split(':').collect();
+                if f.len() < 4 {
+                    return None;
+                }
+                Some(Group {
+                    name: f[0].to_string(),
+                    gid: f[2].parse().ok()?,
+                    members: f[3]
+                        .split(',')
+                        .filter(|m| !m.is_empty())
+                        .map(|m| m.to_string())
+                        .collect(),
+                })
+            })
+            .collect();
+
+        let shadow = read("/etc/shadow")
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, hash)| (name.to_string(), hash.to_string()))
+            .collect();
+
+        UserDb {
+            users,
+            groups,
+            shadow,
+        }
+    }
+
+    pub fn save(&self, fs: &FileSystem) -> Result<(), String> {
+        let fs = fs.as_root();
+        let passwd: String = self
+            .users
+            .iter()
+            .map(|u| format!("{}:x:{}:{}::{}:{}\n", u.name, u.uid, u.gid, u.home, u.shell))
+            .collect();
+        let group: String = self
+            .groups
+            .iter()
+            .map(|g| format!("{}:x:{}:{}\n", g.name, g.gid, g.members.join(",")))
+            .collect();
+        let shadow: String = self
+            .users
+            .iter()
+            .map(|u| {
+                let hash = self.shadow.get(&u.name).map(String::as_str).unwrap_or("");
+                format!("{}:{}\n", u.name, hash)
+            })
+            .collect();
+
+        for (path, content, mode) in [
+            ("/etc/passwd", passwd, 0o644),
+            ("/etc/group", group, 0o644),
+            ("/etc/shadow", shadow, 0o600),
+        ] {
+            let file = match fs.get(path) {
+                Some(node) => node
+                    .as_any()
+                    .downcast_ref::<File>()
+                    .cloned()
+                    .ok_or_else(|| format!("{} is not a regular file", path))?,
+                None => (*fs.create_file(path)?).clone(),
+            };
+            file.set_content(content.as_bytes());
+            fs.chmod(path, mode)?;
+        }
+        Ok(())
+    }
+
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.iter().find(|u| u.name == name)
+    }
+
+    pub fn user_by_uid(&self, uid: u32) -> Option<&User> {
+        self.users.iter().find(|u| u.uid == uid)
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    pub fn group_by_gid(&self, gid: u32) -> Option<&Group> {
+        self.groups.iter().find(|g| g.gid == gid)
+    }
+
+    /// Adds a user with a primary group of the same name and a locked
+    /// password. UIDs are picked from 1000 upwards when not given.
+    pub fn add_user(&mut self, name: &str, uid: Option<u32>) -> Result<User, String> {
+        if !valid_name(name) {
+            return Err(format!("invalid user name '{}'", name));
+        }
+        if self.user(name).is_some() {
+            return Err(format!("user '{}' already exists", name));
+        }
+        let uid = match uid {
+            Some(uid) if self.user_by_uid(uid).is_some() => {
+                return Err(format!("UID {} is not unique", uid))
+            }
+            Some(uid) => uid,
+            None => self.next_id(self.users.iter().map(|u| u.uid)),
+        };
+        let gid = match self.group(name) {
+            Some(group) => group.gid,
+            None => self.add_group(name, Some(uid).filter(|g| self.group_by_gid(*g).is_none()))?,
+        };
+        let user = User {
+            name: name.to_string(),
+            uid,
+            gid,
+            home: format!("/home/{}", name),
+            shell: "/bin/vsh".to_string(),
+        };
+        self.users.push(user.clone());
+        self.shadow.insert(name.to_string(), LOCKED.to_string());
+        Ok(user)
+    }
+
+    pub fn add_group(&mut self, name: &str, gid: Option<u32>) -> Result<u32, String> {
+        if !valid_name(name) {
+            return Err(format!("invalid group name '{}'", name));
+        }
+        if self.group(name).is_some() {
+            return Err(format!("group '{}' already exists", name));
+        }
+        let gid = match gid {
+            Some(gid) if self.group_by_gid(gid).is_some() => {
+                return Err(format!("GID {} is not unique", gid))
+            }
+            Some(gid) => gid,
+            None => self.next_id(self.groups.iter().map(|g| g.gid)),
+        };
+        self.groups.push(Group {
+            name: name.to_string(),
+            gid,
+            members: Vec::new(),
+        });
+        Ok(gid)
+    }
+
+    pub fn add_member(&mut self, group: &str, user: &str) -> Result<(), String> {
+        if self.user(user).is_none() {
+            return Err(format!("user '{}' does not exist", user));
+        }
+        let group = self
+            .groups
+            .iter_mut()
+            .find(|g| g.name == group)
+            .ok_or_else(|| format!("group '{}' does not exist", group))?;
+        if !group.members.iter().any(|m| m == user) {
+            group.members.push(user.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<(), String> {
+        if self.user(name).is_none() {
+            return Err(format!("user '{}' does not exist", name));
+        }
+        self.shadow.insert(name.to_string(), hash_password(name, password));
+        Ok(())
+    }
+
+    /// Users without a password entry can log in with an empty password;
+    /// locked accounts (an entry starting with `!`) cannot log in at all.
+    pub fn authenticate(&self, name: &str, password: &str) -> bool {
+        if self.user(name).is_none() {
+            return false;
+        }
+        match self.shadow.get(name).map(String::as_str) {
+            None | Some("") => password.is_empty(),
+            Some(hash) if hash.starts_with(LOCKED) => false,
+            Some(hash) => hash == hash_password(name, password),
+        }
+    }
+
+    pub fn credentials(&self, name: &str) -> Option<Credentials> {
+        let user = self.user(name)?;
+        let mut groups = vec![user.gid];
+        for group in self.groups.iter().filter(|g| g.members.iter().any(|m| m == name)) {
+            if !groups.contains(&group.gid) {
+                groups.push(group.gid);
+            }
+        }
+        Some(Credentials {
+            uid: user.uid,
+            gid: user.gid,
+            groups,
+        })
+    }
+
+    fn next_id(&self, ids: impl Iterator<Item = u32>) -> u32 {
+        ids.filter(|id| *id >= 1000).max().map(|id| id + 1).unwrap_or(1000)
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// FNV-1a over the user name and password. Stable across runs so the
+/// shadow file survives persistence; not meant to resist real attacks.
+fn hash_password(name: &str, password: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in name.bytes().chain([b':']).chain(password.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("$fnv${:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bootstrap_and_add_user() {
+        let fs = FileSystem::new();
+        UserDb::bootstrap(&fs).unwrap();
+        let mut db = UserDb::load(&fs);
+        assert_eq!(db.user("root").unwrap().uid, 0);
+
+        let alice = db.add_user("alice", None).unwrap();
+        assert_eq!(alice.uid, 1000);
+        assert!(!db.authenticate("alice", ""));
+        db.add_group("staff", None).unwrap();
+        db.add_member("staff", "alice").unwrap();
+        db.set_password("alice", "secret").unwrap();
+        db.save(&fs).unwrap();
+
+        let db = UserDb::load(&fs);
+        assert!(db.authenticate("alice", "secret"));
+        assert!(!db.authenticate("alice", "wrong"));
+        // Root's password is locked until it is set
+        assert!(!db.authenticate("root", ""));
+        assert!(!db.authenticate("root", LOCKED));
+        let cred = db.credentials("alice").unwrap();
+        assert_eq!(cred.uid, 1000);
+        assert!(cred.in_group(db.group("staff").unwrap().gid));
+        assert_eq!(fs.stat("/etc/shadow").unwrap().mode, 0o600);
+    }
+}
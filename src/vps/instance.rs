@@ -1,10 +1,71 @@
-use crate::vfs::{FileSystem, Disk, FileDisk};
-use crate::scheduler::{Scheduler, Process};
+use crate::cgroup::Limits;
+use crate::vfs::{FileSystem, Disk, FileDisk, RamDisk, ThrottledDisk, DEFAULT_BLOCK_SIZE};
+use crate::mm::{MemoryManager, Replacement, PAGE_SIZE};
+use crate::net::{Interface, Network};
+use crate::ipc::PipeReader;
+use crate::scheduler::{Process, ProcessHandle, Scheduler, Signal};
 use crate::vps::manager::VpsManager;
+use crate::tty::Tty;
+use crate::vps::template::Template;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
 
-#[derive(Clone, Debug, serde::Serialize)]
+/// Quanto `stop` espera os processos saírem após o SIGTERM antes de
+/// mandar SIGKILL.
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Comandos que o shell da VPS executa ao iniciar, um por linha; linhas
+/// começadas por `#` são comentários.
+pub const STARTUP_SCRIPT: &str = "/etc/rc.local";
+
+/// Ciclo de vida de uma VPS. Uma VPS parada passa por `starting` até
+/// `running`, e por `stopping` de volta a `stopped`; `crashed` é um shell
+/// que terminou com erro sem ninguém pedir.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VpsState {
+    Starting,
+    Running,
+    Stopping,
+    Stopped,
+    Crashed,
+}
+
+impl VpsState {
+    /// Se a VPS pode passar deste estado para `next`.
+    pub fn can_become(self, next: VpsState) -> bool {
+        use VpsState::*;
+        matches!(
+            (self, next),
+            (Stopped | Crashed, Starting)
+                | (Starting, Running | Crashed)
+                // O shell também pode sair sozinho (`exit`)
+                | (Running, Stopping | Stopped | Crashed)
+                // Processos que sobraram de uma VPS que caiu
+                | (Crashed, Stopping)
+                | (Stopping, Stopped)
+        )
+    }
+}
+
+impl fmt::Display for VpsState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VpsState::Starting => "starting",
+            VpsState::Running => "running",
+            VpsState::Stopping => "stopping",
+            VpsState::Stopped => "stopped",
+            VpsState::Crashed => "crashed",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct VpsConfig {
     pub id: String,
     pub name: String,
@@ -12,7 +73,10 @@ pub struct VpsConfig {
     pub disk_mb: usize,
     pub cpu_cores: usize,
     pub ip_address: String,
-    pub status: String, // "stopped", "running", "paused"
+    pub status: VpsState,
+    /// Iniciar a VPS quando o vbox carrega o registro.
+    #[serde(default)]
+    pub autostart: bool,
 }
 
 pub struct VpsInstance {
@@ -20,99 +84,389 @@ pub struct VpsInstance {
     pub filesystem: Arc<Mutex<FileSystem>>,
     pub disk: Arc<dyn Disk + Send + Sync>,
     pub scheduler: Arc<Scheduler>,
-    pub processes: Vec<u32>, // PIDs dos processos desta VPS
+    /// Placa de rede da VPS; o link só fica ativo enquanto ela roda.
+    pub nic: Arc<Interface>,
+    pub shell: Option<ProcessHandle>, // shell da VPS, enquanto ela roda
+    // Terminal do shell; desligá-lo encerra o shell
+    console: Option<Arc<Tty>>,
+    // Terminais abertos por `exec` até agora
+    sessions: usize,
+    // Thread que roda o scheduler da VPS até seus processos acabarem
+    dispatcher: Option<JoinHandle<()>>,
+    // Estados pelos quais a VPS passou, e quando
+    transitions: Vec<(SystemTime, VpsState)>,
 }
 
 impl VpsInstance {
+    /// Uma VPS numa rede só dela, com a imagem de disco no diretório atual.
     pub fn new(config: VpsConfig) -> Self {
-        // Criar filesystem isolado para a VPS
-        let mut fs = FileSystem::new();
+        Self::in_dir(config, Path::new("."), &Network::default())
+    }
+
+    /// Imagem de disco da VPS `id` dentro de `dir`. O journal, os snapshots
+    /// e demais arquivos do disco usam o mesmo nome com outra extensão.
+    pub fn disk_path(dir: &Path, id: &str) -> PathBuf {
+        dir.join(format!("vps_{}_disk.bin", id))
+    }
 
-        // Criar disco virtual para a VPS
-        let disk_path = format!("vps_{}_disk.bin", config.id);
-        let disk = Arc::new(FileDisk::new(&disk_path));
+    /// Cria a VPS com a imagem de disco em `dir` e a placa de rede ligada ao
+    /// switch de `network`; se a imagem já existe, os arquivos da VPS são
+    /// carregados dela.
+    pub fn in_dir(config: VpsConfig, dir: &Path, network: &Network) -> Self {
+        // Criar disco virtual para a VPS; os arquivos da VPS ficam nele.
+        // O tamanho do disco é limitado por disk_mb
+        let disk_path = Self::disk_path(dir, &config.id);
+        let capacity = (config.disk_mb * 1024 * 1024 / DEFAULT_BLOCK_SIZE) as u64;
+        let raw_disk: Arc<dyn Disk + Send + Sync> = Arc::new(FileDisk::with_geometry(
+            &disk_path.to_string_lossy(),
+            DEFAULT_BLOCK_SIZE,
+            capacity,
+        ));
 
-        // Inicializar disco
-        let block_id = disk.allocate_block();
-        disk.write_block(block_id, format!("VPS {} Disk", config.name).as_bytes());
+        // Criar scheduler dedicado para a VPS, com cpu_cores CPUs. memory_mb
+        // é a RAM da VPS; páginas além dela vão para uma swap própria (tanta
+        // swap quanto RAM) e, com as duas cheias, o OOM killer encerra o
+        // maior processo. A swap fica fora do disco da VPS para que páginas
+        // de processos não acabem em snapshots e clones
+        let frames = config.memory_mb * 1024 * 1024 / PAGE_SIZE;
+        let swap_blocks = (frames * PAGE_SIZE).div_ceil(DEFAULT_BLOCK_SIZE) as u64;
+        let swap = Arc::new(RamDisk::with_geometry(DEFAULT_BLOCK_SIZE, swap_blocks));
+        let memory = MemoryManager::new(frames, Replacement::Clock).with_swap(swap, frames);
+        let scheduler = Arc::new(Scheduler::with_cpus(config.cpu_cores).with_memory(memory));
+        let limits = Limits {
+            memory_max: Some(config.memory_mb * 1024 * 1024),
+            ..Limits::default()
+        };
+        scheduler.cgroups().set_limits("/", limits).unwrap();
 
-        // Criar scheduler dedicado para a VPS
-        let scheduler = Arc::new(Scheduler::new());
+        // Criar filesystem isolado para a VPS; a E/S dos processos conta
+        // para o io.max dos seus grupos
+        let disk: Arc<dyn Disk + Send + Sync> =
+            Arc::new(ThrottledDisk::new(raw_disk, scheduler.cgroups().clone()));
+        let mut fs = FileSystem::new();
+        if let Err(e) = fs.attach_disk(disk.clone()) {
+            eprintln!("VPS {}: could not load filesystem from disk: {}", config.name, e);
+        }
 
         // Configurar diretórios básicos
-        let proc_dir = Arc::new(crate::vfs::ProcDirectory::new(scheduler.clone()));
-        fs.set_proc(proc_dir);
+        let proc_dir = Arc::new(
+            crate::vfs::ProcDirectory::new(scheduler.clone())
+                .with_mounts(&fs.mounts)
+                .with_disk(disk.clone()),
+        );
+        fs.mount("/proc", "proc", proc_dir).unwrap();
 
         let dev_dir = Arc::new(crate::vfs::DevDirectory::new());
-        fs.set_dev(dev_dir);
+        fs.mount("/dev", "devfs", dev_dir).unwrap();
 
-        let network_dir = Arc::new(crate::vfs::NetworkDirectory::new());
-        fs.set_network(network_dir);
+        let sys_dir = Arc::new(crate::vfs::SysDirectory::new(scheduler.clone()));
+        fs.mount("/sys", "sysfs", sys_dir).unwrap();
+
+        // O endereço vem do IPAM do gerenciador; um inválido deixa a VPS sem
+        // endereço em vez de tomar o de outra
+        let addr = config.ip_address.parse().unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let nic = network.connect("eth0", addr);
+        nic.set_up(false);
+
+        // Cada VPS tem a sua tabela de rotas, a da sua placa
+        let network_dir = Arc::new(crate::vfs::NetworkDirectory::new(nic.routes().clone()));
+        fs.mount("/network", "netfs", network_dir).unwrap();
 
         // Criar diretórios básicos
-        fs.create_dir("/network").unwrap();
-        fs.create_dir("/home").unwrap();
-        fs.create_dir("/etc").unwrap();
+        for dir in ["/home", "/etc"] {
+            if fs.get(dir).is_none() {
+                if let Err(e) = fs.create_dir(dir) {
+                    eprintln!("VPS {}: could not create {}: {}", config.name, dir, e);
+                }
+            }
+        }
+        if let Err(e) = crate::vfs::UserDb::bootstrap(&fs) {
+            eprintln!("VPS {}: could not create the user database: {}", config.name, e);
+        }
+        if let Err(e) = fs.sync() {
+            eprintln!("VPS {}: could not sync filesystem: {}", config.name, e);
+        }
 
         VpsInstance {
             config,
             filesystem: Arc::new(Mutex::new(fs)),
             disk,
             scheduler,
-            processes: Vec::new(),
+            nic,
+            shell: None,
+            console: None,
+            sessions: 0,
+            dispatcher: None,
+            transitions: Vec::new(),
         }
     }
 
     pub fn start(&mut self) -> Result<(), String> {
-        if self.config.status == "running" {
-            return Err("VPS already running".to_string());
+        match self.get_status() {
+            VpsState::Stopped | VpsState::Crashed => {}
+            VpsState::Stopping => return Err("VPS is stopping".to_string()),
+            _ => return Err("VPS already running".to_string()),
+        }
+        self.set_state(VpsState::Starting)?;
+
+        // Criar processo shell para a VPS; ele lê comandos do console da
+        // VPS em vez do terminal do host
+        let (input, writer) = self.scheduler.ipc().pipe();
+        let console = Arc::new(Tty::new("console", writer));
+        let shell_process = self.shell_process(input, true).with_tty(console.clone());
+        let shell = match self.scheduler.spawn(shell_process) {
+            Ok(shell) => shell,
+            Err(e) => {
+                self.set_state(VpsState::Crashed)?;
+                return Err(format!("could not start shell: {}", e));
+            }
+        };
+
+        // O scheduler da VPS precisa de alguém que o rode
+        if self.dispatcher.as_ref().is_none_or(|d| d.is_finished()) {
+            let scheduler = self.scheduler.clone();
+            let dispatcher = thread::Builder::new()
+                .name(format!("vps-{}", self.config.name))
+                .spawn(move || scheduler.run());
+            match dispatcher {
+                Ok(dispatcher) => self.dispatcher = Some(dispatcher),
+                Err(e) => {
+                    let _ = shell.kill(Signal::Kill);
+                    self.set_state(VpsState::Crashed)?;
+                    return Err(format!("could not start VPS scheduler: {}", e));
+                }
+            }
         }
 
-        self.config.status = "running".to_string();
+        // Script de inicialização: suas linhas são os primeiros comandos
+        // que o shell lê
+        let script = self.filesystem.lock().unwrap().read_file(STARTUP_SCRIPT).unwrap_or_default();
+        let script = String::from_utf8_lossy(&script);
+        let commands = script
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for command in commands {
+            if let Err(e) = console.send(command) {
+                eprintln!("VPS {}: could not run {}: {}", self.config.name, STARTUP_SCRIPT, e);
+                break;
+            }
+        }
 
-        // Criar processo shell para a VPS
-        let fs_clone = self.filesystem.clone();
-        let disk_clone = self.disk.clone();
-        let scheduler_clone = self.scheduler.clone();
+        self.shell = Some(shell);
+        self.console = Some(console);
+        self.set_state(VpsState::Running)
+    }
 
-        let shell_process = Process::new(
+    // Processo `sh` da VPS, lendo comandos de `input`; com `prompt` ele
+    // mostra o prompt como um shell interativo
+    fn shell_process(&self, input: PipeReader, prompt: bool) -> Process {
+        let fs = self.filesystem.clone();
+        let disk = self.disk.clone();
+        let scheduler = self.scheduler.clone();
+        let nic = self.nic.clone();
+        Process::new(
             0,
             1,
             Duration::from_secs(0),
             Box::new(move || {
                 let mut shell = crate::shell::Shell::new(
-                    (*fs_clone.lock().unwrap()).clone(),
-                    disk_clone,
-                    scheduler_clone,
-                    Arc::new(Mutex::new(VpsManager::new())), // VPS isolada sem VpsManager próprio
-                );
+                    (*fs.lock().unwrap()).clone(),
+                    disk,
+                    scheduler,
+                    Arc::new(Mutex::new(VpsManager::in_memory())), // VPS isolada sem VpsManager próprio
+                )
+                .with_input(input)
+                .with_interface(nic);
+                if prompt {
+                    shell = shell.with_prompt();
+                }
                 shell.run();
             }),
             None,
-        );
+        )
+        .with_command(&["sh"])
+    }
 
-        let pid = shell_process.id;
-        self.scheduler.add_process(shell_process);
-        self.processes.push(pid);
+    /// Terminal do shell da VPS, enquanto ela roda.
+    pub fn console(&mut self) -> Option<Arc<Tty>> {
+        self.refresh();
+        self.console.clone()
+    }
 
-        Ok(())
+    /// Roda `command` num shell novo da VPS, com um terminal próprio que já
+    /// vem ligado ao do host. O shell termina depois do comando; quem chama
+    /// espera por ele com o `ProcessHandle` e depois desliga o terminal.
+    pub fn exec(&mut self, command: &str) -> Result<(ProcessHandle, Arc<Tty>), String> {
+        if self.get_status() != VpsState::Running {
+            return Err("VPS is not running".to_string());
+        }
+        let (input, writer) = self.scheduler.ipc().pipe();
+        self.sessions += 1;
+        let tty = Arc::new(Tty::new(&format!("pts/{}", self.sessions), writer));
+        tty.send(command)?;
+        tty.hangup();
+        tty.attach()?;
+        let shell = self.scheduler.spawn(self.shell_process(input, false).with_tty(tty.clone()))?;
+        Ok((shell, tty))
+    }
+
+    /// Cria os diretórios e arquivos de `template` no sistema de arquivos
+    /// da VPS e os grava no disco. Arquivos que já existem ficam como estão.
+    pub fn apply_template(&mut self, template: &Template) -> Result<(), String> {
+        let fs = self.filesystem.lock().unwrap();
+        for dir in template.dirs {
+            if fs.get(dir).is_none() {
+                fs.create_dir(dir)?;
+            }
+        }
+        for (path, content) in template.files {
+            if fs.get(path).is_none() {
+                fs.create_file(path)?;
+                fs.write_file(path, content.as_bytes())?;
+            }
+        }
+        fs.sync()
+    }
+
+    /// Grava o sistema de arquivos no disco e tira um snapshot do disco.
+    pub fn snapshot(&mut self) -> Result<u64, String> {
+        self.filesystem.lock().unwrap().sync()?;
+        Ok(self.disk.snapshot()?)
     }
 
+    /// Volta o disco, e com ele os arquivos, ao snapshot `id`. A VPS
+    /// precisa estar parada ou ter caído; numa VPS caída, os processos que
+    /// restaram são mortos antes e ela fica parada.
+    pub fn restore_snapshot(&mut self, id: u64) -> Result<(), String> {
+        match self.get_status() {
+            VpsState::Stopped => {}
+            VpsState::Crashed => self.kill()?,
+            _ => return Err("VPS must be stopped".to_string()),
+        }
+        self.disk.restore(id)?;
+        self.filesystem.lock().unwrap().reload()
+    }
+
+    /// Grava tudo no arquivo de imagem do disco, de modo que copiá-lo
+    /// copia a VPS.
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.filesystem.lock().unwrap().sync()?;
+        self.disk.flush()
+    }
+
+    /// Parada graciosa: manda SIGTERM a todos os processos da VPS, espera
+    /// até `STOP_TIMEOUT` e mata com SIGKILL os que restarem.
     pub fn stop(&mut self) -> Result<(), String> {
-        if self.config.status == "stopped" {
+        self.stop_within(STOP_TIMEOUT)
+    }
+
+    /// Como `stop`, esperando no máximo `grace` antes do SIGKILL.
+    pub fn stop_within(&mut self, grace: Duration) -> Result<(), String> {
+        self.shutdown(Some(grace))
+    }
+
+    /// Mata todos os processos da VPS com SIGKILL, sem esperar.
+    pub fn kill(&mut self) -> Result<(), String> {
+        self.shutdown(None)
+    }
+
+    /// Para a VPS, se estiver rodando, e a inicia de novo.
+    pub fn restart(&mut self) -> Result<(), String> {
+        if self.get_status() != VpsState::Stopped {
+            self.stop()?;
+        }
+        self.start()
+    }
+
+    fn shutdown(&mut self, grace: Option<Duration>) -> Result<(), String> {
+        if self.get_status() == VpsState::Stopped {
             return Err("VPS already stopped".to_string());
         }
+        self.set_state(VpsState::Stopping)?;
+
+        // Sem entrada o shell termina por conta própria
+        if let Some(console) = self.console.take() {
+            console.hangup();
+        }
+        self.shell = None;
+        if let Some(grace) = grace {
+            let deadline = Instant::now() + grace;
+            for process in self.live_processes() {
+                let _ = process.kill(Signal::Term);
+            }
+            for process in self.live_processes() {
+                process.join_timeout(deadline.saturating_duration_since(Instant::now()));
+            }
+        }
+        // Quem ignorou o SIGTERM, ou foi criado enquanto isso, morre aqui
+        loop {
+            let stragglers = self.live_processes();
+            if stragglers.is_empty() {
+                break;
+            }
+            for process in stragglers {
+                process.stop();
+            }
+        }
+        // O dispatcher ainda pode estar esperando as threads dos processos;
+        // um start() antes dele terminar ficaria sem ninguém rodando o shell
+        if let Some(dispatcher) = self.dispatcher.take() {
+            let _ = dispatcher.join();
+        }
+        self.set_state(VpsState::Stopped)
+    }
 
-        self.config.status = "stopped".to_string();
-        // TODO: Implementar parada graciosa dos processos
+    fn live_processes(&self) -> Vec<ProcessHandle> {
+        self.scheduler
+            .list_processes()
+            .iter()
+            .filter(|p| p.status.is_alive())
+            .filter_map(|p| self.scheduler.handle(p.id))
+            .collect()
+    }
+
+    fn set_state(&mut self, next: VpsState) -> Result<(), String> {
+        let current = self.config.status;
+        if !current.can_become(next) {
+            return Err(format!("invalid state transition: {} -> {}", current, next));
+        }
+        self.config.status = next;
+        self.transitions.push((SystemTime::now(), next));
+        self.nic.set_up(matches!(next, VpsState::Starting | VpsState::Running | VpsState::Stopping));
         Ok(())
     }
 
-    pub fn get_status(&self) -> &str {
-        &self.config.status
+    // Nota quando o shell saiu sozinho: com 0 a VPS parou, senão caiu
+    fn refresh(&mut self) {
+        let code = match &self.shell {
+            Some(shell) if !shell.is_alive() => shell.info().exit_code,
+            _ => return,
+        };
+        self.shell = None;
+        if let Some(console) = self.console.take() {
+            console.hangup();
+        }
+        let next = if code == Some(0) {
+            VpsState::Stopped
+        } else {
+            VpsState::Crashed
+        };
+        let _ = self.set_state(next);
+    }
+
+    pub fn get_status(&mut self) -> VpsState {
+        self.refresh();
+        self.config.status
+    }
+
+    /// Estados pelos quais a VPS passou, do mais antigo ao mais novo.
+    pub fn transitions(&self) -> &[(SystemTime, VpsState)] {
+        &self.transitions
     }
 
-    pub fn get_info(&self) -> VpsConfig {
+    pub fn get_info(&mut self) -> VpsConfig {
+        self.refresh();
         self.config.clone()
     }
 }
\ No newline at end of file
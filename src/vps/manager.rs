@@ -1,91 +1,271 @@
-use super::instance::{VpsInstance, VpsConfig};
-use std::collections::HashMap;
+use super::instance::{VpsConfig, VpsInstance, VpsState};
+use super::template;
+use crate::net::Network;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
+/// Registro padrão das VPS, ao lado das imagens de disco delas.
+pub const REGISTRY_FILE: &str = "vps_registry.json";
+
 pub struct VpsManager {
     vps_instances: HashMap<String, Arc<Mutex<VpsInstance>>>,
+    // Arquivo com as definições das VPS; sem ele nada sobrevive ao vbox
+    registry: Option<PathBuf>,
+    // Onde ficam as imagens de disco das VPS
+    dir: PathBuf,
+    // Rede das VPS, de onde vêm os endereços delas
+    network: Network,
+}
+
+impl Default for VpsManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VpsManager {
+    /// Carrega as VPS do registro padrão no diretório atual.
     pub fn new() -> Self {
+        Self::open(Path::new(REGISTRY_FILE))
+    }
+
+    /// Gerenciador sem registro: as VPS duram só enquanto ele existir.
+    pub fn in_memory() -> Self {
         VpsManager {
             vps_instances: HashMap::new(),
+            registry: None,
+            dir: PathBuf::from("."),
+            network: Network::default(),
         }
     }
 
+    /// Carrega as VPS de `registry` e reabre as imagens de disco delas, que
+    /// ficam no mesmo diretório. Imagens de VPS que não estão no registro
+    /// são apagadas. VPS com `autostart` são iniciadas.
+    pub fn open(registry: &Path) -> Self {
+        Self::open_on(registry, Network::default())
+    }
+
+    /// Como `open`, com as VPS na rede `network`.
+    pub fn open_on(registry: &Path, network: Network) -> Self {
+        let dir = match registry.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mut manager = VpsManager {
+            vps_instances: HashMap::new(),
+            registry: Some(registry.to_path_buf()),
+            dir,
+            network,
+        };
+
+        let configs = match fs::read(registry) {
+            Ok(bytes) => match serde_json::from_slice::<Vec<VpsConfig>>(&bytes) {
+                Ok(configs) => configs,
+                Err(e) => {
+                    // Guardar o registro danificado em vez de sobrescrevê-lo,
+                    // e não apagar imagens que ele talvez descrevesse
+                    eprintln!("{}: invalid VPS registry: {}", registry.display(), e);
+                    let _ = fs::copy(registry, registry.with_extension("corrupt"));
+                    return manager;
+                }
+            },
+            // Sem registro não há como saber de quem são as imagens
+            // existentes; elas ficam onde estão
+            Err(_) => return manager,
+        };
+
+        let mut renumbered = false;
+        for mut config in configs {
+            // Nenhum processo sobrevive ao vbox
+            config.status = VpsState::Stopped;
+            // Cada VPS mantém seu endereço, a menos que ele não caiba mais na
+            // rede ou outra VPS já o tenha
+            let kept = config
+                .ip_address
+                .parse()
+                .map_err(|_| format!("invalid address {}", config.ip_address))
+                .and_then(|addr| manager.network.reserve(addr));
+            if let Err(e) = kept {
+                match manager.network.allocate() {
+                    Ok(addr) => {
+                        eprintln!("VPS {}: {}; using {}", config.name, e, addr);
+                        config.ip_address = addr.to_string();
+                        renumbered = true;
+                    }
+                    Err(e) => eprintln!("VPS {}: {}", config.name, e),
+                }
+            }
+            let id = config.id.clone();
+            let instance = VpsInstance::in_dir(config, &manager.dir, &manager.network);
+            manager.vps_instances.insert(id, Arc::new(Mutex::new(instance)));
+        }
+        manager.remove_orphaned_disks();
+        if renumbered {
+            if let Err(e) = manager.save() {
+                eprintln!("{}", e);
+            }
+        }
+
+        for instance in manager.vps_instances.values() {
+            let mut instance = instance.lock().unwrap();
+            if instance.config.autostart {
+                if let Err(e) = instance.start() {
+                    eprintln!("VPS {}: autostart failed: {}", instance.config.name, e);
+                }
+            }
+        }
+        manager
+    }
+
     pub fn create_vps(&mut self, name: &str, memory_mb: usize, disk_mb: usize, cpu_cores: usize) -> Result<String, String> {
-        let id = Uuid::new_v4().to_string();
+        let config = self.new_config(name, memory_mb, disk_mb, cpu_cores)?;
+        self.add(VpsInstance::in_dir(config, &self.dir, &self.network))
+    }
 
-        // Gerar IP automaticamente (simples para demonstração)
-        let ip_address = format!("192.168.1.{}", 100 + self.vps_instances.len());
+    /// Cria uma VPS a partir do modelo `template` (veja `template::TEMPLATES`).
+    /// Os recursos não informados vêm do modelo.
+    pub fn create_from_template(
+        &mut self,
+        name: &str,
+        template: &str,
+        memory_mb: Option<usize>,
+        disk_mb: Option<usize>,
+        cpu_cores: Option<usize>,
+    ) -> Result<String, String> {
+        let template = template::find(template).ok_or_else(|| format!("unknown template: {}", template))?;
+        let config = self.new_config(
+            name,
+            memory_mb.unwrap_or(template.memory_mb),
+            disk_mb.unwrap_or(template.disk_mb),
+            cpu_cores.unwrap_or(template.cpu_cores),
+        )?;
+        let (id, ip) = (config.id.clone(), config.ip_address.clone());
+        let mut instance = VpsInstance::in_dir(config, &self.dir, &self.network);
+        if let Err(e) = instance.apply_template(template) {
+            drop(instance);
+            self.release_ip(&ip);
+            let _ = remove_disk_files(&self.dir, &id);
+            return Err(e);
+        }
+        self.add(instance)
+    }
 
-        let config = VpsConfig {
-            id: id.clone(),
+    /// Cria a VPS `name` com os recursos e uma cópia do disco (e portanto
+    /// dos arquivos) da VPS `source`. Os snapshots não são copiados.
+    pub fn clone_vps(&mut self, source: &str, name: &str) -> Result<String, String> {
+        let source = self.find(source)?;
+        let (memory_mb, disk_mb, cpu_cores) = {
+            let source = source.lock().unwrap();
+            (source.config.memory_mb, source.config.disk_mb, source.config.cpu_cores)
+        };
+        let config = self.new_config(name, memory_mb, disk_mb, cpu_cores)?;
+        let id = config.id.clone();
+        {
+            let mut source = source.lock().unwrap();
+            source.flush()?;
+            let from = VpsInstance::disk_path(&self.dir, &source.config.id);
+            let to = VpsInstance::disk_path(&self.dir, &id);
+            if let Err(e) = fs::copy(&from, &to) {
+                self.release_ip(&config.ip_address);
+                let _ = remove_disk_files(&self.dir, &id);
+                return Err(format!("cannot copy {}: {}", from.display(), e));
+            }
+        }
+        self.add(VpsInstance::in_dir(config, &self.dir, &self.network))
+    }
+
+    // Configuração de uma VPS nova, parada, com ID e IP próprios
+    fn new_config(&self, name: &str, memory_mb: usize, disk_mb: usize, cpu_cores: usize) -> Result<VpsConfig, String> {
+        if self.resolve_id(name).is_ok() {
+            return Err(format!("VPS {} already exists", name));
+        }
+        let ip_address = self.network.allocate()?.to_string();
+
+        Ok(VpsConfig {
+            id: Uuid::new_v4().to_string(),
             name: name.to_string(),
             memory_mb,
             disk_mb,
             cpu_cores,
             ip_address,
-            status: "stopped".to_string(),
-        };
+            status: VpsState::Stopped,
+            autostart: false,
+        })
+    }
 
-        let instance = Arc::new(Mutex::new(VpsInstance::new(config)));
-        self.vps_instances.insert(id.clone(), instance);
+    fn release_ip(&self, ip_address: &str) {
+        if let Ok(addr) = ip_address.parse() {
+            self.network.release(addr);
+        }
+    }
+
+    /// Rede das VPS.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
 
+    fn add(&mut self, instance: VpsInstance) -> Result<String, String> {
+        let id = instance.config.id.clone();
+        self.vps_instances.insert(id.clone(), Arc::new(Mutex::new(instance)));
+        self.save()?;
         Ok(id)
     }
 
     pub fn start_vps(&mut self, identifier: &str) -> Result<(), String> {
-        // Try to find by ID first, then by name
-        let vps_id = if self.vps_instances.contains_key(identifier) {
-            identifier.to_string()
-        } else {
-            // Look for VPS by name
-            let mut found_id = None;
-            for (id, instance) in &self.vps_instances {
-                if instance.lock().unwrap().config.name == identifier {
-                    found_id = Some(id.clone());
-                    break;
-                }
-            }
-            match found_id {
-                Some(id) => id,
-                None => return Err(format!("VPS {} not found", identifier)),
-            }
-        };
-
-        if let Some(instance) = self.vps_instances.get(&vps_id) {
-            instance.lock().unwrap().start()
-        } else {
-            Err(format!("VPS {} not found", identifier))
-        }
+        self.find(identifier)?.lock().unwrap().start()
     }
 
+    /// Parada graciosa: SIGTERM, um prazo de espera e então SIGKILL.
     pub fn stop_vps(&mut self, identifier: &str) -> Result<(), String> {
-        // Try to find by ID first, then by name
-        let vps_id = if self.vps_instances.contains_key(identifier) {
-            identifier.to_string()
-        } else {
-            // Look for VPS by name
-            let mut found_id = None;
-            for (id, instance) in &self.vps_instances {
-                if instance.lock().unwrap().config.name == identifier {
-                    found_id = Some(id.clone());
-                    break;
-                }
-            }
-            match found_id {
-                Some(id) => id,
-                None => return Err(format!("VPS {} not found", identifier)),
-            }
-        };
+        self.find(identifier)?.lock().unwrap().stop()
+    }
+
+    pub fn restart_vps(&mut self, identifier: &str) -> Result<(), String> {
+        self.find(identifier)?.lock().unwrap().restart()
+    }
+
+    /// Mata todos os processos da VPS de uma vez.
+    pub fn kill_vps(&mut self, identifier: &str) -> Result<(), String> {
+        self.find(identifier)?.lock().unwrap().kill()
+    }
+
+    /// Grava os arquivos da VPS no disco e tira um snapshot dele.
+    pub fn snapshot_vps(&mut self, identifier: &str) -> Result<u64, String> {
+        self.find(identifier)?.lock().unwrap().snapshot()
+    }
+
+    /// Volta uma VPS parada, ou caída, ao snapshot `id`.
+    pub fn restore_vps(&mut self, identifier: &str, id: u64) -> Result<(), String> {
+        self.find(identifier)?.lock().unwrap().restore_snapshot(id)
+    }
+
+    /// Liga ou desliga o início automático da VPS quando o vbox carrega o
+    /// registro.
+    pub fn set_autostart(&mut self, identifier: &str, autostart: bool) -> Result<(), String> {
+        self.find(identifier)?.lock().unwrap().config.autostart = autostart;
+        self.save()
+    }
 
-        if let Some(instance) = self.vps_instances.get(&vps_id) {
-            instance.lock().unwrap().stop()
-        } else {
-            Err(format!("VPS {} not found", identifier))
+    /// Procura a VPS primeiro pelo ID e depois pelo nome.
+    pub fn find(&self, identifier: &str) -> Result<Arc<Mutex<VpsInstance>>, String> {
+        let id = self.resolve_id(identifier)?;
+        Ok(self.vps_instances[&id].clone())
+    }
+
+    fn resolve_id(&self, identifier: &str) -> Result<String, String> {
+        if self.vps_instances.contains_key(identifier) {
+            return Ok(identifier.to_string());
         }
+        self.vps_instances
+            .iter()
+            .find(|(_, instance)| instance.lock().unwrap().config.name == identifier)
+            .map(|(id, _)| id.clone())
+            .ok_or_else(|| format!("VPS {} not found", identifier))
     }
 
     pub fn list_vps(&self) -> Vec<VpsConfig> {
@@ -99,33 +279,89 @@ impl VpsManager {
     }
 
     pub fn delete_vps(&mut self, identifier: &str) -> Result<(), String> {
-        // Try to find by ID first, then by name
-        let vps_id = if self.vps_instances.contains_key(identifier) {
-            identifier.to_string()
-        } else {
-            // Look for VPS by name
-            let mut found_id = None;
-            for (id, instance) in &self.vps_instances {
-                if instance.lock().unwrap().config.name == identifier {
-                    found_id = Some(id.clone());
-                    break;
-                }
-            }
-            match found_id {
-                Some(id) => id,
-                None => return Err(format!("VPS {} not found", identifier)),
+        let vps_id = self.resolve_id(identifier)?;
+        let instance = self.vps_instances.remove(&vps_id).unwrap();
+        {
+            let mut instance = instance.lock().unwrap();
+            if instance.get_status() != VpsState::Stopped {
+                instance.kill()?;
             }
+        }
+        let ip_address = instance.lock().unwrap().config.ip_address.clone();
+        self.release_ip(&ip_address);
+        // O disco precisa estar fechado antes de apagar seus arquivos
+        drop(instance);
+        self.save()?;
+        remove_disk_files(&self.dir, &vps_id)
+    }
+
+    // Grava as definições de todas as VPS no registro, trocando o arquivo
+    // inteiro de uma vez para nunca deixar um registro pela metade
+    fn save(&self) -> Result<(), String> {
+        let registry = match &self.registry {
+            Some(registry) => registry,
+            None => return Ok(()),
         };
+        let mut configs: Vec<VpsConfig> = self
+            .vps_instances
+            .values()
+            .map(|instance| instance.lock().unwrap().config.clone())
+            .collect();
+        configs.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        let data = serde_json::to_vec_pretty(&configs).map_err(|e| e.to_string())?;
 
-        if let Some(instance) = self.vps_instances.remove(&vps_id) {
-            let mut instance = instance.lock().unwrap();
-            if instance.get_status() == "running" {
-                instance.stop()?;
+        let tmp_path = registry.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut tmp = fs::File::create(&tmp_path)?;
+            tmp.write_all(&data)?;
+            tmp.sync_all()?;
+            fs::rename(&tmp_path, registry)
+        };
+        write().map_err(|e| format!("cannot save {}: {}", registry.display(), e))
+    }
+
+    // Apaga as imagens de disco de VPS que não estão no registro
+    fn remove_orphaned_disks(&self) {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let orphans: HashSet<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| disk_owner(&entry.file_name().to_string_lossy()))
+            .filter(|id| !self.vps_instances.contains_key(id))
+            .collect();
+        for id in orphans {
+            match remove_disk_files(&self.dir, &id) {
+                Ok(()) => eprintln!("Removed disk image of unknown VPS {}", id),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+}
+
+// ID da VPS dona de um arquivo de disco (`vps_<id>_disk.bin` e os arquivos
+// ao lado dele: journal, snapshots, temporários)
+fn disk_owner(file_name: &str) -> Option<String> {
+    let (id, _) = file_name.strip_prefix("vps_")?.split_once("_disk.bin")?;
+    Some(id.to_string())
+}
+
+// Apaga a imagem de disco da VPS `id` e todos os arquivos dela
+fn remove_disk_files(dir: &Path, id: &str) -> Result<(), String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("cannot read {}: {}", dir.display(), e)),
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if disk_owner(&entry.file_name().to_string_lossy()).as_deref() == Some(id) {
+            match fs::remove_file(entry.path()) {
+                // Um arquivo temporário que já foi renomeado nesse meio tempo
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("cannot remove {}: {}", entry.path().display(), e)),
+                Ok(()) => {}
             }
-            // TODO: Limpar arquivos do disco
-            Ok(())
-        } else {
-            Err(format!("VPS {} not found", identifier))
         }
     }
-}
\ No newline at end of file
+    Ok(())
+}
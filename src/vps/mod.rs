@@ -1,2 +1,3 @@
 pub mod manager;
-pub mod instance;
\ No newline at end of file
+pub mod instance;
+pub mod template;
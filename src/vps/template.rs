@@ -0,0 +1,62 @@
+/// Modelo de VPS: recursos sugeridos e arquivos criados no disco novo.
+/// `/etc/rc.local` é o script de inicialização; o shell da VPS executa
+/// suas linhas toda vez que ela inicia.
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub memory_mb: usize,
+    pub disk_mb: usize,
+    pub cpu_cores: usize,
+    /// Diretórios criados antes dos arquivos, em ordem.
+    pub dirs: &'static [&'static str],
+    pub files: &'static [(&'static str, &'static str)],
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "minimal",
+        description: "Shell only, no services",
+        memory_mb: 64,
+        disk_mb: 64,
+        cpu_cores: 1,
+        dirs: &["/var", "/var/log"],
+        files: &[
+            ("/etc/motd", "Minimal VPS"),
+            ("/etc/rc.local", "# Executado pelo shell da VPS ao iniciar\ntouch /var/log/boot.log\necho minimal up > /var/log/boot.log\n"),
+        ],
+    },
+    Template {
+        name: "web",
+        description: "Web server with a document root under /var/www",
+        memory_mb: 512,
+        disk_mb: 1024,
+        cpu_cores: 2,
+        dirs: &["/var", "/var/log", "/var/log/nginx", "/var/www", "/var/www/html", "/etc/nginx", "/home/www"],
+        files: &[
+            ("/etc/motd", "Web server VPS"),
+            ("/etc/nginx/nginx.conf", "worker_processes 2;\nserver {\n    listen 80;\n    root /var/www/html;\n}\n"),
+            ("/var/www/html/index.html", "<html><body><h1>It works!</h1></body></html>\n"),
+            ("/home/www/README", "Publique os arquivos do site em /var/www/html\n"),
+            ("/etc/rc.local", "# Executado pelo shell da VPS ao iniciar\ntouch /var/log/nginx/access.log\ntouch /var/log/boot.log\necho web server up > /var/log/boot.log\n"),
+        ],
+    },
+    Template {
+        name: "db",
+        description: "Database server with data under /var/lib/db",
+        memory_mb: 1024,
+        disk_mb: 2048,
+        cpu_cores: 2,
+        dirs: &["/var", "/var/log", "/var/lib", "/var/lib/db", "/etc/db", "/home/dba"],
+        files: &[
+            ("/etc/motd", "Database server VPS"),
+            ("/etc/db/db.conf", "data_dir = /var/lib/db\nport = 5432\nmax_connections = 100\n"),
+            ("/home/dba/README", "Os dados ficam em /var/lib/db\n"),
+            ("/etc/rc.local", "# Executado pelo shell da VPS ao iniciar\ntouch /var/log/db.log\ntouch /var/log/boot.log\necho database up > /var/log/boot.log\n"),
+        ],
+    },
+];
+
+/// O modelo chamado `name`.
+pub fn find(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.name == name)
+}